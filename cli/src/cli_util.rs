@@ -89,7 +89,7 @@ use crate::config::{
     new_config_path, AnnotatedValue, CommandNameAndArgs, ConfigNamePathBuf, ConfigSource,
     LayeredConfigs,
 };
-use crate::diff_util::{self, DiffFormat, DiffFormatArgs, DiffRenderer};
+use crate::diff_util::{self, DiffContentGrep, DiffFormat, DiffFormatArgs, DiffRenderer};
 use crate::formatter::{FormatRecorder, Formatter, PlainTextFormatter};
 use crate::git_util::{
     is_colocated_git_workspace, print_failed_git_export, print_git_import_stats,
@@ -851,7 +851,14 @@ impl WorkspaceCommandHelper {
         args: &DiffFormatArgs,
     ) -> Result<DiffRenderer<'_>, CommandError> {
         let formats = diff_util::diff_formats_for(&self.settings, args)?;
-        Ok(self.diff_renderer(formats))
+        Ok(self
+            .diff_renderer(formats)
+            .with_max_files(args.max_files)
+            .with_type_filter(args.type_filter.clone())
+            .with_content_grep(DiffContentGrep::from_args(args))
+            .with_max_output_bytes(args.max_output_bytes)
+            .with_group_by_status(args.group_by_status)
+            .with_profile_files(args.profile_files))
     }
 
     /// Loads textual diff renderer from the settings and log-like command
@@ -863,7 +870,15 @@ impl WorkspaceCommandHelper {
         patch: bool,
     ) -> Result<Option<DiffRenderer<'_>>, CommandError> {
         let formats = diff_util::diff_formats_for_log(&self.settings, args, patch)?;
-        Ok((!formats.is_empty()).then(|| self.diff_renderer(formats)))
+        Ok((!formats.is_empty()).then(|| {
+            self.diff_renderer(formats)
+                .with_max_files(args.max_files)
+                .with_type_filter(args.type_filter.clone())
+                .with_content_grep(DiffContentGrep::from_args(args))
+                .with_max_output_bytes(args.max_output_bytes)
+                .with_group_by_status(args.group_by_status)
+                .with_profile_files(args.profile_files)
+        }))
     }
 
     /// Loads diff editor from the settings.
@@ -1108,6 +1123,7 @@ impl WorkspaceCommandHelper {
     pub fn commit_template_language(&self) -> Result<CommitTemplateLanguage<'_>, CommandError> {
         Ok(CommitTemplateLanguage::new(
             self.repo().as_ref(),
+            &self.settings,
             &self.path_converter,
             self.workspace_id(),
             self.revset_parse_context(),
@@ -1725,6 +1741,7 @@ impl WorkspaceCommandTransaction<'_> {
             .expect("parse error should be confined by WorkspaceCommandHelper::new()");
         CommitTemplateLanguage::new(
             self.tx.repo(),
+            &self.helper.settings,
             &self.helper.path_converter,
             self.helper.workspace_id(),
             self.helper.revset_parse_context(),
@@ -379,6 +379,9 @@ impl From<DiffRenderError> for CommandError {
             DiffRenderError::DiffGenerate(_) => user_error(err),
             DiffRenderError::Backend(err) => err.into(),
             DiffRenderError::AccessDenied { .. } => user_error(err),
+            DiffRenderError::InvalidGitDiff { .. } => internal_error(err),
+            DiffRenderError::ExternalToolUnsupported => user_error(err),
+            DiffRenderError::PathPairFormatUnsupported => user_error(err),
             DiffRenderError::Io(err) => err.into(),
         }
     }
@@ -21,12 +21,15 @@ use std::time::Instant;
 use clap::Subcommand;
 use criterion::measurement::Measurement;
 use criterion::{BatchSize, BenchmarkGroup, BenchmarkId, Criterion};
+use jj_lib::backend::CopyRecords;
+use jj_lib::matchers::EverythingMatcher;
 use jj_lib::object_id::HexPrefix;
 use jj_lib::repo::Repo;
 use jj_lib::revset::{self, DefaultSymbolResolver, RevsetExpression, SymbolResolverExtension};
 
 use crate::cli_util::{CommandHelper, RevisionArg, WorkspaceCommandHelper};
 use crate::command_error::CommandError;
+use crate::diff_util::{DiffFormat, DiffStatOptions};
 use crate::ui::Ui;
 
 /// Commands for benchmarking internal operations
@@ -35,6 +38,8 @@ use crate::ui::Ui;
 pub enum BenchCommand {
     #[command(name = "commonancestors")]
     CommonAncestors(BenchCommonAncestorsArgs),
+    #[command(name = "diffstat")]
+    DiffStat(BenchDiffStatArgs),
     #[command(name = "isancestor")]
     IsAncestor(BenchIsAncestorArgs),
     #[command(name = "resolveprefix")]
@@ -52,6 +57,15 @@ pub struct BenchCommonAncestorsArgs {
     criterion: CriterionArgs,
 }
 
+/// Compute the diff stat between two commits sequentially and in parallel
+#[derive(clap::Args, Clone, Debug)]
+pub struct BenchDiffStatArgs {
+    from: RevisionArg,
+    to: RevisionArg,
+    #[command(flatten)]
+    criterion: CriterionArgs,
+}
+
 /// Checks if the first commit is an ancestor of the second commit
 #[derive(clap::Args, Clone, Debug)]
 pub struct BenchIsAncestorArgs {
@@ -147,6 +161,40 @@ pub(crate) fn cmd_bench(
                 routine,
             )?;
         }
+        BenchCommand::DiffStat(args) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let from_tree = workspace_command.resolve_single_rev(&args.from)?.tree()?;
+            let to_tree = workspace_command.resolve_single_rev(&args.to)?.tree()?;
+            let copy_records = CopyRecords::default();
+            let mut criterion = new_criterion(ui, &args.criterion);
+            let mut group =
+                criterion.benchmark_group(format!("diffstat-{}-{}", args.from, args.to));
+            for parallel in [false, true] {
+                let options = DiffStatOptions {
+                    parallel,
+                    ..Default::default()
+                };
+                let renderer =
+                    workspace_command.diff_renderer(vec![DiffFormat::Stat(Box::new(options))]);
+                let routine = || {
+                    let mut output = vec![];
+                    renderer
+                        .write_diff(
+                            &mut output,
+                            &from_tree,
+                            &to_tree,
+                            &EverythingMatcher,
+                            &copy_records,
+                            80,
+                        )
+                        .unwrap();
+                    output.len()
+                };
+                let id = if parallel { "parallel" } else { "sequential" };
+                group.bench_function(id, |bencher| bencher.iter(routine));
+            }
+            group.finish();
+        }
         BenchCommand::IsAncestor(args) => {
             let workspace_command = command.workspace_helper(ui)?;
             let ancestor_commit = workspace_command.resolve_single_rev(&args.ancestor)?;
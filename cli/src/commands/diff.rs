@@ -12,15 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::io::Write as _;
+
 use itertools::Itertools;
 use jj_lib::backend::CopyRecords;
+use jj_lib::fileset::FilesetExpression;
 use jj_lib::repo::Repo;
 use jj_lib::rewrite::merge_commit_trees;
 use tracing::instrument;
 
 use crate::cli_util::{print_unmatched_explicit_paths, CommandHelper, RevisionArg};
-use crate::command_error::CommandError;
-use crate::diff_util::DiffFormatArgs;
+use crate::command_error::{
+    user_error, user_error_with_hint, user_error_with_message, CommandError,
+};
+use crate::diff_util::{self, DiffFormat, DiffFormatArgs, DiffLabel};
 use crate::ui::Ui;
 
 /// Compare file contents between two revisions
@@ -34,6 +39,16 @@ use crate::ui::Ui;
 /// given revisions. If either is left out, it defaults to the working-copy
 /// commit. For example, `jj diff --from main` shows the changes from "main"
 /// (perhaps a branch name) to the working-copy commit.
+///
+/// With the `--follow` option, the diff is restricted to the given file under
+/// all the names it was renamed or copied from or to between the compared
+/// revisions.
+///
+/// With the `--files` option, compares two files on disk without involving
+/// the repo. Only the `--git` and `--color-words` formats are supported.
+///
+/// With the `--split-output` option, the Git-format diff of each changed path
+/// is written to a separate `.patch` file instead of the standard output.
 #[derive(clap::Args, Clone, Debug)]
 pub(crate) struct DiffArgs {
     /// Show changes in this revision, compared to its parent(s)
@@ -52,6 +67,43 @@ pub(crate) struct DiffArgs {
     /// Restrict the diff to these paths
     #[arg(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
+    /// Show changes to this file, following renames and copies
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    follow: Option<String>,
+    /// Compare these two files instead of revisions
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["LEFT", "RIGHT"],
+        value_hint = clap::ValueHint::FilePath,
+        conflicts_with_all = ["revision", "from", "to", "paths", "follow"]
+    )]
+    files: Option<Vec<String>>,
+    /// Write the diff of each changed path to a separate file in this
+    /// directory
+    ///
+    /// Each file is named after the path with a `.patch` extension. Only the
+    /// `--git` format is supported.
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        conflicts_with = "files"
+    )]
+    split_output: Option<String>,
+    /// Also show the diff of the commit descriptions
+    ///
+    /// If the revision is a merge commit, use `--from` and `--to` to specify
+    /// which descriptions to compare.
+    #[arg(long, conflicts_with_all = ["files", "split_output"])]
+    with_description: bool,
+    /// Also show the diff of the commit authors, committers, and their
+    /// timestamps
+    ///
+    /// If the revision is a merge commit, use `--from` and `--to` to specify
+    /// which commits to compare.
+    #[arg(long, conflicts_with_all = ["files", "split_output"])]
+    with_metadata: bool,
     #[command(flatten)]
     format: DiffFormatArgs,
 }
@@ -62,6 +114,9 @@ pub(crate) fn cmd_diff(
     command: &CommandHelper,
     args: &DiffArgs,
 ) -> Result<(), CommandError> {
+    if let Some(files) = &args.files {
+        return show_files_diff(ui, command, files, &args.format);
+    }
     let workspace_command = command.workspace_helper(ui)?;
     let resolve_revision = |r: &Option<RevisionArg>| {
         workspace_command.resolve_single_rev(r.as_ref().unwrap_or(&RevisionArg::AT))
@@ -69,6 +124,8 @@ pub(crate) fn cmd_diff(
 
     let from_tree;
     let to_tree;
+    let from_commit;
+    let to_commit;
     let mut copy_records = CopyRecords::default();
     if args.from.is_some() || args.to.is_some() {
         let from = resolve_revision(&args.from)?;
@@ -81,6 +138,8 @@ pub(crate) fn cmd_diff(
             from.id(),
             to.id(),
         )?)?;
+        from_commit = Some(from);
+        to_commit = to;
     } else {
         let to = resolve_revision(&args.revision)?;
         let parents: Vec<_> = to.parents().try_collect()?;
@@ -94,21 +153,101 @@ pub(crate) fn cmd_diff(
                 to.id(),
             )?)?;
         }
+        from_commit = parents.into_iter().exactly_one().ok();
+        to_commit = to;
     }
 
     let diff_renderer = workspace_command.diff_renderer_for(&args.format)?;
-    let fileset_expression = workspace_command.parse_file_patterns(&args.paths)?;
+    let mut fileset_expression = workspace_command.parse_file_patterns(&args.paths)?;
+    if let Some(path) = &args.follow {
+        let path = workspace_command.parse_file_path(path)?;
+        let followed = diff_util::collect_followed_paths(&copy_records, &path)
+            .into_iter()
+            .map(FilesetExpression::file_path)
+            .collect();
+        let followed_expression = FilesetExpression::union_all(followed);
+        fileset_expression = if args.paths.is_empty() {
+            followed_expression
+        } else {
+            FilesetExpression::union_all(vec![fileset_expression, followed_expression])
+        };
+    }
     let matcher = fileset_expression.to_matcher();
-    ui.request_pager();
-    diff_renderer.show_diff(
-        ui,
-        ui.stdout_formatter().as_mut(),
-        &from_tree,
-        &to_tree,
-        &matcher,
-        &copy_records,
-        ui.term_width(),
-    )?;
+    if let Some(dir) = &args.split_output {
+        let formats = diff_util::diff_formats_for(command.settings(), &args.format)?;
+        let [DiffFormat::Git(options)] = formats.as_slice() else {
+            return Err(user_error(
+                "Only --git format is supported with --split-output",
+            ));
+        };
+        let num_files = diff_util::write_git_diff_files(
+            workspace_command.repo().store(),
+            &from_tree,
+            &to_tree,
+            &matcher,
+            &copy_records,
+            options,
+            &command.cwd().join(dir),
+        )?;
+        writeln!(ui.status(), "Wrote {num_files} patch files to {dir}")?;
+        print_unmatched_explicit_paths(
+            ui,
+            &workspace_command,
+            &fileset_expression,
+            [&from_tree, &to_tree],
+        )?;
+        return Ok(());
+    }
+    if args.with_description || args.with_metadata {
+        let Some(from_commit) = &from_commit else {
+            let what = if args.with_description {
+                "descriptions"
+            } else {
+                "metadata"
+            };
+            return Err(user_error_with_hint(
+                format!("Cannot compare {what} unless the revision has exactly one parent"),
+                "Use --from and --to to specify the revisions to compare",
+            ));
+        };
+        ui.request_pager();
+        let mut formatter = ui.stdout_formatter();
+        if args.with_metadata {
+            diff_renderer.show_metadata_diff(formatter.as_mut(), from_commit, &to_commit)?;
+        }
+        if args.with_description {
+            diff_renderer.show_commit_diff(
+                ui,
+                formatter.as_mut(),
+                from_commit,
+                &to_commit,
+                &matcher,
+                &copy_records,
+                ui.term_width(),
+            )?;
+        } else {
+            diff_renderer.show_diff(
+                ui,
+                formatter.as_mut(),
+                &from_tree,
+                &to_tree,
+                &matcher,
+                &copy_records,
+                ui.term_width(),
+            )?;
+        }
+    } else {
+        ui.request_pager();
+        diff_renderer.show_diff(
+            ui,
+            ui.stdout_formatter().as_mut(),
+            &from_tree,
+            &to_tree,
+            &matcher,
+            &copy_records,
+            ui.term_width(),
+        )?;
+    }
     print_unmatched_explicit_paths(
         ui,
         &workspace_command,
@@ -117,3 +256,42 @@ pub(crate) fn cmd_diff(
     )?;
     Ok(())
 }
+
+fn show_files_diff(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    files: &[String],
+    format_args: &DiffFormatArgs,
+) -> Result<(), CommandError> {
+    let [left_name, right_name] = files else {
+        panic!("clap should require exactly two files");
+    };
+    let read_file = |name: &str| {
+        std::fs::read(command.cwd().join(name))
+            .map_err(|err| user_error_with_message(format!("Failed to read file {name}"), err))
+    };
+    let left = read_file(left_name)?;
+    let right = read_file(right_name)?;
+    let formats = diff_util::diff_formats_for(command.settings(), format_args)?;
+    ui.request_pager();
+    let mut formatter = ui.stdout_formatter();
+    let formatter = formatter.as_mut();
+    formatter.with_label(DiffLabel::Diff.as_str(), |formatter| {
+        for format in &formats {
+            match format {
+                DiffFormat::Git(options) => diff_util::show_git_contents_diff(
+                    formatter, left_name, &left, right_name, &right, options,
+                )?,
+                DiffFormat::ColorWords(options) => diff_util::show_color_words_contents_diff(
+                    formatter, left_name, &left, right_name, &right, options,
+                )?,
+                _ => {
+                    return Err(user_error(
+                        "Only --git and --color-words formats are supported with --files",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    })
+}
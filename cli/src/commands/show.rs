@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use itertools::Itertools as _;
 use jj_lib::matchers::EverythingMatcher;
 use tracing::instrument;
 
 use crate::cli_util::{CommandHelper, RevisionArg};
-use crate::command_error::CommandError;
+use crate::command_error::{user_error, CommandError};
 use crate::diff_util::DiffFormatArgs;
 use crate::ui::Ui;
 
@@ -34,6 +35,12 @@ pub(crate) struct ShowArgs {
     /// For the syntax, see https://github.com/martinvonz/jj/blob/main/docs/templates.md
     #[arg(long, short = 'T')]
     template: Option<String>,
+    /// Show changes compared to the parent at this index (starting from 0)
+    ///
+    /// By default, a merge commit is compared to the automatic merge of all
+    /// of its parents.
+    #[arg(long, value_name = "INDEX")]
+    parent: Option<usize>,
     #[command(flatten)]
     format: DiffFormatArgs,
 }
@@ -46,6 +53,18 @@ pub(crate) fn cmd_show(
 ) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let parent = if let Some(index) = args.parent {
+        let mut parents: Vec<_> = commit.parents().try_collect()?;
+        if index >= parents.len() {
+            return Err(user_error(format!(
+                "Parent index {index} is out of range (the revision has {} parent(s))",
+                parents.len()
+            )));
+        }
+        Some(parents.swap_remove(index))
+    } else {
+        None
+    };
     let template_string = match &args.template {
         Some(value) => value.to_string(),
         None => command.settings().config().get_string("templates.show")?,
@@ -56,6 +75,17 @@ pub(crate) fn cmd_show(
     let mut formatter = ui.stdout_formatter();
     let formatter = formatter.as_mut();
     template.format(&commit, formatter)?;
-    diff_renderer.show_patch(ui, formatter, &commit, &EverythingMatcher, ui.term_width())?;
+    if let Some(parent) = &parent {
+        diff_renderer.show_patch_against_parent(
+            ui,
+            formatter,
+            &commit,
+            parent,
+            &EverythingMatcher,
+            ui.term_width(),
+        )?;
+    } else {
+        diff_renderer.show_patch(ui, formatter, &commit, &EverythingMatcher, ui.term_width())?;
+    }
     Ok(())
 }
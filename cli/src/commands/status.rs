@@ -65,7 +65,8 @@ pub(crate) fn cmd_status(
             writeln!(formatter, "The working copy is clean")?;
         } else {
             writeln!(formatter, "Working copy changes:")?;
-            let diff_renderer = workspace_command.diff_renderer(vec![DiffFormat::Summary]);
+            let diff_renderer =
+                workspace_command.diff_renderer(vec![DiffFormat::Summary(Box::default())]);
             let width = ui.term_width();
             diff_renderer.show_diff(
                 ui,
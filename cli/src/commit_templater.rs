@@ -33,6 +33,7 @@ use jj_lib::op_store::{RefTarget, RemoteRef, WorkspaceId};
 use jj_lib::repo::Repo;
 use jj_lib::repo_path::RepoPathUiConverter;
 use jj_lib::revset::{self, Revset, RevsetExpression, RevsetModifier, RevsetParseContext};
+use jj_lib::settings::UserSettings;
 use jj_lib::store::Store;
 use once_cell::unsync::OnceCell;
 
@@ -50,6 +51,9 @@ use crate::templater::{
 };
 use crate::{diff_util, revset_util, text_util};
 
+/// Display width of diffs rendered by templates if not specified.
+const DEFAULT_PATCH_WIDTH: usize = 80;
+
 pub trait CommitTemplateLanguageExtension {
     fn build_fn_table<'repo>(&self) -> CommitTemplateBuildFnTable<'repo>;
 
@@ -58,6 +62,7 @@ pub trait CommitTemplateLanguageExtension {
 
 pub struct CommitTemplateLanguage<'repo> {
     repo: &'repo dyn Repo,
+    settings: &'repo UserSettings,
     path_converter: &'repo RepoPathUiConverter,
     workspace_id: WorkspaceId,
     // RevsetParseContext doesn't borrow a repo, but we'll need 'repo lifetime
@@ -77,6 +82,7 @@ impl<'repo> CommitTemplateLanguage<'repo> {
     /// evaluation tree.
     pub fn new(
         repo: &'repo dyn Repo,
+        settings: &'repo UserSettings,
         path_converter: &'repo RepoPathUiConverter,
         workspace_id: &WorkspaceId,
         revset_parse_context: RevsetParseContext<'repo>,
@@ -95,6 +101,7 @@ impl<'repo> CommitTemplateLanguage<'repo> {
 
         CommitTemplateLanguage {
             repo,
+            settings,
             path_converter,
             workspace_id: workspace_id.clone(),
             revset_parse_context,
@@ -1343,14 +1350,22 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
             let path_converter = language.path_converter;
             let template = (self_property, context_property)
                 .map(move |(diff, context)| {
-                    let context = context.unwrap_or(diff_util::DEFAULT_CONTEXT_LINES);
+                    let options = diff_util::ColorWordsDiffOptions {
+                        context: context.unwrap_or(diff_util::DEFAULT_CONTEXT_LINES),
+                        ..Default::default()
+                    };
+                    let to_tree = diff.to_tree.clone();
+                    let matcher = diff.matcher.clone();
                     diff.into_formatted(move |formatter, store, tree_diff| {
                         diff_util::show_color_words_diff(
                             formatter,
                             store,
+                            &to_tree,
                             tree_diff,
                             path_converter,
-                            context,
+                            matcher.as_ref(),
+                            &Default::default(), // TODO: real copy tracking
+                            &options,
                         )
                     })
                 })
@@ -1365,7 +1380,10 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
             .transpose()?;
         let template = (self_property, context_property)
             .map(|(diff, context)| {
-                let context = context.unwrap_or(diff_util::DEFAULT_CONTEXT_LINES);
+                let options = diff_util::UnifiedDiffOptions {
+                    context: context.unwrap_or(diff_util::DEFAULT_CONTEXT_LINES),
+                    ..Default::default()
+                };
                 // TODO: don't pass separate copies of from_tree/to_tree/matcher
                 let from_tree = diff.from_tree.clone();
                 let to_tree = diff.to_tree.clone();
@@ -1378,7 +1396,7 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
                         &to_tree,
                         matcher.as_ref(),
                         &Default::default(), // TODO: real copy tracking
-                        context,
+                        &options,
                     )
                 })
             })
@@ -1392,8 +1410,18 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
         let path_converter = language.path_converter;
         let template = (self_property, width_property)
             .map(move |(diff, width)| {
+                let matcher = diff.matcher.clone();
                 diff.into_formatted(move |formatter, store, tree_diff| {
-                    diff_util::show_diff_stat(formatter, store, tree_diff, path_converter, width)
+                    diff_util::show_diff_stat(
+                        formatter,
+                        store,
+                        tree_diff,
+                        path_converter,
+                        matcher.as_ref(),
+                        &Default::default(), // TODO: real copy tracking
+                        width,
+                        &Default::default(),
+                    )
                 })
             })
             .into_template();
@@ -1418,6 +1446,7 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
                             &to_tree,
                             matcher.as_ref(),
                             &Default::default(), // TODO: real copy tracking
+                            &Default::default(),
                         )
                     })
                 })
@@ -1425,6 +1454,48 @@ fn builtin_tree_diff_methods<'repo>() -> CommitTemplateBuildMethodFnMap<'repo, T
             Ok(L::wrap_template(template))
         },
     );
+    map.insert("patch", |language, build_ctx, self_property, function| {
+        let ([], [width_node]) = function.expect_arguments()?;
+        let width_property = width_node
+            .map(|node| template_builder::expect_usize_expression(language, build_ctx, node))
+            .transpose()?;
+        let format =
+            diff_util::default_diff_format_from_settings(language.settings).map_err(|err| {
+                TemplateParseError::expression("Failed to load diff format", function.name_span)
+                    .with_source(err)
+            })?;
+        if matches!(format, diff_util::DiffFormat::Tool(_)) {
+            return Err(TemplateParseError::expression(
+                "External diff tools can't be used in templates",
+                function.name_span,
+            ));
+        }
+        let renderer = Rc::new(diff_util::DiffRenderer::new(
+            language.repo,
+            language.path_converter,
+            vec![format],
+        ));
+        let template = (self_property, width_property)
+            .map(move |(diff, width)| {
+                let renderer = renderer.clone();
+                // TODO: don't pass separate copies of from_tree/to_tree/matcher
+                let from_tree = diff.from_tree.clone();
+                let to_tree = diff.to_tree.clone();
+                let matcher = diff.matcher.clone();
+                diff.into_formatted(move |formatter, _store, _tree_diff| {
+                    renderer.show_diff_without_ui(
+                        formatter,
+                        &from_tree,
+                        &to_tree,
+                        matcher.as_ref(),
+                        &Default::default(), // TODO: real copy tracking
+                        width.unwrap_or(DEFAULT_PATCH_WIDTH),
+                    )
+                })
+            })
+            .into_template();
+        Ok(L::wrap_template(template))
+    });
     // TODO: add types() and name_only()? or let users write their own template?
     // TODO: add support for external tools
     // TODO: add files() or map() to support custom summary-like formatting?
@@ -12,56 +12,129 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cmp::max;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cmp::{max, min, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::{io, mem};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, iter, mem, slice};
 
+use bstr::ByteSlice as _;
 use futures::StreamExt;
 use itertools::Itertools;
-use jj_lib::backend::{BackendError, CopyRecords, TreeValue};
+use jj_lib::backend::{
+    BackendError, CopyRecords, FileId, MillisSinceEpoch, Signature, Timestamp, TreeValue,
+};
 use jj_lib::commit::Commit;
 use jj_lib::conflicts::{
-    materialized_diff_stream, MaterializedTreeDiffEntry, MaterializedTreeValue,
+    extract_as_single_hunk, materialize_tree_value, materialized_diff_stream,
+    MaterializedTreeDiffEntry, MaterializedTreeValue,
 };
-use jj_lib::diff::{Diff, DiffHunk};
-use jj_lib::files::{DiffLine, DiffLineHunkSide, DiffLineIterator, DiffLineNumber};
-use jj_lib::matchers::Matcher;
-use jj_lib::merge::MergedTreeValue;
+use jj_lib::diff::{find_syntax_token_ranges, Diff, DiffHunk};
+use jj_lib::files::{ContentHunk, DiffLine, DiffLineHunkSide, DiffLineIterator, DiffLineNumber};
+use jj_lib::fileset::{FilePattern, FilesetExpression};
+use jj_lib::matchers::{EverythingMatcher, FilesMatcher, Matcher};
+use jj_lib::merge::{Merge, MergedTreeValue};
 use jj_lib::merged_tree::{MergedTree, TreeDiffEntry, TreeDiffStream};
 use jj_lib::object_id::ObjectId;
 use jj_lib::repo::Repo;
-use jj_lib::repo_path::{RepoPath, RepoPathUiConverter};
+use jj_lib::repo_path::{RepoPath, RepoPathBuf, RepoPathUiConverter};
+use jj_lib::rewrite::merge_commit_trees;
 use jj_lib::settings::{ConfigResultExt as _, UserSettings};
 use jj_lib::store::Store;
+use once_cell::sync::Lazy;
 use pollster::FutureExt;
+use rayon::iter::{IntoParallelIterator as _, ParallelExtend as _, ParallelIterator as _};
 use thiserror::Error;
 use tracing::instrument;
-use unicode_width::UnicodeWidthStr as _;
+use unicode_width::{UnicodeWidthChar as _, UnicodeWidthStr as _};
 
-use crate::config::CommandNameAndArgs;
-use crate::formatter::Formatter;
+use crate::cli_util::short_commit_hash;
+use crate::config::{interpolate_variables, CommandNameAndArgs};
+use crate::formatter::{FormatRecorder, Formatter, PlainTextFormatter};
 use crate::merge_tools::{
-    self, generate_diff, invoke_external_diff, new_utf8_temp_dir, DiffGenerateError, DiffToolMode,
-    ExternalMergeTool,
+    self, generate_diff, invoke_external_diff, new_utf8_temp_dir, spawn_diff, DiffGenerateError,
+    DiffToolMode, ExternalMergeTool,
 };
 use crate::text_util;
+use crate::time_util::{
+    format_absolute_timestamp, format_absolute_timestamp_with, FormattingItems,
+};
 use crate::ui::Ui;
 
 pub const DEFAULT_CONTEXT_LINES: usize = 3;
+const DEFAULT_CONTEXT_HUNK_SEPARATOR: &str = "    ...";
+const DEFAULT_HEADER_TEMPLATE: &str = "$description $path$copy_source:";
+const DEFAULT_GIT_HASH_ABBREV: usize = 10;
+// Same as Git's default: a line starting with an alphabetic character,
+// underscore, or dollar sign.
+const DEFAULT_FUNCTION_REGEX: &str = r"^[[:alpha:]_$]";
+
+const DEFAULT_BINARY_HEXDUMP_WINDOW: usize = 64;
+// Number of bytes per row of binary hexdump.
+const HEXDUMP_ROW_BYTES: usize = 16;
+
+const DEFAULT_GENERATED_MARKERS: [&str; 2] = ["@generated", "DO NOT EDIT"];
+// Number of leading lines searched for generated file markers.
+const GENERATED_MARKER_LINES: usize = 5;
 
-#[derive(clap::Args, Clone, Debug)]
+#[derive(clap::Args, Clone, Debug, Default)]
 #[command(next_help_heading = "Diff Formatting Options")]
-#[command(group(clap::ArgGroup::new("short-format").args(&["summary", "stat", "types", "name_only"])))]
-#[command(group(clap::ArgGroup::new("long-format").args(&["git", "color_words", "tool"])))]
+#[command(group(clap::ArgGroup::new("short-format").args(&["summary", "stat", "oneline_stat", "types", "name_only", "mode_only"])))]
+#[command(group(clap::ArgGroup::new("long-format").args(&["git", "color_words", "html", "posix", "tool"])))]
 pub struct DiffFormatArgs {
     /// For each path, show only whether it was modified, added, or deleted
     #[arg(long, short)]
     pub summary: bool,
+    /// Show the content hash of each file in summary
+    ///
+    /// The hash of the file after the change is shown. For deleted files, the
+    /// hash before the change is shown.
+    #[arg(long)]
+    with_hash: bool,
+    /// Show the number of hunks of each file in summary
+    ///
+    /// Hunks are counted as in the Git-format diff with the same `--context`.
+    /// Nothing is shown for binary files.
+    #[arg(long)]
+    with_hunk_count: bool,
+    /// Show the Git file mode of each file in summary
+    ///
+    /// The mode is shown as `(100644)`, or as `(100644->100755)` if it
+    /// changed. For deleted files, the mode before the change is shown.
+    #[arg(long)]
+    show_mode: bool,
+    /// Collapse the changes in a directory into a single line in summary if
+    /// there are more than N of them
+    ///
+    /// Directories are grouped by the number of leading path components
+    /// configured by `ui.diff.summary.collapse-depth` (default 1).
+    #[arg(long, value_name = "N")]
+    collapse_dirs: Option<usize>,
     /// Show a histogram of the changes
     #[arg(long)]
     pub stat: bool,
+    /// Show the stat in the given format (implies `--stat`)
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub stat_format: Option<DiffStatFormat>,
+    /// Show only the number of changed files, insertions, and deletions
+    #[arg(long)]
+    pub shortstat: bool,
+    /// Show only the number of files whose conflicts were introduced or
+    /// resolved
+    ///
+    /// A conflict is introduced if a file that wasn't conflicted becomes
+    /// conflicted, and resolved if a conflicted file is no longer conflicted
+    /// or is deleted.
+    #[arg(long)]
+    pub conflict_stat: bool,
+    /// For each path, show whether it was modified, added, or deleted along
+    /// with the number of inserted and deleted lines
+    #[arg(long)]
+    pub oneline_stat: bool,
     /// For each path, show only its type before and after
     ///
     /// The diff is shown as two letters. The first letter indicates the type
@@ -71,1097 +144,6232 @@ pub struct DiffFormatArgs {
     /// Git submodule.
     #[arg(long)]
     pub types: bool,
+    /// Show the number of sides of conflicts in `--types`, such as `C3` for a
+    /// 3-sided conflict
+    #[arg(long)]
+    conflict_detail: bool,
     /// For each path, show only its path
     ///
     /// Typically useful for shell commands like:
     ///    `jj diff -r @- --name_only | xargs perl -pi -e's/OLD/NEW/g`
     #[arg(long)]
     pub name_only: bool,
+    /// For each path whose file mode changed, show only the modes before and
+    /// after the change
+    ///
+    /// Modes are shown as in Git, such as `100644 -> 100755` for a file that
+    /// became executable. Content changes, additions, and deletions are
+    /// ignored.
+    #[arg(long)]
+    pub mode_only: bool,
     /// Show a Git-format diff
     #[arg(long)]
     pub git: bool,
     /// Show a word-level diff with changes indicated only by color
     #[arg(long)]
     pub color_words: bool,
+    /// Show a diff as HTML
+    ///
+    /// Lines and changed words are enclosed in `<span>` elements with CSS
+    /// classes named after the color labels, such as `removed`, `added`, and
+    /// `token`.
+    #[arg(long)]
+    pub html: bool,
+    /// Show a unified diff in the POSIX `diff -u` format
+    ///
+    /// File headers carry tab-separated timestamps instead of Git's `a/` and
+    /// `b/` prefixes. The commit's author date is used where known, and the
+    /// Unix epoch otherwise.
+    #[arg(long)]
+    pub posix: bool,
     /// Generate diff by external command
     #[arg(long)]
     pub tool: Option<String>,
     /// Number of lines of context to show
     #[arg(long)]
     context: Option<usize>,
+    /// Ignore case differences when comparing lines and words
+    ///
+    /// Only ASCII letters are compared case-insensitively. Changed lines are
+    /// still shown with their original case.
+    #[arg(long)]
+    ignore_case: bool,
+    /// Omit changes whose removed and added lines are all blank
+    ///
+    /// In Git-format diff, hunks containing only such changes are omitted.
+    /// Files with no other changes are omitted entirely.
+    #[arg(long)]
+    ignore_blank_lines: bool,
+    /// Treat lines matching the regex on both sides as unchanged
+    ///
+    /// Similar to Git's `-I`, changes between matching lines don't produce
+    /// hunks, and files with no other changes are omitted. The new version of
+    /// the lines is shown as context of other changes.
+    #[arg(long, value_name = "REGEX", value_parser = regex::bytes::Regex::new)]
+    ignore_lines_matching: Option<regex::bytes::Regex>,
+    /// Show spaces and tabs in changed and context lines as `·` and `→`
+    ///
+    /// Git-format diff printed with this option can no longer be applied as a
+    /// patch.
+    #[arg(long)]
+    show_whitespace: bool,
+    /// Show word-level changes inline in Git-format diff
+    ///
+    /// Instead of separate removed and added lines, changed words are
+    /// enclosed in `[-removed-]` and `{+added+}` markers.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "plain"
+    )]
+    word_diff: Option<WordDiffMode>,
+    /// Show whole function as context lines of each change in Git-format diff
+    ///
+    /// The first line of a function is detected by the
+    /// `ui.diff.function-regex` config.
+    #[arg(long)]
+    function_context: bool,
+    /// Show a note instead of hunks that only reorder lines in Git-format diff
+    ///
+    /// A hunk is considered reordered if its removed lines and added lines
+    /// are the same lines in a different order.
+    #[arg(long)]
+    show_reorders: bool,
+    /// Show only file headers and hunk headers in Git-format diff
+    ///
+    /// With `--function-context`, the first line of the function enclosing
+    /// each hunk is shown after the hunk header.
+    #[arg(long)]
+    hunk_headers_only: bool,
+    /// Show markers in place of context lines skipped between hunks in
+    /// Git-format diff
+    ///
+    /// Each marker looks like `<<<fold lines 7-11>>>`, where the line numbers
+    /// are of the left side.
+    #[arg(long)]
+    fold_markers: bool,
+    /// Show the number of lines skipped between hunks in Git-format diff
+    ///
+    /// A line like `# 42 lines not shown` is inserted between hunks, so the
+    /// output is no longer a valid patch.
+    #[arg(long)]
+    git_annotate_gaps: bool,
+    /// Show left and right line numbers before each line in Git-format diff
+    ///
+    /// The output is no longer a valid patch. Ignored with `--word-diff`.
+    #[arg(long)]
+    line_numbers: bool,
+    /// Show conflicted files as combined diffs in Git-format diff
+    ///
+    /// Like Git's `diff --cc`, each line of the materialized conflict is
+    /// prefixed with one column per side of the conflict, marking whether
+    /// the line was added to or removed from that side.
+    #[arg(long)]
+    combined_conflicts: bool,
+    /// Show files in Git-format diff sorted by path
+    ///
+    /// All file diffs are buffered and then emitted in the byte order of
+    /// their paths, which is the order Git uses (e.g. `a.txt` before `a/b`).
+    #[arg(long)]
+    git_sorted: bool,
+    /// Check that the lines of each hunk in Git-format diff match the line
+    /// counts in the hunk header (for debugging)
+    #[arg(long, hide = true)]
+    verify_git_diff: bool,
+    /// Print the N files that took the longest to diff to stderr (for
+    /// debugging)
+    ///
+    /// The time spent reading the contents and comparing lines and words of
+    /// each file is measured. Only Git and color-words diffs are profiled.
+    #[arg(
+        long,
+        hide = true,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "10"
+    )]
+    pub profile_files: Option<usize>,
+    /// Also show changes to files pointed to by unchanged symlinks in
+    /// color-words diff
+    ///
+    /// Symlinks are followed within the tree. Targets outside of the
+    /// repository and symlink cycles are ignored.
+    #[arg(long)]
+    deref_symlinks: bool,
+    /// Also show changes from the base to each side of conflicts in
+    /// color-words diff
+    #[arg(long)]
+    conflict_base: bool,
+    /// Omit binary files entirely in color-words and Git-format diffs
+    ///
+    /// Binary files are still counted in `--stat`.
+    #[arg(long)]
+    skip_binary: bool,
+    /// Show only a note in place of generated files in color-words diff, and
+    /// omit them from Git-format diff
+    ///
+    /// A file is considered generated if one of its first 5 lines contains
+    /// one of the markers in `ui.diff.generated-markers` (default `@generated`
+    /// and `DO NOT EDIT`).
+    #[arg(long)]
+    skip_generated: bool,
+    /// Show the first region where binary files differ as hexdump in
+    /// color-words diff
+    ///
+    /// The number of bytes shown per side is limited by
+    /// `ui.diff.binary-hexdump-window` (default 64).
+    #[arg(long)]
+    binary_hexdump: bool,
+    /// Show only changes to paths of the given types before or after the change
+    #[arg(long, value_enum, value_name = "TYPES", value_delimiter = ',')]
+    pub type_filter: Vec<DiffFileType>,
+    /// Show at most this many changed files
+    ///
+    /// The number of omitted files is printed after the diff of each format.
+    #[arg(long, value_name = "N")]
+    pub max_files: Option<usize>,
+    /// Show at most this many hunks of each file in Git and color-words
+    /// diffs
+    ///
+    /// The number of omitted hunks is printed after the shown hunks.
+    #[arg(long, value_name = "N")]
+    pub max_hunks_per_file: Option<usize>,
+    /// Show only files where an added or removed line matches the regex
+    #[arg(long, value_name = "REGEX", value_parser = regex::bytes::Regex::new)]
+    pub content_grep: Option<regex::bytes::Regex>,
+    /// Match `--content-grep` against added lines only
+    #[arg(long, requires = "content_grep")]
+    pub content_grep_added_only: bool,
+    /// Stop rendering the diff once this many bytes are written
+    ///
+    /// The output is truncated between files, and `... output truncated` is
+    /// printed in place of the rest.
+    #[arg(long, value_name = "BYTES")]
+    pub max_output_bytes: Option<usize>,
+    /// Group the changed files under `=== Added ===`, `=== Modified ===`,
+    /// etc. section headers
+    #[arg(long)]
+    pub group_by_status: bool,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum DiffFormat {
-    Summary,
-    Stat,
-    Types,
+    Summary(Box<DiffSummaryOptions>),
+    Stat(Box<DiffStatOptions>),
+    ShortStat,
+    ConflictStat,
+    OnelineStat,
+    Types(Box<DiffTypesOptions>),
     NameOnly,
-    Git { context: usize },
-    ColorWords { context: usize },
+    ModeOnly,
+    Git(Box<UnifiedDiffOptions>),
+    ColorWords(Box<ColorWordsDiffOptions>),
+    Html(Box<UnifiedDiffOptions>),
+    Posix(Box<UnifiedDiffOptions>),
     Tool(Box<ExternalMergeTool>),
 }
 
-/// Returns a list of requested diff formats, which will never be empty.
-pub fn diff_formats_for(
-    settings: &UserSettings,
-    args: &DiffFormatArgs,
-) -> Result<Vec<DiffFormat>, config::ConfigError> {
-    let formats = diff_formats_from_args(settings, args)?;
-    if formats.is_empty() {
-        Ok(vec![default_diff_format(settings, args.context)?])
-    } else {
-        Ok(formats)
-    }
+/// Label attached to a part of the diff output.
+///
+/// Labels can be styled by the `colors` config. See `colors.toml` for the
+/// default styles.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DiffLabel {
+    /// Whole diff output.
+    Diff,
+    /// File header of color-words diff.
+    Header,
+    /// File header of Git-format diff.
+    FileHeader,
+    /// `@@ ... @@` line of Git-format diff.
+    HunkHeader,
+    /// Line numbers of color-words diff.
+    LineNumber,
+    /// Unchanged lines of Git-format diff.
+    Context,
+    /// Removed lines or paths.
+    Removed,
+    /// Added lines or paths.
+    Added,
+    /// Modified paths.
+    Modified,
+    /// Renamed paths.
+    Renamed,
+    /// Copied paths.
+    Copied,
+    /// Changed words within removed or added lines.
+    Token,
+    /// Whitespace at end of line.
+    TrailingWhitespace,
+    /// Note in place of a hunk which only reorders lines.
+    Reordered,
+    /// Path whose contents cannot be read.
+    AccessDenied,
+    /// Note in place of binary file contents.
+    Binary,
+    /// Note in place of empty file contents or diff.
+    Empty,
+    /// `N files changed, ...` line of diff stat.
+    StatSummary,
+    /// Diff of commit descriptions.
+    Description,
+    /// Diff of commit authors, committers, and their timestamps.
+    Metadata,
+    /// Space or tab shown as a visible glyph.
+    Whitespace,
 }
 
-/// Returns a list of requested diff formats for log-like commands, which may be
-/// empty.
-pub fn diff_formats_for_log(
-    settings: &UserSettings,
-    args: &DiffFormatArgs,
-    patch: bool,
-) -> Result<Vec<DiffFormat>, config::ConfigError> {
-    let mut formats = diff_formats_from_args(settings, args)?;
-    // --patch implies default if no format other than --summary is specified
-    if patch && matches!(formats.as_slice(), [] | [DiffFormat::Summary]) {
-        formats.push(default_diff_format(settings, args.context)?);
-        formats.dedup();
+impl DiffLabel {
+    /// All labels in declaration order.
+    pub const ALL: &'static [DiffLabel] = &[
+        DiffLabel::Diff,
+        DiffLabel::Header,
+        DiffLabel::FileHeader,
+        DiffLabel::HunkHeader,
+        DiffLabel::LineNumber,
+        DiffLabel::Context,
+        DiffLabel::Removed,
+        DiffLabel::Added,
+        DiffLabel::Modified,
+        DiffLabel::Renamed,
+        DiffLabel::Copied,
+        DiffLabel::Token,
+        DiffLabel::TrailingWhitespace,
+        DiffLabel::Reordered,
+        DiffLabel::AccessDenied,
+        DiffLabel::Binary,
+        DiffLabel::Empty,
+        DiffLabel::StatSummary,
+        DiffLabel::Description,
+        DiffLabel::Metadata,
+        DiffLabel::Whitespace,
+    ];
+
+    /// Label name to be looked up in the `colors` config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiffLabel::Diff => "diff",
+            DiffLabel::Header => "header",
+            DiffLabel::FileHeader => "file_header",
+            DiffLabel::HunkHeader => "hunk_header",
+            DiffLabel::LineNumber => "line_number",
+            DiffLabel::Context => "context",
+            DiffLabel::Removed => "removed",
+            DiffLabel::Added => "added",
+            DiffLabel::Modified => "modified",
+            DiffLabel::Renamed => "renamed",
+            DiffLabel::Copied => "copied",
+            DiffLabel::Token => "token",
+            DiffLabel::TrailingWhitespace => "trailing-whitespace",
+            DiffLabel::Reordered => "reordered",
+            DiffLabel::AccessDenied => "access-denied",
+            DiffLabel::Binary => "binary",
+            DiffLabel::Empty => "empty",
+            DiffLabel::StatSummary => "stat-summary",
+            DiffLabel::Description => "description",
+            DiffLabel::Metadata => "metadata",
+            DiffLabel::Whitespace => "whitespace",
+        }
     }
-    Ok(formats)
 }
 
-fn diff_formats_from_args(
-    settings: &UserSettings,
-    args: &DiffFormatArgs,
-) -> Result<Vec<DiffFormat>, config::ConfigError> {
-    let mut formats = [
-        (args.summary, DiffFormat::Summary),
-        (args.types, DiffFormat::Types),
-        (args.name_only, DiffFormat::NameOnly),
-        (
-            args.git,
-            DiffFormat::Git {
-                context: args.context.unwrap_or(DEFAULT_CONTEXT_LINES),
-            },
-        ),
-        (
-            args.color_words,
-            DiffFormat::ColorWords {
-                context: args.context.unwrap_or(DEFAULT_CONTEXT_LINES),
-            },
-        ),
-        (args.stat, DiffFormat::Stat),
-    ]
-    .into_iter()
-    .filter_map(|(arg, format)| arg.then_some(format))
-    .collect_vec();
-    if let Some(name) = &args.tool {
-        let tool = merge_tools::get_external_tool_config(settings, name)?
-            .unwrap_or_else(|| ExternalMergeTool::with_program(name));
-        formats.push(DiffFormat::Tool(Box::new(tool)));
+impl AsRef<str> for DiffLabel {
+    fn as_ref(&self) -> &str {
+        self.as_str()
     }
-    Ok(formats)
 }
 
-fn default_diff_format(
-    settings: &UserSettings,
-    num_context_lines: Option<usize>,
-) -> Result<DiffFormat, config::ConfigError> {
-    let config = settings.config();
-    if let Some(args) = config.get("ui.diff.tool").optional()? {
-        // External "tool" overrides the internal "format" option.
-        let tool = if let CommandNameAndArgs::String(name) = &args {
-            merge_tools::get_external_tool_config(settings, name)?
+/// Options for color-words diff.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorWordsDiffOptions {
+    /// Number of context lines to show.
+    pub context: usize,
+    /// Numbers of context lines overriding `context` for matching paths.
+    pub context_by_path: ContextByPath,
+    /// Line printed in place of skipped context lines. Variables such as
+    /// `$count` are substituted. See `skipped_context_variables()`.
+    pub context_hunk_separator: String,
+    /// Header line printed for each file. Variables such as `$path` are
+    /// substituted. See `header_variables()`.
+    pub header_template: String,
+    /// Whether to show only the changed components of copied or renamed
+    /// paths in the header, such as `src/{old => new}/file`.
+    pub compact_renames: bool,
+    /// Whether to label trailing whitespace of each line.
+    pub highlight_trailing_whitespace: bool,
+    /// Whether to print a message if there are no changes.
+    pub show_no_changes: bool,
+    /// Whether to omit binary files entirely.
+    pub skip_binary: bool,
+    /// Markers identifying generated files whose contents are omitted, or
+    /// `None` to show all files.
+    pub generated_markers: Option<Vec<String>>,
+    /// Whether to show only the header and the number of lines of deleted
+    /// files.
+    pub collapse_deletions: bool,
+    /// Maximum number of bytes per side to show as hexdump in place of
+    /// binary contents, or `None` to show a note.
+    pub binary_hexdump: Option<usize>,
+    /// Whether to diff files pointed to by unchanged symlinks.
+    pub deref_symlinks: bool,
+    /// Whether to show changes from the base to each side of conflicts.
+    pub conflict_base: bool,
+    /// How changed lines are split into words.
+    pub word_tokenizer: DiffWordTokenizer,
+    /// Which lines are prefixed with line numbers.
+    pub line_numbering: DiffLineNumbering,
+    /// Whether to prefix each line with `-`, `+`, `~`, or ` ` depending on
+    /// whether it is removed, added, modified, or unchanged.
+    pub sigils: bool,
+    /// Ratio of changed words above which changed lines are shown as removed
+    /// and added lines instead of highlighting the changed words.
+    pub full_replace_ratio: Option<f64>,
+    /// Whether to ignore ASCII case differences.
+    pub ignore_case: bool,
+    /// Whether to omit changes consisting only of blank lines.
+    pub ignore_blank_lines: bool,
+    /// Lines matching this pattern compare equal to each other.
+    pub ignore_lines_matching: Option<IgnoredLineRegex>,
+    /// Whether to show spaces and tabs as visible glyphs.
+    pub show_whitespace: bool,
+    /// Maximum display width of each line. Longer lines are wrapped.
+    pub wrap_width: Option<usize>,
+    /// Maximum number of hunks to show per file.
+    pub max_hunks: Option<usize>,
+    /// Whether to link file paths in headers to the files in the workspace.
+    pub hyperlinks: bool,
+    /// Whether to show absolute file system paths in headers.
+    pub absolute_paths: bool,
+    /// Columns at which tabs are expanded to, or empty to write tabs as is.
+    pub tab_stops: Vec<usize>,
+    /// Which parts of the diff are labeled.
+    pub color_scope: DiffColorScope,
+    /// Collects the time spent diffing each file if profiling.
+    pub profile: Option<DiffProfile>,
+}
+
+impl ColorWordsDiffOptions {
+    fn from_settings_and_args(
+        settings: &UserSettings,
+        args: &DiffFormatArgs,
+    ) -> Result<Self, config::ConfigError> {
+        let config = settings.config();
+        let context_hunk_separator = config
+            .get_string("ui.diff.context-hunk-separator")
+            .optional()?
+            .unwrap_or_else(|| DEFAULT_CONTEXT_HUNK_SEPARATOR.to_owned());
+        let header_template = config
+            .get_string("ui.diff.color-words.header-template")
+            .optional()?
+            .unwrap_or_else(|| DEFAULT_HEADER_TEMPLATE.to_owned());
+        let compact_renames = config
+            .get_bool("ui.diff.color-words.compact-renames")
+            .optional()?
+            .unwrap_or(false);
+        let binary_hexdump = if args.binary_hexdump {
+            let window = config
+                .get::<usize>("ui.diff.binary-hexdump-window")
+                .optional()?
+                .unwrap_or(DEFAULT_BINARY_HEXDUMP_WINDOW);
+            Some(window)
         } else {
             None
+        };
+        let collapse_deletions = config
+            .get_bool("ui.diff.collapse-deletions")
+            .optional()?
+            .unwrap_or(false);
+        let full_replace_ratio = config
+            .get::<f64>("ui.diff.color-words.full-replace-ratio")
+            .optional()?;
+        if full_replace_ratio.is_some_and(|ratio| !(0.0..=1.0).contains(&ratio)) {
+            return Err(config::ConfigError::Message(
+                "`ui.diff.color-words.full-replace-ratio` must be between 0 and 1".to_owned(),
+            ));
         }
-        .unwrap_or_else(|| ExternalMergeTool::with_diff_args(&args));
-        return Ok(DiffFormat::Tool(Box::new(tool)));
+        Ok(ColorWordsDiffOptions {
+            context: args.context.unwrap_or(DEFAULT_CONTEXT_LINES),
+            context_by_path: ContextByPath::from_settings(settings)?,
+            context_hunk_separator,
+            header_template,
+            compact_renames,
+            highlight_trailing_whitespace: highlight_trailing_whitespace_from_settings(settings)?,
+            show_no_changes: show_no_changes_from_settings(settings)?,
+            skip_binary: skip_binary_from_settings_and_args(settings, args)?,
+            generated_markers: generated_markers_from_settings_and_args(settings, args)?,
+            collapse_deletions,
+            binary_hexdump,
+            deref_symlinks: args.deref_symlinks,
+            conflict_base: args.conflict_base,
+            word_tokenizer: DiffWordTokenizer::from_settings(settings)?,
+            line_numbering: config
+                .get("ui.diff.color-words.number")
+                .optional()?
+                .unwrap_or_default(),
+            sigils: config
+                .get_bool("ui.diff.color-words.sigils")
+                .optional()?
+                .unwrap_or(false),
+            full_replace_ratio,
+            ignore_case: args.ignore_case,
+            ignore_blank_lines: args.ignore_blank_lines,
+            ignore_lines_matching: args.ignore_lines_matching.clone().map(IgnoredLineRegex),
+            show_whitespace: args.show_whitespace,
+            wrap_width: config.get("ui.diff.wrap-width").optional()?,
+            max_hunks: args.max_hunks_per_file,
+            hyperlinks: hyperlinks_from_settings(settings)?,
+            absolute_paths: absolute_paths_from_settings(settings)?,
+            tab_stops: tab_stops_from_settings(settings)?,
+            color_scope: color_scope_from_settings(settings)?,
+            profile: None,
+        })
     }
-    let name = if let Some(name) = config.get_string("ui.diff.format").optional()? {
-        name
-    } else if let Some(name) = config.get_string("diff.format").optional()? {
-        name // old config name
-    } else {
-        "color-words".to_owned()
-    };
-    match name.as_ref() {
-        "summary" => Ok(DiffFormat::Summary),
-        "types" => Ok(DiffFormat::Types),
-        "name-only" => Ok(DiffFormat::NameOnly),
-        "git" => Ok(DiffFormat::Git {
-            context: num_context_lines.unwrap_or(DEFAULT_CONTEXT_LINES),
-        }),
-        "color-words" => Ok(DiffFormat::ColorWords {
-            context: num_context_lines.unwrap_or(DEFAULT_CONTEXT_LINES),
-        }),
-        "stat" => Ok(DiffFormat::Stat),
-        _ => Err(config::ConfigError::Message(format!(
-            "invalid diff format: {name}"
-        ))),
+
+    /// Returns options with the number of context lines overridden for
+    /// `path`.
+    fn for_path(&self, path: &RepoPath) -> Cow<'_, Self> {
+        let context = self.context_by_path.context_for(path, self.context);
+        if context == self.context {
+            Cow::Borrowed(self)
+        } else {
+            Cow::Owned(ColorWordsDiffOptions {
+                context,
+                ..self.clone()
+            })
+        }
+    }
+
+    /// Returns true if both sides are text, and all changes between them are
+    /// ignored by these options.
+    fn has_only_ignored_changes(&self, left: &FileContent, right: &FileContent) -> bool {
+        !left.is_binary
+            && !right.is_binary
+            && has_only_ignored_changes(
+                &left.contents,
+                &right.contents,
+                self.ignore_case,
+                self.ignore_blank_lines,
+                self.ignore_lines_matching.as_ref(),
+            )
     }
 }
 
-#[derive(Debug, Error)]
-pub enum DiffRenderError {
-    #[error("Failed to generate diff")]
-    DiffGenerate(#[source] DiffGenerateError),
-    #[error(transparent)]
-    Backend(#[from] BackendError),
-    #[error("Access denied to {path}: {source}")]
-    AccessDenied {
-        path: String,
-        source: Box<dyn std::error::Error + Send + Sync>,
-    },
-    #[error(transparent)]
-    Io(#[from] io::Error),
+impl Default for ColorWordsDiffOptions {
+    fn default() -> Self {
+        ColorWordsDiffOptions {
+            context: DEFAULT_CONTEXT_LINES,
+            context_by_path: ContextByPath::default(),
+            context_hunk_separator: DEFAULT_CONTEXT_HUNK_SEPARATOR.to_owned(),
+            header_template: DEFAULT_HEADER_TEMPLATE.to_owned(),
+            compact_renames: false,
+            highlight_trailing_whitespace: false,
+            show_no_changes: false,
+            skip_binary: false,
+            generated_markers: None,
+            collapse_deletions: false,
+            binary_hexdump: None,
+            deref_symlinks: false,
+            conflict_base: false,
+            word_tokenizer: DiffWordTokenizer::default(),
+            line_numbering: DiffLineNumbering::default(),
+            sigils: false,
+            full_replace_ratio: None,
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_lines_matching: None,
+            show_whitespace: false,
+            wrap_width: None,
+            max_hunks: None,
+            hyperlinks: false,
+            absolute_paths: false,
+            tab_stops: vec![],
+            color_scope: DiffColorScope::default(),
+            profile: None,
+        }
+    }
 }
 
-/// Configuration and environment to render textual diff.
-pub struct DiffRenderer<'a> {
-    repo: &'a dyn Repo,
-    path_converter: &'a RepoPathUiConverter,
-    formats: Vec<DiffFormat>,
+/// How word-level changes are rendered in Git-format diff.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WordDiffMode {
+    /// Enclose changed words in `[-removed-]` and `{+added+}` markers.
+    Plain,
 }
 
-impl<'a> DiffRenderer<'a> {
-    pub fn new(
-        repo: &'a dyn Repo,
-        path_converter: &'a RepoPathUiConverter,
-        formats: Vec<DiffFormat>,
-    ) -> Self {
-        DiffRenderer {
-            repo,
-            formats,
-            path_converter,
-        }
-    }
+/// How `--stat` output is rendered.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DiffStatFormat {
+    /// Histogram of the changes
+    #[default]
+    Histogram,
+    /// Tab-separated added and removed line counts per file, like
+    /// `git diff --numstat`
+    Numstat,
+}
 
-    /// Generates diff between `from_tree` and `to_tree`.
-    #[allow(clippy::too_many_arguments)]
-    pub fn show_diff(
-        &self,
-        ui: &Ui, // TODO: remove Ui dependency if possible
-        formatter: &mut dyn Formatter,
-        from_tree: &MergedTree,
-        to_tree: &MergedTree,
-        matcher: &dyn Matcher,
-        copy_records: &CopyRecords,
-        width: usize,
-    ) -> Result<(), DiffRenderError> {
-        formatter.with_label("diff", |formatter| {
-            self.show_diff_inner(
-                ui,
-                formatter,
-                from_tree,
-                to_tree,
-                matcher,
-                copy_records,
-                width,
-            )
-        })
-    }
+/// Type of path value to filter diff entries by.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiffFileType {
+    /// Regular or executable file.
+    File,
+    /// Symbolic link.
+    Symlink,
+    /// Unresolved conflict.
+    Conflict,
+    /// Git submodule.
+    Submodule,
+}
 
-    #[allow(clippy::too_many_arguments)]
-    fn show_diff_inner(
-        &self,
-        ui: &Ui,
-        formatter: &mut dyn Formatter,
-        from_tree: &MergedTree,
-        to_tree: &MergedTree,
-        matcher: &dyn Matcher,
-        copy_records: &CopyRecords,
-        width: usize,
-    ) -> Result<(), DiffRenderError> {
-        let store = self.repo.store();
-        let path_converter = self.path_converter;
-        for format in &self.formats {
-            match format {
-                DiffFormat::Summary => {
-                    show_diff_summary(
-                        formatter,
-                        path_converter,
-                        from_tree,
-                        to_tree,
-                        matcher,
-                        copy_records,
-                    )?;
-                }
-                DiffFormat::Stat => {
-                    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
-                    show_diff_stat(formatter, store, tree_diff, path_converter, width)?;
-                }
-                DiffFormat::Types => {
-                    show_types(
-                        formatter,
-                        path_converter,
-                        from_tree,
-                        to_tree,
-                        matcher,
-                        copy_records,
-                    )?;
-                }
-                DiffFormat::NameOnly => {
-                    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
-                    show_names(formatter, tree_diff, path_converter)?;
-                }
-                DiffFormat::Git { context } => {
-                    show_git_diff(
-                        formatter,
-                        store,
-                        from_tree,
-                        to_tree,
-                        matcher,
-                        copy_records,
-                        *context,
-                    )?;
-                }
-                DiffFormat::ColorWords { context } => {
-                    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
-                    show_color_words_diff(formatter, store, tree_diff, path_converter, *context)?;
-                }
-                DiffFormat::Tool(tool) => {
-                    match tool.diff_invocation_mode {
-                        DiffToolMode::FileByFile => {
-                            let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
-                            show_file_by_file_diff(
-                                ui,
-                                formatter,
-                                store,
-                                tree_diff,
-                                path_converter,
-                                matcher,
-                                copy_records,
-                                tool,
-                            )
-                        }
-                        DiffToolMode::Dir => {
-                            generate_diff(ui, formatter.raw(), from_tree, to_tree, matcher, tool)
-                                .map_err(DiffRenderError::DiffGenerate)
-                        }
-                    }?;
-                }
+impl DiffFileType {
+    /// Returns the type of `value`, or `None` if it is absent.
+    fn of(value: &MergedTreeValue) -> Option<Self> {
+        match value.as_resolved() {
+            Some(None) => None,
+            Some(Some(TreeValue::File { .. })) => Some(DiffFileType::File),
+            Some(Some(TreeValue::Symlink(_))) => Some(DiffFileType::Symlink),
+            Some(Some(TreeValue::GitSubmodule(_))) => Some(DiffFileType::Submodule),
+            None => Some(DiffFileType::Conflict),
+            Some(Some(TreeValue::Tree(_))) | Some(Some(TreeValue::Conflict(_))) => {
+                panic!("Unexpected {value:?} in diff")
             }
         }
-        Ok(())
     }
+}
 
-    /// Generates diff of the given `commit` compared to its parents.
-    pub fn show_patch(
-        &self,
-        ui: &Ui,
-        formatter: &mut dyn Formatter,
-        commit: &Commit,
-        matcher: &dyn Matcher,
-        width: usize,
-    ) -> Result<(), DiffRenderError> {
-        let from_tree = commit.parent_tree(self.repo)?;
-        let to_tree = commit.tree()?;
-        let mut copy_records = CopyRecords::default();
-        for parent_id in commit.parent_ids() {
-            copy_records.add_records(self.repo.store().get_copy_records(
-                None,
-                parent_id,
-                commit.id(),
-            )?)?;
-        }
-        self.show_diff(
-            ui,
-            formatter,
-            &from_tree,
-            &to_tree,
-            matcher,
-            &copy_records,
-            width,
-        )
-    }
+/// Options for Git-format (unified) diff.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnifiedDiffOptions {
+    /// Number of context lines to show.
+    pub context: usize,
+    /// Numbers of context lines overriding `context` for matching paths.
+    pub context_by_path: ContextByPath,
+    /// Renders changed lines as inline word diff if set.
+    pub word_diff: Option<WordDiffMode>,
+    /// Whether to label trailing whitespace of each line.
+    pub highlight_trailing_whitespace: bool,
+    /// Order of removed and added lines within each changed region.
+    pub change_order: DiffChangeOrder,
+    /// Extends context to the enclosing function if set.
+    pub function_context: Option<FunctionLineRegex>,
+    /// Whether to show a note instead of hunks that only reorder lines.
+    pub show_reorders: bool,
+    /// Whether to print a message if there are no changes.
+    pub show_no_changes: bool,
+    /// Whether to omit binary files entirely.
+    pub skip_binary: bool,
+    /// Markers identifying generated files whose contents are omitted, or
+    /// `None` to show all files.
+    pub generated_markers: Option<Vec<String>>,
+    /// Number of hex digits of object IDs in `index` lines, or `None` to show
+    /// full IDs.
+    pub hash_abbrev: Option<usize>,
+    /// Whether to omit hunk lines and show only hunk headers.
+    pub hunk_headers_only: bool,
+    /// Whether to print markers in place of skipped context lines.
+    pub fold_markers: bool,
+    /// Whether to print the number of lines skipped between hunks.
+    pub annotate_gaps: bool,
+    /// Whether to print line numbers before each hunk line.
+    pub line_numbers: bool,
+    /// Whether to render conflicted files as combined diffs against the
+    /// sides of the conflicts.
+    pub combined_conflicts: bool,
+    /// Whether to check that hunk lines match the line counts in hunk headers.
+    pub verify_hunks: bool,
+    /// How changed lines are split into words.
+    pub word_tokenizer: DiffWordTokenizer,
+    /// Whether to ignore ASCII case differences.
+    pub ignore_case: bool,
+    /// Whether to omit changes consisting only of blank lines.
+    pub ignore_blank_lines: bool,
+    /// Lines matching this pattern compare equal to each other.
+    pub ignore_lines_matching: Option<IgnoredLineRegex>,
+    /// Whether to show spaces and tabs as visible glyphs.
+    pub show_whitespace: bool,
+    /// Whether to show copies across directories as deleted and added files.
+    pub rename_same_dir_only: bool,
+    /// Minimum similarity percentage of the contents of a copied or renamed
+    /// file to show it as a copy or rename instead of an added file.
+    pub rename_threshold: Option<u8>,
+    /// Maximum number of hunks to show per file.
+    pub max_hunks: Option<usize>,
+    /// Whether to emit files sorted by path instead of in tree order.
+    pub sort_paths: bool,
+    /// Maximum number of lines per side of a changed region to highlight
+    /// changed words in, or `None` for no limit.
+    pub word_diff_max_lines: Option<usize>,
+    /// Columns at which tabs are expanded to, or empty to write tabs as is.
+    pub tab_stops: Vec<usize>,
+    /// Which parts of the diff are labeled.
+    pub color_scope: DiffColorScope,
+    /// Collects the time spent diffing each file if profiling.
+    pub profile: Option<DiffProfile>,
 }
 
-fn collect_copied_sources<'a>(
-    copy_records: &'a CopyRecords,
-    matcher: &dyn Matcher,
-) -> HashSet<&'a RepoPath> {
-    copy_records
-        .iter()
-        .filter_map(|record| {
-            if matcher.matches(&record.target) {
-                Some(record.source.as_ref())
+impl UnifiedDiffOptions {
+    fn from_settings_and_args(
+        settings: &UserSettings,
+        args: &DiffFormatArgs,
+    ) -> Result<Self, config::ConfigError> {
+        Ok(UnifiedDiffOptions {
+            context: args.context.unwrap_or(DEFAULT_CONTEXT_LINES),
+            context_by_path: ContextByPath::from_settings(settings)?,
+            word_diff: args.word_diff,
+            highlight_trailing_whitespace: highlight_trailing_whitespace_from_settings(settings)?,
+            change_order: settings
+                .config()
+                .get("ui.diff.git.change-order")
+                .optional()?
+                .unwrap_or_default(),
+            function_context: if args.function_context {
+                Some(FunctionLineRegex::from_settings(settings)?)
             } else {
                 None
-            }
+            },
+            show_reorders: args.show_reorders,
+            show_no_changes: show_no_changes_from_settings(settings)?,
+            skip_binary: skip_binary_from_settings_and_args(settings, args)?,
+            generated_markers: generated_markers_from_settings_and_args(settings, args)?,
+            hash_abbrev: hash_abbrev_from_settings(settings)?,
+            hunk_headers_only: args.hunk_headers_only,
+            fold_markers: args.fold_markers,
+            annotate_gaps: args.git_annotate_gaps,
+            line_numbers: args.line_numbers,
+            combined_conflicts: args.combined_conflicts,
+            verify_hunks: args.verify_git_diff,
+            word_tokenizer: DiffWordTokenizer::from_settings(settings)?,
+            ignore_case: args.ignore_case,
+            ignore_blank_lines: args.ignore_blank_lines,
+            ignore_lines_matching: args.ignore_lines_matching.clone().map(IgnoredLineRegex),
+            show_whitespace: args.show_whitespace,
+            rename_same_dir_only: rename_same_dir_only_from_settings(settings)?,
+            rename_threshold: rename_threshold_from_settings(settings)?,
+            max_hunks: args.max_hunks_per_file,
+            sort_paths: args.git_sorted,
+            word_diff_max_lines: settings
+                .config()
+                .get("ui.diff.word-diff-max-lines")
+                .optional()?,
+            tab_stops: tab_stops_from_settings(settings)?,
+            color_scope: color_scope_from_settings(settings)?,
+            profile: None,
         })
-        .collect()
-}
-
-fn show_color_words_diff_hunks(
-    left: &[u8],
-    right: &[u8],
-    num_context_lines: usize,
-    formatter: &mut dyn Formatter,
-) -> io::Result<()> {
-    let line_diff = Diff::by_line([left, right]);
-    let mut line_diff_hunks = line_diff.hunks().peekable();
-    let mut line_number = DiffLineNumber { left: 1, right: 1 };
-    // Have we printed "..." for the last skipped context?
-    let mut skipped_context = false;
-
-    // First "before" context
-    if let Some(DiffHunk::Matching(content)) =
-        line_diff_hunks.next_if(|hunk| matches!(hunk, DiffHunk::Matching(_)))
-    {
-        if line_diff_hunks.peek().is_some() {
-            let (new_line_number, _) = show_color_words_context_lines(
-                formatter,
-                content,
-                line_number,
-                0,
-                num_context_lines,
-            )?;
-            line_number = new_line_number;
-        }
     }
-    while let Some(hunk) = line_diff_hunks.next() {
-        match hunk {
-            // Middle "after"/"before" context
-            DiffHunk::Matching(content) if line_diff_hunks.peek().is_some() => {
-                let (new_line_number, _) = show_color_words_context_lines(
-                    formatter,
-                    content,
-                    line_number,
-                    num_context_lines,
-                    num_context_lines,
-                )?;
-                line_number = new_line_number;
-            }
-            // Last "after" context
-            DiffHunk::Matching(content) => {
-                let (new_line_number, skipped) = show_color_words_context_lines(
-                    formatter,
-                    content,
-                    line_number,
-                    num_context_lines,
-                    0,
-                )?;
-                line_number = new_line_number;
-                skipped_context = skipped;
-            }
-            DiffHunk::Different(contents) => {
-                let word_diff = Diff::by_word(&contents);
-                let mut diff_line_iter =
-                    DiffLineIterator::with_line_number(word_diff.hunks(), line_number);
-                for diff_line in diff_line_iter.by_ref() {
-                    show_color_words_diff_line(formatter, &diff_line)?;
-                }
-                line_number = diff_line_iter.next_line_number();
-            }
+
+    /// Returns options with the number of context lines overridden for
+    /// `path`.
+    fn for_path(&self, path: &RepoPath) -> Cow<'_, Self> {
+        let context = self.context_by_path.context_for(path, self.context);
+        if context == self.context {
+            Cow::Borrowed(self)
+        } else {
+            Cow::Owned(UnifiedDiffOptions {
+                context,
+                ..self.clone()
+            })
         }
     }
 
-    // If the last diff line doesn't end with newline, add it.
-    let no_hunk = left.is_empty() && right.is_empty();
-    let any_last_newline = left.ends_with(b"\n") || right.ends_with(b"\n");
-    if !skipped_context && !no_hunk && !any_last_newline {
-        writeln!(formatter)?;
+    /// Returns true if both sides are text, and all changes between them are
+    /// ignored by these options.
+    fn has_only_ignored_changes(&self, left: &FileContent, right: &FileContent) -> bool {
+        !left.is_binary
+            && !right.is_binary
+            && has_only_ignored_changes(
+                &left.contents,
+                &right.contents,
+                self.ignore_case,
+                self.ignore_blank_lines,
+                self.ignore_lines_matching.as_ref(),
+            )
     }
+}
 
-    Ok(())
+impl Default for UnifiedDiffOptions {
+    fn default() -> Self {
+        UnifiedDiffOptions {
+            context: DEFAULT_CONTEXT_LINES,
+            context_by_path: ContextByPath::default(),
+            word_diff: None,
+            highlight_trailing_whitespace: false,
+            change_order: DiffChangeOrder::default(),
+            function_context: None,
+            show_reorders: false,
+            show_no_changes: false,
+            skip_binary: false,
+            generated_markers: None,
+            hash_abbrev: Some(DEFAULT_GIT_HASH_ABBREV),
+            hunk_headers_only: false,
+            fold_markers: false,
+            annotate_gaps: false,
+            line_numbers: false,
+            combined_conflicts: false,
+            verify_hunks: false,
+            word_tokenizer: DiffWordTokenizer::default(),
+            ignore_case: false,
+            ignore_blank_lines: false,
+            ignore_lines_matching: None,
+            show_whitespace: false,
+            rename_same_dir_only: false,
+            rename_threshold: None,
+            max_hunks: None,
+            sort_paths: false,
+            word_diff_max_lines: None,
+            tab_stops: vec![],
+            color_scope: DiffColorScope::default(),
+            profile: None,
+        }
+    }
 }
 
-/// Prints `num_after` lines, ellipsis, and `num_before` lines.
-fn show_color_words_context_lines(
-    formatter: &mut dyn Formatter,
-    content: &[u8],
-    mut line_number: DiffLineNumber,
-    num_after: usize,
-    num_before: usize,
-) -> io::Result<(DiffLineNumber, bool)> {
-    const SKIPPED_CONTEXT_LINE: &str = "    ...\n";
-    let mut lines = content.split_inclusive(|b| *b == b'\n').fuse();
-    for line in lines.by_ref().take(num_after) {
-        let diff_line = DiffLine {
-            line_number,
-            hunks: vec![(DiffLineHunkSide::Both, line.as_ref())],
-        };
-        show_color_words_diff_line(formatter, &diff_line)?;
-        line_number.left += 1;
-        line_number.right += 1;
-    }
-    let mut before_lines = lines.by_ref().rev().take(num_before + 1).collect_vec();
-    let num_skipped: u32 = lines.count().try_into().unwrap();
-    if num_skipped > 0 {
-        write!(formatter, "{SKIPPED_CONTEXT_LINE}")?;
-        before_lines.pop();
-        line_number.left += num_skipped + 1;
-        line_number.right += num_skipped + 1;
-    }
-    for line in before_lines.into_iter().rev() {
-        let diff_line = DiffLine {
-            line_number,
-            hunks: vec![(DiffLineHunkSide::Both, line.as_ref())],
+/// Numbers of context lines for files matching glob patterns.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ContextByPath(Vec<(FilePattern, usize)>);
+
+impl ContextByPath {
+    fn from_settings(settings: &UserSettings) -> Result<Self, config::ConfigError> {
+        let Some(table) = settings
+            .config()
+            .get::<HashMap<String, usize>>("ui.diff.context-by-path")
+            .optional()?
+        else {
+            return Ok(ContextByPath::default());
         };
-        show_color_words_diff_line(formatter, &diff_line)?;
-        line_number.left += 1;
-        line_number.right += 1;
+        let patterns = table
+            .into_iter()
+            .sorted()
+            .map(|(pattern, context)| {
+                let pattern = FilePattern::root_file_glob(&pattern).map_err(|err| {
+                    config::ConfigError::Message(format!(
+                        "Invalid `ui.diff.context-by-path` pattern {pattern:?}: {err}"
+                    ))
+                })?;
+                Ok((pattern, context))
+            })
+            .try_collect()?;
+        Ok(ContextByPath(patterns))
+    }
+
+    /// Returns the smallest number of context lines of the patterns matching
+    /// `path`, or `default` if no pattern matches.
+    fn context_for(&self, path: &RepoPath, default: usize) -> usize {
+        self.0
+            .iter()
+            .filter(|(pattern, _)| {
+                FilesetExpression::pattern(pattern.clone())
+                    .to_matcher()
+                    .matches(path)
+            })
+            .map(|(_, context)| *context)
+            .min()
+            .unwrap_or(default)
     }
-    Ok((line_number, num_skipped > 0))
 }
 
-fn show_color_words_diff_line(
-    formatter: &mut dyn Formatter,
-    diff_line: &DiffLine,
-) -> io::Result<()> {
-    if diff_line.has_left_content() {
-        formatter.with_label("removed", |formatter| {
-            write!(
-                formatter.labeled("line_number"),
-                "{:>4}",
-                diff_line.line_number.left
-            )
+/// Pattern matching the first line of a function.
+#[derive(Clone, Debug)]
+pub struct FunctionLineRegex(regex::bytes::Regex);
+
+impl FunctionLineRegex {
+    fn from_settings(settings: &UserSettings) -> Result<Self, config::ConfigError> {
+        let pattern = settings
+            .config()
+            .get_string("ui.diff.function-regex")
+            .optional()?
+            .unwrap_or_else(|| DEFAULT_FUNCTION_REGEX.to_owned());
+        let regex = regex::bytes::Regex::new(&pattern).map_err(|err| {
+            config::ConfigError::Message(format!("Invalid `ui.diff.function-regex`: {err}"))
         })?;
-        write!(formatter, " ")?;
-    } else {
-        write!(formatter, "     ")?;
+        Ok(FunctionLineRegex(regex))
     }
-    if diff_line.has_right_content() {
-        formatter.with_label("added", |formatter| {
-            write!(
-                formatter.labeled("line_number"),
-                "{:>4}",
-                diff_line.line_number.right
-            )
-        })?;
-        write!(formatter, ": ")?;
-    } else {
-        write!(formatter, "    : ")?;
+}
+
+impl Eq for FunctionLineRegex {}
+
+impl PartialEq for FunctionLineRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
     }
-    for (side, data) in &diff_line.hunks {
-        let label = match side {
-            DiffLineHunkSide::Both => None,
-            DiffLineHunkSide::Left => Some("removed"),
-            DiffLineHunkSide::Right => Some("added"),
-        };
-        if let Some(label) = label {
-            formatter.with_label(label, |formatter| {
-                formatter.with_label("token", |formatter| formatter.write_all(data))
-            })?;
-        } else {
-            formatter.write_all(data)?;
-        }
+}
+
+/// Pattern matching lines whose changes are ignored.
+#[derive(Clone, Debug)]
+pub struct IgnoredLineRegex(pub regex::bytes::Regex);
+
+impl IgnoredLineRegex {
+    /// Returns true if `line` matches the pattern. The line terminator isn't
+    /// included in the matched text.
+    fn is_match(&self, line: &[u8]) -> bool {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        self.0.is_match(line)
     }
+}
 
-    Ok(())
+impl Eq for IgnoredLineRegex {}
+
+impl PartialEq for IgnoredLineRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
 }
 
-struct FileContent {
-    /// false if this file is likely text; true if it is likely binary.
-    is_binary: bool,
-    contents: Vec<u8>,
+/// Order of removed and added lines within a changed region of Git-format
+/// diff.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub enum DiffChangeOrder {
+    /// Removed lines precede added lines, as Git does.
+    #[default]
+    RemovedFirst,
+    /// Added lines precede removed lines.
+    AddedFirst,
 }
 
-impl FileContent {
-    fn empty() -> Self {
-        Self {
-            is_binary: false,
-            contents: vec![],
+/// How changed lines are split into words to highlight word-level changes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub enum DiffWordTokenizer {
+    /// Split lines at non-word characters.
+    #[default]
+    Word,
+    /// Split lines into identifiers, numbers, strings, and operators if the
+    /// file is written in a known programming language.
+    Syntax,
+}
+
+impl DiffWordTokenizer {
+    /// File extensions of languages the `Syntax` tokenizer can handle.
+    const SYNTAX_EXTENSIONS: &'static [&'static str] = &[
+        "c", "cc", "cpp", "cs", "cxx", "go", "h", "hh", "hpp", "java", "js", "jsx", "kt", "py",
+        "rb", "rs", "scala", "swift", "ts", "tsx",
+    ];
+
+    fn from_settings(settings: &UserSettings) -> Result<Self, config::ConfigError> {
+        Ok(settings
+            .config()
+            .get("ui.diff.word-tokenizer")
+            .optional()?
+            .unwrap_or_default())
+    }
+
+    /// Returns the tokenizer to be used for the file of the given name. Falls
+    /// back to `Word` if the language isn't recognized.
+    fn for_file_name(self, name: &str) -> Self {
+        match self {
+            DiffWordTokenizer::Word => DiffWordTokenizer::Word,
+            DiffWordTokenizer::Syntax => {
+                let extension = Path::new(name).extension().and_then(|ext| ext.to_str());
+                if extension.is_some_and(|ext| Self::SYNTAX_EXTENSIONS.contains(&ext)) {
+                    DiffWordTokenizer::Syntax
+                } else {
+                    DiffWordTokenizer::Word
+                }
+            }
         }
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.contents.is_empty()
+    fn for_path(self, path: &RepoPath) -> Self {
+        self.for_file_name(path.as_internal_file_string())
+    }
+
+    /// Compares `inputs` word by word.
+    fn diff<'input, T: AsRef<[u8]> + ?Sized + 'input>(
+        self,
+        inputs: impl IntoIterator<Item = &'input T>,
+    ) -> Diff<'input> {
+        match self {
+            DiffWordTokenizer::Word => Diff::by_word(inputs),
+            DiffWordTokenizer::Syntax => Diff::for_tokenizer(inputs, find_syntax_token_ranges),
+        }
     }
 }
 
-fn file_content_for_diff(reader: &mut dyn io::Read) -> io::Result<FileContent> {
-    // If this is a binary file, don't show the full contents.
-    // Determine whether it's binary by whether the first 8k bytes contain a null
-    // character; this is the same heuristic used by git as of writing: https://github.com/git/git/blob/eea0e59ffbed6e33d171ace5be13cde9faa41639/xdiff-interface.c#L192-L198
-    const PEEK_SIZE: usize = 8000;
-    // TODO: currently we look at the whole file, even though for binary files we
-    // only need to know the file size. To change that we'd have to extend all
-    // the data backends to support getting the length.
-    let mut contents = vec![];
-    reader.read_to_end(&mut contents)?;
+/// Which lines of color-words diff are prefixed with line numbers.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub enum DiffLineNumbering {
+    /// Number all lines.
+    #[default]
+    All,
+    /// Number only unchanged context lines. Changed lines have blank line
+    /// numbers.
+    ContextOnly,
+}
 
-    let start = &contents[..PEEK_SIZE.min(contents.len())];
-    Ok(FileContent {
-        is_binary: start.contains(&b'\0'),
-        contents,
-    })
+/// Which parts of color-words and Git diffs are labeled, and thus colored.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub enum DiffColorScope {
+    /// Label headers and contents.
+    #[default]
+    Full,
+    /// Label only file and hunk headers. Content lines are written without
+    /// labels.
+    Headers,
 }
 
-fn diff_content(path: &RepoPath, value: MaterializedTreeValue) -> io::Result<FileContent> {
-    match value {
-        MaterializedTreeValue::Absent => Ok(FileContent::empty()),
-        MaterializedTreeValue::AccessDenied(err) => Ok(FileContent {
-            is_binary: false,
-            contents: format!("Access denied: {err}").into_bytes(),
-        }),
-        MaterializedTreeValue::File { mut reader, .. } => {
-            file_content_for_diff(&mut reader).map_err(Into::into)
-        }
-        MaterializedTreeValue::Symlink { id: _, target } => Ok(FileContent {
-            // Unix file paths can't contain null bytes.
-            is_binary: false,
-            contents: target.into_bytes(),
-        }),
-        MaterializedTreeValue::GitSubmodule(id) => Ok(FileContent {
-            is_binary: false,
-            contents: format!("Git submodule checked out at {}", id.hex()).into_bytes(),
-        }),
-        // TODO: are we sure this is never binary?
-        MaterializedTreeValue::Conflict {
-            id: _,
-            contents,
-            executable: _,
-        } => Ok(FileContent {
-            is_binary: false,
-            contents,
-        }),
-        MaterializedTreeValue::Tree(id) => {
-            panic!("Unexpected tree with id {id:?} in diff at path {path:?}");
-        }
+/// Compares `inputs` by `diff_fn`. If `ignore_case` is set, the inputs are
+/// compared in ASCII lowercase, and the returned hunks borrow the original
+/// contents. Matching hunks borrow the left content.
+fn diff_hunks<'input>(
+    inputs: [&'input [u8]; 2],
+    ignore_case: bool,
+    diff_fn: impl for<'a> FnOnce([&'a [u8]; 2]) -> Diff<'a>,
+) -> Vec<DiffHunk<'input>> {
+    if !ignore_case {
+        return diff_fn(inputs).hunks().collect();
     }
+    let lowercase_inputs = inputs.map(|input| input.to_ascii_lowercase());
+    let diff = diff_fn([&lowercase_inputs[0], &lowercase_inputs[1]]);
+    // ASCII lowercasing preserves byte offsets, so hunks can be mapped back to
+    // the original contents.
+    let mut offsets = [0, 0];
+    let mut take = |side: usize, len: usize| -> &'input [u8] {
+        let input = inputs[side];
+        let start = offsets[side];
+        offsets[side] += len;
+        &input[start..offsets[side]]
+    };
+    diff.hunks()
+        .map(|hunk| match hunk {
+            DiffHunk::Matching(content) => {
+                let left = take(0, content.len());
+                take(1, content.len());
+                DiffHunk::matching(left)
+            }
+            DiffHunk::Different(contents) => {
+                let [left, right] = contents.try_into().unwrap();
+                DiffHunk::different([take(0, left.len()), take(1, right.len())])
+            }
+        })
+        .collect()
 }
 
-fn basic_diff_file_type(value: &MaterializedTreeValue) -> &'static str {
-    match value {
-        MaterializedTreeValue::Absent => {
-            panic!("absent path in diff");
-        }
-        MaterializedTreeValue::AccessDenied(_) => "access denied",
-        MaterializedTreeValue::File { executable, .. } => {
-            if *executable {
-                "executable file"
+/// Diffs `inputs` by line. Lines matching `ignore_lines_matching` on both sides
+/// compare equal, and are reported as matching content of the right side.
+fn line_diff_hunks<'input>(
+    inputs: [&'input [u8]; 2],
+    ignore_case: bool,
+    ignore_lines_matching: Option<&IgnoredLineRegex>,
+) -> Vec<DiffHunk<'input>> {
+    let Some(regex) = ignore_lines_matching else {
+        return diff_hunks(inputs, ignore_case, |inputs| Diff::by_line(inputs));
+    };
+    // Replace the matching lines with the same placeholder, and prefix the
+    // other lines so they can't be equal to the placeholder. Each line maps to
+    // one line of the original contents.
+    let normalized_inputs = inputs.map(|input| {
+        let mut normalized = vec![];
+        for line in input.split_inclusive(|b| *b == b'\n') {
+            if regex.is_match(line) {
+                normalized.push(b'#');
+                if line.ends_with(b"\n") {
+                    normalized.push(b'\n');
+                }
             } else {
-                "regular file"
+                normalized.push(b'.');
+                normalized.extend_from_slice(line);
             }
         }
-        MaterializedTreeValue::Symlink { .. } => "symlink",
-        MaterializedTreeValue::Tree(_) => "tree",
-        MaterializedTreeValue::GitSubmodule(_) => "Git submodule",
-        MaterializedTreeValue::Conflict { .. } => "conflict",
+        normalized
+    });
+    let hunks = diff_hunks(
+        [&normalized_inputs[0], &normalized_inputs[1]],
+        ignore_case,
+        |inputs| Diff::by_line(inputs),
+    );
+    let mut offsets = [0, 0];
+    let mut take_lines = |side: usize, content: &[u8]| -> &'input [u8] {
+        let input = inputs[side];
+        let start = offsets[side];
+        let num_lines = content.split_inclusive(|b| *b == b'\n').count();
+        offsets[side] += input[start..]
+            .split_inclusive(|b| *b == b'\n')
+            .take(num_lines)
+            .map(<[u8]>::len)
+            .sum::<usize>();
+        &input[start..offsets[side]]
+    };
+    hunks
+        .into_iter()
+        .map(|hunk| match hunk {
+            DiffHunk::Matching(content) => {
+                take_lines(0, content);
+                DiffHunk::matching(take_lines(1, content))
+            }
+            DiffHunk::Different(contents) => {
+                let [left, right] = contents.try_into().unwrap();
+                DiffHunk::different([take_lines(0, left), take_lines(1, right)])
+            }
+        })
+        .collect()
+}
+
+/// Returns true if `left` and `right` differ only by blank lines if
+/// `ignore_blank_lines` is set, or by lines matching `ignore_lines_matching`.
+fn has_only_ignored_changes(
+    left: &[u8],
+    right: &[u8],
+    ignore_case: bool,
+    ignore_blank_lines: bool,
+    ignore_lines_matching: Option<&IgnoredLineRegex>,
+) -> bool {
+    if !ignore_blank_lines && ignore_lines_matching.is_none() {
+        return false;
     }
+    line_diff_hunks([left, right], ignore_case, ignore_lines_matching)
+        .iter()
+        .all(|hunk| match hunk {
+            DiffHunk::Matching(_) => true,
+            DiffHunk::Different(contents) => {
+                ignore_blank_lines && contents.iter().all(|content| is_blank(content))
+            }
+        })
 }
 
-pub fn show_color_words_diff(
-    formatter: &mut dyn Formatter,
-    store: &Store,
-    tree_diff: TreeDiffStream,
-    path_converter: &RepoPathUiConverter,
-    num_context_lines: usize,
-) -> Result<(), DiffRenderError> {
-    let mut diff_stream = materialized_diff_stream(store, tree_diff);
-    async {
-        while let Some(MaterializedTreeDiffEntry {
-            source: left_path,
-            target: right_path,
-            value: diff,
-        }) = diff_stream.next().await
-        {
-            let left_ui_path = path_converter.format_file_path(&left_path);
-            let right_ui_path = path_converter.format_file_path(&right_path);
-            let (left_value, right_value) = diff?;
+fn highlight_trailing_whitespace_from_settings(
+    settings: &UserSettings,
+) -> Result<bool, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.highlight-trailing-whitespace")
+        .optional()?
+        .unwrap_or(false))
+}
 
-            match (&left_value, &right_value) {
-                (MaterializedTreeValue::AccessDenied(source), _) => {
-                    write!(
-                        formatter.labeled("access-denied"),
-                        "Access denied to {left_ui_path}:"
-                    )?;
-                    writeln!(formatter, " {source}")?;
-                    continue;
-                }
+fn rename_same_dir_only_from_settings(
+    settings: &UserSettings,
+) -> Result<bool, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.rename-same-dir-only")
+        .optional()?
+        .unwrap_or(false))
+}
+
+fn rename_threshold_from_settings(
+    settings: &UserSettings,
+) -> Result<Option<u8>, config::ConfigError> {
+    let Some(threshold) = settings
+        .config()
+        .get::<u8>("ui.diff.git.rename-threshold")
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    if threshold > 100 {
+        return Err(config::ConfigError::Message(
+            "`ui.diff.git.rename-threshold` must be a percentage from 0 to 100".to_owned(),
+        ));
+    }
+    Ok(Some(threshold))
+}
+
+fn hyperlinks_from_settings(settings: &UserSettings) -> Result<bool, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.hyperlinks")
+        .optional()?
+        .unwrap_or(false))
+}
+
+fn tab_stops_from_settings(settings: &UserSettings) -> Result<Vec<usize>, config::ConfigError> {
+    let tab_stops: Vec<usize> = settings
+        .config()
+        .get("ui.diff.tab-stops")
+        .optional()?
+        .unwrap_or_default();
+    if tab_stops.first() == Some(&0) || tab_stops.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(config::ConfigError::Message(
+            "`ui.diff.tab-stops` must be increasing positive column numbers".to_owned(),
+        ));
+    }
+    Ok(tab_stops)
+}
+
+fn color_scope_from_settings(
+    settings: &UserSettings,
+) -> Result<DiffColorScope, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get("ui.diff.color-scope")
+        .optional()?
+        .unwrap_or_default())
+}
+
+fn hash_abbrev_from_settings(
+    settings: &UserSettings,
+) -> Result<Option<usize>, config::ConfigError> {
+    let Some(value) = settings
+        .config()
+        .get_string("ui.diff.git.abbrev")
+        .optional()?
+    else {
+        return Ok(Some(DEFAULT_GIT_HASH_ABBREV));
+    };
+    if value == "full" {
+        return Ok(None);
+    }
+    match value.parse() {
+        Ok(len) if len >= 4 => Ok(Some(len)),
+        _ => Err(config::ConfigError::Message(format!(
+            "Invalid `ui.diff.git.abbrev`: expected \"full\" or an integer of at least 4, got \
+             {value:?}"
+        ))),
+    }
+}
+
+fn show_no_changes_from_settings(settings: &UserSettings) -> Result<bool, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.show-no-changes")
+        .optional()?
+        .unwrap_or(false))
+}
+
+fn skip_binary_from_settings_and_args(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+) -> Result<bool, config::ConfigError> {
+    if args.skip_binary {
+        return Ok(true);
+    }
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.skip-binary")
+        .optional()?
+        .unwrap_or(false))
+}
+
+fn generated_markers_from_settings_and_args(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+) -> Result<Option<Vec<String>>, config::ConfigError> {
+    if !args.skip_generated {
+        return Ok(None);
+    }
+    let markers = settings
+        .config()
+        .get::<Vec<String>>("ui.diff.generated-markers")
+        .optional()?
+        .unwrap_or_else(|| DEFAULT_GENERATED_MARKERS.map(str::to_owned).to_vec());
+    Ok(Some(markers))
+}
+
+/// Returns true if one of the first lines of `contents` contains any of the
+/// `markers`.
+fn has_generated_marker(contents: &[u8], markers: &[String]) -> bool {
+    contents
+        .lines()
+        .take(GENERATED_MARKER_LINES)
+        .any(|line| markers.iter().any(|marker| line.contains_str(marker)))
+}
+
+/// Options for diff summary.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiffSummaryOptions {
+    /// Whether to show the content hash of each file.
+    pub with_hash: bool,
+    /// Whether to show the number of hunks of each file.
+    pub with_hunk_count: bool,
+    /// Number of context lines used to split changes into hunks.
+    pub context: usize,
+    /// Whether to show the Git file mode of each file.
+    pub show_mode: bool,
+    /// Whether to show copies across directories as deleted and added files.
+    pub rename_same_dir_only: bool,
+    /// Directories with more changed files than this are collapsed into a
+    /// single line.
+    pub collapse_dirs: Option<usize>,
+    /// Number of leading path components of the directories to collapse.
+    pub collapse_depth: usize,
+    /// Whether to link file paths to the files in the workspace.
+    pub hyperlinks: bool,
+    /// Whether to show absolute file system paths.
+    pub absolute_paths: bool,
+}
+
+impl DiffSummaryOptions {
+    fn from_settings_and_args(
+        settings: &UserSettings,
+        args: &DiffFormatArgs,
+    ) -> Result<Self, config::ConfigError> {
+        let collapse_dirs = match args.collapse_dirs {
+            Some(threshold) => Some(threshold),
+            None => settings
+                .config()
+                .get::<usize>("ui.diff.summary.collapse-dirs")
+                .optional()?,
+        };
+        let collapse_depth = settings
+            .config()
+            .get::<usize>("ui.diff.summary.collapse-depth")
+            .optional()?
+            .unwrap_or(1);
+        if collapse_depth == 0 {
+            return Err(config::ConfigError::Message(
+                "`ui.diff.summary.collapse-depth` must be at least 1".to_owned(),
+            ));
+        }
+        Ok(DiffSummaryOptions {
+            with_hash: args.with_hash,
+            with_hunk_count: args.with_hunk_count,
+            context: args.context.unwrap_or(DEFAULT_CONTEXT_LINES),
+            show_mode: args.show_mode,
+            rename_same_dir_only: rename_same_dir_only_from_settings(settings)?,
+            collapse_dirs,
+            collapse_depth,
+            hyperlinks: hyperlinks_from_settings(settings)?,
+            absolute_paths: absolute_paths_from_settings(settings)?,
+        })
+    }
+}
+
+impl Default for DiffSummaryOptions {
+    fn default() -> Self {
+        DiffSummaryOptions {
+            with_hash: false,
+            with_hunk_count: false,
+            context: DEFAULT_CONTEXT_LINES,
+            show_mode: false,
+            rename_same_dir_only: false,
+            collapse_dirs: None,
+            collapse_depth: 1,
+            hyperlinks: false,
+            absolute_paths: false,
+        }
+    }
+}
+
+/// Options for diff types.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DiffTypesOptions {
+    /// Whether to show the number of sides of conflicts.
+    pub conflict_detail: bool,
+}
+
+impl DiffTypesOptions {
+    fn from_settings_and_args(
+        _settings: &UserSettings,
+        args: &DiffFormatArgs,
+    ) -> Result<Self, config::ConfigError> {
+        Ok(DiffTypesOptions {
+            conflict_detail: args.conflict_detail,
+        })
+    }
+}
+
+/// Options for diff stat.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiffStatOptions {
+    /// Whether to print the trailing `N files changed, ...` line.
+    pub summary_line: bool,
+    /// Maximum length of the histogram bars, or `None` to fill the display
+    /// width.
+    pub max_bar_width: Option<usize>,
+    /// How the stat is rendered.
+    pub format: DiffStatFormat,
+    /// Whether to compute the line counts of files on the thread pool.
+    pub parallel: bool,
+    /// Whether to show absolute file system paths.
+    pub absolute_paths: bool,
+}
+
+impl DiffStatOptions {
+    fn from_settings_and_args(
+        settings: &UserSettings,
+        args: &DiffFormatArgs,
+    ) -> Result<Self, config::ConfigError> {
+        let config = settings.config();
+        let summary_line = config
+            .get_bool("ui.diff.stat.summary-line")
+            .optional()?
+            .unwrap_or(true);
+        let max_bar_width = config.get("ui.diff.stat.max-bar-width").optional()?;
+        let parallel = config
+            .get_bool("ui.diff.stat.parallel")
+            .optional()?
+            .unwrap_or(false);
+        Ok(DiffStatOptions {
+            summary_line,
+            max_bar_width,
+            format: args.stat_format.unwrap_or_default(),
+            parallel,
+            absolute_paths: absolute_paths_from_settings(settings)?,
+        })
+    }
+}
+
+impl Default for DiffStatOptions {
+    fn default() -> Self {
+        DiffStatOptions {
+            summary_line: true,
+            max_bar_width: None,
+            format: DiffStatFormat::default(),
+            parallel: false,
+            absolute_paths: false,
+        }
+    }
+}
+
+/// Returns whether diffs should display absolute file system paths.
+fn absolute_paths_from_settings(settings: &UserSettings) -> Result<bool, config::ConfigError> {
+    Ok(settings
+        .config()
+        .get_bool("ui.diff.absolute-paths")
+        .optional()?
+        .unwrap_or(false))
+}
+
+/// Returns a list of requested diff formats, which will never be empty.
+pub fn diff_formats_for(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+) -> Result<Vec<DiffFormat>, config::ConfigError> {
+    let formats = diff_formats_from_args(settings, args)?;
+    if formats.is_empty() {
+        Ok(vec![default_diff_format(settings, args)?])
+    } else {
+        Ok(formats)
+    }
+}
+
+/// Returns a list of requested diff formats for log-like commands, which may be
+/// empty.
+pub fn diff_formats_for_log(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+    patch: bool,
+) -> Result<Vec<DiffFormat>, config::ConfigError> {
+    let mut formats = diff_formats_from_args(settings, args)?;
+    // --patch implies default if no format other than --summary is specified
+    if patch && matches!(formats.as_slice(), [] | [DiffFormat::Summary(_)]) {
+        formats.push(default_diff_format(settings, args)?);
+        formats.dedup();
+    }
+    Ok(formats)
+}
+
+fn diff_formats_from_args(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+) -> Result<Vec<DiffFormat>, config::ConfigError> {
+    let mut formats = [
+        (
+            args.summary,
+            DiffFormat::Summary(Box::new(DiffSummaryOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (
+            args.types,
+            DiffFormat::Types(Box::new(DiffTypesOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (args.name_only, DiffFormat::NameOnly),
+        (args.mode_only, DiffFormat::ModeOnly),
+        (
+            args.git,
+            DiffFormat::Git(Box::new(UnifiedDiffOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (
+            args.color_words,
+            DiffFormat::ColorWords(Box::new(ColorWordsDiffOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (
+            args.html,
+            DiffFormat::Html(Box::new(UnifiedDiffOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (
+            args.posix,
+            DiffFormat::Posix(Box::new(UnifiedDiffOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (
+            args.stat || args.stat_format.is_some(),
+            DiffFormat::Stat(Box::new(DiffStatOptions::from_settings_and_args(
+                settings, args,
+            )?)),
+        ),
+        (args.shortstat, DiffFormat::ShortStat),
+        (args.conflict_stat, DiffFormat::ConflictStat),
+        (args.oneline_stat, DiffFormat::OnelineStat),
+    ]
+    .into_iter()
+    .filter_map(|(arg, format)| arg.then_some(format))
+    .collect_vec();
+    if let Some(name) = &args.tool {
+        let tool = match merge_tools::get_external_tool_config(settings, name)? {
+            Some(tool) => tool,
+            None => {
+                merge_tools::check_external_tool_program(settings, name)?;
+                ExternalMergeTool::with_program(name)
+            }
+        };
+        formats.push(DiffFormat::Tool(Box::new(tool)));
+    }
+    Ok(formats)
+}
+
+/// Returns the diff format configured by `ui.diff.format` or `ui.diff.tool`.
+pub fn default_diff_format_from_settings(
+    settings: &UserSettings,
+) -> Result<DiffFormat, config::ConfigError> {
+    default_diff_format(settings, &DiffFormatArgs::default())
+}
+
+fn default_diff_format(
+    settings: &UserSettings,
+    args: &DiffFormatArgs,
+) -> Result<DiffFormat, config::ConfigError> {
+    let config = settings.config();
+    if let Some(args) = config.get("ui.diff.tool").optional()? {
+        // External "tool" overrides the internal "format" option.
+        let tool = if let CommandNameAndArgs::String(name) = &args {
+            merge_tools::get_external_tool_config(settings, name)?
+        } else {
+            None
+        };
+        let tool = match tool {
+            Some(tool) => tool,
+            None => {
+                let tool = ExternalMergeTool::with_diff_args(&args);
+                merge_tools::check_external_tool_program(settings, &tool.program)?;
+                tool
+            }
+        };
+        return Ok(DiffFormat::Tool(Box::new(tool)));
+    }
+    let name = if let Some(name) = config.get_string("ui.diff.format").optional()? {
+        name
+    } else if let Some(name) = config.get_string("diff.format").optional()? {
+        name // old config name
+    } else {
+        "color-words".to_owned()
+    };
+    match name.as_ref() {
+        "summary" => {
+            let options = DiffSummaryOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Summary(Box::new(options)))
+        }
+        "types" => {
+            let options = DiffTypesOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Types(Box::new(options)))
+        }
+        "name-only" => Ok(DiffFormat::NameOnly),
+        "mode-only" => Ok(DiffFormat::ModeOnly),
+        "git" => {
+            let options = UnifiedDiffOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Git(Box::new(options)))
+        }
+        "color-words" => {
+            let options = ColorWordsDiffOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::ColorWords(Box::new(options)))
+        }
+        "html" => {
+            let options = UnifiedDiffOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Html(Box::new(options)))
+        }
+        "posix" => {
+            let options = UnifiedDiffOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Posix(Box::new(options)))
+        }
+        "stat" => {
+            let options = DiffStatOptions::from_settings_and_args(settings, args)?;
+            Ok(DiffFormat::Stat(Box::new(options)))
+        }
+        "shortstat" => Ok(DiffFormat::ShortStat),
+        "conflict-stat" => Ok(DiffFormat::ConflictStat),
+        "oneline-stat" => Ok(DiffFormat::OnelineStat),
+        _ => Err(config::ConfigError::Message(format!(
+            "invalid diff format: {name}"
+        ))),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DiffRenderError {
+    #[error("Failed to generate diff")]
+    DiffGenerate(#[source] DiffGenerateError),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+    #[error("Access denied to {path}: {source}")]
+    AccessDenied {
+        path: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Generated invalid Git diff for {path}: {message}")]
+    InvalidGitDiff { path: String, message: String },
+    #[error("External diff tools can't be used here")]
+    ExternalToolUnsupported,
+    #[error("Only Git and color-words formats are supported for comparing two paths")]
+    PathPairFormatUnsupported,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl DiffRenderError {
+    /// Converts an external tool error. If the output pipe was closed (e.g.
+    /// by quitting the pager), it's reported as a `BrokenPipe` I/O error so
+    /// the command can exit gracefully.
+    fn from_diff_generate(err: DiffGenerateError) -> Self {
+        let is_broken_pipe =
+            iter::successors(Some(&err as &dyn std::error::Error), |err| err.source())
+                .filter_map(|err| err.downcast_ref::<io::Error>())
+                .any(|err| err.kind() == io::ErrorKind::BrokenPipe);
+        if is_broken_pipe {
+            DiffRenderError::Io(io::ErrorKind::BrokenPipe.into())
+        } else {
+            DiffRenderError::DiffGenerate(err)
+        }
+    }
+}
+
+/// Pattern selecting files by the lines changed in them.
+#[derive(Clone, Debug)]
+pub struct DiffContentGrep {
+    /// Pattern to search the changed lines for.
+    pub regex: regex::bytes::Regex,
+    /// Whether to search the added lines only.
+    pub added_only: bool,
+}
+
+impl DiffContentGrep {
+    /// Returns the pattern specified by the command arguments if any.
+    pub fn from_args(args: &DiffFormatArgs) -> Option<Self> {
+        let regex = args.content_grep.clone()?;
+        Some(DiffContentGrep {
+            regex,
+            added_only: args.content_grep_added_only,
+        })
+    }
+
+    /// Returns true if an added (or removed) line between `left_content` and
+    /// `right_content` matches the pattern.
+    fn matches(&self, left_content: &[u8], right_content: &[u8]) -> bool {
+        let diff = Diff::by_line([left_content, right_content]);
+        diff.hunks().any(|hunk| match hunk {
+            DiffHunk::Matching(_) => false,
+            DiffHunk::Different(contents) => {
+                let sides = if self.added_only {
+                    &contents[1..]
+                } else {
+                    &contents[..]
+                };
+                sides
+                    .iter()
+                    .flat_map(|side| side.lines())
+                    .any(|line| self.regex.is_match(line))
+            }
+        })
+    }
+}
+
+/// How `OutputLimitFormatter` splits the output into entries.
+#[derive(Clone, Copy, Debug)]
+enum OutputEntries {
+    /// Each line is an entry.
+    Lines,
+    /// Each entry starts at a line labeled with the label.
+    Labeled(DiffLabel),
+    /// The whole output is one entry.
+    Whole,
+}
+
+/// Number of bytes the diff output may still use.
+struct OutputBudget {
+    remaining: usize,
+    truncated: bool,
+}
+
+/// Formatter which buffers each entry of the output, and forwards it to the
+/// underlying `formatter` if it fits in the `budget`. Once an entry doesn't
+/// fit, the rest of the data is discarded, but labels are still forwarded to
+/// keep them balanced.
+struct OutputLimitFormatter<'a> {
+    formatter: &'a mut dyn Formatter,
+    budget: &'a mut OutputBudget,
+    entries: OutputEntries,
+    /// Labeled data of the current incomplete entry.
+    entry: FormatRecorder,
+    /// Whether each of the pushed labels starts entries.
+    label_stack: Vec<bool>,
+    at_line_start: bool,
+    /// Whether data of the current line is labeled as the start of an entry.
+    is_header_line: bool,
+    /// Whether data of the previous line is labeled as the start of an entry.
+    follows_header_line: bool,
+}
+
+impl<'a> OutputLimitFormatter<'a> {
+    fn new(
+        formatter: &'a mut dyn Formatter,
+        budget: &'a mut OutputBudget,
+        entries: OutputEntries,
+    ) -> Self {
+        OutputLimitFormatter {
+            formatter,
+            budget,
+            entries,
+            entry: FormatRecorder::new(),
+            label_stack: vec![],
+            at_line_start: true,
+            is_header_line: false,
+            follows_header_line: false,
+        }
+    }
+
+    fn flush_entry(&mut self) -> io::Result<()> {
+        let entry = mem::take(&mut self.entry);
+        if !self.budget.truncated && entry.data().len() <= self.budget.remaining {
+            self.budget.remaining -= entry.data().len();
+            entry.replay(self.formatter)
+        } else {
+            self.budget.truncated = true;
+            entry.replay_with(self.formatter, |_, _| Ok(()))
+        }
+    }
+
+    /// Writes the last entry if it fits.
+    fn finish(mut self) -> io::Result<()> {
+        self.flush_entry()
+    }
+}
+
+impl Write for OutputLimitFormatter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.budget.truncated {
+            return Ok(data.len());
+        }
+        for chunk in data.split_inclusive(|b| *b == b'\n') {
+            self.entry.write_all(chunk)?;
+            self.is_header_line |= self.label_stack.contains(&true);
+            self.at_line_start = chunk.ends_with(b"\n");
+            if self.at_line_start {
+                self.follows_header_line = mem::take(&mut self.is_header_line);
+                if matches!(self.entries, OutputEntries::Lines) {
+                    self.flush_entry()?;
+                }
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.formatter.flush()
+    }
+}
+
+impl Formatter for OutputLimitFormatter<'_> {
+    fn raw(&mut self) -> &mut dyn Write {
+        self
+    }
+
+    fn push_label(&mut self, label: &str) -> io::Result<()> {
+        let is_entry_label = matches!(
+            self.entries,
+            OutputEntries::Labeled(entry_label) if entry_label.as_str() == label
+        );
+        // A header following another header line belongs to the same entry.
+        if is_entry_label && self.at_line_start && !self.follows_header_line {
+            self.flush_entry()?;
+        }
+        self.label_stack.push(is_entry_label);
+        if self.budget.truncated {
+            self.formatter.push_label(label)
+        } else {
+            self.entry.push_label(label)
+        }
+    }
+
+    fn pop_label(&mut self) -> io::Result<()> {
+        self.label_stack.pop();
+        if self.budget.truncated {
+            self.formatter.pop_label()
+        } else {
+            self.entry.pop_label()
+        }
+    }
+
+    fn is_color(&self) -> bool {
+        self.formatter.is_color()
+    }
+}
+
+/// Writes the output of `write` to the `formatter`, or only the entries that
+/// fit in the `budget` if any. See `OutputLimitFormatter` for the entries.
+fn write_limited(
+    formatter: &mut dyn Formatter,
+    budget: Option<&mut OutputBudget>,
+    entries: OutputEntries,
+    write: impl FnOnce(&mut dyn Formatter) -> Result<(), DiffRenderError>,
+) -> Result<(), DiffRenderError> {
+    let Some(budget) = budget else {
+        return write(formatter);
+    };
+    if budget.truncated {
+        return Ok(());
+    }
+    let mut limited_formatter = OutputLimitFormatter::new(formatter, budget, entries);
+    write(&mut limited_formatter)?;
+    limited_formatter.finish()?;
+    Ok(())
+}
+
+/// `Formatter` wrapper that writes data without labels.
+struct UnlabeledFormatter<'a> {
+    formatter: &'a mut dyn Formatter,
+}
+
+impl Write for UnlabeledFormatter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.formatter.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.formatter.flush()
+    }
+}
+
+impl Formatter for UnlabeledFormatter<'_> {
+    fn raw(&mut self) -> &mut dyn Write {
+        self.formatter.raw()
+    }
+
+    fn push_label(&mut self, _label: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn pop_label(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Calls `write_content` with a formatter that drops all labels if only
+/// headers are colored.
+fn with_content_formatter(
+    formatter: &mut dyn Formatter,
+    color_scope: DiffColorScope,
+    write_content: impl FnOnce(&mut dyn Formatter) -> io::Result<()>,
+) -> io::Result<()> {
+    match color_scope {
+        DiffColorScope::Full => write_content(formatter),
+        DiffColorScope::Headers => write_content(&mut UnlabeledFormatter { formatter }),
+    }
+}
+
+/// Step of diffing the contents of a file, timed by [`DiffProfile`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiffStep {
+    /// Reading the contents of both sides.
+    ReadContent,
+    /// Comparing the contents line by line.
+    LineDiff,
+    /// Comparing the words of changed lines.
+    WordDiff,
+}
+
+/// Wall-clock time spent in each step of diffing a file.
+#[derive(Clone, Debug)]
+struct FileDiffTimings {
+    source: RepoPathBuf,
+    target: RepoPathBuf,
+    read_content: Duration,
+    line_diff: Duration,
+    word_diff: Duration,
+}
+
+impl FileDiffTimings {
+    fn total(&self) -> Duration {
+        self.read_content + self.line_diff + self.word_diff
+    }
+}
+
+/// Collects the time spent diffing each file in Git and color-words diffs,
+/// for `--profile-files`.
+///
+/// Clones share the same timings, so the renderer can read back the timings
+/// recorded through the diff options.
+#[derive(Clone, Debug, Default)]
+pub struct DiffProfile(Arc<Mutex<Vec<FileDiffTimings>>>);
+
+impl DiffProfile {
+    /// Starts timing the steps of diffing `source` to `target`.
+    fn start_file(&self, source: &RepoPath, target: &RepoPath) {
+        self.0.lock().unwrap().push(FileDiffTimings {
+            source: source.to_owned(),
+            target: target.to_owned(),
+            read_content: Duration::ZERO,
+            line_diff: Duration::ZERO,
+            word_diff: Duration::ZERO,
+        });
+    }
+
+    /// Takes the timings recorded so far.
+    fn take(&self) -> Vec<FileDiffTimings> {
+        mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl PartialEq for DiffProfile {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for DiffProfile {}
+
+/// Starts timing the steps of diffing `source` to `target` if profiling.
+fn start_file_profile(profile: Option<&DiffProfile>, source: &RepoPath, target: &RepoPath) {
+    if let Some(profile) = profile {
+        profile.start_file(source, target);
+    }
+}
+
+/// Runs `f`, and adds the time taken to `step` of the file being diffed if
+/// profiling.
+fn timed<T>(profile: Option<&DiffProfile>, step: DiffStep, f: impl FnOnce() -> T) -> T {
+    let Some(profile) = profile else {
+        return f();
+    };
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    if let Some(timings) = profile.0.lock().unwrap().last_mut() {
+        match step {
+            DiffStep::ReadContent => timings.read_content += elapsed,
+            DiffStep::LineDiff => timings.line_diff += elapsed,
+            DiffStep::WordDiff => timings.word_diff += elapsed,
+        }
+    }
+    result
+}
+
+/// Configuration and environment to render textual diff.
+pub struct DiffRenderer<'a> {
+    repo: &'a dyn Repo,
+    path_converter: &'a RepoPathUiConverter,
+    formats: Vec<DiffFormat>,
+    max_files: Option<usize>,
+    type_filter: Vec<DiffFileType>,
+    content_grep: Option<DiffContentGrep>,
+    max_output_bytes: Option<usize>,
+    group_by_status: bool,
+    profile_files: Option<usize>,
+    profile: Option<DiffProfile>,
+}
+
+impl<'a> DiffRenderer<'a> {
+    pub fn new(
+        repo: &'a dyn Repo,
+        path_converter: &'a RepoPathUiConverter,
+        formats: Vec<DiffFormat>,
+    ) -> Self {
+        DiffRenderer {
+            repo,
+            formats,
+            path_converter,
+            max_files: None,
+            type_filter: vec![],
+            content_grep: None,
+            max_output_bytes: None,
+            group_by_status: false,
+            profile_files: None,
+            profile: None,
+        }
+    }
+
+    /// Limits the number of changed files to be rendered.
+    pub fn with_max_files(mut self, max_files: Option<usize>) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Limits the changed files to be rendered to the paths of the given
+    /// types before or after the change. All files are rendered if empty.
+    pub fn with_type_filter(mut self, type_filter: Vec<DiffFileType>) -> Self {
+        self.type_filter = type_filter;
+        self
+    }
+
+    /// Limits the changed files to be rendered to the files where a changed
+    /// line matches the given pattern. All files are rendered if `None`.
+    pub fn with_content_grep(mut self, content_grep: Option<DiffContentGrep>) -> Self {
+        self.content_grep = content_grep;
+        self
+    }
+
+    /// Limits the number of bytes rendered for each diff. The output is
+    /// truncated at a line boundary if it would exceed the limit.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: Option<usize>) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// Groups the changed files by status, such as added or modified, under
+    /// section headers.
+    pub fn with_group_by_status(mut self, group_by_status: bool) -> Self {
+        self.group_by_status = group_by_status;
+        self
+    }
+
+    /// Prints the given number of files that took the longest to diff after
+    /// rendering the diff. Only diffs rendered with access to the terminal are
+    /// profiled.
+    pub fn with_profile_files(mut self, profile_files: Option<usize>) -> Self {
+        self.profile_files = profile_files;
+        self.profile = profile_files.map(|_| DiffProfile::default());
+        self
+    }
+
+    /// Generates diff between `from_tree` and `to_tree`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show_diff(
+        &self,
+        ui: &Ui, // TODO: remove Ui dependency if possible
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        self.show_diff_with_timestamps(
+            Some(ui),
+            formatter,
+            from_tree,
+            to_tree,
+            matcher,
+            copy_records,
+            [None, None],
+            width,
+        )
+    }
+
+    /// Generates diff between `from_tree` and `to_tree` without access to the
+    /// terminal, such as in templates. External diff tools aren't supported.
+    pub fn show_diff_without_ui(
+        &self,
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        self.show_diff_with_timestamps(
+            None,
+            formatter,
+            from_tree,
+            to_tree,
+            matcher,
+            copy_records,
+            [None, None],
+            width,
+        )
+    }
+
+    /// Writes diff between `from_tree` and `to_tree` to `writer` as plain
+    /// text. External diff tools aren't supported.
+    pub fn write_diff(
+        &self,
+        writer: &mut dyn io::Write,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let mut formatter = PlainTextFormatter::new(writer);
+        self.show_diff_without_ui(
+            &mut formatter,
+            from_tree,
+            to_tree,
+            matcher,
+            copy_records,
+            width,
+        )?;
+        formatter.flush()?;
+        Ok(())
+    }
+
+    /// Generates diff between `from_tree` and `to_tree`, which were taken at
+    /// the given `timestamps`.
+    #[allow(clippy::too_many_arguments)]
+    fn show_diff_with_timestamps(
+        &self,
+        ui: Option<&Ui>,
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        timestamps: [Option<&Timestamp>; 2],
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        formatter.with_label(DiffLabel::Diff.as_str(), |formatter| {
+            self.show_diff_inner(
+                ui,
+                formatter,
+                from_tree,
+                to_tree,
+                matcher,
+                copy_records,
+                timestamps,
+                width,
+            )
+        })?;
+        if let (Some(ui), Some(num_files)) = (ui, self.profile_files) {
+            self.show_file_profile(ui, num_files)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the `num_files` files that took the longest to diff since the
+    /// last call to stderr.
+    fn show_file_profile(&self, ui: &Ui, num_files: usize) -> Result<(), DiffRenderError> {
+        let Some(profile) = &self.profile else {
+            return Ok(());
+        };
+        let mut timings = profile.take();
+        if !self
+            .formats
+            .iter()
+            .any(|format| matches!(format, DiffFormat::Git(_) | DiffFormat::ColorWords(_)))
+        {
+            writeln!(
+                ui.warning_default(),
+                "Only Git and color-words diffs can be profiled"
+            )?;
+            return Ok(());
+        }
+        timings.sort_by_key(|file| Reverse(file.total()));
+        let mut stderr = ui.stderr();
+        writeln!(stderr, "Slowest files to diff:")?;
+        for file in timings.iter().take(num_files) {
+            writeln!(
+                stderr,
+                "  {:.3?} {} (read {:.3?}, line diff {:.3?}, word diff {:.3?})",
+                file.total(),
+                self.path_converter
+                    .format_copied_path(&file.source, &file.target),
+                file.read_content,
+                file.line_diff,
+                file.word_diff,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn show_diff_inner(
+        &self,
+        ui: Option<&Ui>,
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        timestamps: [Option<&Timestamp>; 2],
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let mut budget = self.max_output_bytes.map(|max_output_bytes| OutputBudget {
+            remaining: max_output_bytes,
+            truncated: false,
+        });
+        self.show_diff_formats(
+            ui,
+            formatter,
+            from_tree,
+            to_tree,
+            matcher,
+            copy_records,
+            timestamps,
+            width,
+            budget.as_mut(),
+        )?;
+        if budget.is_some_and(|budget| budget.truncated) {
+            writeln!(formatter, "... output truncated")?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn show_diff_formats(
+        &self,
+        ui: Option<&Ui>,
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        timestamps: [Option<&Timestamp>; 2],
+        width: usize,
+        mut budget: Option<&mut OutputBudget>,
+    ) -> Result<(), DiffRenderError> {
+        if from_tree.id() == to_tree.id() {
+            // Identical trees have no changes, so there's no need to read them.
+            return write_limited(formatter, budget, OutputEntries::Lines, |formatter| {
+                self.show_empty_diff(formatter)
+            });
+        }
+        let store = self.repo.store();
+        let type_matcher;
+        let matcher: &dyn Matcher = if self.type_filter.is_empty() {
+            matcher
+        } else {
+            let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+            let paths = collect_changed_paths_of_types(tree_diff, &self.type_filter)?;
+            type_matcher = FilesMatcher::new(paths);
+            &type_matcher
+        };
+        let content_matcher;
+        let matcher: &dyn Matcher = if let Some(content_grep) = &self.content_grep {
+            let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+            let copied_sources = collect_copied_sources(copy_records, matcher);
+            let paths = collect_changed_paths_matching_content(
+                store,
+                tree_diff,
+                &copied_sources,
+                content_grep,
+            )?;
+            content_matcher = FilesMatcher::new(paths);
+            &content_matcher
+        } else {
+            matcher
+        };
+        let limited_matcher;
+        let (matcher, num_omitted_files): (&dyn Matcher, _) =
+            if let Some(max_files) = self.max_files {
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                let (paths, num_omitted_files) = collect_first_changed_paths(tree_diff, max_files);
+                limited_matcher = FilesMatcher::new(paths);
+                (&limited_matcher, num_omitted_files)
+            } else {
+                (matcher, 0)
+            };
+        if self.group_by_status {
+            self.show_diff_sections(
+                ui,
+                formatter,
+                from_tree,
+                to_tree,
+                matcher,
+                copy_records,
+                timestamps,
+                width,
+                budget.as_deref_mut(),
+            )?;
+            write_limited(formatter, budget, OutputEntries::Lines, |formatter| {
+                Ok(write_omitted_files(formatter, num_omitted_files)?)
+            })?;
+            return Ok(());
+        }
+        for format in &self.formats {
+            write_limited(
+                formatter,
+                budget.as_deref_mut(),
+                output_entries(format),
+                |formatter| {
+                    self.show_diff_format(
+                        ui,
+                        formatter,
+                        format,
+                        from_tree,
+                        to_tree,
+                        matcher,
+                        copy_records,
+                        timestamps,
+                        width,
+                    )?;
+                    Ok(write_omitted_files(formatter, num_omitted_files)?)
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Renders the diff of each group of files under a section header. The
+    /// summary lines of stat formats are printed once for the whole diff after
+    /// the sections.
+    #[allow(clippy::too_many_arguments)]
+    fn show_diff_sections(
+        &self,
+        ui: Option<&Ui>,
+        formatter: &mut dyn Formatter,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        timestamps: [Option<&Timestamp>; 2],
+        width: usize,
+        mut budget: Option<&mut OutputBudget>,
+    ) -> Result<(), DiffRenderError> {
+        let store = self.repo.store();
+        let groups = collect_changed_paths_by_status(from_tree, to_tree, matcher, copy_records)?;
+        let mut stats = DiffStats { entries: vec![] };
+        for (status, paths) in groups {
+            write_limited(
+                formatter,
+                budget.as_deref_mut(),
+                OutputEntries::Lines,
+                |formatter| {
+                    writeln!(
+                        formatter.labeled(DiffLabel::Header),
+                        "=== {} ===",
+                        status.name()
+                    )?;
+                    Ok(())
+                },
+            )?;
+            let section_matcher = FilesMatcher::new(paths);
+            for format in &self.formats {
+                match format {
+                    DiffFormat::Stat(options) => {
+                        let tree_diff =
+                            from_tree.diff_stream(to_tree, &section_matcher, copy_records);
+                        let copied_sources = collect_copied_sources(copy_records, &section_matcher);
+                        let section_stats = DiffStats::collect(
+                            store,
+                            tree_diff,
+                            self.path_converter,
+                            &copied_sources,
+                            options.parallel,
+                            options.absolute_paths,
+                        )?;
+                        let section_options = DiffStatOptions {
+                            summary_line: false,
+                            ..(**options).clone()
+                        };
+                        write_limited(
+                            formatter,
+                            budget.as_deref_mut(),
+                            OutputEntries::Lines,
+                            |formatter| {
+                                Ok(write_diff_stat(
+                                    formatter,
+                                    &section_stats,
+                                    width,
+                                    &section_options,
+                                )?)
+                            },
+                        )?;
+                        stats.entries.extend(section_stats.entries);
+                    }
+                    DiffFormat::ShortStat | DiffFormat::ConflictStat => {}
+                    _ => {
+                        write_limited(
+                            formatter,
+                            budget.as_deref_mut(),
+                            output_entries(format),
+                            |formatter| {
+                                self.show_diff_format(
+                                    ui,
+                                    formatter,
+                                    format,
+                                    from_tree,
+                                    to_tree,
+                                    &section_matcher,
+                                    copy_records,
+                                    timestamps,
+                                    width,
+                                )
+                            },
+                        )?;
+                    }
+                }
+            }
+        }
+        for format in &self.formats {
+            match format {
+                DiffFormat::Stat(options)
+                    if options.summary_line && options.format != DiffStatFormat::Numstat =>
+                {
+                    write_limited(
+                        formatter,
+                        budget.as_deref_mut(),
+                        OutputEntries::Lines,
+                        |formatter| Ok(write_diff_stat_summary(formatter, &stats)?),
+                    )?;
+                }
+                DiffFormat::ShortStat | DiffFormat::ConflictStat => {
+                    write_limited(
+                        formatter,
+                        budget.as_deref_mut(),
+                        OutputEntries::Lines,
+                        |formatter| {
+                            self.show_diff_format(
+                                ui,
+                                formatter,
+                                format,
+                                from_tree,
+                                to_tree,
+                                matcher,
+                                copy_records,
+                                timestamps,
+                                width,
+                            )
+                        },
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the diff in the given `format`.
+    #[allow(clippy::too_many_arguments)]
+    fn show_diff_format(
+        &self,
+        ui: Option<&Ui>,
+        formatter: &mut dyn Formatter,
+        format: &DiffFormat,
+        from_tree: &MergedTree,
+        to_tree: &MergedTree,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        timestamps: [Option<&Timestamp>; 2],
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let store = self.repo.store();
+        let path_converter = self.path_converter;
+        match format {
+            DiffFormat::Summary(options) => {
+                show_diff_summary(
+                    formatter,
+                    path_converter,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                    options,
+                )?;
+            }
+            DiffFormat::Stat(options) => {
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                show_diff_stat(
+                    formatter,
+                    store,
+                    tree_diff,
+                    path_converter,
+                    matcher,
+                    copy_records,
+                    width,
+                    options,
+                )?;
+            }
+            DiffFormat::ShortStat => {
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                show_diff_shortstat(
+                    formatter,
+                    store,
+                    tree_diff,
+                    path_converter,
+                    matcher,
+                    copy_records,
+                )?;
+            }
+            DiffFormat::ConflictStat => {
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                show_diff_conflict_stat(formatter, tree_diff, matcher, copy_records)?;
+            }
+            DiffFormat::OnelineStat => {
+                show_diff_oneline_stat(
+                    formatter,
+                    store,
+                    path_converter,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                )?;
+            }
+            DiffFormat::Types(options) => {
+                show_types(
+                    formatter,
+                    path_converter,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                    options,
+                )?;
+            }
+            DiffFormat::NameOnly => {
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                show_names(formatter, tree_diff, path_converter)?;
+            }
+            DiffFormat::ModeOnly => {
+                show_mode_changes(
+                    formatter,
+                    store,
+                    path_converter,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                )?;
+            }
+            DiffFormat::Git(options) => {
+                let profiled_options;
+                let options: &UnifiedDiffOptions = if let Some(profile) = &self.profile {
+                    profiled_options = UnifiedDiffOptions {
+                        profile: Some(profile.clone()),
+                        ..(**options).clone()
+                    };
+                    &profiled_options
+                } else {
+                    options
+                };
+                show_git_diff(
+                    formatter,
+                    store,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                    options,
+                )?;
+            }
+            DiffFormat::ColorWords(options) => {
+                let profiled_options;
+                let options: &ColorWordsDiffOptions = if let Some(profile) = &self.profile {
+                    profiled_options = ColorWordsDiffOptions {
+                        profile: Some(profile.clone()),
+                        ..(**options).clone()
+                    };
+                    &profiled_options
+                } else {
+                    options
+                };
+                let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                show_color_words_diff(
+                    formatter,
+                    store,
+                    to_tree,
+                    tree_diff,
+                    path_converter,
+                    matcher,
+                    copy_records,
+                    options,
+                )?;
+                if options.deref_symlinks {
+                    show_color_words_symlink_target_diff(
+                        formatter,
+                        store,
+                        from_tree,
+                        to_tree,
+                        matcher,
+                        path_converter,
+                        options,
+                    )?;
+                }
+            }
+            DiffFormat::Html(options) => {
+                show_html_diff(
+                    formatter,
+                    store,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                    options,
+                )?;
+            }
+            DiffFormat::Posix(options) => {
+                show_posix_diff(
+                    formatter,
+                    store,
+                    from_tree,
+                    to_tree,
+                    matcher,
+                    copy_records,
+                    timestamps,
+                    options,
+                )?;
+            }
+            DiffFormat::Tool(tool) => {
+                let ui = ui.ok_or(DiffRenderError::ExternalToolUnsupported)?;
+                match tool.diff_invocation_mode {
+                    DiffToolMode::FileByFile => {
+                        let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+                        show_file_by_file_diff(
+                            ui,
+                            formatter,
+                            store,
+                            tree_diff,
+                            path_converter,
+                            matcher,
+                            copy_records,
+                            tool,
+                        )
+                    }
+                    DiffToolMode::Dir => {
+                        generate_diff(ui, formatter.raw(), from_tree, to_tree, matcher, tool)
+                            .map_err(DiffRenderError::from_diff_generate)
+                    }
+                    DiffToolMode::DirNonBlocking => spawn_diff(from_tree, to_tree, matcher, tool)
+                        .map_err(DiffRenderError::from_diff_generate),
+                }?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints what each format shows for a diff with no changes.
+    fn show_empty_diff(&self, formatter: &mut dyn Formatter) -> Result<(), DiffRenderError> {
+        let empty_stats = DiffStats { entries: vec![] };
+        for format in &self.formats {
+            match format {
+                DiffFormat::Stat(options)
+                    if options.summary_line && options.format != DiffStatFormat::Numstat =>
+                {
+                    write_diff_stat_summary(formatter, &empty_stats)?;
+                }
+                DiffFormat::ShortStat => {
+                    write_diff_stat_summary(formatter, &empty_stats)?;
+                }
+                DiffFormat::ConflictStat => {
+                    write_conflict_stat(formatter, 0, 0)?;
+                }
+                DiffFormat::Git(options) if options.show_no_changes => {
+                    writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
+                }
+                DiffFormat::ColorWords(options) if options.show_no_changes => {
+                    writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
+                }
+                DiffFormat::Posix(options) if options.show_no_changes => {
+                    writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
+                }
+                DiffFormat::Html(options) if options.show_no_changes => {
+                    writeln!(
+                        formatter,
+                        r#"<div class="{}">(no changes)</div>"#,
+                        DiffLabel::Empty.as_str()
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates diff of the descriptions and trees between `from` and `to`
+    /// commits.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show_commit_diff(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        from: &Commit,
+        to: &Commit,
+        matcher: &dyn Matcher,
+        copy_records: &CopyRecords,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let from_tree = from.tree()?;
+        let to_tree = to.tree()?;
+        formatter.with_label(DiffLabel::Diff.as_str(), |formatter| {
+            self.show_description_diff(formatter, from.description(), to.description())?;
+            self.show_diff_inner(
+                Some(ui),
+                formatter,
+                &from_tree,
+                &to_tree,
+                matcher,
+                copy_records,
+                [Some(&from.author().timestamp), Some(&to.author().timestamp)],
+                width,
+            )
+        })
+    }
+
+    /// Generates diff of the file at `path_a` in `commit_a` and the file at
+    /// `path_b` in `commit_b`. Only Git and color-words formats are supported.
+    /// Paths which don't exist as files are compared as empty files.
+    pub fn show_path_pair_diff(
+        &self,
+        formatter: &mut dyn Formatter,
+        commit_a: &Commit,
+        path_a: &RepoPath,
+        commit_b: &Commit,
+        path_b: &RepoPath,
+    ) -> Result<(), DiffRenderError> {
+        let store = self.repo.store();
+        let read_content = |commit: &Commit, path: &RepoPath| -> Result<_, DiffRenderError> {
+            let value = commit.tree()?.path_value(path)?;
+            if value.is_tree() {
+                return Ok(FileContent::empty());
+            }
+            let materialized = materialize_tree_value(store, path, value).block_on()?;
+            Ok(diff_content(path, materialized)?)
+        };
+        let left = read_content(commit_a, path_a)?;
+        let right = read_content(commit_b, path_b)?;
+        let left_name = self.path_converter.format_file_path(path_a);
+        let right_name = self.path_converter.format_file_path(path_b);
+        formatter.with_label(DiffLabel::Diff.as_str(), |formatter| {
+            for format in &self.formats {
+                match format {
+                    DiffFormat::Git(options) => show_git_contents_diff(
+                        formatter,
+                        &left_name,
+                        &left.contents,
+                        &right_name,
+                        &right.contents,
+                        options,
+                    )?,
+                    DiffFormat::ColorWords(options) => show_color_words_contents_diff(
+                        formatter,
+                        &left_name,
+                        &left.contents,
+                        &right_name,
+                        &right.contents,
+                        options,
+                    )?,
+                    _ => return Err(DiffRenderError::PathPairFormatUnsupported),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Generates diff of the authors, committers, and their timestamps
+    /// between `from` and `to` commits. Unchanged fields are omitted.
+    pub fn show_metadata_diff(
+        &self,
+        formatter: &mut dyn Formatter,
+        from: &Commit,
+        to: &Commit,
+    ) -> io::Result<()> {
+        let format_signature =
+            |signature: &Signature| format!("{} <{}>\n", signature.name, signature.email);
+        let format_timestamp = |signature: &Signature| {
+            let text = format_absolute_timestamp(&signature.timestamp)
+                .unwrap_or_else(|err| err.to_string());
+            text + "\n"
+        };
+        let fields = [
+            (
+                "author",
+                format_signature(from.author()),
+                format_signature(to.author()),
+            ),
+            (
+                "author timestamp",
+                format_timestamp(from.author()),
+                format_timestamp(to.author()),
+            ),
+            (
+                "committer",
+                format_signature(from.committer()),
+                format_signature(to.committer()),
+            ),
+            (
+                "committer timestamp",
+                format_timestamp(from.committer()),
+                format_timestamp(to.committer()),
+            ),
+        ];
+        formatter.with_label(DiffLabel::Diff.as_str(), |formatter| {
+            for (name, from_text, to_text) in &fields {
+                self.show_field_diff(formatter, DiffLabel::Metadata, name, from_text, to_text)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn show_description_diff(
+        &self,
+        formatter: &mut dyn Formatter,
+        from_description: &str,
+        to_description: &str,
+    ) -> io::Result<()> {
+        self.show_field_diff(
+            formatter,
+            DiffLabel::Description,
+            "description",
+            from_description,
+            to_description,
+        )
+    }
+
+    /// Writes color-words diff of a commit field under `label`.
+    fn show_field_diff(
+        &self,
+        formatter: &mut dyn Formatter,
+        label: DiffLabel,
+        name: &str,
+        from_text: &str,
+        to_text: &str,
+    ) -> io::Result<()> {
+        if from_text == to_text {
+            return Ok(());
+        }
+        let default_options = ColorWordsDiffOptions::default();
+        let options = self
+            .formats
+            .iter()
+            .find_map(|format| match format {
+                DiffFormat::ColorWords(options) => Some(options.as_ref()),
+                _ => None,
+            })
+            .unwrap_or(&default_options);
+        formatter.with_label(label.as_str(), |formatter| {
+            writeln!(
+                formatter.labeled(DiffLabel::Header),
+                "Modified commit {name}:"
+            )?;
+            show_color_words_diff_hunks(
+                from_text.as_bytes(),
+                to_text.as_bytes(),
+                options,
+                DiffWordTokenizer::Word,
+                formatter,
+            )
+        })
+    }
+
+    /// Generates diff of the given `commit` compared to its parents.
+    pub fn show_patch(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        commit: &Commit,
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let from_tree = commit.parent_tree(self.repo)?;
+        let to_tree = commit.tree()?;
+        let mut copy_records = CopyRecords::default();
+        for parent_id in commit.parent_ids() {
+            copy_records.add_records(self.repo.store().get_copy_records(
+                None,
+                parent_id,
+                commit.id(),
+            )?)?;
+        }
+        // Merge parents have no single timestamp.
+        let parent = match commit.parent_ids() {
+            [parent_id] => Some(self.repo.store().get_commit(parent_id)?),
+            _ => None,
+        };
+        self.show_diff_with_timestamps(
+            Some(ui),
+            formatter,
+            &from_tree,
+            &to_tree,
+            matcher,
+            &copy_records,
+            [
+                parent.as_ref().map(|parent| &parent.author().timestamp),
+                Some(&commit.author().timestamp),
+            ],
+            width,
+        )
+    }
+
+    /// Generates diff of the given `commit` compared to one of its `parent`s.
+    pub fn show_patch_against_parent(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        commit: &Commit,
+        parent: &Commit,
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let from_tree = parent.tree()?;
+        let to_tree = commit.tree()?;
+        let mut copy_records = CopyRecords::default();
+        copy_records.add_records(self.repo.store().get_copy_records(
+            None,
+            parent.id(),
+            commit.id(),
+        )?)?;
+        self.show_diff_with_timestamps(
+            Some(ui),
+            formatter,
+            &from_tree,
+            &to_tree,
+            matcher,
+            &copy_records,
+            [
+                Some(&parent.author().timestamp),
+                Some(&commit.author().timestamp),
+            ],
+            width,
+        )
+    }
+
+    /// Generates diff of `to` compared to the merge base of `from` and `to`,
+    /// which is the same as `git diff from...to`.
+    pub fn show_merge_base_diff(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        from: &Commit,
+        to: &Commit,
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let base_ids = self
+            .repo
+            .index()
+            .common_ancestors(&[from.id().clone()], &[to.id().clone()]);
+        let base_commits: Vec<_> = base_ids
+            .iter()
+            .map(|id| self.repo.store().get_commit(id))
+            .try_collect()?;
+        let from_tree = merge_commit_trees(self.repo, &base_commits)?;
+        let to_tree = to.tree()?;
+        let mut copy_records = CopyRecords::default();
+        for base_id in &base_ids {
+            copy_records.add_records(self.repo.store().get_copy_records(
+                None,
+                base_id,
+                to.id(),
+            )?)?;
+        }
+        self.show_diff(
+            ui,
+            formatter,
+            &from_tree,
+            &to_tree,
+            matcher,
+            &copy_records,
+            width,
+        )
+    }
+
+    /// Generates diff of the working-copy commit `wc_commit` compared to the
+    /// tree of the `base` commit.
+    ///
+    /// The working-copy commit should be snapshotted by the caller. Files that
+    /// aren't in the `base` tree, including ones git would report as
+    /// untracked, are shown as added since snapshotting starts tracking them.
+    /// Ignored files are never snapshotted, so they aren't shown.
+    pub fn show_working_copy_diff(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        wc_commit: &Commit,
+        base: &Commit,
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let base_tree = base.tree()?;
+        let wc_tree = wc_commit.tree()?;
+        let mut copy_records = CopyRecords::default();
+        copy_records.add_records(self.repo.store().get_copy_records(
+            None,
+            base.id(),
+            wc_commit.id(),
+        )?)?;
+        self.show_diff(
+            ui,
+            formatter,
+            &base_tree,
+            &wc_tree,
+            matcher,
+            &copy_records,
+            width,
+        )
+    }
+
+    /// Generates net diff of a stack of `commits`, which is the same as the
+    /// diff of a single commit squashing all of them.
+    ///
+    /// The `commits` should be ordered from the bottom of the stack. The
+    /// parent tree of the first commit is compared to the tree of the last
+    /// commit. Nothing is rendered if `commits` is empty.
+    pub fn show_squashed_diff(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        commits: &[Commit],
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let (Some(first), Some(last)) = (commits.first(), commits.last()) else {
+            return Ok(());
+        };
+        let from_tree = first.parent_tree(self.repo)?;
+        let to_tree = last.tree()?;
+        let mut copy_records = CopyRecords::default();
+        for parent_id in first.parent_ids() {
+            copy_records.add_records(self.repo.store().get_copy_records(
+                None,
+                parent_id,
+                last.id(),
+            )?)?;
+        }
+        self.show_diff(
+            ui,
+            formatter,
+            &from_tree,
+            &to_tree,
+            matcher,
+            &copy_records,
+            width,
+        )
+    }
+
+    /// Generates diffs of `commit` compared to `old_base` and to `new_base`,
+    /// each under its own heading, to review how rebasing `commit` changed its
+    /// patch.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show_rebase_diff(
+        &self,
+        ui: &Ui,
+        formatter: &mut dyn Formatter,
+        commit: &Commit,
+        old_base: &Commit,
+        new_base: &Commit,
+        matcher: &dyn Matcher,
+        width: usize,
+    ) -> Result<(), DiffRenderError> {
+        let to_tree = commit.tree()?;
+        for (name, base) in [("old", old_base), ("new", new_base)] {
+            writeln!(
+                formatter.labeled(DiffLabel::Header),
+                "=== Against {name} base {} ===",
+                short_commit_hash(base.id())
+            )?;
+            let from_tree = base.tree()?;
+            let mut copy_records = CopyRecords::default();
+            copy_records.add_records(self.repo.store().get_copy_records(
+                None,
+                base.id(),
+                commit.id(),
+            )?)?;
+            self.show_diff_with_timestamps(
+                Some(ui),
+                formatter,
+                &from_tree,
+                &to_tree,
+                matcher,
+                &copy_records,
+                [
+                    Some(&base.author().timestamp),
+                    Some(&commit.author().timestamp),
+                ],
+                width,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects source and target paths of the first `max_files` entries, and
+/// returns them with the number of the remaining entries.
+fn collect_first_changed_paths(
+    mut tree_diff: TreeDiffStream,
+    max_files: usize,
+) -> (Vec<RepoPathBuf>, usize) {
+    async {
+        let mut paths = vec![];
+        let mut num_files = 0;
+        while let Some(TreeDiffEntry { source, target, .. }) = tree_diff.next().await {
+            if num_files < max_files {
+                paths.push(source);
+                paths.push(target);
+            }
+            num_files += 1;
+        }
+        (paths, num_files.saturating_sub(max_files))
+    }
+    .block_on()
+}
+
+/// Collects the source and target paths of all entries in the `tree_diff`.
+fn collect_changed_paths(mut tree_diff: TreeDiffStream) -> HashSet<RepoPathBuf> {
+    async {
+        let mut paths = HashSet::new();
+        while let Some(TreeDiffEntry { source, target, .. }) = tree_diff.next().await {
+            paths.insert(source);
+            paths.insert(target);
+        }
+        paths
+    }
+    .block_on()
+}
+
+/// Returns how the output of the `format` is split into file entries. HTML
+/// elements can't be cut off, so the HTML output is kept whole.
+fn output_entries(format: &DiffFormat) -> OutputEntries {
+    match format {
+        DiffFormat::Git(_) | DiffFormat::Posix(_) => OutputEntries::Labeled(DiffLabel::FileHeader),
+        DiffFormat::ColorWords(_) => OutputEntries::Labeled(DiffLabel::Header),
+        DiffFormat::Html(_) => OutputEntries::Whole,
+        _ => OutputEntries::Lines,
+    }
+}
+
+/// Prints the number of changed files omitted by `--max-files` if any.
+fn write_omitted_files(formatter: &mut dyn Formatter, num_omitted_files: usize) -> io::Result<()> {
+    if num_omitted_files > 0 {
+        writeln!(
+            formatter,
+            "... and {num_omitted_files} more file{}",
+            if num_omitted_files == 1 { "" } else { "s" }
+        )?;
+    }
+    Ok(())
+}
+
+/// Collects source and target paths of changes whose value before or after is
+/// of one of the `types`.
+fn collect_changed_paths_of_types(
+    mut tree_diff: TreeDiffStream,
+    types: &[DiffFileType],
+) -> Result<Vec<RepoPathBuf>, BackendError> {
+    async {
+        let mut paths = vec![];
+        while let Some(TreeDiffEntry {
+            source,
+            target,
+            value: diff,
+        }) = tree_diff.next().await
+        {
+            let (before, after) = diff?;
+            let is_selected = [&before, &after]
+                .into_iter()
+                .filter_map(DiffFileType::of)
+                .any(|file_type| types.contains(&file_type));
+            if is_selected {
+                paths.push(source);
+                paths.push(target);
+            }
+        }
+        Ok(paths)
+    }
+    .block_on()
+}
+
+/// Collects source and target paths of changes grouped by status, in the
+/// order of `DiffStatus::ALL`. Groups without changes are omitted.
+fn collect_changed_paths_by_status(
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+) -> Result<Vec<(DiffStatus, Vec<RepoPathBuf>)>, BackendError> {
+    let mut tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut groups = DiffStatus::ALL
+        .iter()
+        .map(|&status| (status, vec![]))
+        .collect_vec();
+    async {
+        while let Some(TreeDiffEntry {
+            source,
+            target,
+            value: diff,
+        }) = tree_diff.next().await
+        {
+            let (before, after) = diff?;
+            let is_moved = source != target;
+            let is_rename = is_moved && to_tree.path_value(&source)?.is_absent();
+            let status = diff_status(&before, &after, is_rename, is_moved && !is_rename);
+            // Skip the "delete" entry when there is a rename.
+            if status == DiffStatus::Deleted && copied_sources.contains(source.as_ref()) {
+                continue;
+            }
+            let (_, paths) = groups
+                .iter_mut()
+                .find(|(group_status, _)| *group_status == status)
+                .unwrap();
+            paths.push(source);
+            paths.push(target);
+        }
+        Ok::<(), BackendError>(())
+    }
+    .block_on()?;
+    groups.retain(|(_, paths)| !paths.is_empty());
+    Ok(groups)
+}
+
+/// Collects source and target paths of changes where an added or removed line
+/// matches the `content_grep` pattern.
+fn collect_changed_paths_matching_content(
+    store: &Store,
+    tree_diff: TreeDiffStream,
+    copied_sources: &HashSet<&RepoPath>,
+    content_grep: &DiffContentGrep,
+) -> Result<Vec<RepoPathBuf>, DiffRenderError> {
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+    async {
+        let mut paths = vec![];
+        while let Some(MaterializedTreeDiffEntry {
+            source,
+            target,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            let (left, right) = diff?;
+            // Skip the "delete" entry when there is a rename.
+            if right.is_absent() && copied_sources.contains(source.as_ref()) {
+                continue;
+            }
+            let left_content = diff_content(&source, left)?;
+            let right_content = diff_content(&target, right)?;
+            if content_grep.matches(&left_content.contents, &right_content.contents) {
+                paths.push(source);
+                paths.push(target);
+            }
+        }
+        Ok(paths)
+    }
+    .block_on()
+}
+
+fn collect_copied_sources<'a>(
+    copy_records: &'a CopyRecords,
+    matcher: &dyn Matcher,
+) -> HashSet<&'a RepoPath> {
+    copy_records
+        .iter()
+        .filter_map(|record| {
+            if matcher.matches(&record.target) {
+                Some(record.source.as_ref())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Collects the paths `path` was renamed or copied from or to according to
+/// `copy_records`, including `path` itself.
+///
+/// Chains of records are followed transitively, so a file renamed more than
+/// once is tracked under all of its names.
+pub fn collect_followed_paths(copy_records: &CopyRecords, path: &RepoPath) -> Vec<RepoPathBuf> {
+    let mut paths = vec![path.to_owned()];
+    let mut pos = 0;
+    while let Some(current) = paths.get(pos).cloned() {
+        for record in copy_records.iter() {
+            let linked = if record.target == current {
+                &record.source
+            } else if record.source == current {
+                &record.target
+            } else {
+                continue;
+            };
+            if !paths.contains(linked) {
+                paths.push(linked.clone());
+            }
+        }
+        pos += 1;
+    }
+    paths
+}
+
+fn show_color_words_diff_hunks(
+    left: &[u8],
+    right: &[u8],
+    options: &ColorWordsDiffOptions,
+    word_tokenizer: DiffWordTokenizer,
+    formatter: &mut dyn Formatter,
+) -> io::Result<()> {
+    let line_diff_hunks = timed(options.profile.as_ref(), DiffStep::LineDiff, || {
+        line_diff_hunks(
+            [left, right],
+            options.ignore_case,
+            options.ignore_lines_matching.as_ref(),
+        )
+    });
+    let mut line_number = DiffLineNumber { left: 1, right: 1 };
+    let width = line_number_width(left, right);
+    // Unchanged lines since the last printed change, and whether any change
+    // has been printed
+    let mut context_lines = vec![];
+    let mut has_changes = false;
+    // Changes separated by more context lines than shown around them start
+    // new hunks.
+    let mut num_hunks = 0;
+    let mut num_omitted_hunks = 0;
+
+    for hunk in line_diff_hunks {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                for line in content.split_inclusive(|b| *b == b'\n') {
+                    context_lines.push((line_number, line));
+                    line_number.left += 1;
+                    line_number.right += 1;
+                }
+            }
+            DiffHunk::Different(contents) => {
+                let [left, right] = contents.try_into().unwrap();
+                if options.ignore_blank_lines && is_blank(left) && is_blank(right) {
+                    line_number.left += num_lines(left);
+                    line_number.right += num_lines(right);
+                    continue;
+                }
+                let starts_hunk = !has_changes || context_lines.len() > 2 * options.context + 1;
+                num_hunks += usize::from(starts_hunk);
+                if options
+                    .max_hunks
+                    .is_some_and(|max_hunks| num_hunks > max_hunks)
+                {
+                    if num_omitted_hunks == 0 && has_changes {
+                        // "after" context of the last shown change
+                        let num_after = options.context.min(context_lines.len());
+                        show_color_words_context_lines(
+                            formatter,
+                            &context_lines[..num_after],
+                            width,
+                            num_after,
+                            0,
+                            options,
+                        )?;
+                    }
+                    num_omitted_hunks += usize::from(starts_hunk);
+                    context_lines.clear();
+                    has_changes = true;
+                    line_number.left += num_lines(left);
+                    line_number.right += num_lines(right);
+                    continue;
+                }
+                // "before" context, preceded by "after" context of the last
+                // change if any
+                let num_after = if has_changes { options.context } else { 0 };
+                show_color_words_context_lines(
+                    formatter,
+                    &context_lines,
+                    width,
+                    num_after,
+                    options.context,
+                    options,
+                )?;
+                context_lines.clear();
+                has_changes = true;
+                let mut word_diff_hunks =
+                    timed(options.profile.as_ref(), DiffStep::WordDiff, || {
+                        diff_hunks([left, right], options.ignore_case, |inputs| {
+                            word_tokenizer.diff(inputs)
+                        })
+                    });
+                if options
+                    .full_replace_ratio
+                    .is_some_and(|ratio| changed_word_ratio(&word_diff_hunks) > ratio)
+                {
+                    // All left lines are emitted before the right lines.
+                    word_diff_hunks = vec![DiffHunk::different([left, right])];
+                }
+                let mut diff_line_iter =
+                    DiffLineIterator::with_line_number(word_diff_hunks.into_iter(), line_number);
+                for diff_line in diff_line_iter.by_ref() {
+                    show_color_words_diff_line(formatter, &diff_line, width, options)?;
+                }
+                line_number = diff_line_iter.next_line_number();
+            }
+        }
+    }
+    if num_omitted_hunks > 0 {
+        return write_omitted_hunks(formatter, num_omitted_hunks);
+    }
+    // Last "after" context. Have we printed "..." for the skipped context?
+    let skipped_context = if has_changes {
+        show_color_words_context_lines(
+            formatter,
+            &context_lines,
+            width,
+            options.context,
+            0,
+            options,
+        )?
+    } else {
+        false
+    };
+
+    // If the last diff line doesn't end with newline, add it. Nothing is
+    // printed if all changes are ignored.
+    let ignores_changes = options.ignore_blank_lines || options.ignore_lines_matching.is_some();
+    let no_hunk = (left.is_empty() && right.is_empty()) || (ignores_changes && !has_changes);
+    let any_last_newline = left.ends_with(b"\n") || right.ends_with(b"\n");
+    if !skipped_context && !no_hunk && !any_last_newline {
+        writeln!(formatter)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the ratio of words in the changed hunks to all words of both
+/// sides.
+fn changed_word_ratio(hunks: &[DiffHunk]) -> f64 {
+    fn count_words(content: &[u8]) -> usize {
+        content
+            .split(u8::is_ascii_whitespace)
+            .filter(|word| !word.is_empty())
+            .count()
+    }
+
+    let mut changed = 0;
+    let mut total = 0;
+    for hunk in hunks {
+        match hunk {
+            DiffHunk::Matching(content) => total += 2 * count_words(content),
+            DiffHunk::Different(contents) => {
+                let count: usize = contents.iter().map(|content| count_words(content)).sum();
+                changed += count;
+                total += count;
+            }
+        }
+    }
+    if total == 0 {
+        0.0
+    } else {
+        changed as f64 / total as f64
+    }
+}
+
+/// Returns true if `content` consists only of whitespace characters.
+fn is_blank(content: &[u8]) -> bool {
+    content.iter().all(u8::is_ascii_whitespace)
+}
+
+fn num_lines(content: &[u8]) -> u32 {
+    let count = content.split_inclusive(|b| *b == b'\n').count();
+    count.try_into().unwrap()
+}
+
+/// Prints `num_after` lines, ellipsis, and `num_before` lines of the unchanged
+/// `lines`. Returns true if the ellipsis is printed.
+///
+/// Line numbers of `lines` may not be contiguous if ignored changes are
+/// interleaved. The ellipsis is indented by the columns added to the line
+/// numbers wider than 4 columns, so it stays aligned with the gutter.
+fn show_color_words_context_lines(
+    formatter: &mut dyn Formatter,
+    lines: &[(DiffLineNumber, &[u8])],
+    line_number_width: usize,
+    num_after: usize,
+    num_before: usize,
+    options: &ColorWordsDiffOptions,
+) -> io::Result<bool> {
+    let (after_lines, rest) = lines.split_at(num_after.min(lines.len()));
+    // The first of the before lines is also skipped so "..." doesn't replace
+    // a single line.
+    let num_skipped = rest.len().saturating_sub(num_before);
+    let (skipped_lines, before_lines) = if num_skipped > 1 {
+        rest.split_at(num_skipped)
+    } else {
+        rest.split_at(0)
+    };
+    let show_lines = |formatter: &mut dyn Formatter, lines: &[(DiffLineNumber, &[u8])]| {
+        for &(line_number, line) in lines {
+            let diff_line = DiffLine {
+                line_number,
+                hunks: vec![(DiffLineHunkSide::Both, line.as_ref())],
+            };
+            show_color_words_diff_line(formatter, &diff_line, line_number_width, options)?;
+        }
+        io::Result::Ok(())
+    };
+    show_lines(formatter, after_lines)?;
+    if let (Some((first, _)), Some((last, _))) = (skipped_lines.first(), skipped_lines.last()) {
+        let variables = skipped_context_variables(*first, *last, skipped_lines.len());
+        let [separator]: [String; 1] =
+            interpolate_variables(slice::from_ref(&options.context_hunk_separator), &variables)
+                .try_into()
+                .unwrap();
+        let indent = line_number_width.saturating_sub(4);
+        writeln!(formatter, "{:indent$}{separator}", "")?;
+    }
+    show_lines(formatter, before_lines)?;
+    Ok(!skipped_lines.is_empty())
+}
+
+/// Returns variables to be substituted in the line printed in place of
+/// `num_skipped` context lines from `first` to `last`.
+///
+/// * `$count`: number of skipped lines
+/// * `$left_start`, `$left_end`: first and last skipped left line numbers
+/// * `$right_start`, `$right_end`: first and last skipped right line numbers
+fn skipped_context_variables(
+    first: DiffLineNumber,
+    last: DiffLineNumber,
+    num_skipped: usize,
+) -> HashMap<&'static str, String> {
+    maplit::hashmap! {
+        "count" => num_skipped.to_string(),
+        "left_start" => first.left.to_string(),
+        "left_end" => last.left.to_string(),
+        "right_start" => first.right.to_string(),
+        "right_end" => last.right.to_string(),
+    }
+}
+
+/// Returns variables to be substituted in the header line of each file.
+///
+/// * `$description`: description of the change, such as `Modified regular
+///   file`
+/// * `$operation`: `added`, `modified`, or `removed`
+/// * `$type`: type of the file after the change, or before if removed
+/// * `$path`: path of the file, or `{SOURCE => PATH}` part of the path if
+///   copied or renamed and `compact_renames` is enabled
+/// * `$copy_source`: ` (SOURCE => PATH)` if copied or renamed and
+///   `compact_renames` is disabled, otherwise empty. ` (N lines)` is appended
+///   if the content of a deleted file is collapsed.
+fn header_variables(
+    description: String,
+    operation: &str,
+    file_type: &str,
+    path: &str,
+    copy_source: String,
+) -> HashMap<&'static str, String> {
+    maplit::hashmap! {
+        "description" => description,
+        "operation" => operation.to_owned(),
+        "type" => file_type.to_owned(),
+        "path" => path.to_owned(),
+        "copy_source" => copy_source,
+    }
+}
+
+fn write_color_words_header(
+    formatter: &mut dyn Formatter,
+    options: &ColorWordsDiffOptions,
+    variables: &HashMap<&str, String>,
+    link: Option<&str>,
+) -> io::Result<()> {
+    let Some(url) = link.filter(|_| formatter.is_color()) else {
+        let [header]: [String; 1] =
+            interpolate_variables(slice::from_ref(&options.header_template), variables)
+                .try_into()
+                .unwrap();
+        return writeln!(formatter.labeled(DiffLabel::Header), "{header}");
+    };
+    // Substitute a placeholder for the path so the hyperlink can be written
+    // around each occurrence of it.
+    let mut placeholder_variables = variables.clone();
+    placeholder_variables.insert("path", "\0".to_owned());
+    let [header]: [String; 1] = interpolate_variables(
+        slice::from_ref(&options.header_template),
+        &placeholder_variables,
+    )
+    .try_into()
+    .unwrap();
+    formatter.with_label(DiffLabel::Header.as_str(), |formatter| {
+        for (i, part) in header.split('\0').enumerate() {
+            if i > 0 {
+                write_hyperlink(formatter, url, &variables["path"])?;
+            }
+            write!(formatter, "{part}")?;
+        }
+        writeln!(formatter)
+    })
+}
+
+/// Formats `path` for display, as an absolute file system path in the
+/// workspace if `absolute` is set.
+fn format_diff_path(
+    path_converter: &RepoPathUiConverter,
+    path: &RepoPath,
+    absolute: bool,
+) -> String {
+    let RepoPathUiConverter::Fs { base, .. } = path_converter;
+    if absolute {
+        path.to_fs_path(base).to_string_lossy().into_owned()
+    } else {
+        path_converter.format_file_path(path)
+    }
+}
+
+/// Formats the renamed or copied path from `source` to `target` for display,
+/// as an absolute file system path in the workspace if `absolute` is set.
+fn format_diff_copied_path(
+    path_converter: &RepoPathUiConverter,
+    source: &RepoPath,
+    target: &RepoPath,
+    absolute: bool,
+) -> String {
+    let RepoPathUiConverter::Fs { base, .. } = path_converter;
+    if absolute {
+        // Abbreviate the paths relative to the workspace root, which is then
+        // prepended as is.
+        let root_converter = RepoPathUiConverter::Fs {
+            cwd: base.clone(),
+            base: base.clone(),
+        };
+        let path = root_converter.format_copied_path(source, target);
+        base.join(path).to_string_lossy().into_owned()
+    } else {
+        path_converter.format_copied_path(source, target)
+    }
+}
+
+/// Returns the `file://` URL of `path` in the workspace.
+fn workspace_file_url(path_converter: &RepoPathUiConverter, path: &RepoPath) -> String {
+    let RepoPathUiConverter::Fs { base, .. } = path_converter;
+    let fs_path = path.to_fs_path(base);
+    let fs_path = fs_path.to_string_lossy();
+    let mut url = "file://".to_owned();
+    // Windows paths start with a drive letter.
+    if !fs_path.starts_with('/') {
+        url.push('/');
+    }
+    for b in fs_path.bytes() {
+        match b {
+            b'\\' => url.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(b));
+            }
+            _ => url.push_str(&format!("%{b:02X}")),
+        }
+    }
+    url
+}
+
+/// Writes `text` wrapped in an OSC 8 hyperlink to `url`. The formatter is
+/// supposed to pass escape sequences written to `raw()` through.
+fn write_hyperlink(formatter: &mut dyn Formatter, url: &str, text: &str) -> io::Result<()> {
+    write!(formatter.raw(), "\x1b]8;;{url}\x1b\\")?;
+    write!(formatter, "{text}")?;
+    write!(formatter.raw(), "\x1b]8;;\x1b\\")
+}
+
+/// Returns the number of columns of each line number in color-words diff of
+/// `left` and `right`, which is at least 4.
+fn line_number_width(left: &[u8], right: &[u8]) -> usize {
+    let max_line_number = max(num_lines(left), num_lines(right));
+    max(4, max_line_number.to_string().len())
+}
+
+/// Writes the left and right line numbers of `diff_line` right-aligned to
+/// `width` columns, followed by `: `. The line number of the side without
+/// content is left blank.
+fn write_line_number_gutter(
+    formatter: &mut dyn Formatter,
+    diff_line: &DiffLine,
+    width: usize,
+    numbering: DiffLineNumbering,
+) -> io::Result<()> {
+    if numbering == DiffLineNumbering::ContextOnly && !diff_line.is_unmodified() {
+        return write!(formatter, "{:width$} {:width$}: ", "", "");
+    }
+    if diff_line.has_left_content() {
+        formatter.with_label(DiffLabel::Removed.as_str(), |formatter| {
+            write!(
+                formatter.labeled(DiffLabel::LineNumber),
+                "{:>width$}",
+                diff_line.line_number.left
+            )
+        })?;
+    } else {
+        write!(formatter, "{:width$}", "")?;
+    }
+    write!(formatter, " ")?;
+    if diff_line.has_right_content() {
+        formatter.with_label(DiffLabel::Added.as_str(), |formatter| {
+            write!(
+                formatter.labeled(DiffLabel::LineNumber),
+                "{:>width$}",
+                diff_line.line_number.right
+            )
+        })?;
+    } else {
+        write!(formatter, "{:width$}", "")?;
+    }
+    write!(formatter, ": ")
+}
+
+/// Writes `-`, `+`, `~`, or ` ` depending on whether the line is removed,
+/// added, modified, or unchanged.
+fn write_line_sigil(formatter: &mut dyn Formatter, diff_line: &DiffLine) -> io::Result<()> {
+    if diff_line.is_unmodified() {
+        write!(formatter, " ")
+    } else if !diff_line.has_right_content() {
+        write!(formatter.labeled(DiffLabel::Removed), "-")
+    } else if !diff_line.has_left_content() {
+        write!(formatter.labeled(DiffLabel::Added), "+")
+    } else {
+        write!(formatter.labeled(DiffLabel::Modified), "~")
+    }
+}
+
+fn show_color_words_diff_line(
+    formatter: &mut dyn Formatter,
+    diff_line: &DiffLine,
+    line_number_width: usize,
+    options: &ColorWordsDiffOptions,
+) -> io::Result<()> {
+    with_content_formatter(formatter, options.color_scope, |formatter| {
+        write_color_words_diff_line(formatter, diff_line, line_number_width, options)
+    })
+}
+
+fn write_color_words_diff_line(
+    formatter: &mut dyn Formatter,
+    diff_line: &DiffLine,
+    line_number_width: usize,
+    options: &ColorWordsDiffOptions,
+) -> io::Result<()> {
+    if options.sigils {
+        write_line_sigil(formatter, diff_line)?;
+    }
+    write_line_number_gutter(
+        formatter,
+        diff_line,
+        line_number_width,
+        options.line_numbering,
+    )?;
+    // Coalesce adjacent hunks of the same side so that each run is written
+    // under a single label.
+    let runs = diff_line
+        .hunks
+        .iter()
+        .chunk_by(|(side, _)| *side)
+        .into_iter()
+        .map(|(side, run)| {
+            let data: Vec<u8> = run.flat_map(|(_, data)| data.iter().copied()).collect();
+            (side, data)
+        })
+        .collect_vec();
+    let line = runs
+        .iter()
+        .flat_map(|(_, data)| data)
+        .copied()
+        .collect_vec();
+    let whitespace_range = if options.highlight_trailing_whitespace {
+        trailing_whitespace_range(&line)
+    } else {
+        0..0
+    };
+    let tab_widths = expanded_tab_widths(&line, &options.tab_stops);
+    let sigil_width = if options.sigils { 1 } else { 0 };
+    let gutter_width = sigil_width + 2 * line_number_width + ": ".len() + 1;
+    let mut wrap_offsets = options
+        .wrap_width
+        .map(|width| wrap_offsets(&line, max(width.saturating_sub(gutter_width), 1)))
+        .unwrap_or_default()
+        .into_iter()
+        .peekable();
+    let mut offset = 0;
+    for (side, data) in &runs {
+        let label = match side {
+            DiffLineHunkSide::Both => None,
+            DiffLineHunkSide::Left => Some(DiffLabel::Removed),
+            DiffLineHunkSide::Right => Some(DiffLabel::Added),
+        };
+        let end = offset + data.len();
+        let mut start = offset;
+        loop {
+            let piece_end = wrap_offsets.next_if(|&pos| pos < end).unwrap_or(end);
+            let write_data = |formatter: &mut dyn Formatter| {
+                write_line_fragment(
+                    formatter,
+                    &data[start - offset..piece_end - offset],
+                    start,
+                    &whitespace_range,
+                    options.show_whitespace,
+                    &tab_widths,
+                )
+            };
+            if piece_end > start {
+                if let Some(label) = label {
+                    formatter.with_label(label.as_str(), |formatter| {
+                        formatter.with_label(DiffLabel::Token.as_str(), write_data)
+                    })?;
+                } else {
+                    write_data(formatter)?;
+                }
+            }
+            if piece_end == end {
+                break;
+            }
+            // Continue the gutter with blank line numbers
+            writeln!(formatter)?;
+            write!(formatter, "{:width$}: ", "", width = gutter_width - 2)?;
+            start = piece_end;
+        }
+        offset = end;
+    }
+
+    Ok(())
+}
+
+/// Returns byte offsets in `line` at which continuation lines start if the
+/// line is wrapped at `width` display columns.
+fn wrap_offsets(line: &[u8], width: usize) -> Vec<usize> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let mut offsets = vec![];
+    let mut column = 0;
+    for (start, _end, c) in content.char_indices() {
+        let char_width = c.width().unwrap_or(0);
+        if column > 0 && column + char_width > width {
+            offsets.push(start);
+            column = 0;
+        }
+        column += char_width;
+    }
+    offsets
+}
+
+/// Returns the range of trailing spaces and tabs in the `line`, excluding the
+/// line terminator.
+fn trailing_whitespace_range(line: &[u8]) -> Range<usize> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let content = content.strip_suffix(b"\r").unwrap_or(content);
+    let start = content
+        .iter()
+        .rposition(|b| !matches!(b, b' ' | b'\t'))
+        .map_or(0, |pos| pos + 1);
+    start..content.len()
+}
+
+/// Writes `data` located at `offset` within a line, labeling the part that
+/// overlaps `whitespace_range` as `trailing-whitespace`. Tabs listed in
+/// `tab_widths` are expanded. See `expanded_tab_widths()`.
+fn write_line_fragment(
+    formatter: &mut dyn Formatter,
+    data: &[u8],
+    offset: usize,
+    whitespace_range: &Range<usize>,
+    show_whitespace: bool,
+    tab_widths: &[(usize, usize)],
+) -> io::Result<()> {
+    let to_local = |pos: usize| pos.clamp(offset, offset + data.len()) - offset;
+    let start = to_local(whitespace_range.start);
+    let end = to_local(whitespace_range.end);
+    let write_content = |formatter: &mut dyn Formatter, range: Range<usize>| {
+        write_line_content(
+            formatter,
+            &data[range.clone()],
+            offset + range.start,
+            show_whitespace,
+            tab_widths,
+        )
+    };
+    if start == end {
+        return write_content(formatter, 0..data.len());
+    }
+    if start > 0 {
+        write_content(formatter, 0..start)?;
+    }
+    formatter.with_label(DiffLabel::TrailingWhitespace.as_str(), |formatter| {
+        write_content(formatter, start..end)
+    })?;
+    if end < data.len() {
+        write_content(formatter, end..data.len())?;
+    }
+    Ok(())
+}
+
+/// Writes `data` located at `offset` within a line, replacing each space and
+/// tab with a single-column glyph labeled as `whitespace` if `show_whitespace`
+/// is set. Tabs listed in `tab_widths` are padded with spaces to their
+/// expanded width.
+fn write_line_content(
+    formatter: &mut dyn Formatter,
+    data: &[u8],
+    offset: usize,
+    show_whitespace: bool,
+    tab_widths: &[(usize, usize)],
+) -> io::Result<()> {
+    if !show_whitespace && tab_widths.is_empty() {
+        return formatter.write_all(data);
+    }
+    let mut start = 0;
+    for (pos, &byte) in data.iter().enumerate() {
+        let tab_width = if byte == b'\t' {
+            tab_widths
+                .binary_search_by_key(&(offset + pos), |&(tab_pos, _)| tab_pos)
+                .ok()
+                .map(|index| tab_widths[index].1)
+        } else {
+            None
+        };
+        let is_whitespace = show_whitespace && matches!(byte, b' ' | b'\t');
+        if !is_whitespace && tab_width.is_none() {
+            continue;
+        }
+        formatter.write_all(&data[start..pos])?;
+        let mut padding = tab_width.unwrap_or(1);
+        if is_whitespace {
+            let glyph = if byte == b' ' { "·" } else { "→" };
+            write!(formatter.labeled(DiffLabel::Whitespace), "{glyph}")?;
+            padding -= 1;
+        }
+        write!(formatter, "{:padding$}", "")?;
+        start = pos + 1;
+    }
+    formatter.write_all(&data[start..])
+}
+
+/// Returns the byte position and display width of each tab in `line` if tabs
+/// are expanded to the next of `tab_stops`. Returns nothing if `tab_stops` is
+/// empty.
+fn expanded_tab_widths(line: &[u8], tab_stops: &[usize]) -> Vec<(usize, usize)> {
+    if tab_stops.is_empty() {
+        return vec![];
+    }
+    let mut tab_widths = vec![];
+    let mut column = 0;
+    for (start, _end, c) in line.char_indices() {
+        if c == '\t' {
+            let stop = next_tab_stop(column, tab_stops);
+            tab_widths.push((start, stop - column));
+            column = stop;
+        } else {
+            column += c.width().unwrap_or(0);
+        }
+    }
+    tab_widths
+}
+
+/// Returns the first of `tab_stops` past `column`. Beyond the last stop, stops
+/// repeat at the interval between the last two stops, or at the last stop if
+/// there's only one.
+fn next_tab_stop(column: usize, tab_stops: &[usize]) -> usize {
+    if let Some(&stop) = tab_stops.iter().find(|&&stop| stop > column) {
+        return stop;
+    }
+    let (interval, last) = match tab_stops {
+        [.., prev, last] => (last - prev, *last),
+        [last] => (*last, *last),
+        [] => panic!("tab stops must not be empty"),
+    };
+    last + ((column - last) / interval + 1) * interval
+}
+
+struct FileContent {
+    /// false if this file is likely text; true if it is likely binary.
+    is_binary: bool,
+    contents: Vec<u8>,
+}
+
+impl FileContent {
+    fn empty() -> Self {
+        Self {
+            is_binary: false,
+            contents: vec![],
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+}
+
+/// Returns true if the `contents` is likely binary.
+fn is_binary_content(contents: &[u8]) -> bool {
+    // Determine whether it's binary by whether the first 8k bytes contain a null
+    // character; this is the same heuristic used by git as of writing: https://github.com/git/git/blob/eea0e59ffbed6e33d171ace5be13cde9faa41639/xdiff-interface.c#L192-L198
+    const PEEK_SIZE: usize = 8000;
+    let start = &contents[..PEEK_SIZE.min(contents.len())];
+    start.contains(&b'\0')
+}
+
+fn file_content_for_diff(reader: &mut dyn io::Read) -> io::Result<FileContent> {
+    // If this is a binary file, don't show the full contents.
+    // TODO: currently we look at the whole file, even though for binary files we
+    // only need to know the file size. To change that we'd have to extend all
+    // the data backends to support getting the length.
+    let mut contents = vec![];
+    reader.read_to_end(&mut contents)?;
+    Ok(FileContent {
+        is_binary: is_binary_content(&contents),
+        contents,
+    })
+}
+
+fn diff_content(path: &RepoPath, value: MaterializedTreeValue) -> io::Result<FileContent> {
+    match value {
+        MaterializedTreeValue::Absent => Ok(FileContent::empty()),
+        MaterializedTreeValue::AccessDenied(err) => Ok(FileContent {
+            is_binary: false,
+            contents: format!("Access denied: {err}").into_bytes(),
+        }),
+        MaterializedTreeValue::File { mut reader, .. } => {
+            file_content_for_diff(&mut reader).map_err(Into::into)
+        }
+        MaterializedTreeValue::Symlink { id: _, target } => Ok(FileContent {
+            // Unix file paths can't contain null bytes.
+            is_binary: false,
+            contents: target.into_bytes(),
+        }),
+        MaterializedTreeValue::GitSubmodule(id) => Ok(FileContent {
+            is_binary: false,
+            contents: format!("Git submodule checked out at {}", id.hex()).into_bytes(),
+        }),
+        MaterializedTreeValue::Conflict {
+            id: _,
+            contents,
+            executable: _,
+        } => Ok(FileContent {
+            // Conflict markers don't hide binary data of the sides.
+            is_binary: is_binary_content(&contents),
+            contents,
+        }),
+        MaterializedTreeValue::Tree(id) => {
+            panic!("Unexpected tree with id {id:?} in diff at path {path:?}");
+        }
+    }
+}
+
+/// Writes a note in place of the color-words header and contents of a
+/// generated file.
+fn write_skipped_generated_file(formatter: &mut dyn Formatter, path: &str) -> io::Result<()> {
+    writeln!(
+        formatter.labeled(DiffLabel::Header),
+        "Skipped generated file {path}"
+    )
+}
+
+fn basic_diff_file_type(value: &MaterializedTreeValue) -> &'static str {
+    match value {
+        MaterializedTreeValue::Absent => {
+            panic!("absent path in diff");
+        }
+        MaterializedTreeValue::AccessDenied(_) => "access denied",
+        MaterializedTreeValue::File { executable, .. } => {
+            if *executable {
+                "executable file"
+            } else {
+                "regular file"
+            }
+        }
+        MaterializedTreeValue::Symlink { .. } => "symlink",
+        MaterializedTreeValue::Tree(_) => "tree",
+        MaterializedTreeValue::GitSubmodule(_) => "Git submodule",
+        MaterializedTreeValue::Conflict { .. } => "conflict",
+    }
+}
+
+pub fn show_color_words_diff(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    to_tree: &MergedTree,
+    tree_diff: TreeDiffStream,
+    path_converter: &RepoPathUiConverter,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+    options: &ColorWordsDiffOptions,
+) -> Result<(), DiffRenderError> {
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+    let mut has_entries = false;
+    async {
+        while let Some(MaterializedTreeDiffEntry {
+            source: left_path,
+            target: right_path,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            has_entries = true;
+            let options: &ColorWordsDiffOptions = &options.for_path(&right_path);
+            let left_ui_path = format_diff_path(path_converter, &left_path, options.absolute_paths);
+            let right_ui_path =
+                format_diff_path(path_converter, &right_path, options.absolute_paths);
+            let link = options
+                .hyperlinks
+                .then(|| workspace_file_url(path_converter, &right_path));
+            let (left_value, right_value) = diff?;
+            // Skip the "delete" entry when there is a rename.
+            if right_value.is_absent() && copied_sources.contains(left_path.as_ref()) {
+                continue;
+            }
+            start_file_profile(options.profile.as_ref(), &left_path, &right_path);
+            let variables_for = |description: String, operation: &str, file_type: &str| {
+                let (path, copy_source) = if left_path == right_path {
+                    (right_ui_path.clone(), String::new())
+                } else if options.compact_renames {
+                    let path = format_diff_copied_path(
+                        path_converter,
+                        &left_path,
+                        &right_path,
+                        options.absolute_paths,
+                    );
+                    (path, String::new())
+                } else {
+                    let copy_source = format!(" ({left_ui_path} => {right_ui_path})");
+                    (right_ui_path.clone(), copy_source)
+                };
+                header_variables(description, operation, file_type, &path, copy_source)
+            };
+
+            match (&left_value, &right_value) {
+                (MaterializedTreeValue::AccessDenied(source), _) => {
+                    write!(
+                        formatter.labeled(DiffLabel::AccessDenied),
+                        "Access denied to {left_ui_path}:"
+                    )?;
+                    writeln!(formatter, " {source}")?;
+                    continue;
+                }
+                (_, MaterializedTreeValue::AccessDenied(source)) => {
+                    write!(
+                        formatter.labeled(DiffLabel::AccessDenied),
+                        "Access denied to {right_ui_path}:"
+                    )?;
+                    writeln!(formatter, " {source}")?;
+                    continue;
+                }
+                _ => {}
+            }
+            let conflict_terms = match &right_value {
+                MaterializedTreeValue::Conflict { id, .. } if options.conflict_base => {
+                    Some(id.clone())
+                }
+                _ => None,
+            };
+            if left_value.is_absent() {
+                let file_type = basic_diff_file_type(&right_value);
+                let variables = variables_for(format!("Added {file_type}"), "added", file_type);
+                let right_content = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                    diff_content(&right_path, right_value)
+                })?;
+                if options.skip_binary && right_content.is_binary {
+                    continue;
+                }
+                if options
+                    .generated_markers
+                    .as_deref()
+                    .is_some_and(|markers| has_generated_marker(&right_content.contents, markers))
+                {
+                    write_skipped_generated_file(formatter, &right_ui_path)?;
+                    continue;
+                }
+                write_color_words_header(formatter, options, &variables, link.as_deref())?;
+                if right_content.is_empty() {
+                    writeln!(formatter.labeled(DiffLabel::Empty), "    (empty)")?;
+                } else if right_content.is_binary {
+                    show_color_words_binary(formatter, &[], &right_content.contents, options)?;
+                } else {
+                    show_color_words_diff_hunks(
+                        &[],
+                        &right_content.contents,
+                        options,
+                        options.word_tokenizer.for_path(&right_path),
+                        formatter,
+                    )?;
+                }
+            } else if right_value.is_present() {
+                let is_rename = left_path != right_path
+                    && to_tree.path_value(&left_path)?.is_absent()
+                    && matches!(
+                        (&left_value, &right_value),
+                        (
+                            MaterializedTreeValue::File { executable: left_executable, .. },
+                            MaterializedTreeValue::File { executable: right_executable, .. },
+                        ) if left_executable == right_executable
+                    );
+                let is_modified_in_place = left_path == right_path
+                    && matches!(
+                        (&left_value, &right_value),
+                        (
+                            MaterializedTreeValue::File { executable: left_executable, .. },
+                            MaterializedTreeValue::File { executable: right_executable, .. },
+                        ) if left_executable == right_executable
+                    );
+                let mut description = match (&left_value, &right_value) {
+                    (
+                        MaterializedTreeValue::File {
+                            executable: left_executable,
+                            ..
+                        },
+                        MaterializedTreeValue::File {
+                            executable: right_executable,
+                            ..
+                        },
+                    ) => {
+                        if *left_executable && *right_executable {
+                            "Modified executable file".to_string()
+                        } else if *left_executable {
+                            "Executable file became non-executable at".to_string()
+                        } else if *right_executable {
+                            "Non-executable file became executable at".to_string()
+                        } else {
+                            "Modified regular file".to_string()
+                        }
+                    }
+                    (
+                        MaterializedTreeValue::Conflict { .. },
+                        MaterializedTreeValue::Conflict { .. },
+                    ) => "Modified conflict in".to_string(),
+                    (MaterializedTreeValue::Conflict { .. }, _) => {
+                        "Resolved conflict in".to_string()
+                    }
+                    (_, MaterializedTreeValue::Conflict { .. }) => {
+                        "Created conflict in".to_string()
+                    }
+                    (
+                        MaterializedTreeValue::Symlink { .. },
+                        MaterializedTreeValue::Symlink { .. },
+                    ) => "Symlink target changed at".to_string(),
+                    (_, _) => {
+                        let left_type = basic_diff_file_type(&left_value);
+                        let right_type = basic_diff_file_type(&right_value);
+                        let (first, rest) = left_type.split_at(1);
+                        format!(
+                            "{}{} became {} at",
+                            first.to_ascii_uppercase(),
+                            rest,
+                            right_type
+                        )
+                    }
+                };
+                let file_type = basic_diff_file_type(&right_value);
+                let left_content = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                    diff_content(&left_path, left_value)
+                })?;
+                let right_content = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                    diff_content(&right_path, right_value)
+                })?;
+                let is_binary = left_content.is_binary || right_content.is_binary;
+                if options.skip_binary && is_binary {
+                    continue;
+                }
+                if options.generated_markers.as_deref().is_some_and(|markers| {
+                    has_generated_marker(&left_content.contents, markers)
+                        || has_generated_marker(&right_content.contents, markers)
+                }) {
+                    write_skipped_generated_file(formatter, &right_ui_path)?;
+                    continue;
+                }
+                // Skip the file if its changes are all ignored.
+                if is_modified_in_place
+                    && options.has_only_ignored_changes(&left_content, &right_content)
+                {
+                    continue;
+                }
+                let is_pure_rename = is_rename && left_content.contents == right_content.contents;
+                if is_pure_rename {
+                    description = format!("Renamed {file_type}");
+                } else if is_rename {
+                    description = format!("Renamed and modified {file_type}");
+                }
+                let variables = variables_for(description, "modified", file_type);
+                write_color_words_header(formatter, options, &variables, link.as_deref())?;
+                if is_pure_rename {
+                    // The content is identical, so there's nothing to show.
+                } else if is_binary {
+                    show_color_words_binary(
+                        formatter,
+                        &left_content.contents,
+                        &right_content.contents,
+                        options,
+                    )?;
+                } else {
+                    show_color_words_diff_hunks(
+                        &left_content.contents,
+                        &right_content.contents,
+                        options,
+                        options.word_tokenizer.for_path(&right_path),
+                        formatter,
+                    )?;
+                }
+            } else {
+                let file_type = basic_diff_file_type(&left_value);
+                let mut variables =
+                    variables_for(format!("Removed {file_type}"), "removed", file_type);
+                let left_content = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                    diff_content(&left_path, left_value)
+                })?;
+                if options.skip_binary && left_content.is_binary {
+                    continue;
+                }
+                if options
+                    .generated_markers
+                    .as_deref()
+                    .is_some_and(|markers| has_generated_marker(&left_content.contents, markers))
+                {
+                    write_skipped_generated_file(formatter, &left_ui_path)?;
+                    continue;
+                }
+                if options.collapse_deletions && !left_content.is_empty() && !left_content.is_binary
+                {
+                    let line_count = num_lines(&left_content.contents);
+                    let plural = if line_count == 1 { "" } else { "s" };
+                    let copy_source = variables.get_mut("copy_source").unwrap();
+                    copy_source.push_str(&format!(" ({line_count} line{plural})"));
+                    write_color_words_header(formatter, options, &variables, None)?;
+                    continue;
+                }
+                write_color_words_header(formatter, options, &variables, None)?;
+                if left_content.is_empty() {
+                    writeln!(formatter.labeled(DiffLabel::Empty), "    (empty)")?;
+                } else if left_content.is_binary {
+                    show_color_words_binary(formatter, &left_content.contents, &[], options)?;
+                } else {
+                    show_color_words_diff_hunks(
+                        &left_content.contents,
+                        &[],
+                        options,
+                        options.word_tokenizer.for_path(&left_path),
+                        formatter,
+                    )?;
+                }
+            }
+            if let Some(id) = &conflict_terms {
+                show_color_words_conflict_base(
+                    formatter,
+                    store,
+                    &right_path,
+                    &right_ui_path,
+                    id,
+                    options,
+                )
+                .await?;
+            }
+        }
+        if options.show_no_changes && !has_entries {
+            writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
+        }
+        Ok(())
+    }
+    .block_on()
+}
+
+/// Renders color-words diff of files pointed to by symlinks which are
+/// unchanged between `from_tree` and `to_tree`. Only targets changed between
+/// the trees and not shown by the normal diff are rendered, each once.
+fn show_color_words_symlink_target_diff(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    path_converter: &RepoPathUiConverter,
+    options: &ColorWordsDiffOptions,
+) -> Result<(), DiffRenderError> {
+    let copy_records = CopyRecords::default();
+    let tree_diff = from_tree.diff_stream(to_tree, &EverythingMatcher, &copy_records);
+    let changed_paths = collect_changed_paths(tree_diff);
+    // Changed paths matching the `matcher` are already shown by the normal diff.
+    let mut rendered_paths: HashSet<&RepoPathBuf> = changed_paths
+        .iter()
+        .filter(|path| matcher.matches(path))
+        .collect();
+    if rendered_paths.len() == changed_paths.len() {
+        return Ok(());
+    }
+    for (path, value) in to_tree.entries_matching(matcher) {
+        let value = value?;
+        if !matches!(value.as_resolved(), Some(Some(TreeValue::Symlink(_)))) {
+            continue;
+        }
+        if changed_paths.contains(&path) {
+            // Changed symlinks are already shown by the normal diff.
+            continue;
+        }
+        let Some((right_path, right_value)) = resolve_symlink_target(to_tree, &path)? else {
+            continue;
+        };
+        let Some(right_path) = changed_paths.get(&right_path) else {
+            continue;
+        };
+        if !rendered_paths.insert(right_path) {
+            continue;
+        }
+        let Some((left_path, left_value)) = resolve_symlink_target(from_tree, &path)? else {
+            continue;
+        };
+        if left_value == right_value || left_value.is_tree() || right_value.is_tree() {
+            continue;
+        }
+        let left_value = materialize_tree_value(store, &left_path, left_value).block_on()?;
+        let right_value = materialize_tree_value(store, right_path, right_value).block_on()?;
+        let left_content = diff_content(&left_path, left_value)?;
+        let right_content = diff_content(right_path, right_value)?;
+        let ui_path = format_diff_path(path_converter, &path, options.absolute_paths);
+        let target_ui_path = format_diff_path(path_converter, right_path, options.absolute_paths);
+        writeln!(
+            formatter.labeled(DiffLabel::Header),
+            "Modified symlink target {ui_path} (=> {target_ui_path}):"
+        )?;
+        if left_content.is_binary || right_content.is_binary {
+            show_color_words_binary(
+                formatter,
+                &left_content.contents,
+                &right_content.contents,
+                options,
+            )?;
+        } else {
+            show_color_words_diff_hunks(
+                &left_content.contents,
+                &right_content.contents,
+                options,
+                options.word_tokenizer.for_path(right_path),
+                formatter,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Shows a note in place of the binary `left` and `right` contents, or the
+/// first region where they differ as hexdump if enabled.
+fn show_color_words_binary(
+    formatter: &mut dyn Formatter,
+    left: &[u8],
+    right: &[u8],
+    options: &ColorWordsDiffOptions,
+) -> io::Result<()> {
+    let Some(window) = options.binary_hexdump else {
+        return writeln!(formatter.labeled(DiffLabel::Binary), "    (binary)");
+    };
+    let start = iter::zip(left, right).take_while(|(l, r)| l == r).count();
+    let left_rest = &left[start..(start + window).min(left.len())];
+    let right_rest = &right[start..(start + window).min(right.len())];
+    let diff = Diff::for_tokenizer([left_rest, right_rest], |text| {
+        (0..text.len()).map(|i| i..i + 1).collect()
+    });
+    let (left_len, right_len) = match diff.hunks().next() {
+        Some(DiffHunk::Different(contents)) => (contents[0].len(), contents[1].len()),
+        // The contents are identical, and only the file type changed.
+        _ => return writeln!(formatter.labeled(DiffLabel::Binary), "    (binary)"),
+    };
+    writeln!(
+        formatter.labeled(DiffLabel::Binary),
+        "    Binary contents differ at offset {start:#x}:"
+    )?;
+    write_hexdump_rows(
+        formatter,
+        DiffLabel::Removed,
+        '-',
+        left,
+        start..start + left_len,
+    )?;
+    write_hexdump_rows(
+        formatter,
+        DiffLabel::Added,
+        '+',
+        right,
+        start..start + right_len,
+    )?;
+    Ok(())
+}
+
+/// Prints the rows of `content` covering the `changed` range as hexdump, with
+/// the changed bytes labeled as tokens.
+fn write_hexdump_rows(
+    formatter: &mut dyn Formatter,
+    label: DiffLabel,
+    sigil: char,
+    content: &[u8],
+    changed: Range<usize>,
+) -> io::Result<()> {
+    let rows_start = changed.start / HEXDUMP_ROW_BYTES * HEXDUMP_ROW_BYTES;
+    let rows_end = max(changed.end, changed.start + 1).min(content.len());
+    for row_start in (rows_start..rows_end).step_by(HEXDUMP_ROW_BYTES) {
+        let row = &content[row_start..(row_start + HEXDUMP_ROW_BYTES).min(content.len())];
+        formatter.with_label(label.as_str(), |formatter| {
+            write!(formatter, "    {sigil}{row_start:08x}:")?;
+            for (i, byte) in row.iter().enumerate() {
+                if changed.contains(&(row_start + i)) {
+                    write!(formatter, " ")?;
+                    write!(formatter.labeled(DiffLabel::Token), "{byte:02x}")?;
+                } else {
+                    write!(formatter, " {byte:02x}")?;
+                }
+            }
+            let padding = "   ".repeat(HEXDUMP_ROW_BYTES - row.len());
+            let ascii: String = row
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        char::from(b)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            writeln!(formatter, "{padding}  |{ascii}|")
+        })?;
+    }
+    Ok(())
+}
+
+/// Prints changes from the base to each side of the conflict `id` at `path`.
+///
+/// Conflicts involving non-file values are skipped.
+async fn show_color_words_conflict_base(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    path: &RepoPath,
+    ui_path: &str,
+    id: &MergedTreeValue,
+    options: &ColorWordsDiffOptions,
+) -> Result<(), DiffRenderError> {
+    let Some(file_merge) = id.to_file_merge() else {
+        return Ok(());
+    };
+    let contents = extract_as_single_hunk(&file_merge, store, path).await?;
+    let num_bases = contents.removes().len();
+    for (i, side) in contents.adds().enumerate() {
+        // Side #1 and #2 are based on the first base, and so on.
+        let base_index = i.saturating_sub(1);
+        let base = contents.get_remove(base_index).unwrap();
+        let base_name = if num_bases == 1 {
+            "base".to_owned()
+        } else {
+            format!("base #{}", base_index + 1)
+        };
+        writeln!(
+            formatter.labeled(DiffLabel::Header),
+            "Changes from {base_name} to side #{} of conflict in {ui_path}:",
+            i + 1
+        )?;
+        if is_binary_content(&base.0) || is_binary_content(&side.0) {
+            show_color_words_binary(formatter, &base.0, &side.0, options)?;
+        } else {
+            show_color_words_diff_hunks(
+                &base.0,
+                &side.0,
+                options,
+                options.word_tokenizer.for_path(path),
+                formatter,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Follows symlinks starting at `path` within the `tree`, and returns the path
+/// and value of the first entry that isn't a symlink.
+///
+/// Returns `None` if a symlink points outside of the tree, or if symlinks form
+/// a cycle.
+fn resolve_symlink_target(
+    tree: &MergedTree,
+    path: &RepoPath,
+) -> Result<Option<(RepoPathBuf, MergedTreeValue)>, BackendError> {
+    let mut visited = HashSet::new();
+    let mut path = path.to_owned();
+    loop {
+        let value = tree.path_value(&path)?;
+        let Some(Some(TreeValue::Symlink(id))) = value.as_resolved() else {
+            return Ok(Some((path, value)));
+        };
+        if !visited.insert(path.clone()) {
+            return Ok(None);
+        }
+        let target = tree.store().read_symlink(&path, id)?;
+        match join_symlink_target(&path, &target) {
+            Some(target_path) => path = target_path,
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Resolves symlink `target` relative to the directory containing `link_path`.
+fn join_symlink_target(link_path: &RepoPath, target: &str) -> Option<RepoPathBuf> {
+    if target.starts_with('/') {
+        return None;
+    }
+    let mut components = link_path
+        .parent()?
+        .components()
+        .map(|component| component.as_str())
+        .collect_vec();
+    for name in target.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            _ => components.push(name),
+        }
+    }
+    if components.is_empty() {
+        return None;
+    }
+    Some(RepoPathBuf::from_internal_string(components.join("/")))
+}
+
+/// Renders color-words diff of two arbitrary buffers without a repository.
+///
+/// `left_name` and `right_name` are displayed in the header as is.
+pub fn show_color_words_contents_diff(
+    formatter: &mut dyn Formatter,
+    left_name: &str,
+    left: &[u8],
+    right_name: &str,
+    right: &[u8],
+    options: &ColorWordsDiffOptions,
+) -> io::Result<()> {
+    let left_content = file_content_for_diff(&mut &*left)?;
+    let right_content = file_content_for_diff(&mut &*right)?;
+    if left_content.contents == right_content.contents {
+        return Ok(());
+    }
+    if left_name == right_name {
+        writeln!(
+            formatter.labeled(DiffLabel::Header),
+            "Modified {right_name}:"
+        )?;
+    } else {
+        writeln!(
+            formatter.labeled(DiffLabel::Header),
+            "Modified {right_name} ({left_name} => {right_name}):"
+        )?;
+    }
+    if left_content.is_binary || right_content.is_binary {
+        show_color_words_binary(
+            formatter,
+            &left_content.contents,
+            &right_content.contents,
+            options,
+        )?;
+    } else {
+        show_color_words_diff_hunks(
+            &left_content.contents,
+            &right_content.contents,
+            options,
+            options.word_tokenizer.for_file_name(right_name),
+            formatter,
+        )?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn show_file_by_file_diff(
+    ui: &Ui,
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    tree_diff: TreeDiffStream,
+    path_converter: &RepoPathUiConverter,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+    tool: &ExternalMergeTool,
+) -> Result<(), DiffRenderError> {
+    fn create_file(
+        path: &RepoPath,
+        wc_dir: &Path,
+        value: MaterializedTreeValue,
+    ) -> Result<PathBuf, DiffRenderError> {
+        let fs_path = path.to_fs_path(wc_dir);
+        std::fs::create_dir_all(fs_path.parent().unwrap())?;
+        let content = diff_content(path, value)?;
+        std::fs::write(&fs_path, content.contents)?;
+        Ok(fs_path)
+    }
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+
+    let temp_dir = new_utf8_temp_dir("jj-diff-")?;
+    let left_wc_dir = temp_dir.path().join("left");
+    let right_wc_dir = temp_dir.path().join("right");
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+    async {
+        while let Some(MaterializedTreeDiffEntry {
+            source: left_path,
+            target: right_path,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            let (left_value, right_value) = diff?;
+            if right_value.is_absent() && copied_sources.contains(left_path.as_ref()) {
+                continue;
+            }
+
+            let left_ui_path = path_converter.format_file_path(&left_path);
+            let right_ui_path = path_converter.format_file_path(&right_path);
+
+            match (&left_value, &right_value) {
                 (_, MaterializedTreeValue::AccessDenied(source)) => {
                     write!(
-                        formatter.labeled("access-denied"),
+                        formatter.labeled(DiffLabel::AccessDenied),
                         "Access denied to {right_ui_path}:"
                     )?;
                     writeln!(formatter, " {source}")?;
                     continue;
                 }
-                _ => {}
-            }
-            if left_value.is_absent() {
-                let description = basic_diff_file_type(&right_value);
-                writeln!(
-                    formatter.labeled("header"),
-                    "Added {description} {right_ui_path}:"
-                )?;
-                let right_content = diff_content(&right_path, right_value)?;
-                if right_content.is_empty() {
-                    writeln!(formatter.labeled("empty"), "    (empty)")?;
-                } else if right_content.is_binary {
-                    writeln!(formatter.labeled("binary"), "    (binary)")?;
+                (MaterializedTreeValue::AccessDenied(source), _) => {
+                    write!(
+                        formatter.labeled(DiffLabel::AccessDenied),
+                        "Access denied to {left_ui_path}:"
+                    )?;
+                    writeln!(formatter, " {source}")?;
+                    continue;
+                }
+                _ => {}
+            }
+            let left_path = create_file(&left_path, &left_wc_dir, left_value)?;
+            let right_path = create_file(&right_path, &right_wc_dir, right_value)?;
+
+            invoke_external_diff(
+                ui,
+                formatter.raw(),
+                tool,
+                &maplit::hashmap! {
+                    "left" => left_path.to_str().expect("temp_dir should be valid utf-8"),
+                    "right" => right_path.to_str().expect("temp_dir should be valid utf-8"),
+                },
+            )
+            .map_err(DiffRenderError::from_diff_generate)?;
+        }
+        Ok::<(), DiffRenderError>(())
+    }
+    .block_on()
+}
+
+struct GitDiffPart {
+    /// Octal mode string or `None` if the file is absent.
+    mode: Option<&'static str>,
+    hash: String,
+    content: FileContent,
+}
+
+fn git_diff_part(
+    path: &RepoPath,
+    value: MaterializedTreeValue,
+) -> Result<GitDiffPart, DiffRenderError> {
+    const DUMMY_HASH: &str = "0000000000000000000000000000000000000000";
+    let mode = git_file_mode(&value);
+    let hash;
+    let content;
+    match value {
+        MaterializedTreeValue::Absent => {
+            return Ok(GitDiffPart {
+                mode: None,
+                hash: DUMMY_HASH.to_owned(),
+                content: FileContent::empty(),
+            });
+        }
+        MaterializedTreeValue::AccessDenied(err) => {
+            return Err(DiffRenderError::AccessDenied {
+                path: path.as_internal_file_string().to_owned(),
+                source: err,
+            });
+        }
+        MaterializedTreeValue::File { id, mut reader, .. } => {
+            hash = id.hex();
+            content = file_content_for_diff(&mut reader)?;
+        }
+        MaterializedTreeValue::Symlink { id, target } => {
+            hash = id.hex();
+            content = FileContent {
+                // Unix file paths can't contain null bytes.
+                is_binary: false,
+                contents: target.into_bytes(),
+            };
+        }
+        MaterializedTreeValue::GitSubmodule(id) => {
+            // Emulate Git's output for gitlinks.
+            hash = id.hex();
+            content = FileContent {
+                is_binary: false,
+                contents: format!("Subproject commit {hash}\n").into_bytes(),
+            };
+        }
+        MaterializedTreeValue::Conflict { contents, .. } => {
+            hash = DUMMY_HASH.to_owned();
+            content = FileContent {
+                is_binary: is_binary_content(&contents),
+                contents,
+            };
+        }
+        MaterializedTreeValue::Tree(_) => {
+            panic!("Unexpected tree in diff at path {path:?}");
+        }
+    }
+    Ok(GitDiffPart {
+        mode,
+        hash,
+        content,
+    })
+}
+
+/// Returns the Git file mode of `value`, or `None` if there's no file.
+fn git_file_mode(value: &MaterializedTreeValue) -> Option<&'static str> {
+    match value {
+        MaterializedTreeValue::Absent
+        | MaterializedTreeValue::AccessDenied(_)
+        | MaterializedTreeValue::Tree(_) => None,
+        MaterializedTreeValue::File { executable, .. }
+        | MaterializedTreeValue::Conflict { executable, .. } => {
+            Some(if *executable { "100755" } else { "100644" })
+        }
+        MaterializedTreeValue::Symlink { .. } => Some("120000"),
+        MaterializedTreeValue::GitSubmodule(_) => Some("160000"),
+    }
+}
+
+fn abbreviate_hash(hash: &str, abbrev: Option<usize>) -> &str {
+    match abbrev {
+        Some(len) if len < hash.len() => &hash[..len],
+        _ => hash,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiffLineType {
+    Context,
+    Removed,
+    Added,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiffTokenType {
+    Matching,
+    Different,
+}
+
+type DiffTokenVec<'content> = Vec<(DiffTokenType, &'content [u8])>;
+
+struct UnifiedDiffHunk<'content> {
+    left_line_range: Range<usize>,
+    right_line_range: Range<usize>,
+    lines: Vec<(DiffLineType, DiffTokenVec<'content>)>,
+}
+
+impl<'content> UnifiedDiffHunk<'content> {
+    fn extend_context_lines(&mut self, lines: impl IntoIterator<Item = &'content [u8]>) {
+        let old_len = self.lines.len();
+        self.lines.extend(lines.into_iter().map(|line| {
+            let tokens = vec![(DiffTokenType::Matching, line)];
+            (DiffLineType::Context, tokens)
+        }));
+        self.left_line_range.end += self.lines.len() - old_len;
+        self.right_line_range.end += self.lines.len() - old_len;
+    }
+
+    fn extend_removed_lines(&mut self, lines: impl IntoIterator<Item = DiffTokenVec<'content>>) {
+        let old_len = self.lines.len();
+        self.lines
+            .extend(lines.into_iter().map(|line| (DiffLineType::Removed, line)));
+        self.left_line_range.end += self.lines.len() - old_len;
+    }
+
+    fn extend_added_lines(&mut self, lines: impl IntoIterator<Item = DiffTokenVec<'content>>) {
+        let old_len = self.lines.len();
+        self.lines
+            .extend(lines.into_iter().map(|line| (DiffLineType::Added, line)));
+        self.right_line_range.end += self.lines.len() - old_len;
+    }
+
+    /// Returns the number of removed lines if the added lines are the same
+    /// lines in a different order.
+    fn num_reordered_lines(&self) -> Option<usize> {
+        let sorted_lines = |target_type: DiffLineType| {
+            self.lines
+                .iter()
+                .filter(|(line_type, _)| *line_type == target_type)
+                .map(|(_, tokens)| {
+                    let line = tokens.iter().flat_map(|(_, content)| *content);
+                    line.copied().collect_vec()
+                })
+                .sorted()
+                .collect_vec()
+        };
+        let removed = sorted_lines(DiffLineType::Removed);
+        let added = sorted_lines(DiffLineType::Added);
+        (!removed.is_empty() && removed == added).then_some(removed.len())
+    }
+
+    /// Returns true if all removed and added lines are blank.
+    fn has_only_blank_changes(&self) -> bool {
+        self.lines
+            .iter()
+            .filter(|(line_type, _)| *line_type != DiffLineType::Context)
+            .all(|(_, tokens)| tokens.iter().all(|(_, content)| is_blank(content)))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn unified_diff_hunks<'content>(
+    left_content: &'content [u8],
+    right_content: &'content [u8],
+    num_context_lines: usize,
+    change_order: DiffChangeOrder,
+    function_regex: Option<&FunctionLineRegex>,
+    word_tokenizer: DiffWordTokenizer,
+    ignore_case: bool,
+    ignore_lines_matching: Option<&IgnoredLineRegex>,
+    word_diff_max_lines: Option<usize>,
+    profile: Option<&DiffProfile>,
+) -> Vec<UnifiedDiffHunk<'content>> {
+    let function_bounds = function_regex.map(|regex| FunctionBounds::new(left_content, regex));
+    let mut hunks = vec![];
+    let mut current_hunk = UnifiedDiffHunk {
+        left_line_range: 1..1,
+        right_line_range: 1..1,
+        lines: vec![],
+    };
+    let mut diff_hunks = timed(profile, DiffStep::LineDiff, || {
+        line_diff_hunks(
+            [left_content, right_content],
+            ignore_case,
+            ignore_lines_matching,
+        )
+    })
+    .into_iter()
+    .peekable();
+    while let Some(hunk) = diff_hunks.next() {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                let mut lines = content.split_inclusive(|b| *b == b'\n').fuse();
+                let (num_after_lines, num_before_lines) = if let Some(bounds) = &function_bounds {
+                    // Index of the first line of this hunk in the left content
+                    let start = current_hunk.left_line_range.end - 1;
+                    let end = start + content.split_inclusive(|b| *b == b'\n').count();
+                    (
+                        max(num_context_lines, bounds.num_lines_after(start)),
+                        max(num_context_lines, bounds.num_lines_before(end)),
+                    )
+                } else {
+                    (num_context_lines, num_context_lines)
+                };
+                if !current_hunk.lines.is_empty() {
+                    // The previous hunk line should be either removed/added.
+                    current_hunk.extend_context_lines(lines.by_ref().take(num_after_lines));
+                }
+                let before_lines = if diff_hunks.peek().is_some() {
+                    lines.by_ref().rev().take(num_before_lines).collect()
                 } else {
-                    show_color_words_diff_hunks(
-                        &[],
-                        &right_content.contents,
-                        num_context_lines,
-                        formatter,
-                    )?;
+                    vec![] // No more hunks
+                };
+                let num_skip_lines = lines.count();
+                if num_skip_lines > 0 {
+                    let left_start = current_hunk.left_line_range.end + num_skip_lines;
+                    let right_start = current_hunk.right_line_range.end + num_skip_lines;
+                    if !current_hunk.lines.is_empty() {
+                        hunks.push(current_hunk);
+                    }
+                    current_hunk = UnifiedDiffHunk {
+                        left_line_range: left_start..left_start,
+                        right_line_range: right_start..right_start,
+                        lines: vec![],
+                    };
                 }
-            } else if right_value.is_present() {
-                let description = match (&left_value, &right_value) {
-                    (
-                        MaterializedTreeValue::File {
-                            executable: left_executable,
-                            ..
-                        },
-                        MaterializedTreeValue::File {
-                            executable: right_executable,
-                            ..
-                        },
-                    ) => {
-                        if *left_executable && *right_executable {
-                            "Modified executable file".to_string()
-                        } else if *left_executable {
-                            "Executable file became non-executable at".to_string()
-                        } else if *right_executable {
-                            "Non-executable file became executable at".to_string()
-                        } else {
-                            "Modified regular file".to_string()
-                        }
+                // The next hunk should be of DiffHunk::Different type if any.
+                current_hunk.extend_context_lines(before_lines.into_iter().rev());
+            }
+            DiffHunk::Different(contents) => {
+                let [left, right] = contents.try_into().unwrap();
+                let is_large = word_diff_max_lines.is_some_and(|max_lines| {
+                    max(num_lines(left), num_lines(right)) as usize > max_lines
+                });
+                let (left_lines, right_lines) = if is_large {
+                    // Diffing words of large regions is slow, and the result
+                    // wouldn't be readable anyway.
+                    (whole_diff_lines(left), whole_diff_lines(right))
+                } else {
+                    timed(profile, DiffStep::WordDiff, || {
+                        inline_diff_hunks(left, right, word_tokenizer, ignore_case)
+                    })
+                };
+                match change_order {
+                    DiffChangeOrder::RemovedFirst => {
+                        current_hunk.extend_removed_lines(left_lines);
+                        current_hunk.extend_added_lines(right_lines);
                     }
-                    (
-                        MaterializedTreeValue::Conflict { .. },
-                        MaterializedTreeValue::Conflict { .. },
-                    ) => "Modified conflict in".to_string(),
-                    (MaterializedTreeValue::Conflict { .. }, _) => {
-                        "Resolved conflict in".to_string()
+                    DiffChangeOrder::AddedFirst => {
+                        current_hunk.extend_added_lines(right_lines);
+                        current_hunk.extend_removed_lines(left_lines);
                     }
-                    (_, MaterializedTreeValue::Conflict { .. }) => {
-                        "Created conflict in".to_string()
+                }
+            }
+        }
+    }
+    if !current_hunk.lines.is_empty() {
+        hunks.push(current_hunk);
+    }
+    hunks
+}
+
+/// Locations of function lines in content, used to extend context lines to
+/// the enclosing function.
+struct FunctionBounds {
+    is_function_line: Vec<bool>,
+    is_blank_line: Vec<bool>,
+}
+
+impl FunctionBounds {
+    fn new(content: &[u8], regex: &FunctionLineRegex) -> Self {
+        let (is_function_line, is_blank_line) = content
+            .split_inclusive(|b| *b == b'\n')
+            .map(|line| {
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                (
+                    regex.0.is_match(line),
+                    line.iter().all(u8::is_ascii_whitespace),
+                )
+            })
+            .unzip();
+        FunctionBounds {
+            is_function_line,
+            is_blank_line,
+        }
+    }
+
+    /// Returns the number of lines between the function line enclosing the
+    /// line at `pos` and that line. If no function line precedes it, all
+    /// lines up to `pos` are included.
+    fn num_lines_before(&self, pos: usize) -> usize {
+        let end = (pos + 1).min(self.is_function_line.len());
+        match self.is_function_line[..end].iter().rposition(|&b| b) {
+            Some(start) => pos - start,
+            None => pos,
+        }
+    }
+
+    /// Returns the number of lines from `pos` to the end of the enclosing
+    /// function, which is just before the next function line excluding
+    /// blank lines.
+    fn num_lines_after(&self, pos: usize) -> usize {
+        let len = self.is_function_line.len();
+        let start = pos.min(len);
+        let Some(next) = self.is_function_line[start..].iter().position(|&b| b) else {
+            return len - start;
+        };
+        let mut end = start + next;
+        while end > start && self.is_blank_line[end - 1] {
+            end -= 1;
+        }
+        end - start
+    }
+}
+
+/// Splits `content` into lines consisting of single tokens without word-level
+/// changes.
+fn whole_diff_lines(content: &[u8]) -> Vec<DiffTokenVec<'_>> {
+    content
+        .split_inclusive(|b| *b == b'\n')
+        .map(|line| vec![(DiffTokenType::Matching, line)])
+        .collect()
+}
+
+/// Splits line-level hunks into word-level tokens. Returns lists of tokens per
+/// line.
+fn inline_diff_hunks<'content>(
+    left_content: &'content [u8],
+    right_content: &'content [u8],
+    word_tokenizer: DiffWordTokenizer,
+    ignore_case: bool,
+) -> (Vec<DiffTokenVec<'content>>, Vec<DiffTokenVec<'content>>) {
+    let mut left_lines: Vec<DiffTokenVec<'content>> = vec![];
+    let mut right_lines: Vec<DiffTokenVec<'content>> = vec![];
+    let mut left_tokens: DiffTokenVec<'content> = vec![];
+    let mut right_tokens: DiffTokenVec<'content> = vec![];
+
+    let hunks = diff_hunks([left_content, right_content], ignore_case, |inputs| {
+        word_tokenizer.diff(inputs)
+    });
+    for hunk in hunks {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                for token in content.split_inclusive(|b| *b == b'\n') {
+                    left_tokens.push((DiffTokenType::Matching, token));
+                    right_tokens.push((DiffTokenType::Matching, token));
+                    if token.ends_with(b"\n") {
+                        left_lines.push(mem::take(&mut left_tokens));
+                        right_lines.push(mem::take(&mut right_tokens));
                     }
-                    (
-                        MaterializedTreeValue::Symlink { .. },
-                        MaterializedTreeValue::Symlink { .. },
-                    ) => "Symlink target changed at".to_string(),
-                    (_, _) => {
-                        let left_type = basic_diff_file_type(&left_value);
-                        let right_type = basic_diff_file_type(&right_value);
-                        let (first, rest) = left_type.split_at(1);
-                        format!(
-                            "{}{} became {} at",
-                            first.to_ascii_uppercase(),
-                            rest,
-                            right_type
-                        )
+                }
+            }
+            DiffHunk::Different(contents) => {
+                let [left, right] = contents.try_into().unwrap();
+                for token in left.split_inclusive(|b| *b == b'\n') {
+                    left_tokens.push((DiffTokenType::Different, token));
+                    if token.ends_with(b"\n") {
+                        left_lines.push(mem::take(&mut left_tokens));
                     }
-                };
-                let left_content = diff_content(&left_path, left_value)?;
-                let right_content = diff_content(&right_path, right_value)?;
-                if left_path == right_path {
-                    writeln!(
-                        formatter.labeled("header"),
-                        "{description} {right_ui_path}:"
-                    )?;
-                } else {
-                    writeln!(
-                        formatter.labeled("header"),
-                        "{description} {right_ui_path} ({left_ui_path} => {right_ui_path}):"
-                    )?;
                 }
-                if left_content.is_binary || right_content.is_binary {
-                    writeln!(formatter.labeled("binary"), "    (binary)")?;
-                } else {
-                    show_color_words_diff_hunks(
-                        &left_content.contents,
-                        &right_content.contents,
-                        num_context_lines,
-                        formatter,
-                    )?;
+                for token in right.split_inclusive(|b| *b == b'\n') {
+                    right_tokens.push((DiffTokenType::Different, token));
+                    if token.ends_with(b"\n") {
+                        right_lines.push(mem::take(&mut right_tokens));
+                    }
                 }
-            } else {
-                let description = basic_diff_file_type(&left_value);
+            }
+        }
+    }
+
+    if !left_tokens.is_empty() {
+        left_lines.push(left_tokens);
+    }
+    if !right_tokens.is_empty() {
+        right_lines.push(right_tokens);
+    }
+    (left_lines, right_lines)
+}
+
+/// Returns the last function line up to the 1-based `line_number` (inclusive)
+/// without the trailing newline.
+fn find_function_line<'a>(
+    content: &'a [u8],
+    line_number: usize,
+    regex: &FunctionLineRegex,
+) -> Option<&'a [u8]> {
+    content
+        .split_inclusive(|b| *b == b'\n')
+        .take(line_number)
+        .map(|line| line.strip_suffix(b"\n").unwrap_or(line))
+        .filter(|line| regex.0.is_match(line))
+        .last()
+}
+
+fn show_unified_diff_hunks(
+    formatter: &mut dyn Formatter,
+    left_content: &[u8],
+    right_content: &[u8],
+    options: &UnifiedDiffOptions,
+    word_tokenizer: DiffWordTokenizer,
+) -> io::Result<()> {
+    let mut next_left_line = 1;
+    let mut hunks = unified_diff_hunks(
+        left_content,
+        right_content,
+        options.context,
+        options.change_order,
+        options.function_context.as_ref(),
+        word_tokenizer,
+        options.ignore_case,
+        options.ignore_lines_matching.as_ref(),
+        options.word_diff_max_lines,
+        options.profile.as_ref(),
+    );
+    if options.ignore_blank_lines {
+        hunks.retain(|hunk| !hunk.has_only_blank_changes());
+    }
+    let num_omitted_hunks = match options.max_hunks {
+        Some(max_hunks) => hunks.drain(max_hunks.min(hunks.len())..).len(),
+        None => 0,
+    };
+    for (i, hunk) in hunks.into_iter().enumerate() {
+        let num_skipped_lines = hunk.left_line_range.start - next_left_line;
+        if options.annotate_gaps && i > 0 && num_skipped_lines > 0 {
+            writeln!(
+                formatter.labeled(DiffLabel::Context),
+                "# {num_skipped_lines} lines not shown"
+            )?;
+        }
+        if options.fold_markers {
+            write_fold_marker(formatter, next_left_line..hunk.left_line_range.start)?;
+        }
+        next_left_line = hunk.left_line_range.end;
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            format_hunk_range(&hunk.left_line_range),
+            format_hunk_range(&hunk.right_line_range)
+        );
+        if options.hunk_headers_only {
+            let function_line = options.function_context.as_ref().and_then(|regex| {
+                find_function_line(left_content, hunk.left_line_range.start, regex)
+            });
+            if let Some(line) = function_line {
+                header.push(' ');
+                header.push_str(&String::from_utf8_lossy(line));
+            }
+        }
+        writeln!(formatter.labeled(DiffLabel::HunkHeader), "{header}")?;
+        if options.hunk_headers_only {
+            continue;
+        }
+        if options.show_reorders {
+            if let Some(num_lines) = hunk.num_reordered_lines() {
                 writeln!(
-                    formatter.labeled("header"),
-                    "Removed {description} {right_ui_path}:"
+                    formatter.labeled(DiffLabel::Reordered),
+                    "({num_lines} lines reordered)"
                 )?;
-                let left_content = diff_content(&left_path, left_value)?;
-                if left_content.is_empty() {
-                    writeln!(formatter.labeled("empty"), "    (empty)")?;
-                } else if left_content.is_binary {
-                    writeln!(formatter.labeled("binary"), "    (binary)")?;
-                } else {
-                    show_color_words_diff_hunks(
-                        &left_content.contents,
-                        &[],
-                        num_context_lines,
-                        formatter,
-                    )?;
+                continue;
+            }
+        }
+        if options.word_diff.is_some() {
+            with_content_formatter(formatter, options.color_scope, |formatter| {
+                show_unified_word_diff_lines(
+                    formatter,
+                    &hunk.lines,
+                    word_tokenizer,
+                    options.ignore_case,
+                )
+            })?;
+            continue;
+        }
+        let mut left_line_number = hunk.left_line_range.start;
+        let mut right_line_number = hunk.right_line_range.start;
+        for (line_type, tokens) in &hunk.lines {
+            let (label, sigil) = match line_type {
+                DiffLineType::Context => (DiffLabel::Context, " "),
+                DiffLineType::Removed => (DiffLabel::Removed, "-"),
+                DiffLineType::Added => (DiffLabel::Added, "+"),
+            };
+            let line_numbers = options.line_numbers.then(|| {
+                let (left, right) = match line_type {
+                    DiffLineType::Context => (Some(left_line_number), Some(right_line_number)),
+                    DiffLineType::Removed => (Some(left_line_number), None),
+                    DiffLineType::Added => (None, Some(right_line_number)),
+                };
+                left_line_number += usize::from(left.is_some());
+                right_line_number += usize::from(right.is_some());
+                let format_number =
+                    |number: Option<usize>| number.map_or_else(String::new, |n| n.to_string());
+                format!("{:>4} {:>4} ", format_number(left), format_number(right))
+            });
+            let line = tokens
+                .iter()
+                .flat_map(|(_, content)| *content)
+                .copied()
+                .collect_vec();
+            let whitespace_range = if options.highlight_trailing_whitespace {
+                trailing_whitespace_range(&line)
+            } else {
+                0..0
+            };
+            let tab_widths = expanded_tab_widths(&line, &options.tab_stops);
+            with_content_formatter(formatter, options.color_scope, |formatter| {
+                formatter.with_label(label.as_str(), |formatter| {
+                    if let Some(line_numbers) = &line_numbers {
+                        write!(formatter.labeled(DiffLabel::LineNumber), "{line_numbers}")?;
+                    }
+                    write!(formatter, "{sigil}")?;
+                    let mut offset = 0;
+                    for (token_type, content) in tokens {
+                        match token_type {
+                            DiffTokenType::Matching => {
+                                write_line_fragment(
+                                    formatter,
+                                    content,
+                                    offset,
+                                    &whitespace_range,
+                                    options.show_whitespace,
+                                    &tab_widths,
+                                )?;
+                            }
+                            DiffTokenType::Different => {
+                                formatter.with_label(DiffLabel::Token.as_str(), |formatter| {
+                                    write_line_fragment(
+                                        formatter,
+                                        content,
+                                        offset,
+                                        &whitespace_range,
+                                        options.show_whitespace,
+                                        &tab_widths,
+                                    )
+                                })?
+                            }
+                        }
+                        offset += content.len();
+                    }
+                    io::Result::Ok(())
+                })
+            })?;
+            // Only the last line of either side can lack a newline, so the
+            // marker is printed after the removed and/or added line as needed.
+            let (_, content) = tokens.last().expect("hunk line must not be empty");
+            if !content.ends_with(b"\n") {
+                write!(formatter, "\n\\ No newline at end of file\n")?;
+            }
+        }
+    }
+    if num_omitted_hunks > 0 {
+        return write_omitted_hunks(formatter, num_omitted_hunks);
+    }
+    if options.fold_markers {
+        let num_left_lines = left_content.split_inclusive(|b| *b == b'\n').count();
+        write_fold_marker(formatter, next_left_line..num_left_lines + 1)?;
+    }
+    Ok(())
+}
+
+/// Prints the number of hunks omitted by the `max_hunks` option.
+fn write_omitted_hunks(formatter: &mut dyn Formatter, num_omitted_hunks: usize) -> io::Result<()> {
+    writeln!(
+        formatter,
+        "... ({num_omitted_hunks} more hunk{})",
+        if num_omitted_hunks == 1 { "" } else { "s" }
+    )
+}
+
+/// Formats `range` of a hunk header as `start,len`. As Git does, the length
+/// is omitted if it is 1, and an empty range starts at the line before it.
+fn format_hunk_range(range: &Range<usize>) -> String {
+    match range.len() {
+        0 => format!("{},0", range.start.saturating_sub(1)),
+        1 => range.start.to_string(),
+        len => format!("{},{}", range.start, len),
+    }
+}
+
+/// Prints a marker in place of the skipped left lines in `range` if any.
+fn write_fold_marker(formatter: &mut dyn Formatter, range: Range<usize>) -> io::Result<()> {
+    if range.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        formatter.labeled(DiffLabel::Context),
+        "<<<fold lines {}-{}>>>",
+        range.start,
+        range.end - 1
+    )
+}
+
+/// Prints hunk lines in `git diff --word-diff=plain` style. Context lines are
+/// printed without sigil, and each run of removed/added lines is merged into
+/// lines where changed words are enclosed in `[-...-]` and `{+...+}`.
+fn show_unified_word_diff_lines(
+    formatter: &mut dyn Formatter,
+    lines: &[(DiffLineType, DiffTokenVec<'_>)],
+    word_tokenizer: DiffWordTokenizer,
+    ignore_case: bool,
+) -> io::Result<()> {
+    fn concat_lines<'a>(
+        lines: impl IntoIterator<Item = &'a (DiffLineType, DiffTokenVec<'a>)>,
+    ) -> Vec<u8> {
+        lines
+            .into_iter()
+            .flat_map(|(_, tokens)| tokens)
+            .flat_map(|(_, content)| content.iter().copied())
+            .collect()
+    }
+
+    let mut ends_with_newline = true;
+    for (is_context, group) in &lines
+        .iter()
+        .chunk_by(|(line_type, _)| *line_type == DiffLineType::Context)
+    {
+        if is_context {
+            let content = concat_lines(group);
+            formatter.with_label(DiffLabel::Context.as_str(), |formatter| {
+                formatter.write_all(&content)
+            })?;
+            ends_with_newline = content.ends_with(b"\n");
+            continue;
+        }
+        let (removed, added): (Vec<_>, Vec<_>) =
+            group.partition(|(line_type, _)| *line_type == DiffLineType::Removed);
+        let left = concat_lines(removed);
+        let right = concat_lines(added);
+        let hunks = diff_hunks([&left, &right], ignore_case, |inputs| {
+            word_tokenizer.diff(inputs)
+        });
+        for hunk in hunks {
+            match hunk {
+                DiffHunk::Matching(content) => {
+                    formatter.write_all(content)?;
+                    ends_with_newline = content.ends_with(b"\n");
+                }
+                DiffHunk::Different(contents) => {
+                    let [left, right] = contents.try_into().unwrap();
+                    let sides = [
+                        (DiffLabel::Removed, "[-", "-]", left),
+                        (DiffLabel::Added, "{+", "+}", right),
+                    ];
+                    for (label, open, close, content) in sides {
+                        for line in content.split_inclusive(|b| *b == b'\n') {
+                            let text = line.strip_suffix(b"\n").unwrap_or(line);
+                            if !text.is_empty() {
+                                formatter.with_label(label.as_str(), |formatter| {
+                                    formatter.with_label(DiffLabel::Token.as_str(), |formatter| {
+                                        write!(formatter, "{open}")?;
+                                        formatter.write_all(text)?;
+                                        write!(formatter, "{close}")
+                                    })
+                                })?;
+                            }
+                            ends_with_newline = text.len() < line.len();
+                            if ends_with_newline {
+                                writeln!(formatter)?;
+                            }
+                        }
+                    }
                 }
             }
         }
-        Ok(())
     }
-    .block_on()
+    if !ends_with_newline {
+        writeln!(formatter)?;
+    }
+    Ok(())
+}
+
+/// Filters `copy_records` by the rename options of Git diff. Returns `None`
+/// if the records should be used as is.
+fn filter_git_copy_records(
+    store: &Store,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    copy_records: &CopyRecords,
+    options: &UnifiedDiffOptions,
+) -> Result<Option<CopyRecords>, DiffRenderError> {
+    let same_dir_copy_records = options
+        .rename_same_dir_only
+        .then(|| filter_same_dir_copy_records(copy_records));
+    let Some(threshold) = options.rename_threshold else {
+        return Ok(same_dir_copy_records);
+    };
+    let copy_records = same_dir_copy_records.as_ref().unwrap_or(copy_records);
+    let similar_copy_records =
+        filter_similar_copy_records(store, from_tree, to_tree, copy_records, threshold)?;
+    Ok(Some(similar_copy_records))
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn show_file_by_file_diff(
-    ui: &Ui,
+/// Returns whether the Git diff entry between `left_part` and `right_part`
+/// should be skipped. The source of a rename is shown together with the target,
+/// and skipped binary and generated files are omitted to keep the output a
+/// valid patch.
+fn should_skip_git_diff_entry(
+    left_path: &RepoPath,
+    left_part: &GitDiffPart,
+    right_part: &GitDiffPart,
+    copied_sources: &HashSet<&RepoPath>,
+    options: &UnifiedDiffOptions,
+) -> bool {
+    (right_part.mode.is_none() && copied_sources.contains(left_path))
+        || (options.skip_binary && (left_part.content.is_binary || right_part.content.is_binary))
+        || options.generated_markers.as_deref().is_some_and(|markers| {
+            has_generated_marker(&left_part.content.contents, markers)
+                || has_generated_marker(&right_part.content.contents, markers)
+        })
+}
+
+pub fn show_git_diff(
     formatter: &mut dyn Formatter,
     store: &Store,
-    tree_diff: TreeDiffStream,
-    path_converter: &RepoPathUiConverter,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
     matcher: &dyn Matcher,
     copy_records: &CopyRecords,
-    tool: &ExternalMergeTool,
+    options: &UnifiedDiffOptions,
 ) -> Result<(), DiffRenderError> {
-    fn create_file(
-        path: &RepoPath,
-        wc_dir: &Path,
-        value: MaterializedTreeValue,
-    ) -> Result<PathBuf, DiffRenderError> {
-        let fs_path = path.to_fs_path(wc_dir);
-        std::fs::create_dir_all(fs_path.parent().unwrap())?;
-        let content = diff_content(path, value)?;
-        std::fs::write(&fs_path, content.contents)?;
-        Ok(fs_path)
-    }
+    let filtered_copy_records =
+        filter_git_copy_records(store, from_tree, to_tree, copy_records, options)?;
+    let copy_records = filtered_copy_records.as_ref().unwrap_or(copy_records);
+    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let diff_stream = materialized_diff_stream(store, tree_diff);
     let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut has_entries = false;
 
-    let temp_dir = new_utf8_temp_dir("jj-diff-")?;
-    let left_wc_dir = temp_dir.path().join("left");
-    let right_wc_dir = temp_dir.path().join("right");
-    let mut diff_stream = materialized_diff_stream(store, tree_diff);
     async {
+        let mut diff_stream = if options.sort_paths {
+            // The tree stream visits directories before sibling files whose
+            // names sort after the directory name ("a/b" before "a.txt"), so
+            // buffer all entries and reorder them by the full path.
+            let mut entries: Vec<_> = diff_stream.collect().await;
+            entries.sort_by(|a, b| {
+                a.target
+                    .as_internal_file_string()
+                    .cmp(b.target.as_internal_file_string())
+            });
+            futures::stream::iter(entries).boxed_local()
+        } else {
+            diff_stream.boxed_local()
+        };
         while let Some(MaterializedTreeDiffEntry {
             source: left_path,
             target: right_path,
             value: diff,
         }) = diff_stream.next().await
         {
+            has_entries = true;
+            let options: &UnifiedDiffOptions = &options.for_path(&right_path);
             let (left_value, right_value) = diff?;
-            if right_value.is_absent() && copied_sources.contains(left_path.as_ref()) {
-                continue;
+            start_file_profile(options.profile.as_ref(), &left_path, &right_path);
+            if options.combined_conflicts {
+                if let MaterializedTreeValue::Conflict { id, contents, .. } = &right_value {
+                    if let Some(file_merge) = id.to_file_merge() {
+                        let sides = extract_as_single_hunk(&file_merge, store, &right_path).await?;
+                        show_git_combined_diff_entry(
+                            formatter,
+                            &right_path,
+                            &file_merge,
+                            &sides,
+                            contents,
+                            options,
+                        )?;
+                        continue;
+                    }
+                }
             }
+            let left_part = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                git_diff_part(&left_path, left_value)
+            })?;
+            let right_part = timed(options.profile.as_ref(), DiffStep::ReadContent, || {
+                git_diff_part(&right_path, right_value)
+            })?;
 
-            let left_ui_path = path_converter.format_file_path(&left_path);
-            let right_ui_path = path_converter.format_file_path(&right_path);
-
-            match (&left_value, &right_value) {
-                (_, MaterializedTreeValue::AccessDenied(source)) => {
-                    write!(
-                        formatter.labeled("access-denied"),
-                        "Access denied to {right_ui_path}:"
-                    )?;
-                    writeln!(formatter, " {source}")?;
-                    continue;
-                }
-                (MaterializedTreeValue::AccessDenied(source), _) => {
-                    write!(
-                        formatter.labeled("access-denied"),
-                        "Access denied to {left_ui_path}:"
-                    )?;
-                    writeln!(formatter, " {source}")?;
-                    continue;
-                }
-                _ => {}
+            if should_skip_git_diff_entry(
+                &left_path,
+                &left_part,
+                &right_part,
+                &copied_sources,
+                options,
+            ) {
+                continue;
             }
-            let left_path = create_file(&left_path, &left_wc_dir, left_value)?;
-            let right_path = create_file(&right_path, &right_wc_dir, right_value)?;
 
-            invoke_external_diff(
-                ui,
-                formatter.raw(),
-                tool,
-                &maplit::hashmap! {
-                    "left" => left_path.to_str().expect("temp_dir should be valid utf-8"),
-                    "right" => right_path.to_str().expect("temp_dir should be valid utf-8"),
-                },
-            )
-            .map_err(DiffRenderError::DiffGenerate)?;
+            show_git_diff_entry(
+                formatter,
+                to_tree,
+                &left_path,
+                &right_path,
+                &left_part,
+                &right_part,
+                options,
+            )?;
+            if options.verify_hunks {
+                // Render again without options that alter hunk lines.
+                let plain_options = UnifiedDiffOptions {
+                    word_diff: None,
+                    show_reorders: false,
+                    hunk_headers_only: false,
+                    fold_markers: false,
+                    annotate_gaps: false,
+                    line_numbers: false,
+                    max_hunks: None,
+                    tab_stops: vec![],
+                    profile: None,
+                    ..options.clone()
+                };
+                let mut output = vec![];
+                show_git_diff_entry(
+                    &mut PlainTextFormatter::new(&mut output),
+                    to_tree,
+                    &left_path,
+                    &right_path,
+                    &left_part,
+                    &right_part,
+                    &plain_options,
+                )?;
+                verify_git_diff_hunks(&output).map_err(|message| {
+                    DiffRenderError::InvalidGitDiff {
+                        path: right_path.as_internal_file_string().to_owned(),
+                        message,
+                    }
+                })?;
+            }
         }
-        Ok::<(), DiffRenderError>(())
+        if options.show_no_changes && !has_entries {
+            writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
+        }
+        Ok(())
     }
     .block_on()
 }
 
-struct GitDiffPart {
-    /// Octal mode string or `None` if the file is absent.
-    mode: Option<&'static str>,
-    hash: String,
-    content: FileContent,
+/// Line of combined diff, which has one column per side of the conflict.
+struct CombinedDiffLine<'a> {
+    /// `b' '`, `b'-'`, or `b'+'` for each side.
+    columns: Vec<u8>,
+    content: &'a [u8],
 }
 
-fn git_diff_part(
-    path: &RepoPath,
-    value: MaterializedTreeValue,
-) -> Result<GitDiffPart, DiffRenderError> {
-    const DUMMY_HASH: &str = "0000000000";
-    let mode;
-    let mut hash;
-    let content;
-    match value {
-        MaterializedTreeValue::Absent => {
-            return Ok(GitDiffPart {
-                mode: None,
-                hash: DUMMY_HASH.to_owned(),
-                content: FileContent::empty(),
-            });
-        }
-        MaterializedTreeValue::AccessDenied(err) => {
-            return Err(DiffRenderError::AccessDenied {
-                path: path.as_internal_file_string().to_owned(),
-                source: err,
-            });
-        }
-        MaterializedTreeValue::File {
-            id,
-            executable,
-            mut reader,
-        } => {
-            mode = if executable { "100755" } else { "100644" };
-            hash = id.hex();
-            content = file_content_for_diff(&mut reader)?;
-        }
-        MaterializedTreeValue::Symlink { id, target } => {
-            mode = "120000";
-            hash = id.hex();
-            content = FileContent {
-                // Unix file paths can't contain null bytes.
-                is_binary: false,
-                contents: target.into_bytes(),
-            };
-        }
-        MaterializedTreeValue::GitSubmodule(id) => {
-            // TODO: What should we actually do here?
-            mode = "040000";
-            hash = id.hex();
-            content = FileContent::empty();
-        }
-        MaterializedTreeValue::Conflict {
-            id: _,
-            contents,
-            executable,
-        } => {
-            mode = if executable { "100755" } else { "100644" };
-            hash = DUMMY_HASH.to_owned();
-            content = FileContent {
-                is_binary: false, // TODO: are we sure this is never binary?
-                contents,
-            };
-        }
-        MaterializedTreeValue::Tree(_) => {
-            panic!("Unexpected tree in diff at path {path:?}");
+impl CombinedDiffLine<'_> {
+    /// Returns true if the line is of the side at `index`.
+    fn is_of_side(&self, index: usize) -> bool {
+        if self.is_of_result() {
+            self.columns[index] == b' '
+        } else {
+            self.columns[index] == b'-'
         }
     }
-    hash.truncate(10);
-    Ok(GitDiffPart {
-        mode: Some(mode),
-        hash,
-        content,
-    })
-}
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum DiffLineType {
-    Context,
-    Removed,
-    Added,
-}
+    /// Returns true if the line is of the materialized conflict.
+    fn is_of_result(&self) -> bool {
+        !self.columns.contains(&b'-')
+    }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum DiffTokenType {
-    Matching,
-    Different,
+    fn is_context(&self) -> bool {
+        self.columns.iter().all(|&c| c == b' ')
+    }
 }
 
-type DiffTokenVec<'content> = Vec<(DiffTokenType, &'content [u8])>;
+/// Compares each of the `sides` with the `result` line by line, and merges
+/// the differences into lines of combined diff.
+///
+/// Lines removed from a side are placed before the result line they were
+/// replaced with.
+fn combined_diff_lines<'a>(sides: &[&'a [u8]], result: &'a [u8]) -> Vec<CombinedDiffLine<'a>> {
+    let result_lines = result.split_inclusive(|b| *b == b'\n').collect_vec();
+    // Per side, whether each result line was added, and the lines removed
+    // before each result line.
+    let mut added: Vec<Vec<bool>> = vec![];
+    let mut removed: Vec<Vec<Vec<&[u8]>>> = vec![];
+    for side in sides {
+        let mut side_added = vec![false; result_lines.len()];
+        let mut side_removed = vec![vec![]; result_lines.len() + 1];
+        let mut result_index = 0;
+        for hunk in Diff::by_line([*side, result]).hunks() {
+            match hunk {
+                DiffHunk::Matching(content) => {
+                    result_index += content.split_inclusive(|b| *b == b'\n').count();
+                }
+                DiffHunk::Different(contents) => {
+                    let [left, right] = contents.try_into().unwrap();
+                    side_removed[result_index].extend(left.split_inclusive(|b| *b == b'\n'));
+                    for _ in right.split_inclusive(|b| *b == b'\n') {
+                        side_added[result_index] = true;
+                        result_index += 1;
+                    }
+                }
+            }
+        }
+        added.push(side_added);
+        removed.push(side_removed);
+    }
 
-struct UnifiedDiffHunk<'content> {
-    left_line_range: Range<usize>,
-    right_line_range: Range<usize>,
-    lines: Vec<(DiffLineType, DiffTokenVec<'content>)>,
+    let mut lines = vec![];
+    for result_index in 0..=result_lines.len() {
+        for (side_index, side_removed) in removed.iter().enumerate() {
+            for content in &side_removed[result_index] {
+                let mut columns = vec![b' '; sides.len()];
+                columns[side_index] = b'-';
+                lines.push(CombinedDiffLine { columns, content });
+            }
+        }
+        if let Some(content) = result_lines.get(result_index) {
+            let columns = added
+                .iter()
+                .map(|side_added| if side_added[result_index] { b'+' } else { b' ' })
+                .collect();
+            lines.push(CombinedDiffLine { columns, content });
+        }
+    }
+    lines
 }
 
-impl<'content> UnifiedDiffHunk<'content> {
-    fn extend_context_lines(&mut self, lines: impl IntoIterator<Item = &'content [u8]>) {
-        let old_len = self.lines.len();
-        self.lines.extend(lines.into_iter().map(|line| {
-            let tokens = vec![(DiffTokenType::Matching, line)];
-            (DiffLineType::Context, tokens)
-        }));
-        self.left_line_range.end += self.lines.len() - old_len;
-        self.right_line_range.end += self.lines.len() - old_len;
+/// Renders a conflicted file as combined diff between the `sides` of the
+/// conflict and the materialized `contents`, similar to Git's `diff --cc`.
+fn show_git_combined_diff_entry(
+    formatter: &mut dyn Formatter,
+    path: &RepoPath,
+    file_merge: &Merge<Option<FileId>>,
+    sides: &Merge<ContentHunk>,
+    contents: &[u8],
+    options: &UnifiedDiffOptions,
+) -> Result<(), DiffRenderError> {
+    const DUMMY_HASH: &str = "0000000000000000000000000000000000000000";
+    let path_string = path.as_internal_file_string();
+    let side_hashes = file_merge
+        .adds()
+        .map(|id| {
+            let hash = id
+                .as_ref()
+                .map_or_else(|| DUMMY_HASH.to_owned(), |id| id.hex());
+            abbreviate_hash(&hash, options.hash_abbrev).to_owned()
+        })
+        .join(",");
+    formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+        writeln!(formatter, "diff --cc {}", quote_git_path(path_string))?;
+        writeln!(
+            formatter,
+            "index {side_hashes}..{}",
+            abbreviate_hash(DUMMY_HASH, options.hash_abbrev)
+        )?;
+        io::Result::Ok(())
+    })?;
+
+    let side_contents = sides.adds().map(|side| side.0.as_slice()).collect_vec();
+    if is_binary_content(contents) || side_contents.iter().any(|side| is_binary_content(side)) {
+        writeln!(formatter, "Binary files differ")?;
+        return Ok(());
     }
+    formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+        writeln!(
+            formatter,
+            "--- {}",
+            quote_git_path(&format!("a/{path_string}"))
+        )?;
+        writeln!(
+            formatter,
+            "+++ {}",
+            quote_git_path(&format!("b/{path_string}"))
+        )?;
+        io::Result::Ok(())
+    })?;
 
-    fn extend_removed_lines(&mut self, lines: impl IntoIterator<Item = DiffTokenVec<'content>>) {
-        let old_len = self.lines.len();
-        self.lines
-            .extend(lines.into_iter().map(|line| (DiffLineType::Removed, line)));
-        self.left_line_range.end += self.lines.len() - old_len;
+    let lines = combined_diff_lines(&side_contents, contents);
+    // Line numbers of each side and the result before each line.
+    let mut line_numbers = vec![vec![1; side_contents.len() + 1]];
+    for line in &lines {
+        let mut numbers = line_numbers.last().unwrap().clone();
+        for (side_index, number) in numbers[..side_contents.len()].iter_mut().enumerate() {
+            *number += usize::from(line.is_of_side(side_index));
+        }
+        *numbers.last_mut().unwrap() += usize::from(line.is_of_result());
+        line_numbers.push(numbers);
     }
 
-    fn extend_added_lines(&mut self, lines: impl IntoIterator<Item = DiffTokenVec<'content>>) {
-        let old_len = self.lines.len();
-        self.lines
-            .extend(lines.into_iter().map(|line| (DiffLineType::Added, line)));
-        self.right_line_range.end += self.lines.len() - old_len;
+    // Extend each changed line by the context lines, and merge the overlapping
+    // ranges into hunks.
+    let mut hunk_ranges: Vec<Range<usize>> = vec![];
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.is_context())
+    {
+        let range =
+            index.saturating_sub(options.context)..(index + 1 + options.context).min(lines.len());
+        match hunk_ranges.last_mut() {
+            Some(last) if last.end >= range.start => last.end = range.end,
+            _ => hunk_ranges.push(range),
+        }
     }
-}
 
-fn unified_diff_hunks<'content>(
-    left_content: &'content [u8],
-    right_content: &'content [u8],
-    num_context_lines: usize,
-) -> Vec<UnifiedDiffHunk<'content>> {
-    let mut hunks = vec![];
-    let mut current_hunk = UnifiedDiffHunk {
-        left_line_range: 1..1,
-        right_line_range: 1..1,
-        lines: vec![],
-    };
-    let diff = Diff::by_line([left_content, right_content]);
-    let mut diff_hunks = diff.hunks().peekable();
-    while let Some(hunk) = diff_hunks.next() {
-        match hunk {
-            DiffHunk::Matching(content) => {
-                let mut lines = content.split_inclusive(|b| *b == b'\n').fuse();
-                if !current_hunk.lines.is_empty() {
-                    // The previous hunk line should be either removed/added.
-                    current_hunk.extend_context_lines(lines.by_ref().take(num_context_lines));
+    let num_sides = side_contents.len();
+    let at_signs = "@".repeat(num_sides + 1);
+    for range in hunk_ranges {
+        let start = &line_numbers[range.start];
+        let end = &line_numbers[range.end];
+        let side_ranges = (0..num_sides)
+            .map(|i| format!("-{},{}", start[i], end[i] - start[i]))
+            .join(" ");
+        writeln!(
+            formatter.labeled(DiffLabel::HunkHeader),
+            "{at_signs} {side_ranges} +{},{} {at_signs}",
+            start[num_sides],
+            end[num_sides] - start[num_sides]
+        )?;
+        for line in &lines[range] {
+            let label = if !line.is_of_result() {
+                DiffLabel::Removed
+            } else if !line.is_context() {
+                DiffLabel::Added
+            } else {
+                DiffLabel::Context
+            };
+            formatter.with_label(label.as_str(), |formatter| {
+                formatter.write_all(&line.columns)?;
+                formatter.write_all(line.content)?;
+                if !line.content.ends_with(b"\n") {
+                    write!(formatter, "\n\\ No newline at end of file\n")?;
                 }
-                let before_lines = if diff_hunks.peek().is_some() {
-                    lines.by_ref().rev().take(num_context_lines).collect()
-                } else {
-                    vec![] // No more hunks
-                };
-                let num_skip_lines = lines.count();
-                if num_skip_lines > 0 {
-                    let left_start = current_hunk.left_line_range.end + num_skip_lines;
-                    let right_start = current_hunk.right_line_range.end + num_skip_lines;
-                    if !current_hunk.lines.is_empty() {
-                        hunks.push(current_hunk);
-                    }
-                    current_hunk = UnifiedDiffHunk {
-                        left_line_range: left_start..left_start,
-                        right_line_range: right_start..right_start,
-                        lines: vec![],
-                    };
+                io::Result::Ok(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the number of lines in each hunk of Git-format diff `output`
+/// matches the line counts declared in the hunk header.
+fn verify_git_diff_hunks(output: &[u8]) -> Result<(), String> {
+    fn parse_range(range: &str, sigil: char) -> Option<usize> {
+        let range = range.strip_prefix(sigil)?;
+        // The length is omitted if it is 1
+        let (start, len) = range.split_once(',').unwrap_or((range, "1"));
+        start.parse::<usize>().ok()?;
+        len.parse().ok()
+    }
+
+    // Numbers of left and right lines remaining in the current hunk
+    let mut remaining: Option<(usize, usize)> = None;
+    for line in output.split(|b| *b == b'\n') {
+        if let Some((left, right)) = remaining.filter(|&counts| counts != (0, 0)) {
+            let counts = match line.first() {
+                Some(b' ') => left.checked_sub(1).zip(right.checked_sub(1)),
+                Some(b'-') => left.checked_sub(1).map(|left| (left, right)),
+                Some(b'+') => right.checked_sub(1).map(|right| (left, right)),
+                Some(b'\\') => Some((left, right)), // "\ No newline at end of file"
+                _ => {
+                    return Err(format!(
+                        "hunk ended with {left} left and {right} right lines missing"
+                    ))
                 }
-                // The next hunk should be of DiffHunk::Different type if any.
-                current_hunk.extend_context_lines(before_lines.into_iter().rev());
-            }
-            DiffHunk::Different(contents) => {
-                let [left, right] = contents.try_into().unwrap();
-                let (left_lines, right_lines) = inline_diff_hunks(left, right);
-                current_hunk.extend_removed_lines(left_lines);
-                current_hunk.extend_added_lines(right_lines);
+            };
+            remaining = Some(counts.ok_or_else(|| "hunk has more lines than declared".to_owned())?);
+        } else if let Some(header) = line.strip_prefix(b"@@ ") {
+            let header = String::from_utf8_lossy(header);
+            let mut ranges = header.split(' ');
+            let counts = ranges
+                .next()
+                .and_then(|range| parse_range(range, '-'))
+                .zip(ranges.next().and_then(|range| parse_range(range, '+')));
+            remaining = Some(counts.ok_or_else(|| format!("malformed hunk header @@ {header}"))?);
+        }
+    }
+    match remaining {
+        Some((left, right)) if (left, right) != (0, 0) => Err(format!(
+            "hunk ended with {left} left and {right} right lines missing"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Writes Git-format diff of each changed path to a separate `.patch` file
+/// under `output_dir`. Returns the number of written files.
+///
+/// Each file is named after the target path, and subdirectories are created
+/// as needed. Files skipped by `show_git_diff()` aren't written.
+pub fn write_git_diff_files(
+    store: &Store,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+    options: &UnifiedDiffOptions,
+    output_dir: &Path,
+) -> Result<usize, DiffRenderError> {
+    let filtered_copy_records =
+        filter_git_copy_records(store, from_tree, to_tree, copy_records, options)?;
+    let copy_records = filtered_copy_records.as_ref().unwrap_or(copy_records);
+    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut num_files = 0;
+
+    async {
+        while let Some(MaterializedTreeDiffEntry {
+            source: left_path,
+            target: right_path,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            let options: &UnifiedDiffOptions = &options.for_path(&right_path);
+            let (left_value, right_value) = diff?;
+            let left_part = git_diff_part(&left_path, left_value)?;
+            let right_part = git_diff_part(&right_path, right_value)?;
+            if should_skip_git_diff_entry(
+                &left_path,
+                &left_part,
+                &right_part,
+                &copied_sources,
+                options,
+            ) {
+                continue;
             }
+
+            let mut content = vec![];
+            show_git_diff_entry(
+                &mut PlainTextFormatter::new(&mut content),
+                to_tree,
+                &left_path,
+                &right_path,
+                &left_part,
+                &right_part,
+                options,
+            )?;
+            let patch_path = patch_file_path(output_dir, &right_path);
+            std::fs::create_dir_all(patch_path.parent().unwrap())?;
+            std::fs::write(&patch_path, content)?;
+            num_files += 1;
         }
+        Ok(num_files)
     }
-    if !current_hunk.lines.is_empty() {
-        hunks.push(current_hunk);
-    }
-    hunks
+    .block_on()
 }
 
-/// Splits line-level hunks into word-level tokens. Returns lists of tokens per
-/// line.
-fn inline_diff_hunks<'content>(
-    left_content: &'content [u8],
-    right_content: &'content [u8],
-) -> (Vec<DiffTokenVec<'content>>, Vec<DiffTokenVec<'content>>) {
-    let mut left_lines: Vec<DiffTokenVec<'content>> = vec![];
-    let mut right_lines: Vec<DiffTokenVec<'content>> = vec![];
-    let mut left_tokens: DiffTokenVec<'content> = vec![];
-    let mut right_tokens: DiffTokenVec<'content> = vec![];
+/// Maps repository `path` to a `.patch` file path under `output_dir`.
+///
+/// Path components which could escape from the `output_dir` are escaped.
+fn patch_file_path(output_dir: &Path, path: &RepoPath) -> PathBuf {
+    let mut patch_path = output_dir.to_owned();
+    for component in path.components() {
+        match component.as_str() {
+            name @ ("." | "..") => patch_path.push(format!("_{name}")),
+            name => patch_path.push(name.replace(['\\', ':'], "_")),
+        }
+    }
+    let mut file_name = patch_path.file_name().unwrap().to_owned();
+    file_name.push(".patch");
+    patch_path.set_file_name(file_name);
+    patch_path
+}
 
-    for hunk in Diff::by_word([left_content, right_content]).hunks() {
-        match hunk {
-            DiffHunk::Matching(content) => {
-                for token in content.split_inclusive(|b| *b == b'\n') {
-                    left_tokens.push((DiffTokenType::Matching, token));
-                    right_tokens.push((DiffTokenType::Matching, token));
-                    if token.ends_with(b"\n") {
-                        left_lines.push(mem::take(&mut left_tokens));
-                        right_lines.push(mem::take(&mut right_tokens));
-                    }
-                }
+fn show_git_diff_entry(
+    formatter: &mut dyn Formatter,
+    to_tree: &MergedTree,
+    left_path: &RepoPath,
+    right_path: &RepoPath,
+    left_part: &GitDiffPart,
+    right_part: &GitDiffPart,
+    options: &UnifiedDiffOptions,
+) -> Result<(), DiffRenderError> {
+    // Skip the entry if the header would be followed by no hunks.
+    if left_path == right_path
+        && left_part.mode == right_part.mode
+        && options.has_only_ignored_changes(&left_part.content, &right_part.content)
+    {
+        return Ok(());
+    }
+    let left_path_string = left_path.as_internal_file_string();
+    let right_path_string = right_path.as_internal_file_string();
+    formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+        writeln!(
+            formatter,
+            "diff --git {} {}",
+            quote_git_path(&format!("a/{left_path_string}")),
+            quote_git_path(&format!("b/{right_path_string}"))
+        )?;
+        let left_hash = abbreviate_hash(&left_part.hash, options.hash_abbrev);
+        let right_hash = abbreviate_hash(&right_part.hash, options.hash_abbrev);
+        let hash_changed = left_part.hash != right_part.hash;
+        match (left_part.mode, right_part.mode) {
+            (None, Some(right_mode)) => {
+                writeln!(formatter, "new file mode {right_mode}")?;
+                writeln!(formatter, "index {left_hash}..{right_hash}")?;
             }
-            DiffHunk::Different(contents) => {
-                let [left, right] = contents.try_into().unwrap();
-                for token in left.split_inclusive(|b| *b == b'\n') {
-                    left_tokens.push((DiffTokenType::Different, token));
-                    if token.ends_with(b"\n") {
-                        left_lines.push(mem::take(&mut left_tokens));
+            (Some(left_mode), None) => {
+                writeln!(formatter, "deleted file mode {left_mode}")?;
+                writeln!(formatter, "index {left_hash}..{right_hash}")?;
+            }
+            (Some(left_mode), Some(right_mode)) => {
+                if left_path != right_path {
+                    let operation = if to_tree.path_value(left_path)?.is_absent() {
+                        "rename"
+                    } else {
+                        "copy"
+                    };
+                    // TODO: include similarity index of modified contents?
+                    if !hash_changed {
+                        writeln!(formatter, "similarity index 100%")?;
                     }
+                    writeln!(
+                        formatter,
+                        "{operation} from {}",
+                        quote_git_path(left_path_string)
+                    )?;
+                    writeln!(
+                        formatter,
+                        "{operation} to {}",
+                        quote_git_path(right_path_string)
+                    )?;
                 }
-                for token in right.split_inclusive(|b| *b == b'\n') {
-                    right_tokens.push((DiffTokenType::Different, token));
-                    if token.ends_with(b"\n") {
-                        right_lines.push(mem::take(&mut right_tokens));
+                if left_mode != right_mode {
+                    writeln!(formatter, "old mode {left_mode}")?;
+                    writeln!(formatter, "new mode {right_mode}")?;
+                    if hash_changed {
+                        writeln!(formatter, "index {left_hash}..{right_hash}")?;
                     }
+                } else if hash_changed {
+                    writeln!(formatter, "index {left_hash}..{right_hash} {left_mode}")?;
                 }
             }
+            (None, None) => panic!("either left or right part should be present"),
         }
+        Ok::<(), DiffRenderError>(())
+    })?;
+
+    // The header is printed for every entry, so pure copies and renames are
+    // still listed even if there are no content hunks.
+    if left_part.content.contents == right_part.content.contents {
+        return Ok(()); // no content hunks
     }
 
-    if !left_tokens.is_empty() {
-        left_lines.push(left_tokens);
+    let left_path = match left_part.mode {
+        Some(_) => quote_git_path(&format!("a/{left_path_string}")).into_owned(),
+        None => "/dev/null".to_owned(),
+    };
+    let right_path = match right_part.mode {
+        Some(_) => quote_git_path(&format!("b/{right_path_string}")).into_owned(),
+        None => "/dev/null".to_owned(),
+    };
+    if left_part.content.is_binary || right_part.content.is_binary {
+        // TODO: add option to emit Git binary diff
+        writeln!(
+            formatter,
+            "Binary files {left_path} and {right_path} differ"
+        )?;
+    } else {
+        formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+            writeln!(formatter, "--- {left_path}")?;
+            writeln!(formatter, "+++ {right_path}")?;
+            io::Result::Ok(())
+        })?;
+        show_unified_diff_hunks(
+            formatter,
+            &left_part.content.contents,
+            &right_part.content.contents,
+            options,
+            options.word_tokenizer.for_file_name(right_path_string),
+        )?;
     }
-    if !right_tokens.is_empty() {
-        right_lines.push(right_tokens);
+    Ok(())
+}
+
+/// Quotes `path` in Git's style if it contains double quotes, backslashes, or
+/// control characters.
+///
+/// Unlike Git with `core.quotePath` enabled, non-ASCII characters are left
+/// unescaped. Repository paths are always valid UTF-8.
+fn quote_git_path(path: &str) -> Cow<'_, str> {
+    let needs_quote = |c: char| c == '"' || c == '\\' || c.is_ascii_control();
+    if !path.contains(needs_quote) {
+        return Cow::Borrowed(path);
     }
-    (left_lines, right_lines)
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('"');
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\x07' => quoted.push_str("\\a"),
+            '\x08' => quoted.push_str("\\b"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            '\x0b' => quoted.push_str("\\v"),
+            '\x0c' => quoted.push_str("\\f"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
 }
 
-fn show_unified_diff_hunks(
+/// Renders Git-format diff of two arbitrary buffers without a repository.
+///
+/// Since there are no blobs to refer to, only the `---`/`+++` file headers are
+/// printed. `left_name` and `right_name` are prefixed with `a/` and `b/`.
+pub fn show_git_contents_diff(
+    formatter: &mut dyn Formatter,
+    left_name: &str,
+    left: &[u8],
+    right_name: &str,
+    right: &[u8],
+    options: &UnifiedDiffOptions,
+) -> io::Result<()> {
+    let left_content = file_content_for_diff(&mut &*left)?;
+    let right_content = file_content_for_diff(&mut &*right)?;
+    if left_content.contents == right_content.contents
+        || options.has_only_ignored_changes(&left_content, &right_content)
+    {
+        return Ok(());
+    }
+    if left_content.is_binary || right_content.is_binary {
+        writeln!(
+            formatter,
+            "Binary files a/{left_name} and b/{right_name} differ"
+        )?;
+        return Ok(());
+    }
+    formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+        writeln!(formatter, "--- a/{left_name}")?;
+        writeln!(formatter, "+++ b/{right_name}")?;
+        io::Result::Ok(())
+    })?;
+    show_unified_diff_hunks(
+        formatter,
+        &left_content.contents,
+        &right_content.contents,
+        options,
+        options.word_tokenizer.for_file_name(right_name),
+    )
+}
+
+/// Renders diff as HTML, with CSS classes named after the diff labels.
+///
+/// Each changed file is rendered as a `<div class="diff">` containing the file
+/// header and a `<pre>` block of hunks. All contents are HTML-escaped.
+pub fn show_html_diff(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+    options: &UnifiedDiffOptions,
+) -> Result<(), DiffRenderError> {
+    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut has_entries = false;
+
+    async {
+        while let Some(MaterializedTreeDiffEntry {
+            source: left_path,
+            target: right_path,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            has_entries = true;
+            let options: &UnifiedDiffOptions = &options.for_path(&right_path);
+            let (left_value, right_value) = diff?;
+            let left_part = git_diff_part(&left_path, left_value)?;
+            let right_part = git_diff_part(&right_path, right_value)?;
+
+            // Skip the "delete" entry when there is a rename.
+            if right_part.mode.is_none() && copied_sources.contains(left_path.as_ref()) {
+                continue;
+            }
+            if options.skip_binary && (left_part.content.is_binary || right_part.content.is_binary)
+            {
+                continue;
+            }
+            // Skip files modified in place whose changes are all ignored.
+            if left_path == right_path
+                && left_part.mode.is_some()
+                && right_part.mode.is_some()
+                && options.has_only_ignored_changes(&left_part.content, &right_part.content)
+            {
+                continue;
+            }
+
+            let left_path_string = left_path.as_internal_file_string();
+            let right_path_string = right_path.as_internal_file_string();
+            let (label, path_string) = match (left_part.mode, right_part.mode) {
+                (None, _) => (DiffLabel::Added, right_path_string.to_owned()),
+                (_, None) => (DiffLabel::Removed, left_path_string.to_owned()),
+                _ if left_path != right_path => {
+                    let label = if to_tree.path_value(&left_path)?.is_absent() {
+                        DiffLabel::Renamed
+                    } else {
+                        DiffLabel::Copied
+                    };
+                    (label, format!("{left_path_string} => {right_path_string}"))
+                }
+                _ => (DiffLabel::Modified, right_path_string.to_owned()),
+            };
+            writeln!(formatter, r#"<div class="{}">"#, DiffLabel::Diff.as_str())?;
+            write!(
+                formatter,
+                r#"<div class="{}"><span class="{}">"#,
+                DiffLabel::FileHeader.as_str(),
+                label.as_str()
+            )?;
+            write_html_escaped(formatter, path_string.as_bytes())?;
+            writeln!(formatter, "</span></div>")?;
+            if left_part.content.is_binary || right_part.content.is_binary {
+                writeln!(
+                    formatter,
+                    r#"<div class="{}">(binary)</div>"#,
+                    DiffLabel::Binary.as_str()
+                )?;
+            } else if left_part.content.contents != right_part.content.contents {
+                writeln!(formatter, "<pre>")?;
+                show_html_diff_hunks(
+                    formatter,
+                    &left_part.content.contents,
+                    &right_part.content.contents,
+                    options,
+                    options.word_tokenizer.for_file_name(right_path_string),
+                )?;
+                writeln!(formatter, "</pre>")?;
+            }
+            writeln!(formatter, "</div>")?;
+        }
+        if options.show_no_changes && !has_entries {
+            writeln!(
+                formatter,
+                r#"<div class="{}">(no changes)</div>"#,
+                DiffLabel::Empty.as_str()
+            )?;
+        }
+        Ok(())
+    }
+    .block_on()
+}
+
+fn show_html_diff_hunks(
     formatter: &mut dyn Formatter,
     left_content: &[u8],
     right_content: &[u8],
-    num_context_lines: usize,
+    options: &UnifiedDiffOptions,
+    word_tokenizer: DiffWordTokenizer,
 ) -> io::Result<()> {
-    for hunk in unified_diff_hunks(left_content, right_content, num_context_lines) {
+    let mut hunks = unified_diff_hunks(
+        left_content,
+        right_content,
+        options.context,
+        options.change_order,
+        options.function_context.as_ref(),
+        word_tokenizer,
+        options.ignore_case,
+        options.ignore_lines_matching.as_ref(),
+        options.word_diff_max_lines,
+        options.profile.as_ref(),
+    );
+    if options.ignore_blank_lines {
+        hunks.retain(|hunk| !hunk.has_only_blank_changes());
+    }
+    for hunk in hunks {
         writeln!(
-            formatter.labeled("hunk_header"),
-            "@@ -{},{} +{},{} @@",
-            hunk.left_line_range.start,
-            hunk.left_line_range.len(),
-            hunk.right_line_range.start,
-            hunk.right_line_range.len()
+            formatter,
+            r#"<span class="{}">@@ -{} +{} @@</span>"#,
+            DiffLabel::HunkHeader.as_str(),
+            format_hunk_range(&hunk.left_line_range),
+            format_hunk_range(&hunk.right_line_range)
         )?;
         for (line_type, tokens) in &hunk.lines {
             let (label, sigil) = match line_type {
-                DiffLineType::Context => ("context", " "),
-                DiffLineType::Removed => ("removed", "-"),
-                DiffLineType::Added => ("added", "+"),
+                DiffLineType::Context => (DiffLabel::Context, " "),
+                DiffLineType::Removed => (DiffLabel::Removed, "-"),
+                DiffLineType::Added => (DiffLabel::Added, "+"),
             };
-            formatter.with_label(label, |formatter| {
-                write!(formatter, "{sigil}")?;
-                for (token_type, content) in tokens {
-                    match token_type {
-                        DiffTokenType::Matching => formatter.write_all(content)?,
-                        DiffTokenType::Different => formatter
-                            .with_label("token", |formatter| formatter.write_all(content))?,
+            write!(formatter, r#"<span class="{}">{sigil}"#, label.as_str())?;
+            for (token_type, content) in tokens {
+                let content = content.strip_suffix(b"\n").unwrap_or(content);
+                match token_type {
+                    DiffTokenType::Matching => write_html_escaped(formatter, content)?,
+                    DiffTokenType::Different => {
+                        write!(formatter, r#"<span class="{}">"#, DiffLabel::Token.as_str())?;
+                        write_html_escaped(formatter, content)?;
+                        write!(formatter, "</span>")?;
                     }
                 }
-                io::Result::Ok(())
-            })?;
+            }
+            writeln!(formatter, "</span>")?;
             let (_, content) = tokens.last().expect("hunk line must not be empty");
             if !content.ends_with(b"\n") {
-                write!(formatter, "\n\\ No newline at end of file\n")?;
+                writeln!(formatter, "\\ No newline at end of file")?;
             }
         }
     }
     Ok(())
 }
 
-pub fn show_git_diff(
+/// Writes `content` with HTML special characters escaped. Invalid UTF-8
+/// sequences are replaced.
+fn write_html_escaped(formatter: &mut dyn Formatter, content: &[u8]) -> io::Result<()> {
+    for c in String::from_utf8_lossy(content).chars() {
+        match c {
+            '&' => write!(formatter, "&amp;")?,
+            '<' => write!(formatter, "&lt;")?,
+            '>' => write!(formatter, "&gt;")?,
+            '"' => write!(formatter, "&quot;")?,
+            '\'' => write!(formatter, "&#39;")?,
+            c => write!(formatter, "{c}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Formats `timestamp` as in the file headers of POSIX unified diff, falling
+/// back to the Unix epoch if there's no timestamp or it's out of range.
+fn format_posix_diff_timestamp(timestamp: Option<&Timestamp>) -> String {
+    static FORMAT: Lazy<FormattingItems> =
+        Lazy::new(|| FormattingItems::parse("%Y-%m-%d %H:%M:%S%.9f %z").unwrap());
+    let epoch = Timestamp {
+        timestamp: MillisSinceEpoch(0),
+        tz_offset: 0,
+    };
+    timestamp
+        .and_then(|timestamp| format_absolute_timestamp_with(timestamp, &FORMAT).ok())
+        .unwrap_or_else(|| format_absolute_timestamp_with(&epoch, &FORMAT).unwrap())
+}
+
+/// Renders diff in the POSIX `diff -u` format.
+///
+/// Unlike Git-format diff, there's no `diff --git` line, and the `---`/`+++`
+/// headers carry paths without `a/`/`b/` prefixes followed by tab-separated
+/// `timestamps`. Absent sides are dated to the Unix epoch. Changes that can't
+/// be expressed in this format, such as mode changes, are omitted.
+#[allow(clippy::too_many_arguments)]
+pub fn show_posix_diff(
     formatter: &mut dyn Formatter,
     store: &Store,
     from_tree: &MergedTree,
     to_tree: &MergedTree,
     matcher: &dyn Matcher,
     copy_records: &CopyRecords,
-    num_context_lines: usize,
+    timestamps: [Option<&Timestamp>; 2],
+    options: &UnifiedDiffOptions,
 ) -> Result<(), DiffRenderError> {
     let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
     let mut diff_stream = materialized_diff_stream(store, tree_diff);
     let copied_sources = collect_copied_sources(copy_records, matcher);
+    let [left_timestamp, right_timestamp] = timestamps;
+    let mut has_entries = false;
 
     async {
         while let Some(MaterializedTreeDiffEntry {
@@ -1170,10 +6378,9 @@ pub fn show_git_diff(
             value: diff,
         }) = diff_stream.next().await
         {
-            let left_path_string = left_path.as_internal_file_string();
-            let right_path_string = right_path.as_internal_file_string();
+            has_entries = true;
+            let options: &UnifiedDiffOptions = &options.for_path(&right_path);
             let (left_value, right_value) = diff?;
-
             let left_part = git_diff_part(&left_path, left_value)?;
             let right_part = git_diff_part(&right_path, right_value)?;
 
@@ -1181,86 +6388,158 @@ pub fn show_git_diff(
             if right_part.mode.is_none() && copied_sources.contains(left_path.as_ref()) {
                 continue;
             }
-
-            formatter.with_label("file_header", |formatter| {
-                writeln!(
-                    formatter,
-                    "diff --git a/{left_path_string} b/{right_path_string}"
-                )?;
-                let left_hash = &left_part.hash;
-                let right_hash = &right_part.hash;
-                match (left_part.mode, right_part.mode) {
-                    (None, Some(right_mode)) => {
-                        writeln!(formatter, "new file mode {right_mode}")?;
-                        writeln!(formatter, "index {left_hash}..{right_hash}")?;
-                    }
-                    (Some(left_mode), None) => {
-                        writeln!(formatter, "deleted file mode {left_mode}")?;
-                        writeln!(formatter, "index {left_hash}..{right_hash}")?;
-                    }
-                    (Some(left_mode), Some(right_mode)) => {
-                        if left_path != right_path {
-                            let operation = if to_tree.path_value(&left_path)?.is_absent() {
-                                "rename"
-                            } else {
-                                "copy"
-                            };
-                            // TODO: include similarity index?
-                            writeln!(formatter, "{operation} from {left_path_string}")?;
-                            writeln!(formatter, "{operation} to {right_path_string}")?;
-                        }
-                        if left_mode != right_mode {
-                            writeln!(formatter, "old mode {left_mode}")?;
-                            writeln!(formatter, "new mode {right_mode}")?;
-                            if left_hash != right_hash {
-                                writeln!(formatter, "index {left_hash}..{right_hash}")?;
-                            }
-                        } else if left_hash != right_hash {
-                            writeln!(formatter, "index {left_hash}..{right_hash} {left_mode}")?;
-                        }
-                    }
-                    (None, None) => panic!("either left or right part should be present"),
-                }
-                Ok::<(), DiffRenderError>(())
-            })?;
-
+            if options.skip_binary && (left_part.content.is_binary || right_part.content.is_binary)
+            {
+                continue;
+            }
             if left_part.content.contents == right_part.content.contents {
                 continue; // no content hunks
             }
+            if left_part.mode.is_some()
+                && right_part.mode.is_some()
+                && options.has_only_ignored_changes(&left_part.content, &right_part.content)
+            {
+                continue;
+            }
 
-            let left_path = match left_part.mode {
-                Some(_) => format!("a/{left_path_string}"),
-                None => "/dev/null".to_owned(),
+            let left_path_string = left_path.as_internal_file_string();
+            let right_path_string = right_path.as_internal_file_string();
+            let (left_path, left_timestamp) = match left_part.mode {
+                Some(_) => (left_path_string, left_timestamp),
+                None => ("/dev/null", None),
             };
-            let right_path = match right_part.mode {
-                Some(_) => format!("b/{right_path_string}"),
-                None => "/dev/null".to_owned(),
+            let (right_path, right_timestamp) = match right_part.mode {
+                Some(_) => (right_path_string, right_timestamp),
+                None => ("/dev/null", None),
             };
             if left_part.content.is_binary || right_part.content.is_binary {
-                // TODO: add option to emit Git binary diff
                 writeln!(
                     formatter,
                     "Binary files {left_path} and {right_path} differ"
                 )?;
-            } else {
-                formatter.with_label("file_header", |formatter| {
-                    writeln!(formatter, "--- {left_path}")?;
-                    writeln!(formatter, "+++ {right_path}")?;
-                    io::Result::Ok(())
-                })?;
-                show_unified_diff_hunks(
+                continue;
+            }
+            formatter.with_label(DiffLabel::FileHeader.as_str(), |formatter| {
+                writeln!(
                     formatter,
-                    &left_part.content.contents,
-                    &right_part.content.contents,
-                    num_context_lines,
+                    "--- {left_path}\t{}",
+                    format_posix_diff_timestamp(left_timestamp)
                 )?;
-            }
+                writeln!(
+                    formatter,
+                    "+++ {right_path}\t{}",
+                    format_posix_diff_timestamp(right_timestamp)
+                )?;
+                io::Result::Ok(())
+            })?;
+            show_unified_diff_hunks(
+                formatter,
+                &left_part.content.contents,
+                &right_part.content.contents,
+                options,
+                options.word_tokenizer.for_file_name(right_path_string),
+            )?;
+        }
+        if options.show_no_changes && !has_entries {
+            writeln!(formatter.labeled(DiffLabel::Empty), "(no changes)")?;
         }
         Ok(())
     }
     .block_on()
 }
 
+/// Status of a changed path, as shown in diff summary.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiffStatus {
+    /// The path was added.
+    Added,
+    /// The path was present before and after the change.
+    Modified,
+    /// The path was deleted.
+    Deleted,
+    /// The path was renamed from another path.
+    Renamed,
+    /// The path was copied from another path.
+    Copied,
+}
+
+impl DiffStatus {
+    /// All statuses in declaration order.
+    pub const ALL: &'static [DiffStatus] = &[
+        DiffStatus::Added,
+        DiffStatus::Modified,
+        DiffStatus::Deleted,
+        DiffStatus::Renamed,
+        DiffStatus::Copied,
+    ];
+
+    /// Returns the name of the status, such as `Modified`.
+    pub fn name(self) -> &'static str {
+        match self {
+            DiffStatus::Added => "Added",
+            DiffStatus::Modified => "Modified",
+            DiffStatus::Deleted => "Deleted",
+            DiffStatus::Renamed => "Renamed",
+            DiffStatus::Copied => "Copied",
+        }
+    }
+
+    /// Returns the character shown in diff summary, such as `M`.
+    pub fn as_char(self) -> char {
+        match self {
+            DiffStatus::Added => 'A',
+            DiffStatus::Modified => 'M',
+            DiffStatus::Deleted => 'D',
+            DiffStatus::Renamed => 'R',
+            DiffStatus::Copied => 'C',
+        }
+    }
+
+    fn label(self) -> DiffLabel {
+        match self {
+            DiffStatus::Added => DiffLabel::Added,
+            DiffStatus::Modified => DiffLabel::Modified,
+            DiffStatus::Deleted => DiffLabel::Removed,
+            DiffStatus::Renamed => DiffLabel::Renamed,
+            DiffStatus::Copied => DiffLabel::Copied,
+        }
+    }
+}
+
+/// Classifies the change from `before` to `after`.
+///
+/// `is_rename` and `is_copy` tell whether the path was renamed or copied from
+/// another path, which take precedence over the values. Panics if neither
+/// value is present otherwise.
+pub fn diff_status(
+    before: &MergedTreeValue,
+    after: &MergedTreeValue,
+    is_rename: bool,
+    is_copy: bool,
+) -> DiffStatus {
+    diff_status_of_presence(before.is_present(), after.is_present(), is_rename, is_copy)
+}
+
+fn diff_status_of_presence(
+    before_present: bool,
+    after_present: bool,
+    is_rename: bool,
+    is_copy: bool,
+) -> DiffStatus {
+    if is_rename {
+        DiffStatus::Renamed
+    } else if is_copy {
+        DiffStatus::Copied
+    } else {
+        match (before_present, after_present) {
+            (true, true) => DiffStatus::Modified,
+            (false, true) => DiffStatus::Added,
+            (true, false) => DiffStatus::Deleted,
+            (false, false) => panic!("absent path in diff"),
+        }
+    }
+}
+
 #[instrument(skip_all)]
 pub fn show_diff_summary(
     formatter: &mut dyn Formatter,
@@ -1269,9 +6548,46 @@ pub fn show_diff_summary(
     to_tree: &MergedTree,
     matcher: &dyn Matcher,
     copy_records: &CopyRecords,
+    options: &DiffSummaryOptions,
 ) -> Result<(), DiffRenderError> {
+    let same_dir_copy_records;
+    let copy_records = if options.rename_same_dir_only {
+        same_dir_copy_records = filter_same_dir_copy_records(copy_records);
+        &same_dir_copy_records
+    } else {
+        copy_records
+    };
     let mut tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
     let copied_sources = collect_copied_sources(copy_records, matcher);
+    let hash_suffix = |value: &MergedTreeValue| {
+        if options.with_hash {
+            content_hash(value).map_or_else(String::new, |hash| format!(" {hash}"))
+        } else {
+            String::new()
+        }
+    };
+    let mode_suffix = |before: &MergedTreeValue, after: &MergedTreeValue| {
+        if !options.show_mode {
+            return String::new();
+        }
+        match (summary_file_mode(before), summary_file_mode(after)) {
+            (Some(before_mode), Some(after_mode)) if before_mode != after_mode => {
+                format!(" ({before_mode}->{after_mode})")
+            }
+            (_, Some(mode)) | (Some(mode), None) => format!(" ({mode})"),
+            (None, None) => String::new(),
+        }
+    };
+
+    let hyperlinks = options.hyperlinks && formatter.is_color();
+    let link_for = |status: DiffStatus, path: &RepoPath| {
+        // Deleted files no longer exist in the workspace.
+        (hyperlinks && status != DiffStatus::Deleted)
+            .then(|| workspace_file_url(path_converter, path))
+    };
+
+    // Lines are buffered to count the changes per directory if collapsing.
+    let mut collapsible_lines = vec![];
 
     async {
         while let Some(TreeDiffEntry {
@@ -1281,37 +6597,268 @@ pub fn show_diff_summary(
         }) = tree_diff.next().await
         {
             let (before, after) = diff?;
-            if before_path != after_path {
-                let path = path_converter.format_copied_path(&before_path, &after_path);
-                if to_tree.path_value(&before_path).unwrap().is_absent() {
-                    writeln!(formatter.labeled("renamed"), "R {path}")?
-                } else {
-                    writeln!(formatter.labeled("copied"), "C {path}")?
-                }
-            } else {
-                let path = path_converter.format_file_path(&after_path);
-                match (before.is_present(), after.is_present()) {
-                    (true, true) => writeln!(formatter.labeled("modified"), "M {path}")?,
-                    (false, true) => writeln!(formatter.labeled("added"), "A {path}")?,
-                    (true, false) => {
-                        if !copied_sources.contains(before_path.as_ref()) {
-                            writeln!(formatter.labeled("removed"), "D {path}")?;
-                        }
+            let is_moved = before_path != after_path;
+            let is_rename = is_moved && to_tree.path_value(&before_path).unwrap().is_absent();
+            let status = diff_status(&before, &after, is_rename, is_moved && !is_rename);
+            let (path, hash) = match status {
+                DiffStatus::Renamed | DiffStatus::Copied => (
+                    format_diff_copied_path(
+                        path_converter,
+                        &before_path,
+                        &after_path,
+                        options.absolute_paths,
+                    ),
+                    hash_suffix(&after),
+                ),
+                DiffStatus::Added | DiffStatus::Modified => (
+                    format_diff_path(path_converter, &after_path, options.absolute_paths),
+                    hash_suffix(&after),
+                ),
+                DiffStatus::Deleted => {
+                    if copied_sources.contains(before_path.as_ref()) {
+                        continue;
                     }
-                    (false, false) => unreachable!(),
+                    (
+                        format_diff_path(path_converter, &after_path, options.absolute_paths),
+                        hash_suffix(&before),
+                    )
+                }
+            };
+            let mut suffix = hash + &mode_suffix(&before, &after);
+            if options.with_hunk_count {
+                let num_hunks = count_hunks(
+                    to_tree.store(),
+                    &before_path,
+                    before.clone(),
+                    &after_path,
+                    after.clone(),
+                    options.context,
+                )
+                .await?;
+                if let Some(num_hunks) = num_hunks {
+                    let plural = if num_hunks == 1 { "" } else { "s" };
+                    suffix += &format!(" ({num_hunks} hunk{plural})");
                 }
             }
+            let link = link_for(status, &after_path);
+            if options.collapse_dirs.is_some() {
+                let dir = collapsed_dir(&after_path, options.collapse_depth);
+                collapsible_lines.push((dir, status, path, suffix, link));
+                continue;
+            }
+            write_summary_line(formatter, status, &path, &suffix, link.as_deref())?;
         }
-        Ok(())
+        Ok::<(), DiffRenderError>(())
+    }
+    .block_on()?;
+
+    if let Some(threshold) = options.collapse_dirs {
+        let mut dir_statuses: HashMap<&RepoPath, Vec<DiffStatus>> = HashMap::new();
+        for (dir, status, ..) in &collapsible_lines {
+            if let Some(dir) = dir {
+                dir_statuses.entry(dir).or_default().push(*status);
+            }
+        }
+        let mut shown_dirs = HashSet::new();
+        for (dir, status, path, suffix, link) in &collapsible_lines {
+            let collapsed = dir
+                .as_deref()
+                .map(|dir| (dir, &dir_statuses[dir]))
+                .filter(|(_, statuses)| statuses.len() > threshold);
+            let Some((dir, statuses)) = collapsed else {
+                write_summary_line(formatter, *status, path, suffix, link.as_deref())?;
+                continue;
+            };
+            if !shown_dirs.insert(dir) {
+                continue;
+            }
+            // Mixed changes are shown as modification of the directory.
+            let status = if statuses.iter().all_equal() {
+                statuses[0]
+            } else {
+                DiffStatus::Modified
+            };
+            writeln!(
+                formatter.labeled(status.label()),
+                "{} {}{} ({} files)",
+                status.as_char(),
+                format_diff_path(path_converter, dir, options.absolute_paths),
+                std::path::MAIN_SEPARATOR,
+                statuses.len()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of hunks in the Git-format diff between `before` and
+/// `after`, or `None` if either side is binary.
+async fn count_hunks(
+    store: &Store,
+    before_path: &RepoPath,
+    before: MergedTreeValue,
+    after_path: &RepoPath,
+    after: MergedTreeValue,
+    num_context_lines: usize,
+) -> Result<Option<usize>, DiffRenderError> {
+    let before = materialize_tree_value(store, before_path, before).await?;
+    let after = materialize_tree_value(store, after_path, after).await?;
+    let before_part = git_diff_part(before_path, before)?;
+    let after_part = git_diff_part(after_path, after)?;
+    if before_part.content.is_binary || after_part.content.is_binary {
+        return Ok(None);
+    }
+    let hunks = unified_diff_hunks(
+        &before_part.content.contents,
+        &after_part.content.contents,
+        num_context_lines,
+        DiffChangeOrder::default(),
+        None,
+        DiffWordTokenizer::default(),
+        false,
+        None,
+        None,
+        None,
+    );
+    Ok(Some(hunks.len()))
+}
+
+/// Writes a line of `status` and `path` followed by `suffix`, optionally
+/// linking the path to the file.
+fn write_summary_line(
+    formatter: &mut dyn Formatter,
+    status: DiffStatus,
+    path: &str,
+    suffix: &str,
+    link: Option<&str>,
+) -> io::Result<()> {
+    formatter.with_label(status.label().as_str(), |formatter| {
+        write!(formatter, "{} ", status.as_char())?;
+        match link {
+            Some(url) => write_hyperlink(formatter, url, path)?,
+            None => write!(formatter, "{path}")?,
+        }
+        writeln!(formatter, "{suffix}")
+    })
+}
+
+/// Returns the directory of the leading `depth` components of the parent of
+/// `path`, or `None` if `path` is at the root.
+fn collapsed_dir(path: &RepoPath, depth: usize) -> Option<RepoPathBuf> {
+    let parent = path.parent().filter(|parent| !parent.is_root())?;
+    let dir = parent
+        .components()
+        .take(depth)
+        .map(|component| component.as_str())
+        .join("/");
+    Some(RepoPathBuf::from_internal_string(dir))
+}
+
+/// Returns copy records whose source and target are in the same directory.
+fn filter_same_dir_copy_records(copy_records: &CopyRecords) -> CopyRecords {
+    let records = copy_records
+        .iter()
+        .filter(|record| record.source.parent() == record.target.parent())
+        .map(|record| Ok::<_, BackendError>(record.clone()))
+        .collect_vec();
+    let mut filtered = CopyRecords::default();
+    filtered
+        .add_records(futures::stream::iter(records).boxed())
+        .expect("in-memory records should not fail");
+    filtered
+}
+
+/// Drops copy records whose target contents are less than `threshold` percent
+/// similar to the source contents, so that Git diffs show them as deleted and
+/// added files as Git does.
+fn filter_similar_copy_records(
+    store: &Store,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    copy_records: &CopyRecords,
+    threshold: u8,
+) -> Result<CopyRecords, DiffRenderError> {
+    let mut records = vec![];
+    for record in copy_records.iter() {
+        let source_value = from_tree.path_value(&record.source)?;
+        let target_value = to_tree.path_value(&record.target)?;
+        // Keep records which can't be compared within the diffed trees.
+        if source_value.is_absent() || target_value.is_absent() {
+            records.push(Ok::<_, BackendError>(record.clone()));
+            continue;
+        }
+        let source_value =
+            materialize_tree_value(store, &record.source, source_value).block_on()?;
+        let target_value =
+            materialize_tree_value(store, &record.target, target_value).block_on()?;
+        let source_part = git_diff_part(&record.source, source_value)?;
+        let target_part = git_diff_part(&record.target, target_value)?;
+        let similarity =
+            content_similarity(&source_part.content.contents, &target_part.content.contents);
+        if similarity >= threshold {
+            records.push(Ok(record.clone()));
+        }
+    }
+    let mut filtered = CopyRecords::default();
+    filtered
+        .add_records(futures::stream::iter(records).boxed())
+        .expect("in-memory records should not fail");
+    Ok(filtered)
+}
+
+/// Returns the percentage of bytes in the larger of `left` and `right` which
+/// belong to lines present on both sides.
+fn content_similarity(left: &[u8], right: &[u8]) -> u8 {
+    let max_len = left.len().max(right.len());
+    if left == right || max_len == 0 {
+        return 100;
+    }
+    let mut left_lines: HashMap<&[u8], usize> = HashMap::new();
+    for line in left.split_inclusive(|b| *b == b'\n') {
+        *left_lines.entry(line).or_default() += 1;
+    }
+    let mut common_len = 0;
+    for line in right.split_inclusive(|b| *b == b'\n') {
+        if let Some(count) = left_lines.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            common_len += line.len();
+        }
+    }
+    (common_len * 100 / max_len) as u8
+}
+
+/// Returns the Git file mode of `value` without reading the file contents.
+/// Conflicts are executable only if all sides agree.
+fn summary_file_mode(value: &MergedTreeValue) -> Option<&'static str> {
+    let executable = match value.as_resolved() {
+        Some(None) | Some(Some(TreeValue::Tree(_))) | Some(Some(TreeValue::Conflict(_))) => {
+            return None;
+        }
+        Some(Some(TreeValue::File { executable, .. })) => *executable,
+        Some(Some(TreeValue::Symlink(_))) => return Some("120000"),
+        Some(Some(TreeValue::GitSubmodule(_))) => return Some("160000"),
+        None => value
+            .to_executable_merge()
+            .and_then(|merge| merge.resolve_trivial().copied())
+            .unwrap_or(false),
+    };
+    Some(if executable { "100755" } else { "100644" })
+}
+
+/// Returns the hex ID of the file or symlink content if `value` is resolved.
+fn content_hash(value: &MergedTreeValue) -> Option<String> {
+    match value.as_resolved()? {
+        Some(TreeValue::File { id, .. }) => Some(id.hex()),
+        Some(TreeValue::Symlink(id)) => Some(id.hex()),
+        _ => None,
     }
-    .block_on()
 }
 
 struct DiffStat {
     path: String,
     added: usize,
     removed: usize,
-    is_deletion: bool,
+    is_binary: bool,
 }
 
 fn get_diff_stat(
@@ -1339,74 +6886,180 @@ fn get_diff_stat(
         path,
         added,
         removed,
-        is_deletion: right_content.contents.is_empty(),
+        is_binary: left_content.is_binary || right_content.is_binary,
+    }
+}
+
+/// Per-file stats of a diff.
+struct DiffStats {
+    entries: Vec<DiffStat>,
+}
+
+impl DiffStats {
+    /// Collects stats of the entries in `tree_diff` in order. If `parallel` is
+    /// set, line counts are computed on the thread pool in batches.
+    fn collect(
+        store: &Store,
+        tree_diff: TreeDiffStream,
+        path_converter: &RepoPathUiConverter,
+        copied_sources: &HashSet<&RepoPath>,
+        parallel: bool,
+        absolute_paths: bool,
+    ) -> Result<Self, DiffRenderError> {
+        let mut entries: Vec<DiffStat> = vec![];
+        let mut diff_stream = tree_diff
+            .map(
+                |TreeDiffEntry {
+                     source,
+                     target,
+                     value,
+                 }| async move {
+                    let (left, right) = value?;
+                    // Skip the "delete" entry when there is a rename.
+                    if right.is_absent() && copied_sources.contains(source.as_ref()) {
+                        return Ok(None);
+                    }
+                    // Unchanged entries (e.g. pure renames) contribute no lines,
+                    // so don't bother reading their contents.
+                    let contents = if left == right {
+                        None
+                    } else {
+                        let (left, right) = futures::try_join!(
+                            materialize_tree_value(store, &source, left),
+                            materialize_tree_value(store, &target, right),
+                        )?;
+                        Some((diff_content(&source, left)?, diff_content(&target, right)?))
+                    };
+                    Ok::<_, DiffRenderError>(Some((source, target, contents)))
+                },
+            )
+            .buffered((store.concurrency() / 2).max(1));
+        let to_stat = |left_path: RepoPathBuf,
+                       right_path: RepoPathBuf,
+                       contents: Option<(FileContent, FileContent)>| {
+            let path = if left_path == right_path {
+                format_diff_path(path_converter, &left_path, absolute_paths)
+            } else {
+                format_diff_copied_path(path_converter, &left_path, &right_path, absolute_paths)
+            };
+            match contents {
+                Some((left_content, right_content)) => {
+                    get_diff_stat(path, &left_content, &right_content)
+                }
+                None => DiffStat {
+                    path,
+                    added: 0,
+                    removed: 0,
+                    is_binary: false,
+                },
+            }
+        };
+        async {
+            if parallel {
+                // Bound the number of files whose contents are held in memory.
+                let mut chunks = diff_stream.chunks(rayon::current_num_threads() * 4);
+                while let Some(chunk) = chunks.next().await {
+                    let chunk: Vec<_> = chunk
+                        .into_iter()
+                        .filter_map(Result::transpose)
+                        .try_collect()?;
+                    entries.par_extend(chunk.into_par_iter().map(
+                        |(left_path, right_path, contents)| {
+                            to_stat(left_path, right_path, contents)
+                        },
+                    ));
+                }
+            } else {
+                while let Some(entry) = diff_stream.next().await {
+                    let Some((left_path, right_path, contents)) = entry? else {
+                        continue;
+                    };
+                    entries.push(to_stat(left_path, right_path, contents));
+                }
+            }
+            Ok::<(), DiffRenderError>(())
+        }
+        .block_on()?;
+        Ok(DiffStats { entries })
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn show_diff_stat(
     formatter: &mut dyn Formatter,
     store: &Store,
     tree_diff: TreeDiffStream,
     path_converter: &RepoPathUiConverter,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
     display_width: usize,
+    options: &DiffStatOptions,
 ) -> Result<(), DiffRenderError> {
-    let mut stats: Vec<DiffStat> = vec![];
-    let mut unresolved_renames = HashSet::new();
-    let mut max_path_width = 0;
-    let mut max_diffs = 0;
-
-    let mut diff_stream = materialized_diff_stream(store, tree_diff);
-    async {
-        while let Some(MaterializedTreeDiffEntry {
-            source: left_path,
-            target: right_path,
-            value: diff,
-        }) = diff_stream.next().await
-        {
-            let (left, right) = diff?;
-            let left_content = diff_content(&left_path, left)?;
-            let right_content = diff_content(&right_path, right)?;
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let stats = DiffStats::collect(
+        store,
+        tree_diff,
+        path_converter,
+        &copied_sources,
+        options.parallel,
+        options.absolute_paths,
+    )?;
+    write_diff_stat(formatter, &stats, display_width, options)?;
+    Ok(())
+}
 
-            let left_ui_path = path_converter.format_file_path(&left_path);
-            let path = if left_path == right_path {
-                left_ui_path
+fn write_diff_stat(
+    formatter: &mut dyn Formatter,
+    stats: &DiffStats,
+    display_width: usize,
+    options: &DiffStatOptions,
+) -> io::Result<()> {
+    if options.format == DiffStatFormat::Numstat {
+        for stat in &stats.entries {
+            if stat.is_binary {
+                writeln!(formatter, "-\t-\t{}", stat.path)?;
             } else {
-                unresolved_renames.insert(left_ui_path);
-                path_converter.format_copied_path(&left_path, &right_path)
-            };
-            max_path_width = max(max_path_width, path.width());
-            let stat = get_diff_stat(path, &left_content, &right_content);
-            max_diffs = max(max_diffs, stat.added + stat.removed);
-            stats.push(stat);
+                writeln!(formatter, "{}\t{}\t{}", stat.added, stat.removed, stat.path)?;
+            }
         }
-        Ok::<(), DiffRenderError>(())
+        return Ok(());
     }
-    .block_on()?;
+    let max_path_width = stats
+        .entries
+        .iter()
+        .map(|stat| stat.path.width())
+        .max()
+        .unwrap_or(0);
+    let max_diffs = stats
+        .entries
+        .iter()
+        .map(|stat| stat.added + stat.removed)
+        .max()
+        .unwrap_or(0);
 
     let number_padding = max_diffs.to_string().len();
     // 4 characters padding for the graph
     let available_width = display_width.saturating_sub(4 + " | ".len() + number_padding);
     // Always give at least a tiny bit of room
     let available_width = max(available_width, 5);
-    let max_path_width = max_path_width.clamp(3, (0.7 * available_width as f64) as usize);
+    let path_width_limit = (0.7 * available_width as f64) as usize;
+    // Paths can use the width beyond the maximum bar length
+    let path_width_limit = match options.max_bar_width {
+        Some(width) => max(path_width_limit, available_width.saturating_sub(width)),
+        None => path_width_limit,
+    };
+    let max_path_width = max_path_width.clamp(3, path_width_limit);
     let max_bar_length = available_width.saturating_sub(max_path_width);
+    let max_bar_length = options
+        .max_bar_width
+        .map_or(max_bar_length, |width| min(max_bar_length, width));
     let factor = if max_diffs < max_bar_length {
         1.0
     } else {
         max_bar_length as f64 / max_diffs as f64
     };
 
-    let mut total_added = 0;
-    let mut total_removed = 0;
-    let mut total_files = 0;
-    for stat in &stats {
-        if stat.is_deletion && unresolved_renames.contains(&stat.path) {
-            continue;
-        }
-
-        total_added += stat.added;
-        total_removed += stat.removed;
-        total_files += 1;
+    for stat in &stats.entries {
         let bar_added = (stat.added as f64 * factor).ceil() as usize;
         let bar_removed = (stat.removed as f64 * factor).ceil() as usize;
         // replace start of path with ellipsis if the path is too long
@@ -1419,11 +7072,56 @@ pub fn show_diff_stat(
             stat.added + stat.removed,
             if bar_added + bar_removed > 0 { " " } else { "" },
         )?;
-        write!(formatter.labeled("added"), "{}", "+".repeat(bar_added))?;
-        writeln!(formatter.labeled("removed"), "{}", "-".repeat(bar_removed))?;
+        write!(
+            formatter.labeled(DiffLabel::Added),
+            "{}",
+            "+".repeat(bar_added)
+        )?;
+        writeln!(
+            formatter.labeled(DiffLabel::Removed),
+            "{}",
+            "-".repeat(bar_removed)
+        )?;
+    }
+    if options.summary_line {
+        write_diff_stat_summary(formatter, &stats)?;
+    }
+    Ok(())
+}
+
+/// Prints only the `N files changed, ...` line of the diff stat.
+pub fn show_diff_shortstat(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    tree_diff: TreeDiffStream,
+    path_converter: &RepoPathUiConverter,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+) -> Result<(), DiffRenderError> {
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let stats = DiffStats::collect(
+        store,
+        tree_diff,
+        path_converter,
+        &copied_sources,
+        false,
+        false,
+    )?;
+    write_diff_stat_summary(formatter, &stats)?;
+    Ok(())
+}
+
+fn write_diff_stat_summary(formatter: &mut dyn Formatter, stats: &DiffStats) -> io::Result<()> {
+    let mut total_added = 0;
+    let mut total_removed = 0;
+    let mut total_files = 0;
+    for stat in &stats.entries {
+        total_added += stat.added;
+        total_removed += stat.removed;
+        total_files += 1;
     }
     writeln!(
-        formatter.labeled("stat-summary"),
+        formatter.labeled(DiffLabel::StatSummary),
         "{} file{} changed, {} insertion{}(+), {} deletion{}(-)",
         total_files,
         if total_files == 1 { "" } else { "s" },
@@ -1431,37 +7129,184 @@ pub fn show_diff_stat(
         if total_added == 1 { "" } else { "s" },
         total_removed,
         if total_removed == 1 { "" } else { "s" },
-    )?;
+    )
+}
+
+/// Prints the number of files whose conflicts were introduced or resolved.
+pub fn show_diff_conflict_stat(
+    formatter: &mut dyn Formatter,
+    mut tree_diff: TreeDiffStream,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+) -> Result<(), DiffRenderError> {
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut num_introduced = 0;
+    let mut num_resolved = 0;
+    async {
+        while let Some(TreeDiffEntry { source, value, .. }) = tree_diff.next().await {
+            let (before, after) = value?;
+            // The source of a rename is still conflicted at the target.
+            if after.is_absent() && copied_sources.contains(source.as_ref()) {
+                continue;
+            }
+            match (before.is_resolved(), after.is_resolved()) {
+                (true, false) => num_introduced += 1,
+                (false, true) => num_resolved += 1,
+                _ => {}
+            }
+        }
+        Ok::<(), DiffRenderError>(())
+    }
+    .block_on()?;
+    write_conflict_stat(formatter, num_introduced, num_resolved)?;
     Ok(())
 }
 
-pub fn show_types(
+fn write_conflict_stat(
+    formatter: &mut dyn Formatter,
+    num_introduced: usize,
+    num_resolved: usize,
+) -> io::Result<()> {
+    writeln!(
+        formatter.labeled(DiffLabel::StatSummary),
+        "{num_introduced} conflict{} introduced, {num_resolved} resolved",
+        if num_introduced == 1 { "" } else { "s" },
+    )
+}
+
+/// Prints each changed path on one line as `M path +added -removed`.
+pub fn show_diff_oneline_stat(
+    formatter: &mut dyn Formatter,
+    store: &Store,
+    path_converter: &RepoPathUiConverter,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+) -> Result<(), DiffRenderError> {
+    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
+
+    async {
+        while let Some(MaterializedTreeDiffEntry {
+            source: left_path,
+            target: right_path,
+            value: diff,
+        }) = diff_stream.next().await
+        {
+            let (left, right) = diff?;
+            let is_moved = left_path != right_path;
+            let is_rename = is_moved && to_tree.path_value(&left_path)?.is_absent();
+            let status = diff_status_of_presence(
+                left.is_present(),
+                right.is_present(),
+                is_rename,
+                is_moved && !is_rename,
+            );
+            // Skip the "delete" entry when there is a rename.
+            if status == DiffStatus::Deleted && copied_sources.contains(left_path.as_ref()) {
+                continue;
+            }
+            let path = path_converter.format_copied_path(&left_path, &right_path);
+            let left_content = diff_content(&left_path, left)?;
+            let right_content = diff_content(&right_path, right)?;
+            let stat = get_diff_stat(path, &left_content, &right_content);
+            write!(
+                formatter.labeled(status.label()),
+                "{} {}",
+                status.as_char(),
+                stat.path
+            )?;
+            write!(formatter, " ")?;
+            write!(formatter.labeled(DiffLabel::Added), "+{}", stat.added)?;
+            write!(formatter, " ")?;
+            writeln!(formatter.labeled(DiffLabel::Removed), "-{}", stat.removed)?;
+        }
+        Ok(())
+    }
+    .block_on()
+}
+
+pub fn show_types(
+    formatter: &mut dyn Formatter,
+    path_converter: &RepoPathUiConverter,
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+    options: &DiffTypesOptions,
+) -> Result<(), DiffRenderError> {
+    let mut tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let type_string = |value: &MergedTreeValue| {
+        let c = diff_summary_char(value);
+        if options.conflict_detail && value.as_resolved().is_none() {
+            format!("{c}{}", value.num_sides())
+        } else {
+            c.to_string()
+        }
+    };
+
+    async {
+        while let Some(TreeDiffEntry {
+            source,
+            target,
+            value: diff,
+        }) = tree_diff.next().await
+        {
+            let (before, after) = diff?;
+            if after.is_absent() && copied_sources.contains(source.as_ref()) {
+                continue;
+            }
+            writeln!(
+                formatter.labeled(DiffLabel::Modified),
+                "{}{} {}",
+                type_string(&before),
+                type_string(&after),
+                path_converter.format_copied_path(&source, &target)
+            )?;
+        }
+        Ok(())
+    }
+    .block_on()
+}
+
+/// Shows the Git file modes before and after the change for each path whose
+/// mode changed.
+pub fn show_mode_changes(
     formatter: &mut dyn Formatter,
+    store: &Store,
     path_converter: &RepoPathUiConverter,
     from_tree: &MergedTree,
     to_tree: &MergedTree,
     matcher: &dyn Matcher,
     copy_records: &CopyRecords,
 ) -> Result<(), DiffRenderError> {
-    let mut tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
-    let copied_sources = collect_copied_sources(copy_records, matcher);
+    let tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let mut diff_stream = materialized_diff_stream(store, tree_diff);
 
     async {
-        while let Some(TreeDiffEntry {
+        while let Some(MaterializedTreeDiffEntry {
             source,
             target,
             value: diff,
-        }) = tree_diff.next().await
+        }) = diff_stream.next().await
         {
             let (before, after) = diff?;
-            if after.is_absent() && copied_sources.contains(source.as_ref()) {
+            // Added and deleted paths, including the "delete" entry of a
+            // rename, have no mode change.
+            let (Some(before_mode), Some(after_mode)) =
+                (git_file_mode(&before), git_file_mode(&after))
+            else {
+                continue;
+            };
+            if before_mode == after_mode {
                 continue;
             }
             writeln!(
-                formatter.labeled("modified"),
-                "{}{} {}",
-                diff_summary_char(&before),
-                diff_summary_char(&after),
+                formatter.labeled(DiffLabel::Modified),
+                "{before_mode} -> {after_mode} {}",
                 path_converter.format_copied_path(&source, &target)
             )?;
         }
@@ -1483,6 +7328,38 @@ fn diff_summary_char(value: &MergedTreeValue) -> char {
     }
 }
 
+/// Returns `(source, target)` paths of changed files without rendering diff.
+///
+/// The source and target paths are equal unless the file was copied or
+/// renamed. The deletion of a renamed file is not reported separately.
+pub fn changed_paths(
+    from_tree: &MergedTree,
+    to_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    copy_records: &CopyRecords,
+) -> Result<Vec<(RepoPathBuf, RepoPathBuf)>, DiffRenderError> {
+    let mut tree_diff = from_tree.diff_stream(to_tree, matcher, copy_records);
+    let copied_sources = collect_copied_sources(copy_records, matcher);
+
+    async {
+        let mut paths = vec![];
+        while let Some(TreeDiffEntry {
+            source,
+            target,
+            value: diff,
+        }) = tree_diff.next().await
+        {
+            let (_, after) = diff?;
+            if after.is_absent() && copied_sources.contains(source.as_ref()) {
+                continue;
+            }
+            paths.push((source, target));
+        }
+        Ok(paths)
+    }
+    .block_on()
+}
+
 pub fn show_names(
     formatter: &mut dyn Formatter,
     mut tree_diff: TreeDiffStream,
@@ -1499,3 +7376,1235 @@ pub fn show_names(
     }
     .block_on()
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use bstr::ByteSlice as _;
+    use jj_lib::backend::{CommitId, CopyRecord};
+    use jj_lib::matchers::EverythingMatcher;
+    use testutils::TestRepo;
+
+    use super::*;
+
+    /// Formatter that counts `push_label()` calls and discards the output.
+    #[derive(Default)]
+    struct LabelCounter {
+        data: Vec<u8>,
+        push_count: usize,
+    }
+
+    impl io::Write for LabelCounter {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Formatter for LabelCounter {
+        fn raw(&mut self) -> &mut dyn io::Write {
+            self
+        }
+
+        fn push_label(&mut self, _label: &str) -> io::Result<()> {
+            self.push_count += 1;
+            Ok(())
+        }
+
+        fn pop_label(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Formatter which fails with `BrokenPipe` once `limit` bytes are written,
+    /// as if the pager were closed.
+    struct ClosedPipe {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl io::Write for ClosedPipe {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            if self.data.len() + data.len() > self.limit {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.data.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Formatter for ClosedPipe {
+        fn raw(&mut self) -> &mut dyn io::Write {
+            self
+        }
+
+        fn push_label(&mut self, _label: &str) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn pop_label(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn two_file_trees(test_repo: &TestRepo) -> (MergedTree, MergedTree) {
+        let file1 = RepoPath::from_internal_string("file1");
+        let file2 = RepoPath::from_internal_string("file2");
+        let from_tree = testutils::create_tree(&test_repo.repo, &[(file1, "a\n"), (file2, "b\n")]);
+        let to_tree = testutils::create_tree(&test_repo.repo, &[(file1, "A\n"), (file2, "B\n")]);
+        (from_tree, to_tree)
+    }
+
+    #[test]
+    fn test_git_diff_rename_threshold() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let old_file = RepoPath::from_internal_string("old");
+        let new_file = RepoPath::from_internal_string("new");
+        // 3 of 10 lines are kept, which is 30% similar
+        let from_tree = testutils::create_tree(
+            &test_repo.repo,
+            &[(old_file, "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n")],
+        );
+        let to_tree = testutils::create_tree(
+            &test_repo.repo,
+            &[(new_file, "a\nb\nc\nk\nl\nm\nn\no\np\nq\n")],
+        );
+        let mut copy_records = CopyRecords::default();
+        copy_records
+            .add_records(Box::pin(futures::stream::iter([Ok(CopyRecord {
+                target: new_file.to_owned(),
+                target_commit: CommitId::new(vec![]),
+                source: old_file.to_owned(),
+                source_file: FileId::new(vec![]),
+                source_commit: CommitId::new(vec![]),
+            })])))
+            .unwrap();
+        let show = |rename_threshold: Option<u8>| {
+            let mut output = vec![];
+            show_git_diff(
+                &mut PlainTextFormatter::new(&mut output),
+                store,
+                &from_tree,
+                &to_tree,
+                &EverythingMatcher,
+                &copy_records,
+                &UnifiedDiffOptions {
+                    rename_threshold,
+                    ..UnifiedDiffOptions::default()
+                },
+            )
+            .unwrap();
+            output.to_str_lossy().into_owned()
+        };
+
+        assert_eq!(
+            content_similarity(
+                b"a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n",
+                b"a\nb\nc\nk\nl\nm\nn\no\np\nq\n"
+            ),
+            30
+        );
+
+        // The copy record is trusted by default
+        let output = show(None);
+        assert!(output.contains("rename from old\nrename to new\n"));
+        assert!(!output.contains("deleted file mode"));
+
+        // Below the threshold, the rename is shown as a deletion and an
+        // addition
+        let output = show(Some(50));
+        assert!(!output.contains("rename from"));
+        assert!(output.contains("diff --git a/new b/new\nnew file mode 100644\n"));
+        assert!(output.contains("diff --git a/old b/old\ndeleted file mode 100644\n"));
+
+        // At the threshold, the rename is kept
+        let output = show(Some(30));
+        assert!(output.contains("rename from old\nrename to new\n"));
+        assert!(!output.contains("deleted file mode"));
+    }
+
+    #[test]
+    fn test_write_git_diff_files_skips_like_show_git_diff() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let old_file = RepoPath::from_internal_string("old");
+        let new_file = RepoPath::from_internal_string("new");
+        let generated_file = RepoPath::from_internal_string("generated");
+        // 3 of 10 lines are kept, which is 30% similar
+        let from_tree = testutils::create_tree(
+            &test_repo.repo,
+            &[(old_file, "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n")],
+        );
+        let to_tree = testutils::create_tree(
+            &test_repo.repo,
+            &[
+                (new_file, "a\nb\nc\nk\nl\nm\nn\no\np\nq\n"),
+                (generated_file, "// @generated\n"),
+            ],
+        );
+        let mut copy_records = CopyRecords::default();
+        copy_records
+            .add_records(Box::pin(futures::stream::iter([Ok(CopyRecord {
+                target: new_file.to_owned(),
+                target_commit: CommitId::new(vec![]),
+                source: old_file.to_owned(),
+                source_file: FileId::new(vec![]),
+                source_commit: CommitId::new(vec![]),
+            })])))
+            .unwrap();
+        let output_dir = testutils::new_temp_dir();
+        let num_files = write_git_diff_files(
+            store,
+            &from_tree,
+            &to_tree,
+            &EverythingMatcher,
+            &copy_records,
+            &UnifiedDiffOptions {
+                rename_threshold: Some(50),
+                generated_markers: Some(vec!["@generated".to_owned()]),
+                ..UnifiedDiffOptions::default()
+            },
+            output_dir.path(),
+        )
+        .unwrap();
+
+        // The dissimilar rename is written as a deletion and an addition, and
+        // the generated file is skipped
+        assert_eq!(num_files, 2);
+        let read_patch = |name: &str| std::fs::read_to_string(output_dir.path().join(name));
+        assert!(read_patch("new.patch")
+            .unwrap()
+            .contains("diff --git a/new b/new\nnew file mode 100644\n"));
+        assert!(read_patch("old.patch")
+            .unwrap()
+            .contains("diff --git a/old b/old\ndeleted file mode 100644\n"));
+        assert!(read_patch("generated.patch").is_err());
+    }
+
+    #[test]
+    fn test_git_diff_stops_at_closed_pipe() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let (from_tree, to_tree) = two_file_trees(&test_repo);
+        let show = |formatter: &mut dyn Formatter| {
+            show_git_diff(
+                formatter,
+                store,
+                &from_tree,
+                &to_tree,
+                &EverythingMatcher,
+                &CopyRecords::default(),
+                &UnifiedDiffOptions::default(),
+            )
+        };
+
+        let mut output = vec![];
+        show(&mut PlainTextFormatter::new(&mut output)).unwrap();
+        let limit = output.find(b"diff --git a/file2").unwrap();
+
+        // The pipe is closed after the first file
+        let mut formatter = ClosedPipe {
+            data: vec![],
+            limit,
+        };
+        assert_matches!(
+            show(&mut formatter),
+            Err(DiffRenderError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe
+        );
+        assert_eq!(formatter.data, output[..limit]);
+    }
+
+    #[test]
+    fn test_color_words_diff_stops_at_closed_pipe() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let (from_tree, to_tree) = two_file_trees(&test_repo);
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let copy_records = CopyRecords::default();
+        let show = |formatter: &mut dyn Formatter| {
+            let tree_diff = from_tree.diff_stream(&to_tree, &EverythingMatcher, &copy_records);
+            show_color_words_diff(
+                formatter,
+                store,
+                &to_tree,
+                tree_diff,
+                &path_converter,
+                &EverythingMatcher,
+                &CopyRecords::default(),
+                &ColorWordsDiffOptions::default(),
+            )
+        };
+
+        let mut output = vec![];
+        show(&mut PlainTextFormatter::new(&mut output)).unwrap();
+        let limit = output.find(b"Modified regular file file2").unwrap();
+
+        // The pipe is closed after the first file
+        let mut formatter = ClosedPipe {
+            data: vec![],
+            limit,
+        };
+        assert_matches!(
+            show(&mut formatter),
+            Err(DiffRenderError::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe
+        );
+        assert_eq!(formatter.data, output[..limit]);
+    }
+
+    #[test]
+    fn test_show_merge_base_diff() {
+        let settings = testutils::user_settings();
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let file = RepoPath::from_internal_string("file");
+        let other_file = RepoPath::from_internal_string("other");
+
+        // B
+        // | A
+        // |/
+        // base
+        let mut tx = repo.start_transaction(&settings);
+        let base_tree = testutils::create_tree(repo, &[(file, "base\n")]);
+        let base = tx
+            .mut_repo()
+            .new_commit(
+                &settings,
+                vec![repo.store().root_commit_id().clone()],
+                base_tree.id(),
+            )
+            .write()
+            .unwrap();
+        let tree_a = testutils::create_tree(repo, &[(file, "base\n"), (other_file, "a\n")]);
+        let commit_a = tx
+            .mut_repo()
+            .new_commit(&settings, vec![base.id().clone()], tree_a.id())
+            .write()
+            .unwrap();
+        let tree_b = testutils::create_tree(repo, &[(file, "b\n")]);
+        let commit_b = tx
+            .mut_repo()
+            .new_commit(&settings, vec![base.id().clone()], tree_b.id())
+            .write()
+            .unwrap();
+
+        let ui = Ui::with_config(&crate::config::default_config()).unwrap();
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let renderer = DiffRenderer::new(
+            tx.repo(),
+            &path_converter,
+            vec![DiffFormat::Summary(Box::default())],
+        );
+
+        // Two-dot diff includes the changes in A
+        let mut output = vec![];
+        renderer
+            .show_commit_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &commit_a,
+                &commit_b,
+                &EverythingMatcher,
+                &CopyRecords::default(),
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "M file\nD other\n");
+
+        // Three-dot diff only includes the changes in B
+        let mut output = vec![];
+        renderer
+            .show_merge_base_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &commit_a,
+                &commit_b,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "M file\n");
+    }
+
+    #[test]
+    fn test_show_rebase_diff() {
+        let settings = testutils::user_settings();
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let file = RepoPath::from_internal_string("file");
+        let other_file = RepoPath::from_internal_string("other");
+
+        // commit
+        // |
+        // new_base  old_base
+        // |        /
+        // root
+        let mut tx = repo.start_transaction(&settings);
+        let mut new_commit = |entries: &[(&RepoPath, &str)], parent_id: &CommitId| {
+            let tree = testutils::create_tree(repo, entries);
+            tx.mut_repo()
+                .new_commit(&settings, vec![parent_id.clone()], tree.id())
+                .write()
+                .unwrap()
+        };
+        let root_id = repo.store().root_commit_id().clone();
+        let old_base = new_commit(&[(file, "base\n")], &root_id);
+        let new_base = new_commit(&[(file, "base\n"), (other_file, "new\n")], &root_id);
+        let commit = new_commit(&[(file, "commit\n"), (other_file, "new\n")], new_base.id());
+
+        let ui = Ui::with_config(&crate::config::default_config()).unwrap();
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let renderer = DiffRenderer::new(
+            tx.repo(),
+            &path_converter,
+            vec![DiffFormat::Summary(Box::default())],
+        );
+
+        // Changes in the new base only show up in the diff against the old
+        // base
+        let mut output = vec![];
+        renderer
+            .show_rebase_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &commit,
+                &old_base,
+                &new_base,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(
+            output.to_str_lossy(),
+            format!(
+                "=== Against old base {} ===\nM file\nA other\n\
+                 === Against new base {} ===\nM file\n",
+                short_commit_hash(old_base.id()),
+                short_commit_hash(new_base.id()),
+            )
+        );
+    }
+
+    #[test]
+    fn test_show_path_pair_diff() {
+        let settings = testutils::user_settings();
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let old_file = RepoPath::from_internal_string("old");
+        let new_file = RepoPath::from_internal_string("new");
+        let missing_file = RepoPath::from_internal_string("missing");
+
+        let mut tx = repo.start_transaction(&settings);
+        let tree_a = testutils::create_tree(repo, &[(old_file, "a\nb\n")]);
+        let commit_a = tx
+            .mut_repo()
+            .new_commit(
+                &settings,
+                vec![repo.store().root_commit_id().clone()],
+                tree_a.id(),
+            )
+            .write()
+            .unwrap();
+        let tree_b = testutils::create_tree(repo, &[(new_file, "a\nc\n")]);
+        let commit_b = tx
+            .mut_repo()
+            .new_commit(&settings, vec![commit_a.id().clone()], tree_b.id())
+            .write()
+            .unwrap();
+
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let show = |formats: Vec<DiffFormat>, path_b: &RepoPath| {
+            let renderer = DiffRenderer::new(tx.repo(), &path_converter, formats);
+            let mut output = vec![];
+            renderer
+                .show_path_pair_diff(
+                    &mut PlainTextFormatter::new(&mut output),
+                    &commit_a,
+                    old_file,
+                    &commit_b,
+                    path_b,
+                )
+                .map(|()| String::from_utf8(output).unwrap())
+        };
+
+        let formats = vec![
+            DiffFormat::ColorWords(Box::default()),
+            DiffFormat::Git(Box::default()),
+        ];
+        assert_eq!(
+            show(formats, new_file).unwrap(),
+            "Modified new (old => new):\n   1    1: a\n   2    2: bc\n\
+             --- a/old\n+++ b/new\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+
+        // A missing path is compared as an empty file
+        let formats = vec![DiffFormat::ColorWords(Box::default())];
+        assert_eq!(
+            show(formats, missing_file).unwrap(),
+            "Modified missing (old => missing):\n   1     : a\n   2     : b\n"
+        );
+
+        let formats = vec![DiffFormat::Summary(Box::default())];
+        assert_matches!(
+            show(formats, new_file),
+            Err(DiffRenderError::PathPairFormatUnsupported)
+        );
+    }
+
+    #[test]
+    fn test_write_diff() {
+        let test_repo = TestRepo::init();
+        let (from_tree, to_tree) = two_file_trees(&test_repo);
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let renderer = DiffRenderer::new(
+            test_repo.repo.as_ref(),
+            &path_converter,
+            vec![
+                DiffFormat::Summary(Box::default()),
+                DiffFormat::Git(Box::default()),
+            ],
+        );
+        let matcher = EverythingMatcher;
+        let copy_records = CopyRecords::default();
+        let write = |writer: &mut dyn io::Write| {
+            renderer.write_diff(writer, &from_tree, &to_tree, &matcher, &copy_records, 80)
+        };
+
+        let mut expected = vec![];
+        renderer
+            .show_diff_without_ui(
+                &mut PlainTextFormatter::new(&mut expected),
+                &from_tree,
+                &to_tree,
+                &matcher,
+                &copy_records,
+                80,
+            )
+            .unwrap();
+        assert!(expected.starts_with(b"M file1\nM file2\ndiff --git a/file1 b/file1\n"));
+
+        let mut output = vec![];
+        write(&mut output).unwrap();
+        assert_eq!(output, expected);
+
+        #[cfg(unix)]
+        {
+            use std::io::Read as _;
+            use std::os::fd::FromRawFd as _;
+
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+            let mut reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+            let mut writer = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+            // The diff is small enough to fit in the pipe buffer.
+            write(&mut writer).unwrap();
+            drop(writer);
+            let mut output = vec![];
+            reader.read_to_end(&mut output).unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_show_squashed_diff() {
+        let settings = testutils::user_settings();
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let file1 = RepoPath::from_internal_string("file1");
+        let file2 = RepoPath::from_internal_string("file2");
+        let file3 = RepoPath::from_internal_string("file3");
+
+        // C: remove file2
+        // B: modify file1, add file2 and file3
+        // A: add file1
+        let mut tx = repo.start_transaction(&settings);
+        let mut parent_id = repo.store().root_commit_id().clone();
+        let mut commits = vec![];
+        for entries in [
+            vec![(file1, "a\n")],
+            vec![(file1, "b\n"), (file2, "b\n"), (file3, "b\n")],
+            vec![(file1, "b\n"), (file3, "b\n")],
+        ] {
+            let tree = testutils::create_tree(repo, &entries);
+            let commit = tx
+                .mut_repo()
+                .new_commit(&settings, vec![parent_id], tree.id())
+                .write()
+                .unwrap();
+            parent_id = commit.id().clone();
+            commits.push(commit);
+        }
+        let squashed = tx
+            .mut_repo()
+            .new_commit(
+                &settings,
+                vec![repo.store().root_commit_id().clone()],
+                commits[2].tree_id().clone(),
+            )
+            .write()
+            .unwrap();
+
+        let ui = Ui::with_config(&crate::config::default_config()).unwrap();
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let renderer = DiffRenderer::new(
+            tx.repo(),
+            &path_converter,
+            vec![DiffFormat::Summary(Box::default())],
+        );
+
+        // Net diff of the stack is the same as the squashed commit
+        let mut output = vec![];
+        renderer
+            .show_squashed_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &commits,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "A file1\nA file3\n");
+        let mut squashed_output = vec![];
+        renderer
+            .show_patch(
+                &ui,
+                &mut PlainTextFormatter::new(&mut squashed_output),
+                &squashed,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output, squashed_output);
+
+        // Sub-stack
+        let mut output = vec![];
+        renderer
+            .show_squashed_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &commits[1..],
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "M file1\nA file3\n");
+    }
+
+    #[test]
+    fn test_show_diff_identical_trees() {
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        // None of the tree and file objects exist in the store, so any attempt
+        // to read them would fail.
+        let mut tree_data = jj_lib::backend::Tree::default();
+        tree_data.set(
+            "file".into(),
+            TreeValue::File {
+                id: FileId::new(vec![0xff; 20]),
+                executable: false,
+            },
+        );
+        let tree = MergedTree::resolved(jj_lib::tree::Tree::new(
+            repo.store().clone(),
+            RepoPathBuf::root(),
+            jj_lib::backend::TreeId::new(vec![0xff; 20]),
+            std::sync::Arc::new(tree_data),
+        ));
+
+        let ui = Ui::with_config(&crate::config::default_config()).unwrap();
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let show = |formats: Vec<DiffFormat>| {
+            let renderer = DiffRenderer::new(repo.as_ref(), &path_converter, formats);
+            let mut output = vec![];
+            renderer
+                .show_diff(
+                    &ui,
+                    &mut PlainTextFormatter::new(&mut output),
+                    &tree,
+                    &tree,
+                    &EverythingMatcher,
+                    &CopyRecords::default(),
+                    80,
+                )
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            show(vec![
+                DiffFormat::Summary(Box::default()),
+                DiffFormat::Git(Box::default()),
+                DiffFormat::ColorWords(Box::default()),
+            ]),
+            ""
+        );
+        assert_eq!(
+            show(vec![
+                DiffFormat::Stat(Box::default()),
+                DiffFormat::ShortStat,
+            ]),
+            "0 files changed, 0 insertions(+), 0 deletions(-)\n\
+             0 files changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+        assert_eq!(
+            show(vec![DiffFormat::ConflictStat]),
+            "0 conflicts introduced, 0 resolved\n"
+        );
+        let options = ColorWordsDiffOptions {
+            show_no_changes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            show(vec![DiffFormat::ColorWords(Box::new(options))]),
+            "(no changes)\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_stats_emptied_file_is_not_deletion() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let emptied = RepoPath::from_internal_string("emptied");
+        let deleted = RepoPath::from_internal_string("deleted");
+        let file_value = |path: &RepoPath, contents: &str| {
+            Merge::normal(TreeValue::File {
+                id: testutils::write_file(store, path, contents),
+                executable: false,
+            })
+        };
+        let entries = vec![
+            TreeDiffEntry {
+                source: emptied.to_owned(),
+                target: emptied.to_owned(),
+                value: Ok((file_value(emptied, "a\nb\n"), file_value(emptied, ""))),
+            },
+            TreeDiffEntry {
+                source: deleted.to_owned(),
+                target: deleted.to_owned(),
+                value: Ok((file_value(deleted, "a\nb\n"), Merge::absent())),
+            },
+        ];
+        // Both paths are sources of copies or renames, but only the deleted
+        // one is suppressed as the "delete" half of a rename.
+        let copied_sources = HashSet::from([emptied, deleted]);
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+
+        let stats = DiffStats::collect(
+            store,
+            Box::pin(futures::stream::iter(entries)),
+            &path_converter,
+            &copied_sources,
+            false,
+            false,
+        )
+        .unwrap();
+        let stats = stats
+            .entries
+            .iter()
+            .map(|stat| (stat.path.as_str(), stat.added, stat.removed))
+            .collect_vec();
+        assert_eq!(stats, [("emptied", 0, 2)]);
+    }
+
+    #[test]
+    fn test_shortstat_skips_unchanged_entries() {
+        let test_repo = TestRepo::init();
+        let store = test_repo.repo.store();
+        let modified = RepoPath::from_internal_string("modified");
+        let unchanged = RepoPath::from_internal_string("unchanged");
+        let renamed_from = RepoPath::from_internal_string("renamed_from");
+        let renamed_to = RepoPath::from_internal_string("renamed_to");
+        let file_value = |id: FileId| {
+            Merge::normal(TreeValue::File {
+                id,
+                executable: false,
+            })
+        };
+        // The file object of the unchanged entries doesn't exist in the store,
+        // so any attempt to read it would fail.
+        let missing_id = FileId::new(vec![0xff; 20]);
+        let entries = vec![
+            TreeDiffEntry {
+                source: modified.to_owned(),
+                target: modified.to_owned(),
+                value: Ok((
+                    file_value(testutils::write_file(store, modified, "a\n")),
+                    file_value(testutils::write_file(store, modified, "b\nc\n")),
+                )),
+            },
+            TreeDiffEntry {
+                source: unchanged.to_owned(),
+                target: unchanged.to_owned(),
+                value: Ok((
+                    file_value(missing_id.clone()),
+                    file_value(missing_id.clone()),
+                )),
+            },
+            TreeDiffEntry {
+                source: renamed_from.to_owned(),
+                target: renamed_to.to_owned(),
+                value: Ok((file_value(missing_id.clone()), file_value(missing_id))),
+            },
+        ];
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+
+        let mut output = vec![];
+        show_diff_shortstat(
+            &mut PlainTextFormatter::new(&mut output),
+            store,
+            Box::pin(futures::stream::iter(entries)),
+            &path_converter,
+            &EverythingMatcher,
+            &CopyRecords::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.to_str_lossy(),
+            "3 files changed, 2 insertions(+), 1 deletion(-)\n"
+        );
+    }
+
+    #[test]
+    fn test_show_working_copy_diff() {
+        let settings = testutils::user_settings();
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let modified = RepoPath::from_internal_string("modified");
+        let unchanged = RepoPath::from_internal_string("unchanged");
+        let added = RepoPath::from_internal_string("added");
+
+        let base_tree = testutils::create_tree(repo, &[(modified, "a\n"), (unchanged, "a\n")]);
+        let wc_tree = testutils::create_tree(
+            repo,
+            &[(modified, "b\n"), (unchanged, "a\n"), (added, "c\n")],
+        );
+        let mut tx = repo.start_transaction(&settings);
+        let base_commit = tx
+            .mut_repo()
+            .new_commit(
+                &settings,
+                vec![repo.store().root_commit_id().clone()],
+                base_tree.id(),
+            )
+            .write()
+            .unwrap();
+        let wc_commit = tx
+            .mut_repo()
+            .new_commit(&settings, vec![base_commit.id().clone()], wc_tree.id())
+            .write()
+            .unwrap();
+
+        let ui = Ui::with_config(&crate::config::default_config()).unwrap();
+        let path_converter = RepoPathUiConverter::Fs {
+            cwd: PathBuf::from("repo"),
+            base: PathBuf::from("repo"),
+        };
+        let renderer = DiffRenderer::new(
+            tx.repo(),
+            &path_converter,
+            vec![DiffFormat::Summary(Box::default())],
+        );
+
+        // Uncommitted modification is shown against the base commit, and a
+        // file that didn't exist in the base commit is shown as added
+        let mut output = vec![];
+        renderer
+            .show_working_copy_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &wc_commit,
+                &base_commit,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "A added\nM modified\n");
+
+        // No changes against itself
+        let mut output = vec![];
+        renderer
+            .show_working_copy_diff(
+                &ui,
+                &mut PlainTextFormatter::new(&mut output),
+                &wc_commit,
+                &wc_commit,
+                &EverythingMatcher,
+                80,
+            )
+            .unwrap();
+        assert_eq!(output.to_str_lossy(), "");
+    }
+
+    #[test]
+    fn test_diff_status() {
+        let absent = MergedTreeValue::absent();
+        let file = MergedTreeValue::normal(TreeValue::File {
+            id: FileId::new(vec![1]),
+            executable: false,
+        });
+        assert_eq!(diff_status(&absent, &file, false, false), DiffStatus::Added);
+        assert_eq!(
+            diff_status(&file, &file, false, false),
+            DiffStatus::Modified
+        );
+        assert_eq!(
+            diff_status(&file, &absent, false, false),
+            DiffStatus::Deleted
+        );
+        // Rename and copy take precedence over the values
+        assert_eq!(diff_status(&file, &file, true, false), DiffStatus::Renamed);
+        assert_eq!(diff_status(&file, &file, false, true), DiffStatus::Copied);
+        assert_eq!(diff_status(&absent, &file, false, true), DiffStatus::Copied);
+
+        let chars = [
+            DiffStatus::Added,
+            DiffStatus::Modified,
+            DiffStatus::Deleted,
+            DiffStatus::Renamed,
+            DiffStatus::Copied,
+        ]
+        .map(DiffStatus::as_char);
+        assert_eq!(chars, ['A', 'M', 'D', 'R', 'C']);
+    }
+
+    #[test]
+    fn test_format_posix_diff_timestamp() {
+        assert_eq!(
+            format_posix_diff_timestamp(None),
+            "1970-01-01 00:00:00.000000000 +0000"
+        );
+        let timestamp = Timestamp {
+            timestamp: MillisSinceEpoch(1_700_000_000_123),
+            tz_offset: -480,
+        };
+        assert_eq!(
+            format_posix_diff_timestamp(Some(&timestamp)),
+            "2023-11-14 14:13:20.123000000 -0800"
+        );
+        // Out-of-range timestamp falls back to the epoch
+        let timestamp = Timestamp {
+            timestamp: MillisSinceEpoch(i64::MAX),
+            tz_offset: 0,
+        };
+        assert_eq!(
+            format_posix_diff_timestamp(Some(&timestamp)),
+            "1970-01-01 00:00:00.000000000 +0000"
+        );
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let test_repo = TestRepo::init();
+        let repo = &test_repo.repo;
+        let added = RepoPath::from_internal_string("added");
+        let modified = RepoPath::from_internal_string("modified");
+        let removed = RepoPath::from_internal_string("removed");
+        let renamed_from = RepoPath::from_internal_string("renamed_from");
+        let renamed_to = RepoPath::from_internal_string("renamed_to");
+
+        let from_tree = testutils::create_tree(
+            repo,
+            &[(modified, "a\n"), (removed, "a\n"), (renamed_from, "r\n")],
+        );
+        let to_tree = testutils::create_tree(
+            repo,
+            &[(added, "a\n"), (modified, "b\n"), (renamed_to, "r\n")],
+        );
+        let mut copy_records = CopyRecords::default();
+        copy_records
+            .add_records(Box::pin(futures::stream::iter([Ok(CopyRecord {
+                target: renamed_to.to_owned(),
+                target_commit: CommitId::new(vec![]),
+                source: renamed_from.to_owned(),
+                source_file: FileId::new(vec![]),
+                source_commit: CommitId::new(vec![]),
+            })])))
+            .unwrap();
+
+        let paths = changed_paths(&from_tree, &to_tree, &EverythingMatcher, &copy_records).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                (added.to_owned(), added.to_owned()),
+                (modified.to_owned(), modified.to_owned()),
+                (removed.to_owned(), removed.to_owned()),
+                (renamed_from.to_owned(), renamed_to.to_owned()),
+            ]
+        );
+
+        // Without copy records, a rename is reported as a deletion and an
+        // addition
+        let paths = changed_paths(
+            &from_tree,
+            &to_tree,
+            &EverythingMatcher,
+            &CopyRecords::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                (added.to_owned(), added.to_owned()),
+                (modified.to_owned(), modified.to_owned()),
+                (removed.to_owned(), removed.to_owned()),
+                (renamed_from.to_owned(), renamed_from.to_owned()),
+                (renamed_to.to_owned(), renamed_to.to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote_git_path() {
+        assert_eq!(quote_git_path("a/dir/file"), "a/dir/file");
+        assert_eq!(quote_git_path("a/caf\u{e9}"), "a/caf\u{e9}");
+        assert_eq!(quote_git_path("a/tab\there"), r#""a/tab\there""#);
+        assert_eq!(quote_git_path(r#"a/"q"\x"#), r#""a/\"q\"\\x""#);
+        assert_eq!(quote_git_path("a/\x01\x7f"), r#""a/\001\177""#);
+    }
+
+    #[test]
+    fn test_unified_diff_hunks_word_diff_max_lines() {
+        let has_word_tokens = |left: &str, right: &str, max_lines: Option<usize>| {
+            let hunks = unified_diff_hunks(
+                left.as_bytes(),
+                right.as_bytes(),
+                DEFAULT_CONTEXT_LINES,
+                DiffChangeOrder::default(),
+                None,
+                DiffWordTokenizer::default(),
+                false,
+                None,
+                max_lines,
+                None,
+            );
+            hunks
+                .iter()
+                .flat_map(|hunk| &hunk.lines)
+                .flat_map(|(_, tokens)| tokens)
+                .any(|(token_type, _)| *token_type == DiffTokenType::Different)
+        };
+
+        // Small changed regions are diffed by word
+        let left = "foo 1\nfoo 2\n";
+        let right = "bar 1\nbar 2\n";
+        assert!(has_word_tokens(left, right, None));
+        assert!(has_word_tokens(left, right, Some(1000)));
+
+        // Large changed regions are shown as whole lines
+        let left: String = (0..5000).map(|i| format!("foo {i}\n")).collect();
+        let right: String = (0..5000).map(|i| format!("bar {i}\n")).collect();
+        assert!(has_word_tokens(&left, &right, None));
+        assert!(!has_word_tokens(&left, &right, Some(1000)));
+
+        // The lines are the same either way
+        let diff_lines = |max_lines: Option<usize>| {
+            unified_diff_hunks(
+                left.as_bytes(),
+                right.as_bytes(),
+                DEFAULT_CONTEXT_LINES,
+                DiffChangeOrder::default(),
+                None,
+                DiffWordTokenizer::default(),
+                false,
+                None,
+                max_lines,
+                None,
+            )
+            .into_iter()
+            .flat_map(|hunk| hunk.lines)
+            .map(|(line_type, tokens)| {
+                let line = tokens.iter().map(|(_, content)| *content).collect_vec();
+                (line_type, line.concat())
+            })
+            .collect_vec()
+        };
+        assert_eq!(diff_lines(None), diff_lines(Some(1000)));
+    }
+
+    #[test]
+    fn test_color_words_diff_line_coalesces_tokens() {
+        let diff_line = DiffLine {
+            line_number: DiffLineNumber { left: 1, right: 1 },
+            hunks: vec![
+                (DiffLineHunkSide::Both, "a ".as_ref()),
+                (DiffLineHunkSide::Left, "b".as_ref()),
+                (DiffLineHunkSide::Left, " c".as_ref()),
+                (DiffLineHunkSide::Left, " d".as_ref()),
+                (DiffLineHunkSide::Right, "x".as_ref()),
+                (DiffLineHunkSide::Right, " y".as_ref()),
+                (DiffLineHunkSide::Both, "\n".as_ref()),
+            ],
+        };
+        let mut formatter = LabelCounter::default();
+        show_color_words_diff_line(&mut formatter, &diff_line, 4, &Default::default()).unwrap();
+        assert_eq!(formatter.data, b"   1    1: a b c dx y\n");
+        // 2 labels for each line number, and 2 labels for each of the removed
+        // and added runs
+        assert_eq!(formatter.push_count, 8);
+    }
+
+    #[test]
+    fn test_color_words_diff_wide_line_numbers() {
+        let left = (1..=10000).map(|i| format!("{i}\n")).join("");
+        let right = left.replace("9999\n", "") + "new\n";
+        let mut output = vec![];
+        show_color_words_diff_hunks(
+            left.as_bytes(),
+            right.as_bytes(),
+            &ColorWordsDiffOptions::default(),
+            DiffWordTokenizer::Word,
+            &mut PlainTextFormatter::new(&mut output),
+        )
+        .unwrap();
+        // Line numbers, blank columns, and the ellipsis are aligned
+        assert_eq!(
+            output.to_str_lossy(),
+            indoc::indoc! {"
+                     ...
+                 9996  9996: 9996
+                 9997  9997: 9997
+                 9998  9998: 9998
+                 9999      : 9999
+                10000  9999: 10000
+                      10000: new
+            "}
+        );
+    }
+
+    #[test]
+    fn test_color_words_diff_wrap_width() {
+        let options = ColorWordsDiffOptions {
+            wrap_width: Some(80),
+            ..Default::default()
+        };
+        let show = |left: &[u8], right: &[u8]| {
+            let mut output = vec![];
+            show_color_words_diff_hunks(
+                left,
+                right,
+                &options,
+                DiffWordTokenizer::Word,
+                &mut PlainTextFormatter::new(&mut output),
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // 80 columns minus 11 columns of line numbers
+        let line = "0123456789".repeat(20);
+        let output = show(b"short\n", format!("{line}\n").as_bytes());
+        assert_eq!(
+            output,
+            format!(
+                "   1    1: short{}\n         : {}\n         : {}\n",
+                &line[..64],
+                &line[64..133],
+                &line[133..]
+            )
+        );
+        assert!(output.lines().all(|line| line.width() <= 80));
+
+        // Wide characters aren't split across lines
+        let line = "\u{3042}".repeat(100);
+        let output = show(b"", format!("{line}\n").as_bytes());
+        let chars = line.chars().collect_vec();
+        assert_eq!(
+            output,
+            format!(
+                "        1: {}\n         : {}\n         : {}\n",
+                String::from_iter(&chars[..34]),
+                String::from_iter(&chars[34..68]),
+                String::from_iter(&chars[68..])
+            )
+        );
+    }
+
+    #[test]
+    fn test_expanded_tab_widths() {
+        assert!(expanded_tab_widths(b"\ta\tb\n", &[]).is_empty());
+        // Tabs at, before, and past the last stop
+        assert_eq!(
+            expanded_tab_widths(b"\ta\tbcdef\tg\n", &[4, 8, 12]),
+            [(0, 4), (2, 3), (8, 3)]
+        );
+        // Wide characters take two columns
+        assert_eq!(expanded_tab_widths("\u{3042}\t".as_bytes(), &[4]), [(3, 2)]);
+
+        assert_eq!(next_tab_stop(0, &[3]), 3);
+        assert_eq!(next_tab_stop(3, &[3]), 6);
+        assert_eq!(next_tab_stop(7, &[3]), 9);
+        assert_eq!(next_tab_stop(8, &[2, 8]), 14);
+        assert_eq!(next_tab_stop(15, &[2, 8]), 20);
+    }
+
+    #[test]
+    fn test_verify_git_diff_hunks() {
+        let output = indoc::indoc! {r"
+            diff --git a/file b/file
+            --- a/file
+            +++ b/file
+            @@ -1,3 +1,2 @@
+             a
+            -b
+            -c
+            +d
+            @@ -5,0 +4 @@
+            +e
+            \ No newline at end of file
+        "};
+        assert_eq!(verify_git_diff_hunks(output.as_bytes()), Ok(()));
+
+        let missing = output.replace(" a\n", "");
+        assert_eq!(
+            verify_git_diff_hunks(missing.as_bytes()),
+            Err("hunk ended with 1 left and 1 right lines missing".to_owned())
+        );
+
+        let extra = output.replace("+d\n", "+d\n+x\n");
+        assert_eq!(
+            verify_git_diff_hunks(extra.as_bytes()),
+            Err("hunk has more lines than declared".to_owned())
+        );
+
+        let malformed = output.replace("@@ -5,0 +4 @@", "@@ -5,x +4 @@");
+        assert_eq!(
+            verify_git_diff_hunks(malformed.as_bytes()),
+            Err("malformed hunk header @@ -5,x +4 @@".to_owned())
+        );
+    }
+}
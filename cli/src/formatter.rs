@@ -32,6 +32,12 @@ pub trait Formatter: Write {
     fn push_label(&mut self, label: &str) -> io::Result<()>;
 
     fn pop_label(&mut self) -> io::Result<()>;
+
+    /// Returns true if labels are rendered as terminal escape sequences. Other
+    /// escape sequences may then be written to `raw()` as well.
+    fn is_color(&self) -> bool {
+        false
+    }
 }
 
 impl dyn Formatter + '_ {
@@ -550,6 +556,10 @@ impl<W: Write> Formatter for ColorFormatter<W> {
         }
         Ok(())
     }
+
+    fn is_color(&self) -> bool {
+        true
+    }
 }
 
 impl<W: Write> Drop for ColorFormatter<W> {
@@ -53,6 +53,12 @@ impl DiffWorkingCopies {
             .map(|state| state.working_copy_path())
     }
 
+    /// Leaves the temporary directory behind instead of deleting it when
+    /// this is dropped.
+    pub fn persist(self) {
+        let _ = self._temp_dir.into_path();
+    }
+
     pub fn to_command_variables(&self) -> HashMap<&'static str, &str> {
         let left_wc_dir = self.left_working_copy_path();
         let right_wc_dir = self.right_working_copy_path();
@@ -60,6 +60,9 @@ pub enum DiffToolMode {
     Dir,
     /// Invoke the diff tool on each of the modified files individually.
     FileByFile,
+    /// Invoke the diff tool on a temp directory of the modified files, but
+    /// don't wait for it to exit. Useful for interactive GUI tools.
+    DirNonBlocking,
 }
 
 impl Default for ExternalMergeTool {
@@ -288,6 +291,47 @@ pub fn generate_diff(
     invoke_external_diff(ui, writer, tool, &diff_wc.to_command_variables())
 }
 
+/// Checks out the trees to a temporary directory and spawns the `tool` on it
+/// without waiting for the tool to exit. The directory is left behind since
+/// the tool may still be reading it after `jj` exits.
+pub fn spawn_diff(
+    left_tree: &MergedTree,
+    right_tree: &MergedTree,
+    matcher: &dyn Matcher,
+    tool: &ExternalMergeTool,
+) -> Result<(), DiffGenerateError> {
+    let store = left_tree.store();
+    let diff_wc = check_out_trees(store, left_tree, right_tree, matcher, None)?;
+    set_readonly_recursively(diff_wc.left_working_copy_path())
+        .map_err(ExternalToolError::SetUpDir)?;
+    set_readonly_recursively(diff_wc.right_working_copy_path())
+        .map_err(ExternalToolError::SetUpDir)?;
+    spawn_external_diff(tool, &diff_wc.to_command_variables())?;
+    diff_wc.persist();
+    Ok(())
+}
+
+/// Spawns the specified `tool` without waiting for it to exit.
+fn spawn_external_diff(
+    tool: &ExternalMergeTool,
+    patterns: &HashMap<&str, &str>,
+) -> Result<(), DiffGenerateError> {
+    let mut cmd = Command::new(&tool.program);
+    cmd.args(interpolate_variables(&tool.diff_args, patterns));
+    tracing::info!(?cmd, "Spawning the external diff tool:");
+    // The output isn't attached to the pager, which would otherwise wait for
+    // the tool to exit.
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|source| ExternalToolError::FailedToExecute {
+            tool_binary: tool.program.clone(),
+            source,
+        })?;
+    Ok(())
+}
+
 /// Invokes the specified `tool` directing its output into `writer`.
 pub fn invoke_external_diff(
     ui: &Ui,
@@ -19,8 +19,10 @@ mod external;
 use std::sync::Arc;
 
 use config::ConfigError;
+use itertools::Itertools as _;
 use jj_lib::backend::MergedTreeId;
 use jj_lib::conflicts::extract_as_single_hunk;
+use jj_lib::dsl_util::collect_similar;
 use jj_lib::gitignore::GitIgnoreFile;
 use jj_lib::matchers::Matcher;
 use jj_lib::merged_tree::MergedTree;
@@ -34,7 +36,9 @@ use self::builtin::{edit_diff_builtin, edit_merge_builtin, BuiltinToolError};
 pub(crate) use self::diff_working_copies::new_utf8_temp_dir;
 use self::diff_working_copies::DiffCheckoutError;
 use self::external::{edit_diff_external, ExternalToolError};
-pub use self::external::{generate_diff, invoke_external_diff, DiffToolMode, ExternalMergeTool};
+pub use self::external::{
+    generate_diff, invoke_external_diff, spawn_diff, DiffToolMode, ExternalMergeTool,
+};
 use crate::config::CommandNameAndArgs;
 use crate::ui::Ui;
 
@@ -164,6 +168,36 @@ pub fn get_external_tool_config(
     }
 }
 
+/// Checks that the external tool `name`, which isn't configured in
+/// `[merge-tools]`, can be run as a program.
+///
+/// A bare name which isn't found in `PATH` but is similar to configured tool
+/// names is probably misspelled, and is reported as error.
+pub fn check_external_tool_program(settings: &UserSettings, name: &str) -> Result<(), ConfigError> {
+    if name.contains(std::path::is_separator) || is_program_in_path(name) {
+        return Ok(());
+    }
+    let tools_table = settings.config().get_table("merge-tools")?;
+    let candidates = collect_similar(name, tools_table.keys());
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    Err(ConfigError::Message(format!(
+        "Tool `{name}` is not configured in `merge-tools` and not found in PATH. Did you mean {}?",
+        candidates.iter().map(|name| format!("`{name}`")).join(", ")
+    )))
+}
+
+fn is_program_in_path(name: &str) -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&paths).any(|dir| {
+        let path = dir.join(name);
+        path.is_file() || (cfg!(windows) && path.with_extension("exe").is_file())
+    })
+}
+
 /// Configured diff editor.
 #[derive(Clone, Debug)]
 pub struct DiffEditor {
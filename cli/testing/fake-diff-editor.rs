@@ -88,6 +88,9 @@ fn main() {
                     exit(1)
                 }
             }
+            ["sleep", secs] => {
+                std::thread::sleep(std::time::Duration::from_secs(secs.parse().unwrap()));
+            }
             ["print", message] => {
                 println!("{message}");
             }
@@ -995,7 +995,7 @@ fn test_log_diff_predefined_formats() {
     [1mindex 7898192261..9ddeb5c484 100644[0m
     [1m--- a/file2[0m
     [1m+++ b/file2[0m
-    [38;5;6m@@ -1,1 +1,2 @@[39m
+    [38;5;6m@@ -1 +1,2 @@[39m
     [38;5;1m-[4ma[24m[39m
     [38;5;2m+[4mb[24m[39m
     [38;5;2m+[4mc[24m[39m
@@ -1035,7 +1035,7 @@ fn test_log_diff_predefined_formats() {
     [1m<<log diff git file_header::index 7898192261..9ddeb5c484 100644>>[0m
     [1m<<log diff git file_header::--- a/file2>>[0m
     [1m<<log diff git file_header::+++ b/file2>>[0m
-    [38;5;6m<<log diff git hunk_header::@@ -1,1 +1,2 @@>>[39m
+    [38;5;6m<<log diff git hunk_header::@@ -1 +1,2 @@>>[39m
     [38;5;1m<<log diff git removed::->>[4m<<log diff git removed token::a>>[24m<<log diff git removed::>>[39m
     [38;5;2m<<log diff git added::+>>[4m<<log diff git added token::b>>[24m<<log diff git added::>>[39m
     [38;5;2m<<log diff git added::+>>[4m<<log diff git added token::c>>[24m[39m
@@ -1075,7 +1075,7 @@ fn test_log_diff_predefined_formats() {
     index 7898192261..9ddeb5c484 100644
     --- a/file2
     +++ b/file2
-    @@ -1,1 +1,2 @@
+    @@ -1 +1,2 @@
     -a
     +b
     +c
@@ -1105,8 +1105,105 @@ fn test_log_diff_predefined_formats() {
     index 422c2b7ab3..de980441c3 100644
     --- a/file1
     +++ b/file1
-    @@ -2,1 +2,2 @@
+    @@ -2 +2,2 @@
      b
     +c
     "###);
 }
+
+#[test]
+fn test_log_diff_patch_default_format() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "b\nc\n").unwrap();
+
+    let template = r#"concat("=== patch ===\n", diff.patch())"#;
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "--no-graph", "-r@", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    === patch ===
+    Modified regular file file1:
+       1    1: a
+       2    2: b
+            3: c
+    Modified regular file file2:
+       1    1: ab
+            2: c
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r@",
+            "-T",
+            template,
+            "--config-toml=ui.diff.format='git'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === patch ===
+    diff --git a/file1 b/file1
+    index 422c2b7ab3..de980441c3 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,2 +1,3 @@
+     a
+     b
+    +c
+    diff --git a/file2 b/file2
+    index 7898192261..9ddeb5c484 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1 +1,2 @@
+    -a
+    +b
+    +c
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r@",
+            "-T",
+            "diff.patch()",
+            "--config-toml=ui.diff.format='summary'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    M file2
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r@",
+            "-T",
+            "diff.patch()",
+            "--config-toml=ui.diff.format='bogus'",
+        ],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse template: Failed to load diff format
+    Caused by:
+    1:  --> 1:6
+      |
+    1 | diff.patch()
+      |      ^---^
+      |
+      = Failed to load diff format
+    2: invalid diff format: bogus
+    "###);
+}
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
 use itertools::Itertools;
+use jj_cli::diff_util::DiffLabel;
+use jj_lib::secret_backend::SecretBackend;
 
 use crate::common::{escaped_fake_diff_editor_path, strip_last_line, TestEnvironment};
 
@@ -32,40 +37,34 @@ fn test_diff_basic() {
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    Removed regular file file1:
-       1     : foo
     Modified regular file file2:
        1    1: 1
        2    2: 25
        3    3: 3
        4     : 4
-    Modified regular file file3 (file1 => file3):
+    Renamed regular file file3 (file1 => file3):
     Modified regular file file4 (file2 => file4):
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=0"]);
     insta::assert_snapshot!(stdout, @r###"
-    Removed regular file file1:
-       1     : foo
     Modified regular file file2:
        1    1: 1
        2    2: 25
        3    3: 3
        4     : 4
-    Modified regular file file3 (file1 => file3):
+    Renamed regular file file3 (file1 => file3):
     Modified regular file file4 (file2 => file4):
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug"]);
     insta::assert_snapshot!(stdout, @r###"
-    [38;5;3m<<diff header::Removed regular file file1:>>[39m
-    [38;5;1m<<diff removed line_number::   1>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::foo>>[24m[39m
     [38;5;3m<<diff header::Modified regular file file2:>>[39m
     [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: 1>>
     [38;5;1m<<diff removed line_number::   2>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   2>>[39m<<diff::: >>[4m[38;5;1m<<diff removed token::2>>[38;5;2m<<diff added token::5>>[24m[39m<<diff::>>
     [38;5;1m<<diff removed line_number::   3>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   3>>[39m<<diff::: 3>>
     [38;5;1m<<diff removed line_number::   4>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::4>>[24m[39m
-    [38;5;3m<<diff header::Modified regular file file3 (file1 => file3):>>[39m
+    [38;5;3m<<diff header::Renamed regular file file3 (file1 => file3):>>[39m
     [38;5;3m<<diff header::Modified regular file file4 (file2 => file4):>>[39m
     "###);
 
@@ -96,7 +95,7 @@ fn test_diff_basic() {
     index 257cc5642c..0000000000
     --- a/file1
     +++ /dev/null
-    @@ -1,1 +1,0 @@
+    @@ -1 +0,0 @@
     -foo
     "###);
 
@@ -113,9 +112,11 @@ fn test_diff_basic() {
      3
     -4
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     diff --git a/file2 b/file4
+    similarity index 100%
     copy from file2
     copy to file4
     "###);
@@ -126,15 +127,17 @@ fn test_diff_basic() {
     index 94ebaf9001..1ffc51b472 100644
     --- a/file2
     +++ b/file2
-    @@ -2,1 +2,1 @@
+    @@ -2 +2 @@
     -2
     +5
-    @@ -4,1 +4,0 @@
+    @@ -4 +3,0 @@
     -4
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     diff --git a/file2 b/file4
+    similarity index 100%
     copy from file2
     copy to file4
     "###);
@@ -152,9 +155,11 @@ fn test_diff_basic() {
     <<diff context:: 3>>
     [38;5;1m<<diff removed::->>[4m<<diff removed token::4>>[24m[39m
     [1m<<diff file_header::diff --git a/file1 b/file3>>[0m
+    [1m<<diff file_header::similarity index 100%>>[0m
     [1m<<diff file_header::rename from file1>>[0m
     [1m<<diff file_header::rename to file3>>[0m
     [1m<<diff file_header::diff --git a/file2 b/file4>>[0m
+    [1m<<diff file_header::similarity index 100%>>[0m
     [1m<<diff file_header::copy from file2>>[0m
     [1m<<diff file_header::copy to file4>>[0m
     "###);
@@ -175,9 +180,11 @@ fn test_diff_basic() {
      3
     -4
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     diff --git a/file2 b/file4
+    similarity index 100%
     copy from file2
     copy to file4
     "###);
@@ -226,1453 +233,5286 @@ fn test_diff_basic() {
 }
 
 #[test]
-fn test_diff_empty() {
+fn test_diff_summary_with_hash() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    std::fs::write(repo_path.join("file1"), "").unwrap();
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
-    insta::assert_snapshot!(stdout, @r###"
-    Added regular file file1:
-        (empty)
-    "###);
-
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
     std::fs::remove_file(repo_path.join("file1")).unwrap();
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "baz\n").unwrap();
+
+    // Deleted file shows the hash of the old content
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--with-hash"]);
     insta::assert_snapshot!(stdout, @r###"
-    Removed regular file file1:
-        (empty)
+    D file1 257cc5642cb1a054f08cc83f2d943e56fd3ebe99
+    M file2 5716ca5987cbf97d6bb54920bea6adde242d87e6
+    A file3 76018072e09c5d31c8c6e3113b8aa0fe625195ca
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--with-hash",
+            "--config-toml=ui.diff.format='summary'",
+        ],
+    );
     insta::assert_snapshot!(stdout, @r###"
-    file1 | 0
-    1 file changed, 0 insertions(+), 0 deletions(-)
+    D file1 257cc5642cb1a054f08cc83f2d943e56fd3ebe99
+    M file2 5716ca5987cbf97d6bb54920bea6adde242d87e6
+    A file3 76018072e09c5d31c8c6e3113b8aa0fe625195ca
     "###);
 }
 
 #[test]
-fn test_diff_file_mode() {
+fn test_diff_summary_with_hunk_count() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    // Test content+mode/mode-only changes of empty/non-empty files:
-    // - file1: ("",  x) -> ("2", n)  empty, content+mode
-    // - file2: ("1", x) -> ("1", n)  non-empty, mode-only
-    // - file3: ("1", n) -> ("2", x)  non-empty, content+mode
-    // - file4: ("",  n) -> ("",  x)  empty, mode-only
-
-    std::fs::write(repo_path.join("file1"), "").unwrap();
-    std::fs::write(repo_path.join("file2"), "1\n").unwrap();
-    std::fs::write(repo_path.join("file3"), "1\n").unwrap();
-    std::fs::write(repo_path.join("file4"), "").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "x", "file1", "file2"]);
-
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::write(repo_path.join("file1"), "2\n").unwrap();
-    std::fs::write(repo_path.join("file3"), "2\n").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "n", "file1", "file2"]);
-    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "x", "file3", "file4"]);
-
+    let lines = (1..=20).map(|i| format!("{i}\n")).collect::<Vec<_>>();
+    std::fs::write(repo_path.join("file1"), lines.concat()).unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    let mut modified_lines = lines.clone();
+    modified_lines[1] = "two\n".to_owned();
+    modified_lines[17] = "eighteen\n".to_owned();
+    std::fs::write(repo_path.join("file1"), modified_lines.concat()).unwrap();
     std::fs::remove_file(repo_path.join("file2")).unwrap();
-    std::fs::remove_file(repo_path.join("file3")).unwrap();
-    std::fs::remove_file(repo_path.join("file4")).unwrap();
+    std::fs::write(repo_path.join("file3"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file4"), b"\0").unwrap();
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@--"]);
+    // Binary files have no hunk count
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--with-hunk-count"]);
     insta::assert_snapshot!(stdout, @r###"
-    Added executable file file1:
-        (empty)
-    Added executable file file2:
-            1: 1
-    Added regular file file3:
-            1: 1
-    Added regular file file4:
-        (empty)
+    M file1 (2 hunks)
+    D file2 (1 hunk)
+    A file3 (1 hunk)
+    A file4
     "###);
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-"]);
+
+    // Changes within the context lines of each other are in the same hunk
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--summary", "--with-hunk-count", "--context=10"],
+    );
     insta::assert_snapshot!(stdout, @r###"
-    Executable file became non-executable at file1:
-            1: 2
-    Executable file became non-executable at file2:
-    Non-executable file became executable at file3:
-       1    1: 12
-    Non-executable file became executable at file4:
+    M file1 (1 hunk)
+    D file2 (1 hunk)
+    A file3 (1 hunk)
+    A file4
     "###);
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@"]);
+}
+
+#[test]
+fn test_diff_summary_show_mode() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    // file1 is a chmod-only change, and file2 is a content modification
+    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "x", "file1"]);
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+    std::fs::remove_file(repo_path.join("file3")).unwrap();
+    std::fs::write(repo_path.join("file4"), "foo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--show-mode"]);
     insta::assert_snapshot!(stdout, @r###"
-    Removed regular file file1:
-       1     : 2
-    Removed regular file file2:
-       1     : 1
-    Removed executable file file3:
-       1     : 2
-    Removed executable file file4:
-        (empty)
+    M file1 (100644->100755)
+    M file2 (100644)
+    D file3 (100644)
+    A file4 (100644)
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@--", "--git"]);
+    // Without the mode, the chmod-only change looks like a content change
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    new file mode 100755
-    index 0000000000..e69de29bb2
-    diff --git a/file2 b/file2
-    new file mode 100755
-    index 0000000000..d00491fd7e
-    --- /dev/null
-    +++ b/file2
-    @@ -1,0 +1,1 @@
-    +1
-    diff --git a/file3 b/file3
-    new file mode 100644
-    index 0000000000..d00491fd7e
-    --- /dev/null
-    +++ b/file3
-    @@ -1,0 +1,1 @@
-    +1
-    diff --git a/file4 b/file4
-    new file mode 100644
-    index 0000000000..e69de29bb2
+    M file1
+    M file2
+    D file3
+    A file4
     "###);
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-", "--git"]);
-    insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    old mode 100755
-    new mode 100644
-    index e69de29bb2..0cfbf08886
-    --- a/file1
-    +++ b/file1
-    @@ -1,0 +1,1 @@
-    +2
-    diff --git a/file2 b/file2
-    old mode 100755
-    new mode 100644
-    diff --git a/file3 b/file3
-    old mode 100644
-    new mode 100755
-    index d00491fd7e..0cfbf08886
-    --- a/file3
-    +++ b/file3
-    @@ -1,1 +1,1 @@
-    -1
-    +2
-    diff --git a/file4 b/file4
-    old mode 100644
-    new mode 100755
+}
+
+#[test]
+fn test_diff_rename_same_dir_only() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir1")).unwrap();
+    std::fs::create_dir(repo_path.join("dir2")).unwrap();
+    std::fs::write(repo_path.join("dir1").join("a"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("dir1").join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::rename(
+        repo_path.join("dir1").join("a"),
+        repo_path.join("dir1").join("b"),
+    )
+    .unwrap();
+    std::fs::rename(
+        repo_path.join("dir1").join("file1"),
+        repo_path.join("dir2").join("file1"),
+    )
+    .unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    R dir1/{a => b}
+    R {dir1 => dir2}/file1
     "###);
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@", "--git"]);
+
+    // Moves across directories are shown as deleted and added files
+    let config = "--config-toml=ui.diff.rename-same-dir-only=true";
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", config]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    R dir1/{a => b}
+    D dir1/file1
+    A dir2/file1
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", config]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    deleted file mode 100644
-    index 0cfbf08886..0000000000
-    --- a/file1
-    +++ /dev/null
-    @@ -1,1 +1,0 @@
-    -2
-    diff --git a/file2 b/file2
+    diff --git a/dir1/a b/dir1/b
+    similarity index 100%
+    rename from dir1/a
+    rename to dir1/b
+    diff --git a/dir1/file1 b/dir1/file1
     deleted file mode 100644
-    index d00491fd7e..0000000000
-    --- a/file2
-    +++ /dev/null
-    @@ -1,1 +1,0 @@
-    -1
-    diff --git a/file3 b/file3
-    deleted file mode 100755
-    index 0cfbf08886..0000000000
-    --- a/file3
+    index 257cc5642c..0000000000
+    --- a/dir1/file1
     +++ /dev/null
-    @@ -1,1 +1,0 @@
-    -2
-    diff --git a/file4 b/file4
-    deleted file mode 100755
-    index e69de29bb2..0000000000
+    @@ -1 +0,0 @@
+    -foo
+    diff --git a/dir2/file1 b/dir2/file1
+    new file mode 100644
+    index 0000000000..257cc5642c
+    --- /dev/null
+    +++ b/dir2/file1
+    @@ -0,0 +1 @@
+    +foo
     "###);
 }
 
 #[test]
-fn test_diff_types() {
+fn test_diff_html() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    let file_path = repo_path.join("foo");
-
-    // Missing
-    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=missing"]);
-
-    // Normal file
-    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=file"]);
-    std::fs::write(&file_path, "foo").unwrap();
-
-    // Conflict (add/add)
-    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=conflict"]);
-    std::fs::write(&file_path, "foo").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "root()"]);
-    std::fs::write(&file_path, "bar").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["squash", r#"--into=description("conflict")"#]);
+    std::fs::write(repo_path.join("file1"), "x < old\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "x < new\n").unwrap();
+    std::fs::write(repo_path.join("a&b"), "\"q\"\n").unwrap();
 
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        use std::path::PathBuf;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--html"]);
+    insta::assert_snapshot!(stdout, @r###"
+    <div class="diff">
+    <div class="file_header"><span class="added">a&amp;b</span></div>
+    <pre>
+    <span class="hunk_header">@@ -0,0 +1 @@</span>
+    <span class="added">+<span class="token">&quot;q&quot;</span></span>
+    </pre>
+    </div>
+    <div class="diff">
+    <div class="file_header"><span class="modified">file1</span></div>
+    <pre>
+    <span class="hunk_header">@@ -1 +1 @@</span>
+    <span class="removed">-x &lt; <span class="token">old</span></span>
+    <span class="added">+x &lt; <span class="token">new</span></span>
+    </pre>
+    </div>
+    "###);
 
-        // Executable
-        test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=executable"]);
-        std::fs::write(&file_path, "foo").unwrap();
-        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // HTML diff can't be combined with other long formats
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--html", "--git"]);
+    insta::assert_snapshot!(stderr, @r###"
+    error: the argument '--html' cannot be used with '--git'
 
-        // Symlink
-        test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=symlink"]);
-        std::os::unix::fs::symlink(PathBuf::from("."), &file_path).unwrap();
-    }
+    Usage: jj diff --html [PATHS]...
 
-    let diff = |from: &str, to: &str| {
-        test_env.jj_cmd_success(
-            &repo_path,
-            &[
-                "diff",
-                "--types",
-                &format!(r#"--from=description("{}")"#, from),
-                &format!(r#"--to=description("{}")"#, to),
-            ],
-        )
-    };
-    insta::assert_snapshot!(diff("missing", "file"), @r###"
-    -F foo
-    "###);
-    insta::assert_snapshot!(diff("file", "conflict"), @r###"
-    FC foo
-    "###);
-    insta::assert_snapshot!(diff("conflict", "missing"), @r###"
-    C- foo
+    For more information, try '--help'.
     "###);
-
-    #[cfg(unix)]
-    {
-        insta::assert_snapshot!(diff("symlink", "file"), @r###"
-        LF foo
-        "###);
-        insta::assert_snapshot!(diff("missing", "executable"), @r###"
-        -F foo
-        "###);
-    }
 }
 
 #[test]
-fn test_diff_name_only() {
+fn test_diff_posix() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\n").unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::write(repo_path.join("deleted"), "d").unwrap();
-    std::fs::write(repo_path.join("modified"), "m").unwrap();
-    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", "--name-only"]), @r###"
-    deleted
-    modified
-    "###);
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-mfirst"]);
-    std::fs::remove_file(repo_path.join("deleted")).unwrap();
-    std::fs::write(repo_path.join("modified"), "mod").unwrap();
-    std::fs::write(repo_path.join("added"), "add").unwrap();
-    std::fs::create_dir(repo_path.join("sub")).unwrap();
-    std::fs::write(repo_path.join("sub/added"), "sub/add").unwrap();
-    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", "--name-only"]).replace('\\', "/"),
-    @r###"
-    added
-    deleted
-    modified
-    sub/added
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::write(repo_path.join("file4"), "baz\n").unwrap();
+    #[cfg(unix)]
+    std::fs::set_permissions(
+        repo_path.join("file3"),
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .unwrap();
+
+    // Trees have no timestamps, so the epoch is used. The mode change can't
+    // be expressed.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--posix"]);
+    insta::assert_snapshot!(stdout, @r###"
+    --- file1	1970-01-01 00:00:00.000000000 +0000
+    +++ file1	1970-01-01 00:00:00.000000000 +0000
+    @@ -1 +1 @@
+    -foo
+    +bar
+    --- file2	1970-01-01 00:00:00.000000000 +0000
+    +++ /dev/null	1970-01-01 00:00:00.000000000 +0000
+    @@ -1 +0,0 @@
+    -foo
+    --- /dev/null	1970-01-01 00:00:00.000000000 +0000
+    +++ file4	1970-01-01 00:00:00.000000000 +0000
+    @@ -0,0 +1 @@
+    +baz
     "###);
-}
 
-#[test]
-fn test_diff_bad_args() {
-    let test_env = TestEnvironment::default();
-    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
-    let repo_path = test_env.env_root().join("repo");
+    // Commit author dates are used where the commits are known
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", "", "--posix"]);
+    insta::assert_snapshot!(stdout, @r###"
+    --- file1	2001-02-03 04:05:08.000000000 +0700
+    +++ file1	2001-02-03 04:05:09.000000000 +0700
+    @@ -1 +1 @@
+    -foo
+    +bar
+    --- file2	2001-02-03 04:05:08.000000000 +0700
+    +++ /dev/null	1970-01-01 00:00:00.000000000 +0000
+    @@ -1 +0,0 @@
+    -foo
+    --- /dev/null	1970-01-01 00:00:00.000000000 +0000
+    +++ file4	2001-02-03 04:05:09.000000000 +0700
+    @@ -0,0 +1 @@
+    +baz
+    "###);
 
-    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "-s", "--types"]);
+    // POSIX diff can't be combined with other long formats
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--posix", "--git"]);
     insta::assert_snapshot!(stderr, @r###"
-    error: the argument '--summary' cannot be used with '--types'
+    error: the argument '--posix' cannot be used with '--git'
 
-    Usage: jj diff --summary [PATHS]...
+    Usage: jj diff --posix [PATHS]...
 
     For more information, try '--help'.
     "###);
+}
 
-    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--color-words", "--git"]);
-    insta::assert_snapshot!(stderr, @r###"
-    error: the argument '--color-words' cannot be used with '--git'
+#[test]
+fn test_diff_git_rename_similarity() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
 
-    Usage: jj diff --color-words [PATHS]...
+    std::fs::write(repo_path.join("mod1"), "a\nb\nc\nd\n").unwrap();
+    std::fs::write(repo_path.join("pure1"), "1\n2\n3\n4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("mod1")).unwrap();
+    std::fs::write(repo_path.join("mod2"), "a\nb\nc\nD\n").unwrap();
+    std::fs::rename(repo_path.join("pure1"), repo_path.join("pure2")).unwrap();
 
-    For more information, try '--help'.
+    // Pure rename has no hunks
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/mod1 b/mod2
+    rename from mod1
+    rename to mod2
+    index d68dd4031d..5790697ef6 100644
+    --- a/mod1
+    +++ b/mod2
+    @@ -1,4 +1,4 @@
+     a
+     b
+     c
+    -d
+    +D
+    diff --git a/pure1 b/pure2
+    similarity index 100%
+    rename from pure1
+    rename to pure2
     "###);
 }
 
 #[test]
-fn test_diff_relative_paths() {
+fn test_diff_from_to_branches() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    std::fs::create_dir_all(repo_path.join("dir1").join("subdir1")).unwrap();
-    std::fs::create_dir(repo_path.join("dir2")).unwrap();
-    std::fs::write(repo_path.join("file1"), "foo1\n").unwrap();
-    std::fs::write(repo_path.join("dir1").join("file2"), "foo2\n").unwrap();
-    std::fs::write(
-        repo_path.join("dir1").join("subdir1").join("file3"),
-        "foo3\n",
-    )
-    .unwrap();
-    std::fs::write(repo_path.join("dir2").join("file4"), "foo4\n").unwrap();
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["branch", "create", "old"]);
     test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::write(repo_path.join("file1"), "bar1\n").unwrap();
-    std::fs::write(repo_path.join("dir1").join("file2"), "bar2\n").unwrap();
-    std::fs::write(
-        repo_path.join("dir1").join("subdir1").join("file3"),
-        "bar3\n",
-    )
-    .unwrap();
-    std::fs::write(repo_path.join("dir2").join("file4"), "bar4\n").unwrap();
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["branch", "create", "new"]);
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file3"), "baz\n").unwrap();
 
-    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff"]);
-    #[cfg(unix)]
-    insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file2:
-       1    1: foo2bar2
-    Modified regular file subdir1/file3:
-       1    1: foo3bar3
-    Modified regular file ../dir2/file4:
-       1    1: foo4bar4
-    Modified regular file ../file1:
-       1    1: foo1bar1
-    "###);
-    #[cfg(windows)]
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "--from=old", "--to=new"]);
     insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file2:
-       1    1: foo2bar2
-    Modified regular file subdir1\file3:
-       1    1: foo3bar3
-    Modified regular file ..\dir2\file4:
-       1    1: foo4bar4
-    Modified regular file ..\file1:
-       1    1: foo1bar1
+    M file1
+    A file2
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "-s"]);
-    #[cfg(unix)]
-    insta::assert_snapshot!(stdout, @r###"
-    M file2
-    M subdir1/file3
-    M ../dir2/file4
-    M ../file1
-    "###);
-    #[cfg(windows)]
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "--from=new", "--to=old"]);
     insta::assert_snapshot!(stdout, @r###"
-    M file2
-    M subdir1\file3
-    M ..\dir2\file4
-    M ..\file1
+    M file1
+    D file2
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--types"]);
-    #[cfg(unix)]
+    // The other side defaults to the working-copy commit
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "--from=new"]);
     insta::assert_snapshot!(stdout, @r###"
-    FF file2
-    FF subdir1/file3
-    FF ../dir2/file4
-    FF ../file1
+    A file3
     "###);
-    #[cfg(windows)]
-    insta::assert_snapshot!(stdout, @r###"
-    FF file2
-    FF subdir1\file3
-    FF ..\dir2\file4
-    FF ..\file1
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--from=old|new", "--to=@"]);
+    insta::assert_snapshot!(stderr.lines().next().unwrap(), @r###"
+    Error: Revset "old|new" resolved to more than one revision
     "###);
+}
 
-    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--git"]);
+#[test]
+fn test_diff_empty() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/dir1/file2 b/dir1/file2
-    index 54b060eee9..1fe912cdd8 100644
-    --- a/dir1/file2
-    +++ b/dir1/file2
-    @@ -1,1 +1,1 @@
-    -foo2
-    +bar2
-    diff --git a/dir1/subdir1/file3 b/dir1/subdir1/file3
-    index c1ec6c6f12..f3c8b75ec6 100644
-    --- a/dir1/subdir1/file3
-    +++ b/dir1/subdir1/file3
-    @@ -1,1 +1,1 @@
-    -foo3
-    +bar3
-    diff --git a/dir2/file4 b/dir2/file4
-    index a0016dbc4c..17375f7a12 100644
-    --- a/dir2/file4
-    +++ b/dir2/file4
-    @@ -1,1 +1,1 @@
-    -foo4
-    +bar4
-    diff --git a/file1 b/file1
-    index 1715acd6a5..05c4fe6772 100644
-    --- a/file1
-    +++ b/file1
-    @@ -1,1 +1,1 @@
-    -foo1
-    +bar1
+    Added regular file file1:
+        (empty)
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--stat"]);
-    #[cfg(unix)]
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    file2         | 2 +-
-    subdir1/file3 | 2 +-
-    ../dir2/file4 | 2 +-
-    ../file1      | 2 +-
-    4 files changed, 4 insertions(+), 4 deletions(-)
+    Removed regular file file1:
+        (empty)
     "###);
-    #[cfg(windows)]
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
     insta::assert_snapshot!(stdout, @r###"
-    file2         | 2 +-
-    subdir1\file3 | 2 +-
-    ..\dir2\file4 | 2 +-
-    ..\file1      | 2 +-
-    4 files changed, 4 insertions(+), 4 deletions(-)
+    file1 | 0
+    1 file changed, 0 insertions(+), 0 deletions(-)
     "###);
 }
 
 #[test]
-fn test_diff_hunks() {
+fn test_diff_show_no_changes() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
-
-    // Test added, removed, inserted, and modified lines. The modified line
-    // contains unchanged words.
-    std::fs::write(repo_path.join("file1"), "").unwrap();
-    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
-    std::fs::write(repo_path.join("file3"), "foo\nbaz qux blah blah\n").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
     std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
-    std::fs::write(repo_path.join("file2"), "").unwrap();
-    std::fs::write(repo_path.join("file3"), "foo\nbar\nbaz quux blah blah\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
 
+    // Identical trees produce no output by default
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
-    insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file1:
-            1: foo
-    Modified regular file file2:
-       1     : foo
-    Modified regular file file3:
-       1    1: foo
-            2: bar
-       2    3: baz quxquux blah blah
-    "###);
+    insta::assert_snapshot!(stdout, @"");
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug"]);
+    test_env.add_config("ui.diff.show-no-changes = true");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    [38;5;3m<<diff header::Modified regular file file1:>>[39m
-    <<diff::     >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: >>[4m[38;5;2m<<diff added token::foo>>[24m[39m
-    [38;5;3m<<diff header::Modified regular file file2:>>[39m
-    [38;5;1m<<diff removed line_number::   1>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::foo>>[24m[39m
-    [38;5;3m<<diff header::Modified regular file file3:>>[39m
-    [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: foo>>
-    <<diff::     >>[38;5;2m<<diff added line_number::   2>>[39m<<diff::: >>[4m[38;5;2m<<diff added token::bar>>[24m[39m
-    [38;5;1m<<diff removed line_number::   2>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   3>>[39m<<diff::: baz >>[4m[38;5;1m<<diff removed token::qux>>[38;5;2m<<diff added token::quux>>[24m[39m<<diff:: blah blah>>
+    (no changes)
     "###);
-
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    index e69de29bb2..257cc5642c 100644
-    --- a/file1
-    +++ b/file1
-    @@ -1,0 +1,1 @@
-    +foo
-    diff --git a/file2 b/file2
-    index 257cc5642c..e69de29bb2 100644
-    --- a/file2
-    +++ b/file2
-    @@ -1,1 +1,0 @@
-    -foo
-    diff --git a/file3 b/file3
-    index 221a95a095..a543ef3892 100644
-    --- a/file3
-    +++ b/file3
-    @@ -1,2 +1,3 @@
-     foo
-    -baz qux blah blah
-    +bar
-    +baz quux blah blah
+    (no changes)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--posix"]);
+    insta::assert_snapshot!(stdout, @r###"
+    (no changes)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-", "--posix", "none()"]);
+    insta::assert_snapshot!(stdout, @r###"
+    (no changes)
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--color=debug"]);
+    // Paths excluded by the matcher
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-", "none()"]);
     insta::assert_snapshot!(stdout, @r###"
-    [1m<<diff file_header::diff --git a/file1 b/file1>>[0m
-    [1m<<diff file_header::index e69de29bb2..257cc5642c 100644>>[0m
-    [1m<<diff file_header::--- a/file1>>[0m
-    [1m<<diff file_header::+++ b/file1>>[0m
-    [38;5;6m<<diff hunk_header::@@ -1,0 +1,1 @@>>[39m
-    [38;5;2m<<diff added::+>>[4m<<diff added token::foo>>[24m[39m
-    [1m<<diff file_header::diff --git a/file2 b/file2>>[0m
-    [1m<<diff file_header::index 257cc5642c..e69de29bb2 100644>>[0m
-    [1m<<diff file_header::--- a/file2>>[0m
-    [1m<<diff file_header::+++ b/file2>>[0m
-    [38;5;6m<<diff hunk_header::@@ -1,1 +1,0 @@>>[39m
-    [38;5;1m<<diff removed::->>[4m<<diff removed token::foo>>[24m[39m
-    [1m<<diff file_header::diff --git a/file3 b/file3>>[0m
-    [1m<<diff file_header::index 221a95a095..a543ef3892 100644>>[0m
-    [1m<<diff file_header::--- a/file3>>[0m
-    [1m<<diff file_header::+++ b/file3>>[0m
-    [38;5;6m<<diff hunk_header::@@ -1,2 +1,3 @@>>[39m
-    <<diff context:: foo>>
-    [38;5;1m<<diff removed::-baz >>[4m<<diff removed token::qux>>[24m<<diff removed:: blah blah>>[39m
-    [38;5;2m<<diff added::+>>[4m<<diff added token::bar>>[24m[39m
-    [38;5;2m<<diff added::+baz >>[4m<<diff added token::quux>>[24m<<diff added:: blah blah>>[39m
+    (no changes)
     "###);
+
+    // Machine-readable formats aren't affected
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--name-only"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @"");
 }
 
 #[test]
-fn test_diff_missing_newline() {
+fn test_diff_labels() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
+    test_env.add_config("ui.diff.highlight-trailing-whitespace = true");
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=old"]);
+
+    std::fs::write(repo_path.join("copy-source"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("empty"), "").unwrap();
+    std::fs::write(repo_path.join("modified"), "a\nb\nc \n").unwrap();
+    std::fs::write(repo_path.join("rename-source"), "renamed\n").unwrap();
+    std::fs::write(repo_path.join("reordered"), "1\n2\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "-m=new"]);
+    std::fs::write(repo_path.join("binary"), b"\0").unwrap();
+    std::fs::write(repo_path.join("copy-source"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("copy-target"), "bar\n").unwrap();
+    std::fs::remove_file(repo_path.join("empty")).unwrap();
+    std::fs::write(repo_path.join("modified"), "a\nx\nc \n").unwrap();
+    std::fs::rename(
+        repo_path.join("rename-source"),
+        repo_path.join("rename-target"),
+    )
+    .unwrap();
+    std::fs::write(repo_path.join("reordered"), "2\n1\n").unwrap();
+    std::fs::write(repo_path.join("secret"), "baz\n").unwrap();
 
-    std::fs::write(repo_path.join("file1"), "foo").unwrap();
-    std::fs::write(repo_path.join("file2"), "foo\nbar").unwrap();
+    let mut stdout = String::new();
+    for args in [
+        &["diff", "--color=debug", "--color-words"][..],
+        &[
+            "diff",
+            "--color=debug",
+            "--git",
+            "--show-reorders",
+            "--show-whitespace",
+        ],
+        &["diff", "--color=debug", "--summary"],
+        &["diff", "--color=debug", "--stat"],
+        &["diff", "--color=debug", "--with-description"],
+        &["diff", "--color=debug", "--with-metadata"],
+    ] {
+        stdout += &test_env.jj_cmd_success(&repo_path, args);
+    }
+    SecretBackend::adopt_git_repo(&repo_path);
+    stdout += &test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug", "--color-words"]);
+
+    // Debug output is formatted as "<<label1 label2::text>>"
+    let used_labels: HashSet<&str> = stdout
+        .split("<<")
+        .skip(1)
+        .filter_map(|part| part.split_once("::"))
+        .flat_map(|(labels, _)| labels.split(' '))
+        .collect();
+    let unused_labels = DiffLabel::ALL
+        .iter()
+        .map(|label| label.as_str())
+        .filter(|label| !used_labels.contains(label))
+        .collect_vec();
+    assert!(unused_labels.is_empty(), "unused labels: {unused_labels:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_deref_symlinks() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "foo\n").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::os::unix::fs::symlink("../file", repo_path.join("dir").join("link")).unwrap();
+    std::os::unix::fs::symlink("dir/link", repo_path.join("chained")).unwrap();
+    std::os::unix::fs::symlink("loop2", repo_path.join("loop1")).unwrap();
+    std::os::unix::fs::symlink("loop1", repo_path.join("loop2")).unwrap();
+    std::os::unix::fs::symlink("../outside", repo_path.join("outside")).unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::write(repo_path.join("file1"), "foo\nbar").unwrap();
-    std::fs::write(repo_path.join("file2"), "foo").unwrap();
+    std::fs::write(repo_path.join("file"), "bar\n").unwrap();
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    // Unchanged symlinks aren't shown by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "dir/link"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // Targets shown by the normal diff aren't shown again
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--deref-symlinks"]);
     insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file1:
-       1    1: foo
-            2: bar
-    Modified regular file file2:
-       1    1: foo
-       2     : bar
+    Modified regular file file:
+       1    1: foobar
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--deref-symlinks", "dir/link"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    index 1910281566..a907ec3f43 100644
-    --- a/file1
-    +++ b/file1
-    @@ -1,1 +1,2 @@
-    -foo
-    \ No newline at end of file
-    +foo
-    +bar
-    \ No newline at end of file
-    diff --git a/file2 b/file2
-    index a907ec3f43..1910281566 100644
-    --- a/file2
-    +++ b/file2
-    @@ -1,2 +1,1 @@
-    -foo
-    -bar
-    \ No newline at end of file
-    +foo
-    \ No newline at end of file
+    Modified symlink target dir/link (=> file):
+       1    1: foobar
     "###);
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    // Chained symlinks are followed, and each target is shown once. Cycles and
+    // targets outside of the repository are ignored.
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--deref-symlinks",
+            "chained",
+            "dir",
+            "loop1",
+            "outside",
+        ],
+    );
     insta::assert_snapshot!(stdout, @r###"
-    file1 | 3 ++-
-    file2 | 3 +--
-    2 files changed, 3 insertions(+), 3 deletions(-)
+    Modified symlink target chained (=> file):
+       1    1: foobar
     "###);
 }
 
+#[cfg(unix)]
 #[test]
-fn test_color_words_diff_missing_newline() {
+fn test_diff_type_filter() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
-
-    std::fs::write(repo_path.join("file1"), "").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Empty"]);
-    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nf\ng\nh\ni").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Add no newline"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\ni").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify first line"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\ni").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify middle line"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify last line"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI\n").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Append newline"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Remove newline"]);
-    std::fs::write(repo_path.join("file1"), "").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Empty"]);
-
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "x\n").unwrap();
+    std::os::unix::fs::symlink("file1", repo_path.join("link2")).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::os::unix::fs::symlink("file1", repo_path.join("file2")).unwrap();
+    std::os::unix::fs::symlink("file1", repo_path.join("link1")).unwrap();
+    std::fs::remove_file(repo_path.join("link2")).unwrap();
+    std::os::unix::fs::symlink("file2", repo_path.join("link2")).unwrap();
+
+    // Changes are selected by the type before or after
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--type-filter=symlink"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    A link1
+    M link2
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--types", "--type-filter=file"]);
+    insta::assert_snapshot!(stdout, @r###"
+    FF file1
+    FL file2
+    "###);
     let stdout = test_env.jj_cmd_success(
         &repo_path,
-        &[
-            "log",
-            "-Tdescription",
-            "-pr::@-",
-            "--no-graph",
-            "--reversed",
-        ],
+        &["diff", "--git", "--type-filter=symlink", "link1"],
     );
     insta::assert_snapshot!(stdout, @r###"
-    === Empty
-    Added regular file file1:
-        (empty)
-    === Add no newline
-    Modified regular file file1:
-            1: a
-            2: b
-            3: c
-            4: d
-            5: e
-            6: f
-            7: g
-            8: h
-            9: i
-    === Modify first line
-    Modified regular file file1:
-       1    1: aA
-       2    2: b
-       3    3: c
-       4    4: d
-        ...
-    === Modify middle line
-    Modified regular file file1:
-       1    1: A
-       2    2: b
-       3    3: c
-       4    4: d
-       5    5: eE
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: i
-    === Modify last line
-    Modified regular file file1:
-        ...
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: iI
-    === Append newline
-    Modified regular file file1:
-        ...
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: I
-    === Remove newline
-    Modified regular file file1:
-        ...
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: I
-    === Empty
-    Modified regular file file1:
-       1     : A
-       2     : b
-       3     : c
-       4     : d
-       5     : E
-       6     : f
-       7     : g
-       8     : h
-       9     : I
+    diff --git a/link1 b/link1
+    new file mode 120000
+    index 0000000000..08219db9b0
+    --- /dev/null
+    +++ b/link1
+    @@ -0,0 +1 @@
+    +file1
+    \ No newline at end of file
     "###);
+
+    // Multiple types can be separated by comma
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--summary", "--type-filter=conflict,submodule"],
+    );
+    insta::assert_snapshot!(stdout, @"");
 }
 
 #[test]
-fn test_diff_skipped_context() {
+fn test_diff_file_mode() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["describe", "-m", "=== Left side of diffs"]);
+    // Test content+mode/mode-only changes of empty/non-empty files:
+    // - file1: ("",  x) -> ("2", n)  empty, content+mode
+    // - file2: ("1", x) -> ("1", n)  non-empty, mode-only
+    // - file3: ("1", n) -> ("2", x)  non-empty, content+mode
+    // - file4: ("",  n) -> ("",  x)  empty, mode-only
 
-    test_env.jj_cmd_ok(&repo_path, &["new", "@", "-m", "=== Must skip 2 lines"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\ni\nJ").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== Don't skip 1 line"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\nI\nj").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
-    std::fs::write(repo_path.join("file1"), "a\nB\nc\nd\ne\nf\ng\nh\nI\nj").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
-    std::fs::write(repo_path.join("file1"), "a\nb\nC\nd\ne\nf\ng\nh\nI\nj").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at start"]);
-    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\nE\nf\ng\nh\ni\nj").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at end"]);
-    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nF\ng\nh\ni\nj").unwrap();
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "1\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "x", "file1", "file2"]);
 
-    let stdout = test_env.jj_cmd_success(
-        &repo_path,
-        &["log", "-Tdescription", "-p", "--no-graph", "--reversed"],
-    );
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "2\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "2\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "n", "file1", "file2"]);
+    test_env.jj_cmd_ok(&repo_path, &["file", "chmod", "x", "file3", "file4"]);
+
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::remove_file(repo_path.join("file3")).unwrap();
+    std::fs::remove_file(repo_path.join("file4")).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@--"]);
     insta::assert_snapshot!(stdout, @r###"
-    === Left side of diffs
-    Added regular file file1:
-            1: a
-            2: b
-            3: c
-            4: d
-            5: e
-            6: f
-            7: g
-            8: h
-            9: i
-           10: j
-    === Must skip 2 lines
-    Modified regular file file1:
-       1    1: aA
-       2    2: b
-       3    3: c
-       4    4: d
-        ...
-       7    7: g
-       8    8: h
-       9    9: i
-      10   10: jJ
-    === Don't skip 1 line
-    Modified regular file file1:
-       1    1: aA
-       2    2: b
-       3    3: c
-       4    4: d
-       5    5: e
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: iI
-      10   10: j
-    === No gap to skip
-    Modified regular file file1:
-       1    1: a
-       2    2: bB
-       3    3: c
-       4    4: d
-       5    5: e
-       6    6: f
-       7    7: g
-       8    8: h
-       9    9: iI
-      10   10: j
-    === No gap to skip
-    Modified regular file file1:
-       1    1: a
-       2    2: b
-       3    3: cC
-       4    4: d
-       5    5: e
-       6    6: f
-       7    7: g
-       8    8: h
+    Added executable file file1:
+        (empty)
+    Added executable file file2:
+            1: 1
+    Added regular file file3:
+            1: 1
+    Added regular file file4:
+        (empty)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Executable file became non-executable at file1:
+            1: 2
+    Executable file became non-executable at file2:
+    Non-executable file became executable at file3:
+       1    1: 12
+    Non-executable file became executable at file4:
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Removed regular file file1:
+       1     : 2
+    Removed regular file file2:
+       1     : 1
+    Removed executable file file3:
+       1     : 2
+    Removed executable file file4:
+        (empty)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@--", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    new file mode 100755
+    index 0000000000..e69de29bb2
+    diff --git a/file2 b/file2
+    new file mode 100755
+    index 0000000000..d00491fd7e
+    --- /dev/null
+    +++ b/file2
+    @@ -0,0 +1 @@
+    +1
+    diff --git a/file3 b/file3
+    new file mode 100644
+    index 0000000000..d00491fd7e
+    --- /dev/null
+    +++ b/file3
+    @@ -0,0 +1 @@
+    +1
+    diff --git a/file4 b/file4
+    new file mode 100644
+    index 0000000000..e69de29bb2
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@-", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    old mode 100755
+    new mode 100644
+    index e69de29bb2..0cfbf08886
+    --- a/file1
+    +++ b/file1
+    @@ -0,0 +1 @@
+    +2
+    diff --git a/file2 b/file2
+    old mode 100755
+    new mode 100644
+    diff --git a/file3 b/file3
+    old mode 100644
+    new mode 100755
+    index d00491fd7e..0cfbf08886
+    --- a/file3
+    +++ b/file3
+    @@ -1 +1 @@
+    -1
+    +2
+    diff --git a/file4 b/file4
+    old mode 100644
+    new mode 100755
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r@", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    deleted file mode 100644
+    index 0cfbf08886..0000000000
+    --- a/file1
+    +++ /dev/null
+    @@ -1 +0,0 @@
+    -2
+    diff --git a/file2 b/file2
+    deleted file mode 100644
+    index d00491fd7e..0000000000
+    --- a/file2
+    +++ /dev/null
+    @@ -1 +0,0 @@
+    -1
+    diff --git a/file3 b/file3
+    deleted file mode 100755
+    index 0cfbf08886..0000000000
+    --- a/file3
+    +++ /dev/null
+    @@ -1 +0,0 @@
+    -2
+    diff --git a/file4 b/file4
+    deleted file mode 100755
+    index e69de29bb2..0000000000
+    "###);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_git_quoted_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("tab\there"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::rename(repo_path.join("tab\there"), repo_path.join("quote\"d")).unwrap();
+
+    // Paths with special characters are quoted as Git does
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git "a/tab\there" "b/quote\"d"
+    similarity index 100%
+    rename from "tab\there"
+    rename to "quote\"d"
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "-r=@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git "a/tab\there" "b/tab\there"
+    new file mode 100644
+    index 0000000000..257cc5642c
+    --- /dev/null
+    +++ "b/tab\there"
+    @@ -0,0 +1 @@
+    +foo
+    "###);
+}
+
+#[test]
+fn test_diff_types() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let file_path = repo_path.join("foo");
+
+    // Missing
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=missing"]);
+
+    // Normal file
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=file"]);
+    std::fs::write(&file_path, "foo").unwrap();
+
+    // Conflict (add/add)
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=conflict"]);
+    std::fs::write(&file_path, "foo").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()"]);
+    std::fs::write(&file_path, "bar").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["squash", r#"--into=description("conflict")"#]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        use std::path::PathBuf;
+
+        // Executable
+        test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=executable"]);
+        std::fs::write(&file_path, "foo").unwrap();
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Symlink
+        test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=symlink"]);
+        std::os::unix::fs::symlink(PathBuf::from("."), &file_path).unwrap();
+    }
+
+    let diff = |from: &str, to: &str| {
+        test_env.jj_cmd_success(
+            &repo_path,
+            &[
+                "diff",
+                "--types",
+                &format!(r#"--from=description("{}")"#, from),
+                &format!(r#"--to=description("{}")"#, to),
+            ],
+        )
+    };
+    insta::assert_snapshot!(diff("missing", "file"), @r###"
+    -F foo
+    "###);
+    insta::assert_snapshot!(diff("file", "conflict"), @r###"
+    FC foo
+    "###);
+    insta::assert_snapshot!(diff("conflict", "missing"), @r###"
+    C- foo
+    "###);
+
+    #[cfg(unix)]
+    {
+        insta::assert_snapshot!(diff("symlink", "file"), @r###"
+        LF foo
+        "###);
+        insta::assert_snapshot!(diff("missing", "executable"), @r###"
+        -F foo
+        "###);
+    }
+}
+
+#[test]
+fn test_diff_types_conflict_detail() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let file_path = repo_path.join("foo");
+
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=file"]);
+    std::fs::write(&file_path, "foo").unwrap();
+    for side in ["side1", "side2", "side3"] {
+        test_env.jj_cmd_ok(&repo_path, &["new", "root()", &format!("-m={side}")]);
+        std::fs::write(&file_path, side).unwrap();
+    }
+
+    // 2-sided conflict
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            r#"description("side1")"#,
+            r#"description("side2")"#,
+            "-m=conflict2",
+        ],
+    );
+    // 3-sided conflict
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            r#"description("side1")"#,
+            r#"description("side2")"#,
+            r#"description("side3")"#,
+            "-m=conflict3",
+        ],
+    );
+
+    let diff = |from: &str, to: &str, extra_args: &[&str]| {
+        let from_arg = format!(r#"--from=description("{from}")"#);
+        let to_arg = format!(r#"--to=description("{to}")"#);
+        let mut args = vec!["diff", "--types", &from_arg, &to_arg];
+        args.extend_from_slice(extra_args);
+        test_env.jj_cmd_success(&repo_path, &args)
+    };
+    insta::assert_snapshot!(diff("conflict2", "conflict3", &[]), @r###"
+    CC foo
+    "###);
+    insta::assert_snapshot!(diff("file", "conflict2", &["--conflict-detail"]), @r###"
+    FC2 foo
+    "###);
+    insta::assert_snapshot!(diff("conflict2", "conflict3", &["--conflict-detail"]), @r###"
+    C2C3 foo
+    "###);
+    insta::assert_snapshot!(diff("conflict3", "file", &["--conflict-detail"]), @r###"
+    C3F foo
+    "###);
+}
+
+#[test]
+fn test_diff_conflict_stat() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=base"]);
+    std::fs::write(repo_path.join("file1"), "base\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "base\n").unwrap();
+    for (side, file) in [
+        ("a", "file1"),
+        ("b", "file1"),
+        ("c", "file2"),
+        ("d", "file2"),
+    ] {
+        test_env.jj_cmd_ok(
+            &repo_path,
+            &["new", r#"description("base")"#, &format!("-m={side}")],
+        );
+        std::fs::write(repo_path.join(file), format!("{side}\n")).unwrap();
+    }
+    // file1 is conflicted
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            r#"description("side1")"#,
+            r#"description("side2")"#,
+            "-m=conflict1",
+        ],
+    );
+    // file2 is conflicted
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            r#"description("side3")"#,
+            r#"description("side4")"#,
+            "-m=conflict2",
+        ],
+    );
+
+    let diff = |from: &str, to: &str| {
+        let from_arg = format!(r#"--from=description("{from}")"#);
+        let to_arg = format!(r#"--to=description("{to}")"#);
+        test_env.jj_cmd_success(&repo_path, &["diff", "--conflict-stat", &from_arg, &to_arg])
+    };
+    insta::assert_snapshot!(diff("base", "conflict1"), @r###"
+    1 conflict introduced, 0 resolved
+    "###);
+    // One conflict is resolved and another is introduced
+    insta::assert_snapshot!(diff("conflict1", "conflict2"), @r###"
+    1 conflict introduced, 1 resolved
+    "###);
+    insta::assert_snapshot!(diff("conflict2", "base"), @r###"
+    0 conflicts introduced, 1 resolved
+    "###);
+    // Changes without conflicts aren't counted
+    insta::assert_snapshot!(diff("side1", "side3"), @r###"
+    0 conflicts introduced, 0 resolved
+    "###);
+}
+
+#[test]
+fn test_diff_summary_collapse_dirs() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    for dir in ["many", "few", "deep/a", "deep/b"] {
+        std::fs::create_dir_all(repo_path.join(dir)).unwrap();
+    }
+    for i in 0..50 {
+        std::fs::write(repo_path.join(format!("many/file{i:02}")), "a\n").unwrap();
+    }
+    for i in 0..6 {
+        std::fs::write(repo_path.join(format!("deep/a/file{i}")), "a\n").unwrap();
+        std::fs::write(repo_path.join(format!("deep/b/file{i}")), "a\n").unwrap();
+    }
+    std::fs::write(repo_path.join("few/file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("few/file2"), "a\n").unwrap();
+    std::fs::write(repo_path.join("top"), "a\n").unwrap();
+
+    // Directories with more than N changes are collapsed
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--collapse-dirs=10"]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    A deep/ (12 files)
+    A few/file1
+    A few/file2
+    A many/ (50 files)
+    A top
+    "###);
+
+    // Nested directories are collapsed separately at deeper depth
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--summary",
+            "--config-toml=ui.diff.summary.collapse-dirs=5",
+            "--config-toml=ui.diff.summary.collapse-depth=2",
+        ],
+    );
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    A deep/a/ (6 files)
+    A deep/b/ (6 files)
+    A few/file1
+    A few/file2
+    A many/ (50 files)
+    A top
+    "###);
+
+    // Mixed changes are collapsed as modification
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("many/file00"), "b\n").unwrap();
+    for i in 1..=10 {
+        std::fs::remove_file(repo_path.join(format!("many/file{i:02}"))).unwrap();
+    }
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--collapse-dirs=10"]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    M many/ (11 files)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--collapse-dirs=11"]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    M many/file00
+    D many/file01
+    D many/file02
+    D many/file03
+    D many/file04
+    D many/file05
+    D many/file06
+    D many/file07
+    D many/file08
+    D many/file09
+    D many/file10
+    "###);
+}
+
+#[test]
+fn test_diff_name_only() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("deleted"), "d").unwrap();
+    std::fs::write(repo_path.join("modified"), "m").unwrap();
+    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", "--name-only"]), @r###"
+    deleted
+    modified
+    "###);
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-mfirst"]);
+    std::fs::remove_file(repo_path.join("deleted")).unwrap();
+    std::fs::write(repo_path.join("modified"), "mod").unwrap();
+    std::fs::write(repo_path.join("added"), "add").unwrap();
+    std::fs::create_dir(repo_path.join("sub")).unwrap();
+    std::fs::write(repo_path.join("sub/added"), "sub/add").unwrap();
+    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", "--name-only"]).replace('\\', "/"),
+    @r###"
+    added
+    deleted
+    modified
+    sub/added
+    "###);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_mode_only() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("exec"), "a\n").unwrap();
+    std::fs::write(repo_path.join("link"), "b\n").unwrap();
+    std::fs::write(repo_path.join("modified"), "c\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::set_permissions(repo_path.join("exec"), PermissionsExt::from_mode(0o755)).unwrap();
+    std::fs::remove_file(repo_path.join("link")).unwrap();
+    std::os::unix::fs::symlink("exec", repo_path.join("link")).unwrap();
+    std::fs::write(repo_path.join("modified"), "d\n").unwrap();
+    std::fs::write(repo_path.join("added"), "e\n").unwrap();
+
+    // Content changes and added files aren't listed
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--mode-only"]);
+    insta::assert_snapshot!(stdout, @r###"
+    100644 -> 100755 exec
+    100644 -> 120000 link
+    "###);
+
+    // Mode-only format can't be combined with other short formats
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--mode-only", "--summary"]);
+    insta::assert_snapshot!(stderr, @r###"
+    error: the argument '--mode-only' cannot be used with '--summary'
+
+    Usage: jj diff --mode-only [PATHS]...
+
+    For more information, try '--help'.
+    "###);
+}
+
+#[test]
+fn test_diff_bad_args() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "-s", "--types"]);
+    insta::assert_snapshot!(stderr, @r###"
+    error: the argument '--summary' cannot be used with '--types'
+
+    Usage: jj diff --summary [PATHS]...
+
+    For more information, try '--help'.
+    "###);
+
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--color-words", "--git"]);
+    insta::assert_snapshot!(stderr, @r###"
+    error: the argument '--color-words' cannot be used with '--git'
+
+    Usage: jj diff --color-words [PATHS]...
+
+    For more information, try '--help'.
+    "###);
+}
+
+#[test]
+fn test_diff_relative_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir_all(repo_path.join("dir1").join("subdir1")).unwrap();
+    std::fs::create_dir(repo_path.join("dir2")).unwrap();
+    std::fs::write(repo_path.join("file1"), "foo1\n").unwrap();
+    std::fs::write(repo_path.join("dir1").join("file2"), "foo2\n").unwrap();
+    std::fs::write(
+        repo_path.join("dir1").join("subdir1").join("file3"),
+        "foo3\n",
+    )
+    .unwrap();
+    std::fs::write(repo_path.join("dir2").join("file4"), "foo4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar1\n").unwrap();
+    std::fs::write(repo_path.join("dir1").join("file2"), "bar2\n").unwrap();
+    std::fs::write(
+        repo_path.join("dir1").join("subdir1").join("file3"),
+        "bar3\n",
+    )
+    .unwrap();
+    std::fs::write(repo_path.join("dir2").join("file4"), "bar4\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file2:
+       1    1: foo2bar2
+    Modified regular file subdir1/file3:
+       1    1: foo3bar3
+    Modified regular file ../dir2/file4:
+       1    1: foo4bar4
+    Modified regular file ../file1:
+       1    1: foo1bar1
+    "###);
+    #[cfg(windows)]
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file2:
+       1    1: foo2bar2
+    Modified regular file subdir1\file3:
+       1    1: foo3bar3
+    Modified regular file ..\dir2\file4:
+       1    1: foo4bar4
+    Modified regular file ..\file1:
+       1    1: foo1bar1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "-s"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    M subdir1/file3
+    M ../dir2/file4
+    M ../file1
+    "###);
+    #[cfg(windows)]
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    M subdir1\file3
+    M ..\dir2\file4
+    M ..\file1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--types"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    FF file2
+    FF subdir1/file3
+    FF ../dir2/file4
+    FF ../file1
+    "###);
+    #[cfg(windows)]
+    insta::assert_snapshot!(stdout, @r###"
+    FF file2
+    FF subdir1\file3
+    FF ..\dir2\file4
+    FF ..\file1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/dir1/file2 b/dir1/file2
+    index 54b060eee9..1fe912cdd8 100644
+    --- a/dir1/file2
+    +++ b/dir1/file2
+    @@ -1 +1 @@
+    -foo2
+    +bar2
+    diff --git a/dir1/subdir1/file3 b/dir1/subdir1/file3
+    index c1ec6c6f12..f3c8b75ec6 100644
+    --- a/dir1/subdir1/file3
+    +++ b/dir1/subdir1/file3
+    @@ -1 +1 @@
+    -foo3
+    +bar3
+    diff --git a/dir2/file4 b/dir2/file4
+    index a0016dbc4c..17375f7a12 100644
+    --- a/dir2/file4
+    +++ b/dir2/file4
+    @@ -1 +1 @@
+    -foo4
+    +bar4
+    diff --git a/file1 b/file1
+    index 1715acd6a5..05c4fe6772 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo1
+    +bar1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--stat"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    file2         | 2 +-
+    subdir1/file3 | 2 +-
+    ../dir2/file4 | 2 +-
+    ../file1      | 2 +-
+    4 files changed, 4 insertions(+), 4 deletions(-)
+    "###);
+    #[cfg(windows)]
+    insta::assert_snapshot!(stdout, @r###"
+    file2         | 2 +-
+    subdir1\file3 | 2 +-
+    ..\dir2\file4 | 2 +-
+    ..\file1      | 2 +-
+    4 files changed, 4 insertions(+), 4 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_absolute_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir1")).unwrap();
+    std::fs::write(repo_path.join("file1"), "foo1\n").unwrap();
+    std::fs::write(repo_path.join("dir1").join("file2"), "foo2\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar1\n").unwrap();
+    std::fs::write(repo_path.join("dir1").join("file2"), "bar2\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--summary"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    M ../file1
+    "###);
+    #[cfg(windows)]
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    M ..\file1
+    "###);
+
+    test_env.add_config("ui.diff.absolute-paths = true");
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file $TEST_ENV/repo/dir1/file2:
+       1    1: foo2bar2
+    Modified regular file $TEST_ENV/repo/file1:
+       1    1: foo1bar1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M $TEST_ENV/repo/dir1/file2
+    M $TEST_ENV/repo/file1
+    "###);
+
+    // Git-format diff keeps repo-relative paths
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir1"), &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/dir1/file2 b/dir1/file2
+    index 54b060eee9..1fe912cdd8 100644
+    --- a/dir1/file2
+    +++ b/dir1/file2
+    @@ -1 +1 @@
+    -foo2
+    +bar2
+    diff --git a/file1 b/file1
+    index 1715acd6a5..05c4fe6772 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo1
+    +bar1
+    "###);
+}
+
+#[test]
+fn test_diff_hyperlinks() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+
+    // Disabled by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--color=always"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;6mM file1[39m
+    [38;5;1mD file2[39m
+    "###);
+
+    test_env.add_config("ui.diff.hyperlinks = true");
+    // Deleted files aren't linked
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--color=always"]);
+    #[cfg(unix)]
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;6mM ]8;;file://$TEST_ENV/repo/file1\file1]8;;\[39m
+    [38;5;1mD file2[39m
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=always"]);
+    #[cfg(unix)]
+    assert!(stdout.contains(
+        "Modified regular file \u{1b}]8;;file://$TEST_ENV/repo/file1\u{1b}\\file1\u{1b}]8;;\u{1b}\\:"
+    ));
+    assert!(stdout.contains("Removed regular file file2:"));
+
+    // Not linked if the output isn't colored
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    D file2
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    assert!(!stdout.contains("\u{1b}]8;;"));
+}
+
+#[test]
+fn test_diff_hunks() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // Test added, removed, inserted, and modified lines. The modified line
+    // contains unchanged words.
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\nbaz qux blah blah\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\nbar\nbaz quux blah blah\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+            1: foo
+    Modified regular file file2:
+       1     : foo
+    Modified regular file file3:
+       1    1: foo
+            2: bar
+       2    3: baz quxquux blah blah
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;3m<<diff header::Modified regular file file1:>>[39m
+    <<diff::     >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: >>[4m[38;5;2m<<diff added token::foo>>[24m[39m
+    [38;5;3m<<diff header::Modified regular file file2:>>[39m
+    [38;5;1m<<diff removed line_number::   1>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::foo>>[24m[39m
+    [38;5;3m<<diff header::Modified regular file file3:>>[39m
+    [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: foo>>
+    <<diff::     >>[38;5;2m<<diff added line_number::   2>>[39m<<diff::: >>[4m[38;5;2m<<diff added token::bar>>[24m[39m
+    [38;5;1m<<diff removed line_number::   2>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   3>>[39m<<diff::: baz >>[4m[38;5;1m<<diff removed token::qux>>[38;5;2m<<diff added token::quux>>[24m[39m<<diff:: blah blah>>
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index e69de29bb2..257cc5642c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -0,0 +1 @@
+    +foo
+    diff --git a/file2 b/file2
+    index 257cc5642c..e69de29bb2 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1 +0,0 @@
+    -foo
+    diff --git a/file3 b/file3
+    index 221a95a095..a543ef3892 100644
+    --- a/file3
+    +++ b/file3
+    @@ -1,2 +1,3 @@
+     foo
+    -baz qux blah blah
+    +bar
+    +baz quux blah blah
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [1m<<diff file_header::diff --git a/file1 b/file1>>[0m
+    [1m<<diff file_header::index e69de29bb2..257cc5642c 100644>>[0m
+    [1m<<diff file_header::--- a/file1>>[0m
+    [1m<<diff file_header::+++ b/file1>>[0m
+    [38;5;6m<<diff hunk_header::@@ -0,0 +1 @@>>[39m
+    [38;5;2m<<diff added::+>>[4m<<diff added token::foo>>[24m[39m
+    [1m<<diff file_header::diff --git a/file2 b/file2>>[0m
+    [1m<<diff file_header::index 257cc5642c..e69de29bb2 100644>>[0m
+    [1m<<diff file_header::--- a/file2>>[0m
+    [1m<<diff file_header::+++ b/file2>>[0m
+    [38;5;6m<<diff hunk_header::@@ -1 +0,0 @@>>[39m
+    [38;5;1m<<diff removed::->>[4m<<diff removed token::foo>>[24m[39m
+    [1m<<diff file_header::diff --git a/file3 b/file3>>[0m
+    [1m<<diff file_header::index 221a95a095..a543ef3892 100644>>[0m
+    [1m<<diff file_header::--- a/file3>>[0m
+    [1m<<diff file_header::+++ b/file3>>[0m
+    [38;5;6m<<diff hunk_header::@@ -1,2 +1,3 @@>>[39m
+    <<diff context:: foo>>
+    [38;5;1m<<diff removed::-baz >>[4m<<diff removed token::qux>>[24m<<diff removed:: blah blah>>[39m
+    [38;5;2m<<diff added::+>>[4m<<diff added token::bar>>[24m[39m
+    [38;5;2m<<diff added::+baz >>[4m<<diff added token::quux>>[24m<<diff added:: blah blah>>[39m
+    "###);
+}
+
+#[test]
+fn test_diff_git_submodule() {
+    let test_env = TestEnvironment::default();
+    let repo_path = test_env.env_root().join("repo");
+    let git_repo = git2::Repository::init(&repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let mut parent = None;
+    for (branch, submodule_id) in [
+        ("old", "1111111111111111111111111111111111111111"),
+        ("new", "2222222222222222222222222222222222222222"),
+    ] {
+        let mut tree_builder = git_repo.treebuilder(None).unwrap();
+        tree_builder
+            .insert(
+                "sub",
+                git2::Oid::from_str(submodule_id).unwrap(),
+                git2::FileMode::Commit.into(),
+            )
+            .unwrap();
+        let tree = git_repo.find_tree(tree_builder.write().unwrap()).unwrap();
+        let commit_id = git_repo
+            .commit(
+                Some(&format!("refs/heads/{branch}")),
+                &signature,
+                &signature,
+                branch,
+                &tree,
+                &parent.iter().collect_vec(),
+            )
+            .unwrap();
+        parent = Some(git_repo.find_commit(commit_id).unwrap());
+    }
+    test_env.jj_cmd_ok(&repo_path, &["git", "init", "--git-repo", "."]);
+
+    // Added submodule
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "-r", "old"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/sub b/sub
+    new file mode 160000
+    index 0000000000..1111111111
+    --- /dev/null
+    +++ b/sub
+    @@ -0,0 +1 @@
+    +Subproject commit 1111111111111111111111111111111111111111
+    "###);
+
+    // Submodule pointer change
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "-r", "new"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/sub b/sub
+    index 1111111111..2222222222 160000
+    --- a/sub
+    +++ b/sub
+    @@ -1 +1 @@
+    -Subproject commit 1111111111111111111111111111111111111111
+    +Subproject commit 2222222222222222222222222222222222222222
+    "###);
+}
+
+#[test]
+fn test_diff_git_copy_with_modification() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n5\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n2\n3\nX\n").unwrap();
+
+    // The copy is diffed against the source content, not as a new file
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 94ebaf9001..8a1218a102 100644
+    --- a/file1
+    +++ b/file1
+    @@ -2,3 +2,4 @@
+     2
+     3
+     4
+    +5
+    diff --git a/file1 b/file2
+    copy from file1
+    copy to file2
+    index 94ebaf9001..cd558711f9 100644
+    --- a/file1
+    +++ b/file2
+    @@ -1,4 +1,4 @@
+     1
+     2
+     3
+    -4
+    +X
+    "###);
+}
+
+#[test]
+fn test_diff_git_abbrev() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "baz\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--config-toml=ui.diff.git.abbrev='full'"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 257cc5642cb1a054f08cc83f2d943e56fd3ebe99..5716ca5987cbf97d6bb54920bea6adde242d87e6 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo
+    +bar
+    diff --git a/file2 b/file2
+    new file mode 100644
+    index 0000000000000000000000000000000000000000..76018072e09c5d31c8c6e3113b8aa0fe625195ca
+    --- /dev/null
+    +++ b/file2
+    @@ -0,0 +1 @@
+    +baz
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--config-toml=ui.diff.git.abbrev=7"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 257cc56..5716ca5 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo
+    +bar
+    diff --git a/file2 b/file2
+    new file mode 100644
+    index 0000000..7601807
+    --- /dev/null
+    +++ b/file2
+    @@ -0,0 +1 @@
+    +baz
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["diff", "--git", "--config-toml=ui.diff.git.abbrev=2"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid `ui.diff.git.abbrev`: expected "full" or an integer of at least 4, got "2"
+    For help, see https://github.com/martinvonz/jj/blob/main/docs/config.md.
+    "###);
+}
+
+#[test]
+fn test_diff_git_change_order() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "1\nX\nY\n3\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 01e79c32a8..cb3217f956 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,4 @@
+     1
+    -2
+    +X
+    +Y
+     3
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "--config-toml=ui.diff.git.change-order='added-first'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 01e79c32a8..cb3217f956 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,4 @@
+     1
+    +X
+    +Y
+    -2
+     3
+    "###);
+}
+
+#[test]
+fn test_diff_git_function_context() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = |x5: &str| {
+        format!(
+            "fn a() {{\n    0\n}}\n\nfn b() {{\n    let x1 = 1;\n    let x2 = 2;\n    \
+             let x3 = 3;\n    let x4 = 4;\n    let x5 = {x5};\n    let x6 = 6;\n    \
+             let x7 = 7;\n    let x8 = 8;\n    let x9 = 9;\n}}\n\nfn c() {{\n    0\n}}\n"
+        )
+    };
+    std::fs::write(repo_path.join("file.rs"), content("5")).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file.rs"), content("50")).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index db5c84c063..3a27c4503f 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -7,7 +7,7 @@
+         let x2 = 2;
+         let x3 = 3;
+         let x4 = 4;
+    -    let x5 = 5;
+    +    let x5 = 50;
+         let x6 = 6;
+         let x7 = 7;
+         let x8 = 8;
+    "###);
+
+    // The hunk is expanded to the whole function, excluding the blank line
+    // before the next function
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--function-context"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index db5c84c063..3a27c4503f 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -5,11 +5,11 @@
+     fn b() {
+         let x1 = 1;
+         let x2 = 2;
+         let x3 = 3;
+         let x4 = 4;
+    -    let x5 = 5;
+    +    let x5 = 50;
+         let x6 = 6;
+         let x7 = 7;
+         let x8 = 8;
+         let x9 = 9;
+     }
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "--function-context",
+            "--config-toml=ui.diff.function-regex='('",
+        ],
+    );
+    insta::assert_snapshot!(stderr.lines().next().unwrap(), @"Config error: Invalid `ui.diff.function-regex`: regex parse error:");
+}
+
+#[test]
+fn test_diff_git_show_reorders() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n5\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "5\n4\n3\n2\n1\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+
+    // One of the lines is matched, and the other 4 lines are moved around it
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--show-reorders"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 8a1218a102..86e24d1b4c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,5 +1,5 @@
+    (4 lines reordered)
+    diff --git a/file2 b/file2
+    index 7898192261..6178079822 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1 +1 @@
+    -a
+    +b
+    "###);
+}
+
+#[test]
+fn test_diff_git_hunk_header_counts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n5\n6\n7\n8\n9\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "x\n2\n3\n4\ny\nz\n7\n8\n9\n").unwrap();
+
+    // Line count is omitted for single-line ranges, as Git does
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=0"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 0719398930..89ae475c7c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -1
+    +x
+    @@ -5,2 +5,2 @@
+    -5
+    -6
+    +y
+    +z
+    "###);
+}
+
+#[test]
+fn test_diff_git_hunk_headers_only() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = |a2: &str, b8: &str| {
+        let a = (1..=9)
+            .map(|i| match i {
+                2 => format!("    let a{i} = {a2};\n"),
+                _ => format!("    let a{i} = {i};\n"),
+            })
+            .join("");
+        let b = (1..=9)
+            .map(|i| match i {
+                8 => format!("    let b{i} = {b8};\n"),
+                _ => format!("    let b{i} = {i};\n"),
+            })
+            .join("");
+        format!("fn foo() {{\n{a}}}\n\nfn bar() {{\n{b}}}\n")
+    };
+    std::fs::write(repo_path.join("file.rs"), content("2", "8")).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file.rs"), content("20", "80")).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--hunk-headers-only"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index 0a9eb8eea9..4c21437fb0 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -1,6 +1,6 @@
+    @@ -18,6 +18,6 @@
+    "###);
+
+    // The enclosing function line is appended to each hunk header
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--hunk-headers-only", "--function-context"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index 0a9eb8eea9..4c21437fb0 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -1,11 +1,11 @@ fn foo() {
+    @@ -13,11 +13,11 @@ fn bar() {
+    "###);
+}
+
+#[test]
+fn test_diff_git_fold_markers() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = (1..=20).map(|i| format!("{i}\n")).join("");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let content = content
+        .replace("\n3\n", "\nthree\n")
+        .replace("\n15\n", "\nfifteen\n");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+
+    // No markers by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    assert!(!stdout.contains("<<<fold"));
+
+    // Skipped lines between and after the hunks are folded
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--fold-markers"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..34689b6317 100644
+    --- a/file
+    +++ b/file
+    @@ -1,6 +1,6 @@
+     1
+     2
+    -3
+    +three
+     4
+     5
+     6
+    <<<fold lines 7-11>>>
+    @@ -12,7 +12,7 @@
+     12
+     13
+     14
+    -15
+    +fifteen
+     16
+     17
+     18
+    <<<fold lines 19-20>>>
+    "###);
+
+    // Skipped lines before the first hunk are folded
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--fold-markers", "--context=0"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..34689b6317 100644
+    --- a/file
+    +++ b/file
+    <<<fold lines 1-2>>>
+    @@ -3 +3 @@
+    -3
+    +three
+    <<<fold lines 4-14>>>
+    @@ -15 +15 @@
+    -15
+    +fifteen
+    <<<fold lines 16-20>>>
+    "###);
+}
+
+#[test]
+fn test_diff_git_annotate_gaps() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = (1..=20).map(|i| format!("{i}\n")).join("");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let content = content
+        .replace("\n3\n", "\nthree\n")
+        .replace("\n15\n", "\nfifteen\n");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+
+    // No annotations by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    assert!(!stdout.contains("lines not shown"));
+
+    // Lines 7-11 are skipped
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--git-annotate-gaps"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..34689b6317 100644
+    --- a/file
+    +++ b/file
+    @@ -1,6 +1,6 @@
+     1
+     2
+    -3
+    +three
+     4
+     5
+     6
+    # 5 lines not shown
+    @@ -12,7 +12,7 @@
+     12
+     13
+     14
+    -15
+    +fifteen
+     16
+     17
+     18
+    "###);
+
+    // Lines 5-13 are skipped
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--git-annotate-gaps", "--context=1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..34689b6317 100644
+    --- a/file
+    +++ b/file
+    @@ -2,3 +2,3 @@
+     2
+    -3
+    +three
+     4
+    # 9 lines not shown
+    @@ -14,3 +14,3 @@
+     14
+    -15
+    +fifteen
+     16
+    "###);
+}
+
+#[test]
+fn test_diff_git_line_numbers() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = (1..=12).map(|i| format!("{i}\n")).join("");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let content = content
+        .replace("\n3\n", "\nthree\nthree2\n")
+        .replace("\n10\n", "\n");
+    std::fs::write(repo_path.join("file"), &content).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--line-numbers"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 08fe19ca4d..37bccbe80c 100644
+    --- a/file
+    +++ b/file
+    @@ -1,12 +1,12 @@
+       1    1  1
+       2    2  2
+       3      -3
+            3 +three
+            4 +three2
+       4    5  4
+       5    6  5
+       6    7  6
+       7    8  7
+       8    9  8
+       9   10  9
+      10      -10
+      11   11  11
+      12   12  12
+    "###);
+
+    // Line numbers continue from the hunk header
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--line-numbers", "--context=1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 08fe19ca4d..37bccbe80c 100644
+    --- a/file
+    +++ b/file
+    @@ -2,3 +2,4 @@
+       2    2  2
+       3      -3
+            3 +three
+            4 +three2
+       4    5  4
+    @@ -9,3 +10,2 @@
+       9   10  9
+      10      -10
+      11   11  11
+    "###);
+}
+
+#[test]
+fn test_diff_git_verify() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let content = (1..=20).map(|i| format!("{i}\n")).join("");
+    std::fs::write(repo_path.join("file1"), &content).unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nb").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let content = content
+        .replace("\n3\n", "\n")
+        .replace("\n15\n", "\n15\n15a\n");
+    std::fs::write(repo_path.join("file1"), &content).unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nc").unwrap();
+
+    // The output is unchanged if the hunks are valid
+    let expected = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--verify-git-diff"]);
+    assert_eq!(stdout, expected);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--verify-git-diff", "--hunk-headers-only"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 0ff3bbb9c8..cac6619cf5 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,6 +1,5 @@
+    @@ -13,6 +12,7 @@
+    diff --git a/file2 b/file2
+    index 0a207c060e..817f660e44 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1,2 +1,2 @@
+    "###);
+}
+
+#[test]
+fn test_diff_git_word_diff() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\nhello world\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n2\n3\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "foo\nhello there\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n3\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--word-diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 4fd776d255..381b153a67 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,3 @@
+    foo
+    hello [-world-]{+there+}
+    bar
+    diff --git a/file2 b/file2
+    index 01e79c32a8..2b2f2e1b92 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1,3 +1,2 @@
+    1
+    [-2-]
+    3
+    "###);
+
+    // The mode can be specified explicitly
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--word-diff=plain", "--context=0", "file1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 4fd776d255..381b153a67 100644
+    --- a/file1
+    +++ b/file1
+    @@ -2 +2 @@
+    hello [-world-]{+there+}
+    "###);
+
+    // Line-based diff is the default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 4fd776d255..381b153a67 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,3 @@
+     foo
+    -hello world
+    +hello there
+     bar
+    "###);
+}
+
+#[test]
+fn test_diff_word_tokenizer_syntax() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file.rs"), "x+=1\n").unwrap();
+    std::fs::write(repo_path.join("file.txt"), "x+=1\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file.rs"), "x-=1\n").unwrap();
+    std::fs::write(repo_path.join("file.txt"), "x-=1\n").unwrap();
+
+    // Operators are split into characters by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--word-diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index 66cdf4ebbe..3ee8cd0e18 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -1 +1 @@
+    x[-+-]{+-+}=1
+    diff --git a/file.txt b/file.txt
+    index 66cdf4ebbe..3ee8cd0e18 100644
+    --- a/file.txt
+    +++ b/file.txt
+    @@ -1 +1 @@
+    x[-+-]{+-+}=1
+    "###);
+
+    // The whole operator is highlighted in files of known languages
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "--word-diff",
+            "--config-toml=ui.diff.word-tokenizer='syntax'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.rs b/file.rs
+    index 66cdf4ebbe..3ee8cd0e18 100644
+    --- a/file.rs
+    +++ b/file.rs
+    @@ -1 +1 @@
+    x[-+=-]{+-=+}1
+    diff --git a/file.txt b/file.txt
+    index 66cdf4ebbe..3ee8cd0e18 100644
+    --- a/file.txt
+    +++ b/file.txt
+    @@ -1 +1 @@
+    x[-+-]{+-+}=1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--color=debug",
+            "--config-toml=ui.diff.word-tokenizer='syntax'",
+            "file.rs",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;3m<<diff header::Modified regular file file.rs:>>[39m
+    [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: x>>[4m[38;5;1m<<diff removed token::+=>>[38;5;2m<<diff added token::-=>>[24m[39m<<diff::1>>
+    "###);
+}
+
+#[test]
+fn test_diff_ignore_case() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("case.txt"), "FOO\n").unwrap();
+    std::fs::write(repo_path.join("mixed.txt"), "FOO\nbar\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("case.txt"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("mixed.txt"), "foo\nBaz\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/case.txt b/case.txt
+    index b7d6715e2d..257cc5642c 100644
+    --- a/case.txt
+    +++ b/case.txt
+    @@ -1 +1 @@
+    -FOO
+    +foo
+    diff --git a/mixed.txt b/mixed.txt
+    index 2d56156f60..f347ee16b1 100644
+    --- a/mixed.txt
+    +++ b/mixed.txt
+    @@ -1,2 +1,2 @@
+    -FOO
+    -bar
+    +foo
+    +Baz
+    "###);
+
+    // Case-only changes produce no hunks. Context lines are shown as in the
+    // left side.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--ignore-case"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/case.txt b/case.txt
+    index b7d6715e2d..257cc5642c 100644
+    --- a/case.txt
+    +++ b/case.txt
+    diff --git a/mixed.txt b/mixed.txt
+    index 2d56156f60..f347ee16b1 100644
+    --- a/mixed.txt
+    +++ b/mixed.txt
+    @@ -1,2 +1,2 @@
+     FOO
+    -bar
+    +Baz
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--ignore-case"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file case.txt:
+    Modified regular file mixed.txt:
+       1    1: FOO
+       2    2: barBaz
+    "###);
+}
+
+#[test]
+fn test_diff_ignore_blank_lines() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let left = (1..=20).map(|i| format!("{i}\n")).join("");
+    std::fs::write(repo_path.join("file"), &left).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let right = left
+        .replacen("2\n3\n", "2\n\n3\n", 1)
+        .replace("15\n", "X\n");
+    std::fs::write(repo_path.join("file"), right).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..3a6b506e05 100644
+    --- a/file
+    +++ b/file
+    @@ -1,5 +1,6 @@
+     1
+     2
+    +
+     3
+     4
+     5
+    @@ -12,7 +13,7 @@
+     12
+     13
+     14
+    -15
+    +X
+     16
+     17
+     18
+    "###);
+
+    // The hunk adding only a blank line is omitted
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--ignore-blank-lines"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 0ff3bbb9c8..3a6b506e05 100644
+    --- a/file
+    +++ b/file
+    @@ -12,7 +13,7 @@
+     12
+     13
+     14
+    -15
+    +X
+     16
+     17
+     18
+    "###);
+
+    // Line numbers after the blank line are still shifted
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--ignore-blank-lines"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file:
+        ...
+      12   13: 12
+      13   14: 13
+      14   15: 14
+      15   16: 15X
+      16   17: 16
+      17   18: 17
+      18   19: 18
+        ...
+    "###);
+
+    // Files with only blank line changes are omitted entirely
+    std::fs::write(
+        repo_path.join("file"),
+        left.replacen("2\n3\n", "2\n\n3\n", 1),
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--ignore-blank-lines"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--ignore-blank-lines"]);
+    insta::assert_snapshot!(stdout, @"");
+}
+
+#[test]
+fn test_diff_ignore_lines_matching() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let body = (1..=10).map(|i| format!("{i}\n")).join("");
+    std::fs::write(
+        repo_path.join("file1"),
+        format!("// Copyright 2023 Example\n{body}foo\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        repo_path.join("file2"),
+        "// Copyright 2023 Example\nfn main() {}\n",
+    )
+    .unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(
+        repo_path.join("file1"),
+        format!("// Copyright 2024 Example\n{body}bar\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        repo_path.join("file2"),
+        "// Copyright 2024 Example\nfn main() {}\n",
+    )
+    .unwrap();
+
+    // The year bump doesn't produce hunks
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--ignore-lines-matching=^// Copyright"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 4677662cf5..9a5ef4c8af 100644
+    --- a/file1
+    +++ b/file1
+    @@ -9,4 +9,4 @@
+     8
+     9
+     10
+    -foo
+    +bar
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--ignore-lines-matching=^// Copyright"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+        ...
+       9    9: 8
+      10   10: 9
+      11   11: 10
+      12   12: foobar
+    "###);
+
+    // The matching lines compare equal, so they are shown as context
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "--context=11",
+            "--ignore-lines-matching=^// Copyright",
+            "file1",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 4677662cf5..9a5ef4c8af 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,12 +1,12 @@
+     // Copyright 2024 Example
+     1
+     2
+     3
+     4
+     5
+     6
+     7
+     8
+     9
+     10
+    -foo
+    +bar
+    "###);
+
+    // Changes are kept unless both sides match
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--ignore-lines-matching=2023", "file2"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file2 b/file2
+    index abf3f8d775..dabe11de17 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1,2 +1,2 @@
+    -// Copyright 2023 Example
+    +// Copyright 2024 Example
+     fn main() {}
+    "###);
+}
+
+#[test]
+fn test_diff_highlight_trailing_whitespace() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config("ui.diff.highlight-trailing-whitespace = true");
+
+    std::fs::write(repo_path.join("file1"), "a  \nb \nc\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a  \nc\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;3m<<diff header::Modified regular file file1:>>[39m
+    [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: a>>[48;5;1m<<diff trailing-whitespace::  >>[49m<<diff::>>
+    [38;5;1m<<diff removed line_number::   2>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::b>>[48;5;1m<<diff removed token trailing-whitespace:: >>[49m<<diff removed token::>>[24m[39m
+    [38;5;1m<<diff removed line_number::   3>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   2>>[39m<<diff::: c>>
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [1m<<diff file_header::diff --git a/file1 b/file1>>[0m
+    [1m<<diff file_header::index 30f8815f87..442bf01048 100644>>[0m
+    [1m<<diff file_header::--- a/file1>>[0m
+    [1m<<diff file_header::+++ b/file1>>[0m
+    [38;5;6m<<diff hunk_header::@@ -1,3 +1,2 @@>>[39m
+    <<diff context:: a>>[48;5;1m<<diff context trailing-whitespace::  >>[49m<<diff context::>>
+    [38;5;1m<<diff removed::->>[4m<<diff removed token::b>>[48;5;1m<<diff removed token trailing-whitespace:: >>[49m<<diff removed token::>>[24m[39m
+    <<diff context:: c>>
+    "###);
+
+    // The bytes are unchanged
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    assert!(stdout.contains("\n a  \n-b \n c\n"));
+}
+
+#[test]
+fn test_diff_show_whitespace() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "x = 1\n\tfoo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "x = 2\n\tfoo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--show-whitespace"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: x·=·12
+       2    2: →foo
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--show-whitespace"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 1a7dc6e149..33d7dda535 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,2 +1,2 @@
+    -x·=·1
+    +x·=·2
+     →foo
+    "###);
+
+    // The bytes are unchanged by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    assert!(stdout.contains("\n-x = 1\n+x = 2\n \tfoo\n"));
+}
+
+#[test]
+fn test_diff_tab_stops() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config("ui.diff.tab-stops = [4, 8, 12]");
+
+    std::fs::write(repo_path.join("file1"), "x\ty\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "x\ty\tlonger_word\tz\nabcd\te\n").unwrap();
+
+    // Tabs expand to the configured stops, and then every 4 columns past the
+    // last stop
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: x   y   longer_word z
+            2: abcd    e
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index a8bd5f696d..9fdcbf2830 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1,2 @@
+    -x   y
+    +x   y   longer_word z
+    +abcd    e
+    "###);
+
+    // The tab glyph is padded to the expanded width
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--show-whitespace"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: x→  y→  longer_word→z
+            2: abcd→   e
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["diff", "--config-toml=ui.diff.tab-stops=[8, 4]"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: `ui.diff.tab-stops` must be increasing positive column numbers
+    For help, see https://github.com/martinvonz/jj/blob/main/docs/config.md.
+    "###);
+}
+
+#[test]
+fn test_diff_color_scope() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(r#"ui.diff.color-scope = "headers""#);
+
+    std::fs::write(repo_path.join("file1"), "foo\nbar\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "foo\nbaz\n").unwrap();
+
+    // Only the file header is labeled
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [38;5;3m<<diff header::Modified regular file file1:>>[39m
+    <<diff::   1    1: foo>>
+    <<diff::   2    2: barbaz>>
+    "###);
+
+    // Only the file and hunk headers are labeled
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--color=debug"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [1m<<diff file_header::diff --git a/file1 b/file1>>[0m
+    [1m<<diff file_header::index 3bd1f0e297..0c071e1d07 100644>>[0m
+    [1m<<diff file_header::--- a/file1>>[0m
+    [1m<<diff file_header::+++ b/file1>>[0m
+    [38;5;6m<<diff hunk_header::@@ -1,2 +1,2 @@>>[39m
+    <<diff:: foo>>
+    <<diff::-bar>>
+    <<diff::+baz>>
+    "###);
+}
+
+#[test]
+fn test_diff_missing_newline() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "foo\nbar").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: foo
+            2: bar
+    Modified regular file file2:
+       1    1: foo
+       2     : bar
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 1910281566..a907ec3f43 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1,2 @@
+    -foo
+    \ No newline at end of file
+    +foo
+    +bar
+    \ No newline at end of file
+    diff --git a/file2 b/file2
+    index a907ec3f43..1910281566 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1,2 +1 @@
+    -foo
+    -bar
+    \ No newline at end of file
+    +foo
+    \ No newline at end of file
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 3 ++-
+    file2 | 3 +--
+    2 files changed, 3 insertions(+), 3 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_git_missing_newline_per_side() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nb\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "a\nb").unwrap();
+    std::fs::write(repo_path.join("file4"), "a\nb").unwrap();
+    std::fs::write(repo_path.join("file5"), "a\nb\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    // Only the removed side lacks newline
+    std::fs::write(repo_path.join("file1"), "a\nc\n").unwrap();
+    // Only the added side lacks newline
+    std::fs::write(repo_path.join("file2"), "a\nc").unwrap();
+    // Both sides lack newline
+    std::fs::write(repo_path.join("file3"), "a\nc").unwrap();
+    // Both sides lack newline, and the last line is unchanged
+    std::fs::write(repo_path.join("file4"), "A\nb").unwrap();
+    // Neither side lacks newline
+    std::fs::write(repo_path.join("file5"), "a\nc\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 0a207c060e..0f7bc76605 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,2 +1,2 @@
+     a
+    -b
+    \ No newline at end of file
+    +c
+    diff --git a/file2 b/file2
+    index 422c2b7ab3..817f660e44 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1,2 +1,2 @@
+     a
+    -b
+    +c
+    \ No newline at end of file
+    diff --git a/file3 b/file3
+    index 0a207c060e..817f660e44 100644
+    --- a/file3
+    +++ b/file3
+    @@ -1,2 +1,2 @@
+     a
+    -b
+    \ No newline at end of file
+    +c
+    \ No newline at end of file
+    diff --git a/file4 b/file4
+    index 0a207c060e..e815843ade 100644
+    --- a/file4
+    +++ b/file4
+    @@ -1,2 +1,2 @@
+    -a
+    +A
+     b
+    \ No newline at end of file
+    diff --git a/file5 b/file5
+    index 422c2b7ab3..0f7bc76605 100644
+    --- a/file5
+    +++ b/file5
+    @@ -1,2 +1,2 @@
+     a
+    -b
+    +c
+    "###);
+}
+
+#[test]
+fn test_color_words_diff_missing_newline() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Empty"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nf\ng\nh\ni").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Add no newline"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\ni").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify first line"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\ni").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify middle line"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Modify last line"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Append newline"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\nE\nf\ng\nh\nI").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Remove newline"]);
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "=== Empty"]);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-Tdescription",
+            "-pr::@-",
+            "--no-graph",
+            "--reversed",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === Empty
+    Added regular file file1:
+        (empty)
+    === Add no newline
+    Modified regular file file1:
+            1: a
+            2: b
+            3: c
+            4: d
+            5: e
+            6: f
+            7: g
+            8: h
+            9: i
+    === Modify first line
+    Modified regular file file1:
+       1    1: aA
+       2    2: b
+       3    3: c
+       4    4: d
+        ...
+    === Modify middle line
+    Modified regular file file1:
+       1    1: A
+       2    2: b
+       3    3: c
+       4    4: d
+       5    5: eE
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: i
+    === Modify last line
+    Modified regular file file1:
+        ...
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: iI
+    === Append newline
+    Modified regular file file1:
+        ...
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: I
+    === Remove newline
+    Modified regular file file1:
+        ...
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: I
+    === Empty
+    Modified regular file file1:
+       1     : A
+       2     : b
+       3     : c
+       4     : d
+       5     : E
+       6     : f
+       7     : g
+       8     : h
+       9     : I
+    "###);
+}
+
+#[test]
+fn test_diff_skipped_context() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m", "=== Left side of diffs"]);
+
+    test_env.jj_cmd_ok(&repo_path, &["new", "@", "-m", "=== Must skip 2 lines"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\ni\nJ").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== Don't skip 1 line"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\nI\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
+    std::fs::write(repo_path.join("file1"), "a\nB\nc\nd\ne\nf\ng\nh\nI\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nC\nd\ne\nf\ng\nh\nI\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at start"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\nE\nf\ng\nh\ni\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at end"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nF\ng\nh\ni\nj").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-Tdescription", "-p", "--no-graph", "--reversed"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === Left side of diffs
+    Added regular file file1:
+            1: a
+            2: b
+            3: c
+            4: d
+            5: e
+            6: f
+            7: g
+            8: h
+            9: i
+           10: j
+    === Must skip 2 lines
+    Modified regular file file1:
+       1    1: aA
+       2    2: b
+       3    3: c
+       4    4: d
+        ...
+       7    7: g
+       8    8: h
+       9    9: i
+      10   10: jJ
+    === Don't skip 1 line
+    Modified regular file file1:
+       1    1: aA
+       2    2: b
+       3    3: c
+       4    4: d
+       5    5: e
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: iI
+      10   10: j
+    === No gap to skip
+    Modified regular file file1:
+       1    1: a
+       2    2: bB
+       3    3: c
+       4    4: d
+       5    5: e
+       6    6: f
+       7    7: g
+       8    8: h
+       9    9: iI
+      10   10: j
+    === No gap to skip
+    Modified regular file file1:
+       1    1: a
+       2    2: b
+       3    3: cC
+       4    4: d
+       5    5: e
+       6    6: f
+       7    7: g
+       8    8: h
        9    9: iI
       10   10: j
     === 1 line at start
     Modified regular file file1:
        1    1: a
-       2    2: b
-       3    3: c
+       2    2: b
+       3    3: c
+       4    4: d
+       5    5: eE
+       6    6: f
+       7    7: g
+       8    8: h
+        ...
+    === 1 line at end
+    Modified regular file file1:
+        ...
+       3    3: c
+       4    4: d
+       5    5: e
+       6    6: fF
+       7    7: g
+       8    8: h
+       9    9: i
+      10   10: j
+    "###);
+}
+
+#[test]
+fn test_diff_skipped_context_separator() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nd\ne\nf\ng\nh\ni\nJ").unwrap();
+    std::fs::write(repo_path.join("file2"), "A\nb\nc\nd\ne\nf\ng\nh\ni\nj").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml",
+            "ui.diff.context-hunk-separator='    ... $count lines ($left_start-$left_end) ...'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: aA
+       2    2: b
+       3    3: c
+       4    4: d
+        ... 2 lines (5-6) ...
+       7    7: g
+       8    8: h
+       9    9: i
+      10   10: jJ
+    Modified regular file file2:
+       1    1: aA
+       2    2: b
+       3    3: c
+       4    4: d
+        ... 6 lines (5-10) ...
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_header_template() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "c\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "d\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    std::fs::remove_file(repo_path.join("file3")).unwrap();
+    std::fs::rename(repo_path.join("file4"), repo_path.join("file5")).unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.header-template='~ $path'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    ~ file1
+       1    1: foobar
+    ~ file2
+            1: a
+    ~ file3
+       1     : c
+    ~ file5
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.header-template='$operation $type $path$copy_source'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    modified regular file file1
+       1    1: foobar
+    added regular file file2
+            1: a
+    removed regular file file3
+       1     : c
+    modified regular file file5 (file4 => file5)
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_compact_renames() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let src_path = repo_path.join("src");
+    std::fs::create_dir_all(src_path.join("old")).unwrap();
+    std::fs::create_dir(src_path.join("new")).unwrap();
+    std::fs::write(src_path.join("old").join("file"), "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::rename(
+        src_path.join("old").join("file"),
+        src_path.join("new").join("file"),
+    )
+    .unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    Renamed regular file src/new/file (src/old/file => src/new/file):
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.compact-renames=true",
+        ],
+    );
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    Renamed regular file src/{old => new}/file:
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_full_replace_ratio() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a b c d e f g h i j\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a b c d e f g h i j\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a B C D E F G H I J\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a b c d e f g h i J\n").unwrap();
+
+    // 90% of the words in file1 are changed, but only 10% in file2
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.full-replace-ratio=0.5",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1     : a b c d e f g h i j
+            1: a B C D E F G H I J
+    Modified regular file file2:
+       1    1: a b c d e f g h i jJ
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.full-replace-ratio=1.5",
+        ],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: `ui.diff.color-words.full-replace-ratio` must be between 0 and 1
+    For help, see https://github.com/martinvonz/jj/blob/main/docs/config.md.
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_number_context_only() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a\nB\nc\nd\ne\nf\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: a
+       2    2: bB
+       3    3: c
+       4    4: d
+       5    5: e
+            6: f
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.color-words.number='context-only'",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: a
+             : bB
+       3    3: c
+       4    4: d
+       5    5: e
+             : f
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_sigils() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd\ne\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a\nB\nc\ne\nf\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--config-toml=ui.diff.color-words.sigils=true"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+        1    1: a
+    ~   2    2: bB
+        3    3: c
+    -   4     : d
+        5    4: e
+    +        5: f
+    "###);
+}
+
+#[test]
+fn test_diff_color_words_collapse_deletions() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n".repeat(100)).unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "").unwrap();
+    std::fs::write(repo_path.join("file4"), "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::remove_file(repo_path.join("file3")).unwrap();
+    std::fs::write(repo_path.join("file4"), "b\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--config-toml=ui.diff.collapse-deletions=true"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Removed regular file file1 (100 lines):
+    Removed regular file file2 (1 line):
+    Removed regular file file3:
+        (empty)
+    Modified regular file file4:
+       1    1: ab
+    "###);
+
+    // The content is shown by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Removed regular file file2:
+       1     : a
+    "###);
+}
+
+#[test]
+fn test_diff_skipped_context_nondefault() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m", "=== Left side of diffs"]);
+
+    test_env.jj_cmd_ok(&repo_path, &["new", "@", "-m", "=== Must skip 2 lines"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nc\nD").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== Don't skip 1 line"]);
+    std::fs::write(repo_path.join("file1"), "A\nb\nC\nd").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
+    std::fs::write(repo_path.join("file1"), "a\nB\nC\nd").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at start"]);
+    std::fs::write(repo_path.join("file1"), "a\nB\nc\nd").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at end"]);
+    std::fs::write(repo_path.join("file1"), "a\nb\nC\nd").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-Tdescription",
+            "-p",
+            "--no-graph",
+            "--reversed",
+            "--context=0",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === Left side of diffs
+    Added regular file file1:
+            1: a
+            2: b
+            3: c
+            4: d
+    === Must skip 2 lines
+    Modified regular file file1:
+       1    1: aA
+        ...
+       4    4: dD
+    === Don't skip 1 line
+    Modified regular file file1:
+       1    1: aA
+       2    2: b
+       3    3: cC
+       4    4: d
+    === No gap to skip
+    Modified regular file file1:
+       1    1: a
+       2    2: bB
+       3    3: cC
+       4    4: d
+    === 1 line at start
+    Modified regular file file1:
+       1    1: a
+       2    2: bB
+        ...
+    === 1 line at end
+    Modified regular file file1:
+        ...
+       3    3: cC
        4    4: d
-       5    5: eE
-       6    6: f
-       7    7: g
-       8    8: h
+    "###);
+}
+
+#[test]
+fn test_diff_leading_trailing_context() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // N=5 context lines at start/end of the file
+    std::fs::write(
+        repo_path.join("file1"),
+        "1\n2\n3\n4\n5\nL\n6\n7\n8\n9\n10\n11\n",
+    )
+    .unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(
+        repo_path.join("file1"),
+        "1\n2\n3\n4\n5\n6\nR\n7\n8\n9\n10\n11\n",
+    )
+    .unwrap();
+
+    // N=5 <= num_context_lines + 1: No room to skip.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=4"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: 1
+       2    2: 2
+       3    3: 3
+       4    4: 4
+       5    5: 5
+       6     : L
+       7    6: 6
+            7: R
+       8    8: 7
+       9    9: 8
+      10   10: 9
+      11   11: 10
+      12   12: 11
+    "###);
+
+    // N=5 <= 2 * num_context_lines + 1: The last hunk wouldn't be split if
+    // trailing diff existed.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+        ...
+       3    3: 3
+       4    4: 4
+       5    5: 5
+       6     : L
+       7    6: 6
+            7: R
+       8    8: 7
+       9    9: 8
+      10   10: 9
+        ...
+    "###);
+
+    // N=5 > 2 * num_context_lines + 1: The last hunk should be split no matter
+    // if trailing diff existed.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+        ...
+       5    5: 5
+       6     : L
+       7    6: 6
+            7: R
+       8    8: 7
+        ...
+    "###);
+
+    // N=5 <= num_context_lines: No room to skip.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=5"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 1bf57dee4a..69b3e1865c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,12 +1,12 @@
+     1
+     2
+     3
+     4
+     5
+    -L
+     6
+    +R
+     7
+     8
+     9
+     10
+     11
+    "###);
+
+    // N=5 <= 2 * num_context_lines: The last hunk wouldn't be split if
+    // trailing diff existed.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 1bf57dee4a..69b3e1865c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -3,8 +3,8 @@
+     3
+     4
+     5
+    -L
+     6
+    +R
+     7
+     8
+     9
+    "###);
+
+    // N=5 > 2 * num_context_lines: The last hunk should be split no matter
+    // if trailing diff existed.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 1bf57dee4a..69b3e1865c 100644
+    --- a/file1
+    +++ b/file1
+    @@ -4,6 +4,6 @@
+     4
+     5
+    -L
+     6
+    +R
+     7
+     8
+    "###);
+}
+
+#[test]
+fn test_diff_context_by_path() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        r#"
+        [ui.diff.context-by-path]
+        "*.lock" = 0
+        "*.rs" = 3
+        "#,
+    );
+
+    let content = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n";
+    std::fs::write(repo_path.join("Cargo.lock"), content).unwrap();
+    std::fs::write(repo_path.join("main.rs"), content).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    let content = "1\n2\n3\n4\n5\nX\n7\n8\n9\n10\n11\n";
+    std::fs::write(repo_path.join("Cargo.lock"), content).unwrap();
+    std::fs::write(repo_path.join("main.rs"), content).unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file Cargo.lock:
+        ...
+       6    6: 6X
+        ...
+    Modified regular file main.rs:
+        ...
+       3    3: 3
+       4    4: 4
+       5    5: 5
+       6    6: 6X
+       7    7: 7
+       8    8: 8
+       9    9: 9
+        ...
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/Cargo.lock b/Cargo.lock
+    index 3bb459b831..6052102c0d 100644
+    --- a/Cargo.lock
+    +++ b/Cargo.lock
+    @@ -6 +6 @@
+    -6
+    +X
+    diff --git a/main.rs b/main.rs
+    index 3bb459b831..6052102c0d 100644
+    --- a/main.rs
+    +++ b/main.rs
+    @@ -3,7 +3,7 @@
+     3
+     4
+     5
+    -6
+    +X
+     7
+     8
+     9
+    "###);
+
+    // The smallest number of context lines wins if several patterns match
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "main.rs",
+            r#"--config-toml=ui.diff.context-by-path."**" = 1"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/main.rs b/main.rs
+    index 3bb459b831..6052102c0d 100644
+    --- a/main.rs
+    +++ b/main.rs
+    @@ -5,3 +5,3 @@
+     5
+    -6
+    +X
+     7
+    "###);
+}
+
+#[test]
+fn test_diff_external_tool() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\n").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    std::fs::write(
+        &edit_script,
+        "print-files-before\0print --\0print-files-after",
+    )
+    .unwrap();
+
+    // diff without file patterns
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor"]), @r###"
+    file1
+    file2
+    --
+    file2
+    file3
+    "###);
+
+    // diff with file patterns
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor", "file1"]), @r###"
+    file1
+    --
+    "###);
+
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["log", "-p", "--tool=fake-diff-editor"]), @r###"
+    @  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 39d9055d
+    │  (no description set)
+    │  file1
+    │  file2
+    │  --
+    │  file2
+    │  file3
+    ○  qpvuntsm test.user@example.com 2001-02-03 08:05:08 0ad4ef22
+    │  (no description set)
+    │  --
+    │  file1
+    │  file2
+    ◆  zzzzzzzz root() 00000000
+       --
+    "###);
+
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["show", "--tool=fake-diff-editor"]), @r###"
+    Commit ID: 39d9055d70873099fd924b9af218289d5663eac8
+    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
+    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
+    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
+
+        (no description set)
+
+    file1
+    file2
+    --
+    file2
+    file3
+    "###);
+
+    // Enabled by default, looks up the merge-tools table
+    let config = "--config-toml=ui.diff.tool='fake-diff-editor'";
+    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", config]), @r###"
+    file1
+    file2
+    --
+    file2
+    file3
+    "###);
+
+    // Inlined command arguments
+    let command = escaped_fake_diff_editor_path();
+    let config = format!(r#"--config-toml=ui.diff.tool=["{command}", "$right", "$left"]"#);
+    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", &config]), @r###"
+    file2
+    file3
+    --
+    file1
+    file2
+    "###);
+
+    // Output of external diff tool shouldn't be escaped
+    std::fs::write(&edit_script, "print \x1b[1;31mred").unwrap();
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", "--color=always", "--tool=fake-diff-editor"]),
+        @r###"
+    [1;31mred
+    "###);
+
+    // Non-zero exit code isn't an error
+    std::fs::write(&edit_script, "print diff\0fail").unwrap();
+    let (stdout, stderr) = test_env.jj_cmd_ok(&repo_path, &["show", "--tool=fake-diff-editor"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Commit ID: 39d9055d70873099fd924b9af218289d5663eac8
+    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
+    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
+    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
+
+        (no description set)
+
+    diff
+    "###);
+    insta::assert_snapshot!(stderr.replace("exit code:", "exit status:"), @r###"
+    Warning: Tool exited with exit status: 1 (run with --debug to see the exact invocation)
+    "###);
+
+    // --tool=:builtin shouldn't be ignored
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--tool=:builtin"]);
+    insta::assert_snapshot!(strip_last_line(&stderr), @r###"
+    Error: Failed to generate diff
+    Caused by:
+    1: Error executing ':builtin' (run with --debug to see the exact invocation)
+    "###);
+}
+
+#[test]
+fn test_diff_external_tool_name_typo() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    let edit_script = test_env.set_up_fake_diff_editor();
+    std::fs::write(&edit_script, "print-files-after").unwrap();
+
+    // Misspelled name of configured tool
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--tool=fake-diff-editr"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Tool `fake-diff-editr` is not configured in `merge-tools` and not found in PATH. Did you mean `fake-diff-editor`?
+    For help, see https://github.com/martinvonz/jj/blob/main/docs/config.md.
+    "###);
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["diff", "--config-toml=ui.diff.tool='fake-diff-editr'"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Tool `fake-diff-editr` is not configured in `merge-tools` and not found in PATH. Did you mean `fake-diff-editor`?
+    For help, see https://github.com/martinvonz/jj/blob/main/docs/config.md.
+    "###);
+
+    // Program path is run as is
+    let command = escaped_fake_diff_editor_path();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", &format!("--tool={command}")]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1
+    "###);
+}
+
+#[test]
+fn test_diff_external_tool_non_blocking() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    let config = "--config-toml=ui.diff.tool='fake-diff-editor'\nmerge-tools.fake-diff-editor.\
+                  diff-invocation-mode='dir-non-blocking'";
+
+    // jj exits without waiting for the tool
+    std::fs::write(&edit_script, "sleep 30").unwrap();
+    let start = Instant::now();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", config]);
+    assert!(start.elapsed() < Duration::from_secs(30));
+    insta::assert_snapshot!(stdout, @"");
+
+    // The directories are left behind for the tool to read after jj exits
+    std::fs::write(&edit_script, "sleep 1\0dump file1 dumped").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["diff", config]);
+    let dumped_path = edit_script.parent().unwrap().join("dumped");
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while std::fs::read_to_string(&dumped_path).ok().as_deref() != Some("bar\n") {
+        assert!(Instant::now() < deadline, "the tool didn't dump the file");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_diff_external_file_by_file_tool() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "file1\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "file2\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "file2\nfile2\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "file3\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "file1\n").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    std::fs::write(
+        edit_script,
+        "print ==\0print-files-before\0print --\0print-files-after",
+    )
+    .unwrap();
+
+    // Enabled by default, looks up the merge-tools table
+    let config = "--config-toml=ui.diff.tool='fake-diff-editor'\nmerge-tools.fake-diff-editor.\
+                  diff-invocation-mode='file-by-file'";
+
+    // diff without file patterns
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", config]), @r###"
+    ==
+    file2
+    --
+    file2
+    ==
+    file3
+    --
+    file3
+    ==
+    file1
+    --
+    file4
+    "###);
+
+    // diff with file patterns
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", config, "file1"]), @r###"
+    ==
+    file1
+    --
+    file1
+    "###);
+
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["log", "-p", config]), @r###"
+    @  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 7b01704a
+    │  (no description set)
+    │  ==
+    │  file2
+    │  --
+    │  file2
+    │  ==
+    │  file3
+    │  --
+    │  file3
+    │  ==
+    │  file1
+    │  --
+    │  file4
+    ○  qpvuntsm test.user@example.com 2001-02-03 08:05:08 6e485984
+    │  (no description set)
+    │  ==
+    │  file1
+    │  --
+    │  file1
+    │  ==
+    │  file2
+    │  --
+    │  file2
+    ◆  zzzzzzzz root() 00000000
+    "###);
+
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["show", config]), @r###"
+    Commit ID: 7b01704a670bc77d11ed117d362855cff1d4513b
+    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
+    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
+    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
+
+        (no description set)
+
+    ==
+    file2
+    --
+    file2
+    ==
+    file3
+    --
+    file3
+    ==
+    file1
+    --
+    file4
+    "###);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_external_tool_symlink() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let external_file_path = test_env.env_root().join("external-file");
+    std::fs::write(&external_file_path, "").unwrap();
+    let external_file_permissions = external_file_path.symlink_metadata().unwrap().permissions();
+
+    std::os::unix::fs::symlink("non-existent1", repo_path.join("dead")).unwrap();
+    std::os::unix::fs::symlink(&external_file_path, repo_path.join("file")).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("dead")).unwrap();
+    std::os::unix::fs::symlink("non-existent2", repo_path.join("dead")).unwrap();
+    std::fs::remove_file(repo_path.join("file")).unwrap();
+    std::fs::write(repo_path.join("file"), "").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    std::fs::write(
+        edit_script,
+        "print-files-before\0print --\0print-files-after",
+    )
+    .unwrap();
+
+    // Shouldn't try to change permission of symlinks
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor"]), @r###"
+    dead
+    file
+    --
+    dead
+    file
+    "###);
+
+    // External file should be intact
+    assert_eq!(
+        external_file_path.symlink_metadata().unwrap().permissions(),
+        external_file_permissions
+    );
+}
+
+#[test]
+fn test_diff_stat() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    "###);
+
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @"0 files changed, 0 insertions(+), 0 deletions(-)");
+
+    std::fs::write(repo_path.join("file1"), "foo\nbar\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 -
+    1 file changed, 0 insertions(+), 1 deletion(-)
+    "###);
+}
+
+#[test]
+fn test_diff_stat_rename_with_modification() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n2\n3\nX\n").unwrap();
+
+    // The deletion of the rename source isn't shown as a separate row
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    {file1 => file2} | 2 +-
+    1 file changed, 1 insertion(+), 1 deletion(-)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--shortstat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    1 file changed, 1 insertion(+), 1 deletion(-)
+    "###);
+
+    // The source is shown as deleted if the target isn't matched
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 4 ----
+    1 file changed, 0 insertions(+), 4 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_shortstat() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "baz\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::write(repo_path.join("file3"), "qux\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--shortstat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    3 files changed, 2 insertions(+), 3 deletions(-)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--shortstat", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    1 file changed, 1 insertion(+), 1 deletion(-)
+    "###);
+}
+
+#[test]
+fn test_diff_oneline_stat() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "1\n2\n3\n4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "baz\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::write(repo_path.join("file3"), "qux\n").unwrap();
+    std::fs::remove_file(repo_path.join("file4")).unwrap();
+    std::fs::write(repo_path.join("file5"), "1\n2\n3\nX\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--oneline-stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1 +1 -1
+    D file2 +0 -2
+    A file3 +1 -0
+    R {file4 => file5} +1 -1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.format='oneline-stat'",
+            "file1",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    M file1 +1 -1
+    "###);
+
+    let stderr = test_env.jj_cmd_cli_error(&repo_path, &["diff", "--oneline-stat", "--summary"]);
+    insta::assert_snapshot!(stderr, @r###"
+    error: the argument '--oneline-stat' cannot be used with '--summary'
+
+    Usage: jj diff --oneline-stat [PATHS]...
+
+    For more information, try '--help'.
+    "###);
+}
+
+#[test]
+fn test_diff_max_files() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    for i in 0..10 {
+        std::fs::write(repo_path.join(format!("file{i}")), "a\n").unwrap();
+    }
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--max-files=3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file0
+    A file1
+    A file2
+    ... and 7 more files
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat", "--max-files=3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file0 | 1 +
+    file1 | 1 +
+    file2 | 1 +
+    3 files changed, 3 insertions(+), 0 deletions(-)
+    ... and 7 more files
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--max-files=1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file0 b/file0
+    new file mode 100644
+    index 0000000000..7898192261
+    --- /dev/null
+    +++ b/file0
+    @@ -0,0 +1 @@
+    +a
+    ... and 9 more files
+    "###);
+
+    // The tail line is printed after each format
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--name-only", "--color-words", "--max-files=9"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    file0
+    file1
+    file2
+    file3
+    file4
+    file5
+    file6
+    file7
+    file8
+    ... and 1 more file
+    Added regular file file0:
+            1: a
+    Added regular file file1:
+            1: a
+    Added regular file file2:
+            1: a
+    Added regular file file3:
+            1: a
+    Added regular file file4:
+            1: a
+    Added regular file file5:
+            1: a
+    Added regular file file6:
+            1: a
+    Added regular file file7:
+            1: a
+    Added regular file file8:
+            1: a
+    ... and 1 more file
+    "###);
+
+    // Nothing is omitted if the limit isn't exceeded
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--max-files=10"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file0
+    A file1
+    A file2
+    A file3
+    A file4
+    A file5
+    A file6
+    A file7
+    A file8
+    A file9
+    "###);
+}
+
+#[test]
+fn test_diff_max_output_bytes() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    for i in 0..10 {
+        std::fs::write(repo_path.join(format!("file{i}")), "a\n").unwrap();
+    }
+
+    // Each line is 8 bytes long
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--max-output-bytes=24"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file0
+    A file1
+    A file2
+    ... output truncated
+    "###);
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--max-output-bytes=23"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file0
+    A file1
+    ... output truncated
+    "###);
+
+    // The output isn't truncated in the middle of a file
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--max-output-bytes=200"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file0 b/file0
+    new file mode 100644
+    index 0000000000..7898192261
+    --- /dev/null
+    +++ b/file0
+    @@ -0,0 +1 @@
+    +a
+    ... output truncated
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--max-output-bytes=100"]);
+    insta::assert_snapshot!(stdout, @r###"
+    ... output truncated
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--color-words", "--max-output-bytes=80"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Added regular file file0:
+            1: a
+    Added regular file file1:
+            1: a
+    ... output truncated
+    "###);
+
+    // HTML output is never cut off in the middle of an element
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--html", "--max-output-bytes=200"]);
+    insta::assert_snapshot!(stdout, @r###"
+    ... output truncated
+    "###);
+
+    // No notice if the output fits
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--max-output-bytes=80"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file0
+    A file1
+    A file2
+    A file3
+    A file4
+    A file5
+    A file6
+    A file7
+    A file8
+    A file9
+    "###);
+}
+
+#[test]
+fn test_diff_max_hunks_per_file() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // 10 changed lines far enough apart to be rendered as separate hunks
+    let left = (1..=100).map(|n| format!("line{n}\n")).join("");
+    let right = (1..=100)
+        .map(|n| {
+            if n % 10 == 1 {
+                format!("changed{n}\n")
+            } else {
+                format!("line{n}\n")
+            }
+        })
+        .join("");
+    std::fs::write(repo_path.join("file"), left).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), right).unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--context=0", "--max-hunks-per-file=3"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file:
+       1    1: line1changed1
         ...
-    === 1 line at end
-    Modified regular file file1:
+      11   11: line11changed11
         ...
-       3    3: c
-       4    4: d
-       5    5: e
-       6    6: fF
-       7    7: g
-       8    8: h
-       9    9: i
-      10   10: j
+      21   21: line21changed21
+    ... (7 more hunks)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--context=0", "--max-hunks-per-file=3"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    index 48a0110907..00d9679740 100644
+    --- a/file
+    +++ b/file
+    @@ -1 +1 @@
+    -line1
+    +changed1
+    @@ -11 +11 @@
+    -line11
+    +changed11
+    @@ -21 +21 @@
+    -line21
+    +changed21
+    ... (7 more hunks)
+    "###);
+
+    // All hunks are shown if there are no more than the limit
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--context=0", "--max-hunks-per-file=10"],
+    );
+    assert_eq!(stdout.matches("@@ -").count(), 10);
+    assert!(!stdout.contains("more hunk"));
+}
+
+#[test]
+fn test_diff_git_sorted() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("a")).unwrap();
+    std::fs::write(repo_path.join("a").join("b"), "a/b\n").unwrap();
+    std::fs::write(repo_path.join("a.txt"), "a.txt\n").unwrap();
+    std::fs::write(repo_path.join("b"), "b\n").unwrap();
+
+    // Files in a directory are visited before siblings of the directory
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A a/b
+    A a.txt
+    A b
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--git-sorted"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/a.txt b/a.txt
+    new file mode 100644
+    index 0000000000..eaa5fa8755
+    --- /dev/null
+    +++ b/a.txt
+    @@ -0,0 +1 @@
+    +a.txt
+    diff --git a/a/b b/a/b
+    new file mode 100644
+    index 0000000000..0ee729686a
+    --- /dev/null
+    +++ b/a/b
+    @@ -0,0 +1 @@
+    +a/b
+    diff --git a/b b/b
+    new file mode 100644
+    index 0000000000..6178079822
+    --- /dev/null
+    +++ b/b
+    @@ -0,0 +1 @@
+    +b
+    "###);
+}
+
+#[test]
+fn test_diff_group_by_status() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "rename me\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    std::fs::rename(repo_path.join("file3"), repo_path.join("file4")).unwrap();
+    std::fs::write(repo_path.join("file5"), "new\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--group-by-status", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    === Added ===
+    A file5
+    === Modified ===
+    M file2
+    === Deleted ===
+    D file1
+    === Renamed ===
+    R {file3 => file4}
+    "###);
+
+    // Each section is rendered by the normal per-file renderer
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", "--group-by-status", "file2", "file5"]);
+    insta::assert_snapshot!(stdout, @r###"
+    === Added ===
+    Added regular file file5:
+            1: new
+    === Modified ===
+    Modified regular file file2:
+       1    1: foo
+            2: bar
+    "###);
+
+    // The stat summary line is printed once for the whole diff
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--group-by-status", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    === Added ===
+    file5 | 1 +
+    === Modified ===
+    file2 | 1 +
+    === Deleted ===
+    file1 | 1 -
+    === Renamed ===
+    {file3 => file4} | 0
+    4 files changed, 2 insertions(+), 1 deletion(-)
+    "###);
+
+    // Files omitted by --max-files are counted once
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--group-by-status",
+            "--summary",
+            "--max-files=1",
+            "file1",
+            "file2",
+            "file5",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === Deleted ===
+    D file1
+    ... and 2 more files
+    "###);
+
+    // Sections without changes are omitted
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--group-by-status", "--summary", "file1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    === Deleted ===
+    D file1
+    "###);
+}
+#[test]
+fn test_diff_content_grep() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "TODO: x\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "TODO: y\nc\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a\nTODO: z\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "x\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "d\n").unwrap();
+    std::fs::write(repo_path.join("file4"), "TODO: y\ne\n").unwrap();
+
+    // Unchanged lines aren't searched
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--content-grep=TODO"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    M file2
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--git",
+            "--content-grep=TODO",
+            "--content-grep-added-only",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 7898192261..295be831ae 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1,2 @@
+     a
+    +TODO: z
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary", "--content-grep=^e$"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file4
+    "###);
+}
+
+#[test]
+fn test_diff_stat_no_summary_line() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--stat",
+            "--config-toml=ui.diff.stat.summary-line=false",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 +
+    file2 | 2 ++
+    "###);
+
+    // The default format is affected as well
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--config-toml=ui.diff.format='stat'\nui.diff.stat.summary-line=false",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 +
+    file2 | 2 ++
+    "###);
+}
+
+#[test]
+fn test_diff_stat_max_bar_width() {
+    let mut test_env = TestEnvironment::default();
+    test_env.add_env_var("COLUMNS", "300");
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    let long_name = "x".repeat(220);
+    std::fs::write(repo_path.join("file1"), "foo\n".repeat(500)).unwrap();
+    std::fs::write(repo_path.join(&long_name), "foo\n").unwrap();
+
+    // Without the limit, the bars fill the width left by the elided path
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    let bar_lengths = stdout
+        .lines()
+        .map(|line| line.chars().filter(|&c| c == '+').count())
+        .collect_vec();
+    assert_eq!(bar_lengths, [87, 1, 1]);
+    assert!(stdout.contains(&format!("...{} |", &long_name[..200])));
+
+    // The bars are capped, and the path is shown in full
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--stat",
+            "--config-toml=ui.diff.stat.max-bar-width=40",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    file1                                                                                                                                                                                                                        | 500 ++++++++++++++++++++++++++++++++++++++++
+    xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx |   1 +
+    2 files changed, 501 insertions(+), 0 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_stat_long_name_or_stat() {
+    let mut test_env = TestEnvironment::default();
+    test_env.add_env_var("COLUMNS", "30");
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let get_stat = |test_env: &TestEnvironment, path_length: usize, stat_size: usize| {
+        test_env.jj_cmd_ok(&repo_path, &["new", "root()"]);
+        let ascii_name = "1234567890".chars().cycle().take(path_length).join("");
+        let han_name = "一二三四五六七八九十"
+            .chars()
+            .cycle()
+            .take(path_length)
+            .join("");
+        let content = "content line\n".repeat(stat_size);
+        std::fs::write(repo_path.join(ascii_name), &content).unwrap();
+        std::fs::write(repo_path.join(han_name), &content).unwrap();
+        test_env.jj_cmd_success(&repo_path, &["diff", "--stat"])
+    };
+
+    insta::assert_snapshot!(get_stat(&test_env, 1, 1), @r###"
+    1   | 1 +
+    一  | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 1, 10), @r###"
+    1   | 10 ++++++++++
+    一  | 10 ++++++++++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 1, 100), @r###"
+    1   | 100 +++++++++++++++++
+    一  | 100 +++++++++++++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 10, 1), @r###"
+    1234567890      | 1 +
+    ...五六七八九十 | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
+    1234567890     | 10 +++++++
+    ...六七八九十  | 10 +++++++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 10, 100), @r###"
+    1234567890     | 100 ++++++
+    ...六七八九十  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 50, 1), @r###"
+    ...901234567890 | 1 +
+    ...五六七八九十 | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 50, 10), @r###"
+    ...01234567890 | 10 +++++++
+    ...六七八九十  | 10 +++++++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 50, 100), @r###"
+    ...01234567890 | 100 ++++++
+    ...六七八九十  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+
+    // Lengths around where we introduce the ellipsis
+    insta::assert_snapshot!(get_stat(&test_env, 13, 100), @r###"
+    1234567890123  | 100 ++++++
+    ...九十一二三  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 14, 100), @r###"
+    12345678901234 | 100 ++++++
+    ...十一二三四  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 15, 100), @r###"
+    ...56789012345 | 100 ++++++
+    ...一二三四五  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 16, 100), @r###"
+    ...67890123456 | 100 ++++++
+    ...二三四五六  | 100 ++++++
+    2 files changed, 200 insertions(+), 0 deletions(-)
+    "###);
+
+    // Very narrow terminal (doesn't have to fit, just don't crash)
+    test_env.add_env_var("COLUMNS", "10");
+    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
+    ... | 10 ++
+    ... | 10 ++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    test_env.add_env_var("COLUMNS", "3");
+    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
+    ... | 10 ++
+    ... | 10 ++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 3, 10), @r###"
+    123 | 10 ++
+    ... | 10 ++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+    insta::assert_snapshot!(get_stat(&test_env, 1, 10), @r###"
+    1   | 10 ++
+    一  | 10 ++
+    2 files changed, 20 insertions(+), 0 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_binary() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1.png"), b"\x89PNG\r\n\x1a\nabcdefg\0").unwrap();
+    std::fs::write(repo_path.join("file2.png"), b"\x89PNG\r\n\x1a\n0123456\0").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1.png")).unwrap();
+    std::fs::write(repo_path.join("file2.png"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file3.png"), b"\x89PNG\r\n\x1a\nxyz\0").unwrap();
+    // try a file that's valid UTF-8 but contains control characters
+    std::fs::write(repo_path.join("file4.png"), b"\0\0\0").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Removed regular file file1.png:
+        (binary)
+    Modified regular file file2.png:
+        (binary)
+    Added regular file file3.png:
+        (binary)
+    Added regular file file4.png:
+        (binary)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1.png b/file1.png
+    deleted file mode 100644
+    index 2b65b23c22..0000000000
+    Binary files a/file1.png and /dev/null differ
+    diff --git a/file2.png b/file2.png
+    index 7f036ce788..3bd1f0e297 100644
+    Binary files a/file2.png and b/file2.png differ
+    diff --git a/file3.png b/file3.png
+    new file mode 100644
+    index 0000000000..deacfbc286
+    Binary files /dev/null and b/file3.png differ
+    diff --git a/file4.png b/file4.png
+    new file mode 100644
+    index 0000000000..4227ca4e87
+    Binary files /dev/null and b/file4.png differ
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1.png | 3 ---
+    file2.png | 5 ++---
+    file3.png | 3 +++
+    file4.png | 1 +
+    4 files changed, 6 insertions(+), 6 deletions(-)
     "###);
 }
 
 #[test]
-fn test_diff_skipped_context_nondefault() {
+fn test_diff_binary_conflict() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    std::fs::write(repo_path.join("file1"), "a\nb\nc\nd").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["describe", "-m", "=== Left side of diffs"]);
+    let file_path = repo_path.join("file.bin");
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=side1"]);
+    std::fs::write(&file_path, b"side1\0\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m=side2"]);
+    std::fs::write(&file_path, b"side2\0\n").unwrap();
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            "description(side1)",
+            "description(side2)",
+            "-m=conflict",
+        ],
+    );
 
-    test_env.jj_cmd_ok(&repo_path, &["new", "@", "-m", "=== Must skip 2 lines"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nc\nD").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== Don't skip 1 line"]);
-    std::fs::write(repo_path.join("file1"), "A\nb\nC\nd").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== No gap to skip"]);
-    std::fs::write(repo_path.join("file1"), "a\nB\nC\nd").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at start"]);
-    std::fs::write(repo_path.join("file1"), "a\nB\nc\nd").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new", "@-", "-m", "=== 1 line at end"]);
-    std::fs::write(repo_path.join("file1"), "a\nb\nC\nd").unwrap();
+    // The materialized conflict contains NUL bytes of the sides
+    let args = ["diff", "--from=description(side1)", "--to=@"];
+    let stdout = test_env.jj_cmd_success(&repo_path, &args);
+    insta::assert_snapshot!(stdout, @r###"
+    Created conflict in file.bin:
+        (binary)
+    "###);
 
-    let stdout = test_env.jj_cmd_success(
+    let stdout = test_env.jj_cmd_success(&repo_path, &[&args[..], &["--git"]].concat());
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file.bin b/file.bin
+    index 20b683e6bc..0000000000 100644
+    Binary files a/file.bin and b/file.bin differ
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &[&args[..], &["--skip-binary"]].concat());
+    insta::assert_snapshot!(stdout, @"");
+}
+
+#[test]
+fn test_diff_conflict_base() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let file_path = repo_path.join("file");
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=base"]);
+    std::fs::write(&file_path, "base\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "-m=side1"]);
+    std::fs::write(&file_path, "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "description(base)", "-m=side2"]);
+    std::fs::write(&file_path, "b\n").unwrap();
+    test_env.jj_cmd_ok(
         &repo_path,
         &[
-            "log",
-            "-Tdescription",
-            "-p",
-            "--no-graph",
-            "--reversed",
-            "--context=0",
+            "new",
+            "description(side1)",
+            "description(side2)",
+            "-m=conflict",
         ],
     );
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--from=root()"]);
     insta::assert_snapshot!(stdout, @r###"
-    === Left side of diffs
-    Added regular file file1:
-            1: a
-            2: b
-            3: c
-            4: d
-    === Must skip 2 lines
-    Modified regular file file1:
-       1    1: aA
-        ...
-       4    4: dD
-    === Don't skip 1 line
-    Modified regular file file1:
-       1    1: aA
-       2    2: b
-       3    3: cC
-       4    4: d
-    === No gap to skip
-    Modified regular file file1:
-       1    1: a
-       2    2: bB
-       3    3: cC
-       4    4: d
-    === 1 line at start
-    Modified regular file file1:
-       1    1: a
-       2    2: bB
-        ...
-    === 1 line at end
-    Modified regular file file1:
-        ...
-       3    3: cC
-       4    4: d
+    Added conflict file:
+            1: <<<<<<< Conflict 1 of 1
+            2: %%%%%%% Changes from base to side #1
+            3: -base
+            4: +a
+            5: +++++++ Contents of side #2
+            6: b
+            7: >>>>>>> Conflict 1 of 1 ends
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--from=root()", "--conflict-base"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Added conflict file:
+            1: <<<<<<< Conflict 1 of 1
+            2: %%%%%%% Changes from base to side #1
+            3: -base
+            4: +a
+            5: +++++++ Contents of side #2
+            6: b
+            7: >>>>>>> Conflict 1 of 1 ends
+    Changes from base to side #1 of conflict in file:
+       1    1: basea
+    Changes from base to side #2 of conflict in file:
+       1    1: baseb
     "###);
 }
 
 #[test]
-fn test_diff_leading_trailing_context() {
+fn test_diff_git_combined_conflicts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let file_path = repo_path.join("file");
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=base"]);
+    std::fs::write(&file_path, "base\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "-m=side1"]);
+    std::fs::write(&file_path, "a\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "description(base)", "-m=side2"]);
+    std::fs::write(&file_path, "b\n").unwrap();
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            "description(side1)",
+            "description(side2)",
+            "-m=conflict",
+        ],
+    );
+
+    // Each line has one column per side of the conflict
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--combined-conflicts", "--from=root()"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --cc file
+    index 7898192261,6178079822..0000000000
+    --- a/file
+    +++ b/file
+    @@@ -1,1 -1,1 +1,7 @@@
+    - a
+    ++<<<<<<< Conflict 1 of 1
+    ++%%%%%%% Changes from base to side #1
+    ++-base
+    +++a
+    +++++++++ Contents of side #2
+    + b
+    ++>>>>>>> Conflict 1 of 1 ends
+    "###);
+}
+
+#[test]
+fn test_diff_binary_hexdump() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    // N=5 context lines at start/end of the file
     std::fs::write(
-        repo_path.join("file1"),
-        "1\n2\n3\n4\n5\nL\n6\n7\n8\n9\n10\n11\n",
+        repo_path.join("file.bin"),
+        b"\0ABCDEFGHIJKLMNOPQRSTUVWXYZabcde",
     )
     .unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
+    // Bytes at 0x12 and 0x13 are changed
     std::fs::write(
-        repo_path.join("file1"),
-        "1\n2\n3\n4\n5\n6\nR\n7\n8\n9\n10\n11\n",
+        repo_path.join("file.bin"),
+        b"\0ABCDEFGHIJKLMNOPQrsTUVWXYZabcde",
     )
     .unwrap();
+    std::fs::write(repo_path.join("new.bin"), b"\0\x01\x02hello").unwrap();
 
-    // N=5 <= num_context_lines + 1: No room to skip.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=4"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file1:
-       1    1: 1
-       2    2: 2
-       3    3: 3
-       4    4: 4
-       5    5: 5
-       6     : L
-       7    6: 6
-            7: R
-       8    8: 7
-       9    9: 8
-      10   10: 9
-      11   11: 10
-      12   12: 11
+    Modified regular file file.bin:
+        (binary)
+    Added regular file new.bin:
+        (binary)
     "###);
 
-    // N=5 <= 2 * num_context_lines + 1: The last hunk wouldn't be split if
-    // trailing diff existed.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=3"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--binary-hexdump"]);
     insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file1:
-        ...
-       3    3: 3
-       4    4: 4
-       5    5: 5
-       6     : L
-       7    6: 6
-            7: R
-       8    8: 7
-       9    9: 8
-      10   10: 9
-        ...
+    Modified regular file file.bin:
+        Binary contents differ at offset 0x12:
+        -00000010: 50 51 52 53 54 55 56 57 58 59 5a 61 62 63 64 65  |PQRSTUVWXYZabcde|
+        +00000010: 50 51 72 73 54 55 56 57 58 59 5a 61 62 63 64 65  |PQrsTUVWXYZabcde|
+    Added regular file new.bin:
+        Binary contents differ at offset 0x0:
+        +00000000: 00 01 02 68 65 6c 6c 6f                          |...hello|
     "###);
+}
 
-    // N=5 > 2 * num_context_lines + 1: The last hunk should be split no matter
-    // if trailing diff existed.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--context=1"]);
-    insta::assert_snapshot!(stdout, @r###"
-    Modified regular file file1:
-        ...
-       5    5: 5
-       6     : L
-       7    6: 6
-            7: R
-       8    8: 7
-        ...
-    "###);
+#[test]
+fn test_diff_skip_binary() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
 
-    // N=5 <= num_context_lines: No room to skip.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=5"]);
+    std::fs::write(repo_path.join("file1.png"), b"\x89PNG\r\n\x1a\nabcdefg\0").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1.png"), b"\x89PNG\r\n\x1a\n0123456\0").unwrap();
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file3.png"), b"\0\0\0").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--skip-binary"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    index 1bf57dee4a..69b3e1865c 100644
-    --- a/file1
-    +++ b/file1
-    @@ -1,12 +1,12 @@
-     1
-     2
-     3
-     4
-     5
-    -L
-     6
-    +R
-     7
-     8
-     9
-     10
-     11
+    Modified regular file file2:
+       1    1: foobar
     "###);
 
-    // N=5 <= 2 * num_context_lines: The last hunk wouldn't be split if
-    // trailing diff existed.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=3"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--git", "--config-toml=ui.diff.skip-binary=true"],
+    );
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    index 1bf57dee4a..69b3e1865c 100644
-    --- a/file1
-    +++ b/file1
-    @@ -3,8 +3,8 @@
-     3
-     4
-     5
-    -L
-     6
-    +R
-     7
-     8
-     9
+    diff --git a/file2 b/file2
+    index 257cc5642c..5716ca5987 100644
+    --- a/file2
+    +++ b/file2
+    @@ -1 +1 @@
+    -foo
+    +bar
     "###);
 
-    // N=5 > 2 * num_context_lines: The last hunk should be split no matter
-    // if trailing diff existed.
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--context=2"]);
+    // Binary files are still counted in diff stat
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat", "--skip-binary"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    index 1bf57dee4a..69b3e1865c 100644
-    --- a/file1
-    +++ b/file1
-    @@ -4,6 +4,6 @@
-     4
-     5
-    -L
-     6
-    +R
-     7
-     8
+    file1.png | 2 +-
+    file2     | 2 +-
+    file3.png | 1 +
+    3 files changed, 3 insertions(+), 2 deletions(-)
     "###);
 }
 
 #[test]
-fn test_diff_external_tool() {
-    let mut test_env = TestEnvironment::default();
+fn test_diff_skip_generated() {
+    let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
-
-    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
-    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::remove_file(repo_path.join("file1")).unwrap();
-    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
-    std::fs::write(repo_path.join("file3"), "foo\n").unwrap();
-
-    let edit_script = test_env.set_up_fake_diff_editor();
-    std::fs::write(
-        &edit_script,
-        "print-files-before\0print --\0print-files-after",
-    )
-    .unwrap();
-
-    // diff without file patterns
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor"]), @r###"
-    file1
-    file2
-    --
-    file2
-    file3
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("gen.rs"), "// @generated\nfn a() {}\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("gen.rs"), "// @generated\nfn b() {}\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--skip-generated"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file file1:
+       1    1: foobar
+    Skipped generated file gen.rs
     "###);
 
-    // diff with file patterns
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor", "file1"]), @r###"
-    file1
-    --
+    // Git diff stays a valid patch
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--skip-generated"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 257cc5642c..5716ca5987 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo
+    +bar
     "###);
 
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["log", "-p", "--tool=fake-diff-editor"]), @r###"
-    @  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 39d9055d
-    │  (no description set)
-    │  file1
-    │  file2
-    │  --
-    │  file2
-    │  file3
-    ○  qpvuntsm test.user@example.com 2001-02-03 08:05:08 0ad4ef22
-    │  (no description set)
-    │  --
-    │  file1
-    │  file2
-    ◆  zzzzzzzz root() 00000000
-       --
+    // Configured markers replace the default ones
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--skip-generated",
+            r#"--config-toml=ui.diff.generated-markers=["foo"]"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Skipped generated file file1
+    Modified regular file gen.rs:
+       1    1: // @generated
+       2    2: fn ab() {}
     "###);
+}
 
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["show", "--tool=fake-diff-editor"]), @r###"
-    Commit ID: 39d9055d70873099fd924b9af218289d5663eac8
-    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
-    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
-    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
+#[test]
+fn test_diff_follow() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
 
-        (no description set)
+    std::fs::write(repo_path.join("old"), "1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+    std::fs::write(repo_path.join("other"), "x\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "first"]);
+    std::fs::rename(repo_path.join("old"), repo_path.join("new")).unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "second"]);
+    std::fs::write(repo_path.join("new"), "1\n2\n3\n4\n5\n6\n7\nX\n").unwrap();
+    std::fs::write(repo_path.join("other"), "y\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["commit", "-m", "third"]);
 
-    file1
-    file2
-    --
-    file2
-    file3
-    "###);
+    let from = "--from=description(first)";
 
-    // Enabled by default, looks up the merge-tools table
-    let config = "--config-toml=ui.diff.tool='fake-diff-editor'";
-    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", config]), @r###"
-    file1
-    file2
-    --
-    file2
-    file3
+    // Without --follow, the old name only shows the deletion
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", from, "--to=@-", "-s", "old"]);
+    insta::assert_snapshot!(stdout, @r###"
+    D old
     "###);
 
-    // Inlined command arguments
-    let command = escaped_fake_diff_editor_path();
-    let config = format!(r#"--config-toml=ui.diff.tool=["{command}", "$right", "$left"]"#);
-    insta::assert_snapshot!(test_env.jj_cmd_success(&repo_path, &["diff", &config]), @r###"
-    file2
-    file3
-    --
-    file1
-    file2
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", from, "--to=@-", "-s", "--follow=old"]);
+    insta::assert_snapshot!(stdout, @r###"
+    R {old => new}
     "###);
 
-    // Output of external diff tool shouldn't be escaped
-    std::fs::write(&edit_script, "print \x1b[1;31mred").unwrap();
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", "--color=always", "--tool=fake-diff-editor"]),
-        @r###"
-    [1;31mred
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", from, "--to=@-", "--follow=old"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Renamed and modified regular file new (old => new):
+        ...
+       5    5: 5
+       6    6: 6
+       7    7: 7
+       8    8: 8X
     "###);
 
-    // Non-zero exit code isn't an error
-    std::fs::write(&edit_script, "print diff\0fail").unwrap();
-    let (stdout, stderr) = test_env.jj_cmd_ok(&repo_path, &["show", "--tool=fake-diff-editor"]);
+    // The new name can be followed back to the old name
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["diff", from, "--to=@-", "-s", "--follow=new"]);
     insta::assert_snapshot!(stdout, @r###"
-    Commit ID: 39d9055d70873099fd924b9af218289d5663eac8
-    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
-    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
-    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
-
-        (no description set)
-
-    diff
-    "###);
-    insta::assert_snapshot!(stderr.replace("exit code:", "exit status:"), @r###"
-    Warning: Tool exited with exit status: 1 (run with --debug to see the exact invocation)
+    R {old => new}
     "###);
 
-    // --tool=:builtin shouldn't be ignored
-    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--tool=:builtin"]);
-    insta::assert_snapshot!(strip_last_line(&stderr), @r###"
-    Error: Failed to generate diff
-    Caused by:
-    1: Error executing ':builtin' (run with --debug to see the exact invocation)
+    // Explicit paths are still included
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", from, "--to=@-", "-s", "--follow=new", "other"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    R {old => new}
+    M other
     "###);
 }
 
 #[test]
-fn test_diff_external_file_by_file_tool() {
-    let mut test_env = TestEnvironment::default();
+fn test_diff_with_description() {
+    let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m=first"]);
+    test_env.jj_cmd_ok(&repo_path, &["new", "-m=second change"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
 
-    std::fs::write(repo_path.join("file1"), "file1\n").unwrap();
-    std::fs::write(repo_path.join("file2"), "file2\n").unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::remove_file(repo_path.join("file1")).unwrap();
-    std::fs::write(repo_path.join("file2"), "file2\nfile2\n").unwrap();
-    std::fs::write(repo_path.join("file3"), "file3\n").unwrap();
-    std::fs::write(repo_path.join("file4"), "file1\n").unwrap();
-
-    let edit_script = test_env.set_up_fake_diff_editor();
-    std::fs::write(
-        edit_script,
-        "print ==\0print-files-before\0print --\0print-files-after",
-    )
-    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--with-description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified commit description:
+       1    1: firstsecond change
+    Modified regular file file1:
+       1    1: foobar
+    "###);
 
-    // Enabled by default, looks up the merge-tools table
-    let config = "--config-toml=ui.diff.tool='fake-diff-editor'\nmerge-tools.fake-diff-editor.\
-                  diff-invocation-mode='file-by-file'";
+    // Identical descriptions aren't shown
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--with-description", "--from=@", "--to=@"],
+    );
+    insta::assert_snapshot!(stdout, @"");
 
-    // diff without file patterns
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", config]), @r###"
-    ==
-    file2
-    --
-    file2
-    ==
-    file3
-    --
-    file3
-    ==
-    file1
-    --
-    file4
+    // The root commit has no parent to compare with
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--with-description", "-rroot()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot compare descriptions unless the revision has exactly one parent
+    Hint: Use --from and --to to specify the revisions to compare
     "###);
+}
 
-    // diff with file patterns
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", config, "file1"]), @r###"
-    ==
-    file1
-    --
-    file1
-    "###);
+#[test]
+fn test_diff_with_metadata() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env
+        .jj_cmd(&repo_path, &["new", "root()", "-m=first"])
+        .env("JJ_TIMESTAMP", "2001-02-03T04:05:06+07:00")
+        .assert()
+        .success();
+    test_env
+        .jj_cmd(&repo_path, &["new", "root()", "-m=second"])
+        .env("JJ_USER", "Other User")
+        .env("JJ_TIMESTAMP", "2002-02-03T04:05:06+07:00")
+        .assert()
+        .success();
 
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["log", "-p", config]), @r###"
-    @  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 7b01704a
-    │  (no description set)
-    │  ==
-    │  file2
-    │  --
-    │  file2
-    │  ==
-    │  file3
-    │  --
-    │  file3
-    │  ==
-    │  file1
-    │  --
-    │  file4
-    ○  qpvuntsm test.user@example.com 2001-02-03 08:05:08 6e485984
-    │  (no description set)
-    │  ==
-    │  file1
-    │  --
-    │  file1
-    │  ==
-    │  file2
-    │  --
-    │  file2
-    ◆  zzzzzzzz root() 00000000
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "diff",
+            "--with-metadata",
+            "--from=description(first)",
+            "--to=@",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified commit author:
+       1    1: TestOther User <test.user@example.com>
+    Modified commit author timestamp:
+       1    1: 20012002-02-03 04:05:06.000 +07:00
+    Modified commit committer:
+       1    1: TestOther User <test.user@example.com>
+    Modified commit committer timestamp:
+       1    1: 20012002-02-03 04:05:06.000 +07:00
     "###);
 
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["show", config]), @r###"
-    Commit ID: 7b01704a670bc77d11ed117d362855cff1d4513b
-    Change ID: rlvkpnrzqnoowoytxnquwvuryrwnrmlp
-    Author: Test User <test.user@example.com> (2001-02-03 08:05:09)
-    Committer: Test User <test.user@example.com> (2001-02-03 08:05:09)
-
-        (no description set)
+    // Identical metadata isn't shown
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--with-metadata", "--from=@", "--to=@"],
+    );
+    insta::assert_snapshot!(stdout, @"");
 
-    ==
-    file2
-    --
-    file2
-    ==
-    file3
-    --
-    file3
-    ==
-    file1
-    --
-    file4
+    // The root commit has no parent to compare with
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["diff", "--with-metadata", "-rroot()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot compare metadata unless the revision has exactly one parent
+    Hint: Use --from and --to to specify the revisions to compare
     "###);
 }
 
-#[cfg(unix)]
 #[test]
-fn test_diff_external_tool_symlink() {
-    let mut test_env = TestEnvironment::default();
+fn test_diff_split_output() {
+    let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
+    let output_dir = test_env.env_root().join("patches");
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "qux\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("file2"), "baz\n").unwrap();
+    std::fs::remove_file(repo_path.join("file3")).unwrap();
+
+    let (stdout, stderr) =
+        test_env.jj_cmd_ok(&repo_path, &["diff", "--git", "--split-output=../patches"]);
+    insta::assert_snapshot!(stdout, @"");
+    insta::assert_snapshot!(stderr, @r###"
+    Wrote 3 patch files to ../patches
+    "###);
+    let read_patch = |path: &str| std::fs::read_to_string(output_dir.join(path)).unwrap();
+    insta::assert_snapshot!(read_patch("file1.patch"), @r###"
+    diff --git a/file1 b/file1
+    index 257cc5642c..5716ca5987 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1 +1 @@
+    -foo
+    +bar
+    "###);
+    insta::assert_snapshot!(read_patch("dir/file2.patch"), @r###"
+    diff --git a/dir/file2 b/dir/file2
+    new file mode 100644
+    index 0000000000..76018072e0
+    --- /dev/null
+    +++ b/dir/file2
+    @@ -0,0 +1 @@
+    +baz
+    "###);
+    insta::assert_snapshot!(read_patch("file3.patch"), @r###"
+    diff --git a/file3 b/file3
+    deleted file mode 100644
+    index 100b0dec8c..0000000000
+    --- a/file3
+    +++ /dev/null
+    @@ -1 +0,0 @@
+    -qux
+    "###);
+
+    // Only the Git format is supported
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["diff", "--color-words", "--split-output=../patches"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Only --git format is supported with --split-output
+    "###);
+}
 
-    let external_file_path = test_env.env_root().join("external-file");
-    std::fs::write(&external_file_path, "").unwrap();
-    let external_file_permissions = external_file_path.symlink_metadata().unwrap().permissions();
+#[test]
+fn test_diff_files() {
+    let test_env = TestEnvironment::default();
+    let root = test_env.env_root();
+    std::fs::write(root.join("left.txt"), "1\n2\n3\n4\n5\n").unwrap();
+    std::fs::write(root.join("right.txt"), "1\n2\nX\n4\n5\n").unwrap();
 
-    std::os::unix::fs::symlink("non-existent1", repo_path.join("dead")).unwrap();
-    std::os::unix::fs::symlink(&external_file_path, repo_path.join("file")).unwrap();
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::remove_file(repo_path.join("dead")).unwrap();
-    std::os::unix::fs::symlink("non-existent2", repo_path.join("dead")).unwrap();
-    std::fs::remove_file(repo_path.join("file")).unwrap();
-    std::fs::write(repo_path.join("file"), "").unwrap();
+    // No repo is needed
+    let stdout = test_env.jj_cmd_success(root, &["diff", "--files", "left.txt", "right.txt"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Modified right.txt (left.txt => right.txt):
+       1    1: 1
+       2    2: 2
+       3    3: 3X
+       4    4: 4
+       5    5: 5
+    "###);
 
-    let edit_script = test_env.set_up_fake_diff_editor();
-    std::fs::write(
-        edit_script,
-        "print-files-before\0print --\0print-files-after",
-    )
-    .unwrap();
+    let stdout =
+        test_env.jj_cmd_success(root, &["diff", "--git", "--files", "left.txt", "right.txt"]);
+    insta::assert_snapshot!(stdout, @r###"
+    --- a/left.txt
+    +++ b/right.txt
+    @@ -1,5 +1,5 @@
+     1
+     2
+    -3
+    +X
+     4
+     5
+    "###);
 
-    // Shouldn't try to change permission of symlinks
-    insta::assert_snapshot!(
-        test_env.jj_cmd_success(&repo_path, &["diff", "--tool=fake-diff-editor"]), @r###"
-    dead
-    file
-    --
-    dead
-    file
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &[
+            "diff",
+            "--git",
+            "--context=0",
+            "--files",
+            "left.txt",
+            "right.txt",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    --- a/left.txt
+    +++ b/right.txt
+    @@ -3 +3 @@
+    -3
+    +X
     "###);
 
-    // External file should be intact
-    assert_eq!(
-        external_file_path.symlink_metadata().unwrap().permissions(),
-        external_file_permissions
+    // Identical files produce no output
+    let stdout = test_env.jj_cmd_success(root, &["diff", "--files", "left.txt", "left.txt"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    let stderr = test_env.jj_cmd_failure(
+        root,
+        &["diff", "--summary", "--files", "left.txt", "right.txt"],
     );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Only --git and --color-words formats are supported with --files
+    "###);
 }
 
 #[test]
-fn test_diff_stat() {
+fn test_diff_profile_files() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
-    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
-
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
-    insta::assert_snapshot!(stdout, @r###"
-    file1 | 1 +
-    1 file changed, 1 insertion(+), 0 deletions(-)
-    "###);
-
-    test_env.jj_cmd_ok(&repo_path, &["new"]);
-
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
-    insta::assert_snapshot!(stdout, @"0 files changed, 0 insertions(+), 0 deletions(-)");
 
-    std::fs::write(repo_path.join("file1"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
     std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
-    insta::assert_snapshot!(stdout, @r###"
-    file1 | 1 -
-    1 file changed, 0 insertions(+), 1 deletion(-)
+    // The diff itself isn't affected
+    let (stdout, stderr) = test_env.jj_cmd_ok(&repo_path, &["diff", "--git", "--profile-files"]);
+    assert_eq!(
+        stdout,
+        test_env.jj_cmd_success(&repo_path, &["diff", "--git"])
+    );
+    let mut lines = stderr.lines();
+    assert_eq!(lines.next(), Some("Slowest files to diff:"));
+    let mut paths = lines
+        .map(|line| {
+            let (duration, rest) = line.trim_start().split_once(' ').unwrap();
+            let digits = duration.trim_end_matches(|c: char| c.is_alphabetic());
+            assert!(digits.parse::<f64>().unwrap() > 0.0, "{line}");
+            let (path, steps) = rest.split_once(" (").unwrap();
+            assert!(steps.starts_with("read "), "{line}");
+            assert!(steps.contains(", line diff "), "{line}");
+            assert!(steps.contains(", word diff "), "{line}");
+            path.to_owned()
+        })
+        .collect_vec();
+    paths.sort();
+    assert_eq!(paths, ["file1", "file2"]);
+
+    // Only the slowest file is listed
+    let (_stdout, stderr) = test_env.jj_cmd_ok(&repo_path, &["diff", "--profile-files=1"]);
+    assert_eq!(stderr.lines().count(), 2);
+
+    let (_stdout, stderr) =
+        test_env.jj_cmd_ok(&repo_path, &["diff", "--summary", "--profile-files"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Warning: Only Git and color-words diffs can be profiled
     "###);
 }
 
 #[test]
-fn test_diff_stat_long_name_or_stat() {
-    let mut test_env = TestEnvironment::default();
-    test_env.add_env_var("COLUMNS", "30");
+fn test_diff_stat_format_numstat() {
+    let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    let get_stat = |test_env: &TestEnvironment, path_length: usize, stat_size: usize| {
-        test_env.jj_cmd_ok(&repo_path, &["new", "root()"]);
-        let ascii_name = "1234567890".chars().cycle().take(path_length).join("");
-        let han_name = "一二三四五六七八九十"
-            .chars()
-            .cycle()
-            .take(path_length)
-            .join("");
-        let content = "content line\n".repeat(stat_size);
-        std::fs::write(repo_path.join(ascii_name), &content).unwrap();
-        std::fs::write(repo_path.join(han_name), &content).unwrap();
-        test_env.jj_cmd_success(&repo_path, &["diff", "--stat"])
-    };
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n2\n3\n4\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    std::fs::rename(repo_path.join("file1"), repo_path.join("file3")).unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n5\n3\n").unwrap();
+    std::fs::write(repo_path.join("binary"), b"\0\x01\x02\n").unwrap();
 
-    insta::assert_snapshot!(get_stat(&test_env, 1, 1), @r###"
-    1   | 1 +
-    一  | 1 +
-    2 files changed, 2 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 1, 10), @r###"
-    1   | 10 ++++++++++
-    一  | 10 ++++++++++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 1, 100), @r###"
-    1   | 100 +++++++++++++++++
-    一  | 100 +++++++++++++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 10, 1), @r###"
-    1234567890      | 1 +
-    ...五六七八九十 | 1 +
-    2 files changed, 2 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
-    1234567890     | 10 +++++++
-    ...六七八九十  | 10 +++++++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 10, 100), @r###"
-    1234567890     | 100 ++++++
-    ...六七八九十  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 50, 1), @r###"
-    ...901234567890 | 1 +
-    ...五六七八九十 | 1 +
-    2 files changed, 2 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 50, 10), @r###"
-    ...01234567890 | 10 +++++++
-    ...六七八九十  | 10 +++++++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 50, 100), @r###"
-    ...01234567890 | 100 ++++++
-    ...六七八九十  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat-format=numstat"]);
+    insta::assert_snapshot!(stdout.replace('\t', "<TAB>"), @r###"
+    -<TAB>-<TAB>binary
+    1<TAB>2<TAB>file2
+    0<TAB>0<TAB>{file1 => file3}
     "###);
 
-    // Lengths around where we introduce the ellipsis
-    insta::assert_snapshot!(get_stat(&test_env, 13, 100), @r###"
-    1234567890123  | 100 ++++++
-    ...九十一二三  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 14, 100), @r###"
-    12345678901234 | 100 ++++++
-    ...十一二三四  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 15, 100), @r###"
-    ...56789012345 | 100 ++++++
-    ...一二三四五  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 16, 100), @r###"
-    ...67890123456 | 100 ++++++
-    ...二三四五六  | 100 ++++++
-    2 files changed, 200 insertions(+), 0 deletions(-)
-    "###);
+    // The histogram format is the same as --stat
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat-format=histogram"]);
+    assert_eq!(
+        stdout,
+        test_env.jj_cmd_success(&repo_path, &["diff", "--stat"])
+    );
 
-    // Very narrow terminal (doesn't have to fit, just don't crash)
-    test_env.add_env_var("COLUMNS", "10");
-    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
-    ... | 10 ++
-    ... | 10 ++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    test_env.add_env_var("COLUMNS", "3");
-    insta::assert_snapshot!(get_stat(&test_env, 10, 10), @r###"
-    ... | 10 ++
-    ... | 10 ++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 3, 10), @r###"
-    123 | 10 ++
-    ... | 10 ++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
-    insta::assert_snapshot!(get_stat(&test_env, 1, 10), @r###"
-    1   | 10 ++
-    一  | 10 ++
-    2 files changed, 20 insertions(+), 0 deletions(-)
-    "###);
+    // No summary line is printed for an empty diff
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["diff", "--stat-format=numstat", "-r", "root()"],
+    );
+    insta::assert_snapshot!(stdout, @"");
 }
 
 #[test]
-fn test_diff_binary() {
+fn test_diff_color_words_rename_headers() {
     let test_env = TestEnvironment::default();
     test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
     let repo_path = test_env.env_root().join("repo");
 
-    std::fs::write(repo_path.join("file1.png"), b"\x89PNG\r\n\x1a\nabcdefg\0").unwrap();
-    std::fs::write(repo_path.join("file2.png"), b"\x89PNG\r\n\x1a\n0123456\0").unwrap();
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "1\n2\n3\n4\n5\n").unwrap();
     test_env.jj_cmd_ok(&repo_path, &["new"]);
-    std::fs::remove_file(repo_path.join("file1.png")).unwrap();
-    std::fs::write(repo_path.join("file2.png"), "foo\nbar\n").unwrap();
-    std::fs::write(repo_path.join("file3.png"), b"\x89PNG\r\n\x1a\nxyz\0").unwrap();
-    // try a file that's valid UTF-8 but contains control characters
-    std::fs::write(repo_path.join("file4.png"), b"\0\0\0").unwrap();
+    std::fs::rename(repo_path.join("file1"), repo_path.join("renamed")).unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::write(repo_path.join("modified"), "1\n2\n3\nX\n5\n").unwrap();
 
+    // A pure rename shows only the header, whereas a rename with content
+    // changes also shows the hunks
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
     insta::assert_snapshot!(stdout, @r###"
-    Removed regular file file1.png:
-        (binary)
-    Modified regular file file2.png:
-        (binary)
-    Added regular file file3.png:
-        (binary)
-    Added regular file file4.png:
-        (binary)
+    Renamed and modified regular file modified (file2 => modified):
+       1    1: 1
+       2    2: 2
+       3    3: 3
+       4    4: 4X
+       5    5: 5
+    Renamed regular file renamed (file1 => renamed):
     "###);
+}
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
-    insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1.png b/file1.png
-    deleted file mode 100644
-    index 2b65b23c22..0000000000
-    Binary files a/file1.png and /dev/null differ
-    diff --git a/file2.png b/file2.png
-    index 7f036ce788..3bd1f0e297 100644
-    Binary files a/file2.png and b/file2.png differ
-    diff --git a/file3.png b/file3.png
-    new file mode 100644
-    index 0000000000..deacfbc286
-    Binary files /dev/null and b/file3.png differ
-    diff --git a/file4.png b/file4.png
-    new file mode 100644
-    index 0000000000..4227ca4e87
-    Binary files /dev/null and b/file4.png differ
-    "###);
+#[test]
+fn test_diff_stat_parallel() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
 
-    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
-    insta::assert_snapshot!(stdout, @r###"
-    file1.png | 3 ---
-    file2.png | 5 ++---
-    file3.png | 3 +++
-    file4.png | 1 +
-    4 files changed, 6 insertions(+), 6 deletions(-)
-    "###);
+    for i in 0..100 {
+        let dir = repo_path.join(format!("dir{}", i % 7));
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = (0..i).map(|j| format!("{j}\n")).join("");
+        std::fs::write(dir.join(format!("file{i}")), content).unwrap();
+    }
+    test_env.jj_cmd_ok(&repo_path, &["new"]);
+    for i in 0..100 {
+        let path = repo_path
+            .join(format!("dir{}", i % 7))
+            .join(format!("file{i}"));
+        match i % 4 {
+            0 => std::fs::remove_file(path).unwrap(),
+            1 => std::fs::write(path, "changed\n").unwrap(),
+            2 => std::fs::write(path, b"\0binary\n").unwrap(),
+            _ => {}
+        }
+    }
+    std::fs::rename(
+        repo_path.join("dir3").join("file3"),
+        repo_path.join("dir3").join("renamed"),
+    )
+    .unwrap();
+
+    // The output is identical to the sequential one
+    for args in [&["diff", "--stat"][..], &["diff", "--stat-format=numstat"]] {
+        let sequential = test_env.jj_cmd_success(&repo_path, args);
+        let parallel = test_env.jj_cmd_success(
+            &repo_path,
+            &[args, &["--config-toml=ui.diff.stat.parallel=true"]].concat(),
+        );
+        assert!(sequential.lines().count() >= 76, "{sequential}");
+        assert_eq!(parallel, sequential);
+    }
 }
@@ -70,7 +70,7 @@ fn test_interdiff_basic() {
     index 257cc5642c..3bd1f0e297 100644
     --- a/file2
     +++ b/file2
-    @@ -1,1 +1,2 @@
+    @@ -1 +1,2 @@
      foo
     +bar
     "###);
@@ -152,7 +152,7 @@ fn test_interdiff_conflicting() {
     index 0000000000..24c5735c3e 100644
     --- a/file
     +++ b/file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --foo
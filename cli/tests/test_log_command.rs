@@ -117,7 +117,7 @@ fn test_log_with_or_without_diff() {
     │  index 257cc5642c..3bd1f0e297 100644
     │  --- a/file1
     │  +++ b/file1
-    │  @@ -1,1 +1,2 @@
+    │  @@ -1 +1,2 @@
     │   foo
     │  +bar
     ○  add a file
@@ -127,7 +127,7 @@ fn test_log_with_or_without_diff() {
     │  index 0000000000..257cc5642c
     │  --- /dev/null
     │  +++ b/file1
-    │  @@ -1,0 +1,1 @@
+    │  @@ -0,0 +1 @@
     │  +foo
     ◆
     "###);
@@ -179,7 +179,7 @@ fn test_log_with_or_without_diff() {
     index 257cc5642c..3bd1f0e297 100644
     --- a/file1
     +++ b/file1
-    @@ -1,1 +1,2 @@
+    @@ -1 +1,2 @@
      foo
     +bar
     add a file
@@ -188,7 +188,7 @@ fn test_log_with_or_without_diff() {
     index 0000000000..257cc5642c
     --- /dev/null
     +++ b/file1
-    @@ -1,0 +1,1 @@
+    @@ -0,0 +1 @@
     +foo
     "###);
 
@@ -244,7 +244,7 @@ fn test_log_with_or_without_diff() {
     ~  index 257cc5642c..3bd1f0e297 100644
        --- a/file1
        +++ b/file1
-       @@ -1,1 +1,2 @@
+       @@ -1 +1,2 @@
         foo
        +bar
     "###);
@@ -258,7 +258,7 @@ fn test_log_with_or_without_diff() {
     index 257cc5642c..3bd1f0e297 100644
     --- a/file1
     +++ b/file1
-    @@ -1,1 +1,2 @@
+    @@ -1 +1,2 @@
      foo
     +bar
     "###);
@@ -110,7 +110,7 @@ fn test_obslog_with_or_without_diff() {
     index 0000000000..2ab19ae607 100644
     --- a/file1
     +++ b/file1
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --foo
@@ -127,7 +127,7 @@ fn test_obslog_with_or_without_diff() {
     index 257cc5642c..3bd1f0e297 100644
     --- a/file1
     +++ b/file1
-    @@ -1,1 +1,2 @@
+    @@ -1 +1,2 @@
      foo
     +bar
     diff --git a/file2 b/file2
@@ -135,7 +135,7 @@ fn test_obslog_with_or_without_diff() {
     index 0000000000..257cc5642c
     --- /dev/null
     +++ b/file2
-    @@ -1,0 +1,1 @@
+    @@ -0,0 +1 @@
     +foo
     rlvkpnrz hidden test.user@example.com 2001-02-03 08:05:08 2b023b5f
     (empty) my description
@@ -1113,7 +1113,7 @@ fn test_op_diff_patch() {
        index 0000000000..7898192261
        --- /dev/null
        +++ b/file
-       @@ -1,0 +1,1 @@
+       @@ -0,0 +1 @@
        +a
     "###);
     let stdout = test_env.jj_cmd_success(&repo_path, &["op", "diff", "--op", "@", "-p", "--git"]);
@@ -1148,7 +1148,7 @@ fn test_op_diff_patch() {
     │    index 7898192261..6178079822 100644
     │    --- a/file
     │    +++ b/file
-    │    @@ -1,1 +1,1 @@
+    │    @@ -1 +1 @@
     │    -a
     │    +b
     ○  Change qpvuntsmwlqt
@@ -1158,7 +1158,7 @@ fn test_op_diff_patch() {
        index 7898192261..6178079822 100644
        --- a/file
        +++ b/file
-       @@ -1,1 +1,1 @@
+       @@ -1 +1 @@
        -a
        +b
     "###);
@@ -1502,7 +1502,7 @@ fn test_op_show_patch() {
        index 0000000000..7898192261
        --- /dev/null
        +++ b/file
-       @@ -1,0 +1,1 @@
+       @@ -0,0 +1 @@
        +a
     "###);
     let stdout = test_env.jj_cmd_success(&repo_path, &["op", "show", "@", "-p", "--git"]);
@@ -1539,7 +1539,7 @@ fn test_op_show_patch() {
     │    index 7898192261..6178079822 100644
     │    --- a/file
     │    +++ b/file
-    │    @@ -1,1 +1,1 @@
+    │    @@ -1 +1 @@
     │    -a
     │    +b
     ○  Change qpvuntsmwlqt
@@ -1549,7 +1549,7 @@ fn test_op_show_patch() {
        index 7898192261..6178079822 100644
        --- a/file
        +++ b/file
-       @@ -1,1 +1,1 @@
+       @@ -1 +1 @@
        -a
        +b
     "###);
@@ -103,7 +103,7 @@ fn test_resolution() {
     index 0000000000..88425ec521 100644
     --- a/file
     +++ b/file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --base
@@ -143,7 +143,7 @@ fn test_resolution() {
     index 0000000000..88425ec521 100644
     --- a/file
     +++ b/file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --base
@@ -192,7 +192,7 @@ fn test_resolution() {
     index 0000000000..88425ec521 100644
     --- a/file
     +++ b/file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --base
@@ -882,7 +882,7 @@ fn test_multiple_conflicts() {
     index 0000000000..a9fcc7d486 100644
     --- a/another_file
     +++ b/another_file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --second base
@@ -921,7 +921,7 @@ fn test_multiple_conflicts() {
     index 0000000000..7903e1c1c7 100644
     --- a/another_file
     +++ b/another_file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --second base
@@ -948,7 +948,7 @@ fn test_multiple_conflicts() {
     index 0000000000..7903e1c1c7 100644
     --- a/another_file
     +++ b/another_file
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --second base
@@ -961,7 +961,7 @@ fn test_multiple_conflicts() {
     index 0000000000..f8c72adf17 100644
     --- a/this_file_has_a_very_long_name_to_test_padding
     +++ b/this_file_has_a_very_long_name_to_test_padding
-    @@ -1,7 +1,1 @@
+    @@ -1,7 +1 @@
     -<<<<<<< Conflict 1 of 1
     -%%%%%%% Changes from base to side #1
     --first base
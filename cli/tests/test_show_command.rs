@@ -56,13 +56,11 @@ fn test_show_basic() {
 
         (no description set)
 
-    Removed regular file file1:
-       1     : foo
     Modified regular file file2:
        1    1: foo
             2: bar
        2    3: baz quxquux
-    Modified regular file file3 (file1 => file3):
+    Renamed regular file file3 (file1 => file3):
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["show", "--context=0"]);
@@ -74,13 +72,11 @@ fn test_show_basic() {
 
         (no description set)
 
-    Removed regular file file1:
-       1     : foo
     Modified regular file file2:
        1    1: foo
             2: bar
        2    3: baz quxquux
-    Modified regular file file3 (file1 => file3):
+    Renamed regular file file3 (file1 => file3):
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["show", "--color=debug"]);
@@ -92,13 +88,11 @@ fn test_show_basic() {
 
     [38;5;3m<<description placeholder::    (no description set)>>[39m
 
-    [38;5;3m<<diff header::Removed regular file file1:>>[39m
-    [38;5;1m<<diff removed line_number::   1>>[39m<<diff::     : >>[4m[38;5;1m<<diff removed token::foo>>[24m[39m
     [38;5;3m<<diff header::Modified regular file file2:>>[39m
     [38;5;1m<<diff removed line_number::   1>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   1>>[39m<<diff::: foo>>
     <<diff::     >>[38;5;2m<<diff added line_number::   2>>[39m<<diff::: >>[4m[38;5;2m<<diff added token::bar>>[24m[39m
     [38;5;1m<<diff removed line_number::   2>>[39m<<diff:: >>[38;5;2m<<diff added line_number::   3>>[39m<<diff::: baz >>[4m[38;5;1m<<diff removed token::qux>>[38;5;2m<<diff added token::quux>>[24m[39m<<diff::>>
-    [38;5;3m<<diff header::Modified regular file file3 (file1 => file3):>>[39m
+    [38;5;3m<<diff header::Renamed regular file file3 (file1 => file3):>>[39m
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-s"]);
@@ -146,6 +140,7 @@ fn test_show_basic() {
     +bar
     +baz quux
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     "###);
@@ -163,11 +158,12 @@ fn test_show_basic() {
     index 523a4a9de8..485b56a572 100644
     --- a/file2
     +++ b/file2
-    @@ -2,1 +2,2 @@
+    @@ -2 +2,2 @@
     -baz qux
     +bar
     +baz quux
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     "###);
@@ -191,6 +187,7 @@ fn test_show_basic() {
     [38;5;2m<<diff added::+>>[4m<<diff added token::bar>>[24m[39m
     [38;5;2m<<diff added::+baz >>[4m<<diff added token::quux>>[24m<<diff added::>>[39m
     [1m<<diff file_header::diff --git a/file1 b/file3>>[0m
+    [1m<<diff file_header::similarity index 100%>>[0m
     [1m<<diff file_header::rename from file1>>[0m
     [1m<<diff file_header::rename to file3>>[0m
     "###);
@@ -216,6 +213,7 @@ fn test_show_basic() {
     +bar
     +baz quux
     diff --git a/file1 b/file3
+    similarity index 100%
     rename from file1
     rename to file3
     "###);
@@ -235,6 +233,51 @@ fn test_show_basic() {
     "###);
 }
 
+#[test]
+fn test_show_parent() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_ok(test_env.env_root(), &["git", "init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_ok(&repo_path, &["describe", "-m", "left"]);
+    std::fs::write(repo_path.join("file1"), "1\n").unwrap();
+    test_env.jj_cmd_ok(&repo_path, &["new", "root()", "-m", "right"]);
+    std::fs::write(repo_path.join("file2"), "2\n").unwrap();
+    test_env.jj_cmd_ok(
+        &repo_path,
+        &[
+            "new",
+            "description(left)",
+            "description(right)",
+            "-m",
+            "merge",
+        ],
+    );
+    std::fs::write(repo_path.join("file3"), "3\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", "", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file3
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", "", "-s", "--parent=0"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file2
+    A file3
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", "", "-s", "--parent=1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file1
+    A file3
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["show", "-T", "", "--parent=2"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Parent index 2 is out of range (the revision has 2 parent(s))
+    "###);
+}
+
 #[test]
 fn test_show_with_template() {
     let test_env = TestEnvironment::default();
@@ -69,6 +69,79 @@ pub fn find_nonword_ranges(text: &[u8]) -> Vec<Range<usize>> {
         .collect()
 }
 
+/// Operators which are treated as a single token by
+/// `find_syntax_token_ranges()`. Longer operators must precede their prefixes.
+const SYNTAX_OPERATORS: &[&[u8]] = &[
+    b"<<=", b">>=", b"...", b"..=", b"**=", b"//=", b"===", b"!==", b"::", b"->", b"=>", b"==",
+    b"!=", b"<=", b">=", b"&&", b"||", b"+=", b"-=", b"*=", b"/=", b"%=", b"^=", b"&=", b"|=",
+    b"<<", b">>", b"++", b"--", b"..", b"**", b"//",
+];
+
+/// Splits `text` into tokens of typical programming languages: identifiers,
+/// numbers, string literals, and operators. Whitespace is not included in any
+/// token.
+///
+/// This is a lightweight lexer which doesn't know about the grammar of any
+/// particular language. String literals don't span multiple lines, and
+/// single-quoted literals are recognized only if they are closed on the same
+/// line.
+pub fn find_syntax_token_ranges(text: &[u8]) -> Vec<Range<usize>> {
+    let find_closing_quote = |start: usize, quote: u8| {
+        let mut pos = start + 1;
+        while pos < text.len() {
+            match text[pos] {
+                b'\\' => pos += 2,
+                b'\n' => return None,
+                b if b == quote => return Some(pos),
+                _ => pos += 1,
+            }
+        }
+        None
+    };
+    let mut token_ranges = vec![];
+    let mut pos = 0;
+    while pos < text.len() {
+        let start = pos;
+        let b = text[pos];
+        if b.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        } else if b.is_ascii_digit() {
+            pos += 1;
+            while pos < text.len()
+                && (is_word_byte(text[pos])
+                    || (text[pos] == b'.' && text.get(pos + 1).is_some_and(u8::is_ascii_digit)))
+            {
+                pos += 1;
+            }
+        } else if is_word_byte(b) {
+            pos += 1;
+            while pos < text.len() && is_word_byte(text[pos]) {
+                pos += 1;
+            }
+        } else if b == b'"' || b == b'\'' {
+            match find_closing_quote(pos, b) {
+                Some(end) => pos = end + 1,
+                // Unterminated string literal ends at the end of the line
+                None if b == b'"' => {
+                    let line_len = text[pos..].iter().position(|&b| b == b'\n');
+                    pos = line_len.map_or(text.len(), |len| pos + len);
+                }
+                // Probably a lifetime or a quote in a comment
+                None => pos += 1,
+            }
+        } else {
+            let len = SYNTAX_OPERATORS
+                .iter()
+                .find(|op| text[pos..].starts_with(op))
+                .map_or(1, |op| op.len());
+            pos += len;
+        }
+        token_ranges.push(start..pos);
+    }
+    token_ranges
+}
+
 struct Histogram<'a> {
     word_to_positions: HashMap<&'a [u8], Vec<usize>>,
     count_to_words: BTreeMap<usize, Vec<&'a [u8]>>,
@@ -741,6 +814,43 @@ mod tests {
         assert_eq!(find_word_ranges("⊢".as_bytes()), vec![0..3])
     }
 
+    #[test]
+    fn test_find_syntax_token_ranges_empty() {
+        assert_eq!(find_syntax_token_ranges(b""), no_ranges());
+    }
+
+    #[test]
+    fn test_find_syntax_token_ranges_operators() {
+        assert_eq!(find_syntax_token_ranges(b"x+=1"), vec![0..1, 1..3, 3..4]);
+        assert_eq!(
+            find_syntax_token_ranges(b"a::b->c <<= !d"),
+            vec![0..1, 1..3, 3..4, 4..6, 6..7, 8..11, 12..13, 13..14]
+        );
+    }
+
+    #[test]
+    fn test_find_syntax_token_ranges_numbers() {
+        assert_eq!(
+            find_syntax_token_ranges(b"1.5e3 0x1f x.0"),
+            vec![0..5, 6..10, 11..12, 12..13, 13..14]
+        );
+    }
+
+    #[test]
+    fn test_find_syntax_token_ranges_strings() {
+        assert_eq!(
+            find_syntax_token_ranges(br#"f("a \" b", 'c')"#),
+            vec![0..1, 1..2, 2..10, 10..11, 12..15, 15..16]
+        );
+        // Unterminated string ends at newline
+        assert_eq!(find_syntax_token_ranges(b"\"a b\nc"), vec![0..4, 5..6]);
+        // Lifetime isn't a string literal
+        assert_eq!(
+            find_syntax_token_ranges(b"&'a str"),
+            vec![0..1, 1..2, 2..3, 4..7]
+        );
+    }
+
     #[test]
     fn test_find_lcs_empty() {
         let empty: Vec<(usize, usize)> = vec![];